@@ -0,0 +1,235 @@
+use crate::attachments::BlobStore;
+use crate::markdown_folder::{self, FolderImport, ATTACHMENTS_DIR};
+use crate::note::Note;
+use crate::properties;
+use age::armor::{ArmoredReader, ArmoredWriter, Format};
+use age::secrecy::SecretString;
+use age::{x25519, Decryptor, Encryptor, Identity};
+use std::collections::HashSet;
+use std::fs;
+use std::io::{BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+
+pub const EXTENSION: &str = "age";
+
+/// Who can decrypt an export
+pub enum AgeRecipients {
+    /// scrypt-protected, like `age --passphrase`
+    Passphrase(String),
+    /// Public keys such as `age1…`, like `age --recipient`
+    Keys(Vec<x25519::Recipient>),
+}
+
+/// Parses `age1…` public keys separated by whitespace or commas; `#` starts a comment
+pub fn parse_recipients(text: &str) -> Result<Vec<x25519::Recipient>, String> {
+    let recipients = text
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default())
+        .flat_map(|line| line.split([',', ' ', '\t']))
+        .filter(|key| !key.is_empty())
+        .map(|key| key.parse().map_err(|e| format!("Invalid recipient {}: {}", key, e)))
+        .collect::<Result<Vec<_>, String>>()?;
+    if recipients.is_empty() {
+        return Err("No recipients given".to_string());
+    }
+    Ok(recipients)
+}
+
+pub fn encrypt(plaintext: &[u8], recipients: &AgeRecipients, armor: bool) -> Result<Vec<u8>, String> {
+    let encryptor = match recipients {
+        AgeRecipients::Passphrase(passphrase) => {
+            Encryptor::with_user_passphrase(SecretString::from(passphrase.clone()))
+        }
+        AgeRecipients::Keys(keys) => Encryptor::with_recipients(keys.iter().map(|key| key as &dyn age::Recipient))
+            .map_err(|e| format!("Encryption failed: {}", e))?,
+    };
+
+    let mut ciphertext = Vec::new();
+    let format = if armor { Format::AsciiArmor } else { Format::Binary };
+    let result = ArmoredWriter::wrap_output(&mut ciphertext, format).and_then(|armored| {
+        let mut writer = encryptor.wrap_output(armored)?;
+        writer.write_all(plaintext)?;
+        writer.finish()?.finish()
+    });
+    result.map_err(|e| format!("Encryption failed: {}", e))?;
+    Ok(ciphertext)
+}
+
+/// Decrypts binary or armored age data with any of the identities
+pub fn decrypt(ciphertext: &[u8], identities: &[Box<dyn Identity>]) -> Result<Vec<u8>, String> {
+    let decryptor =
+        Decryptor::new_buffered(ArmoredReader::new(ciphertext)).map_err(|e| format!("Not an age file: {}", e))?;
+    let mut reader = decryptor
+        .decrypt(identities.iter().map(|identity| identity.as_ref()))
+        .map_err(|e| format!("Decryption failed: {}", e))?;
+    let mut plaintext = Vec::new();
+    reader
+        .read_to_end(&mut plaintext)
+        .map_err(|e| format!("Decryption failed: {}", e))?;
+    Ok(plaintext)
+}
+
+pub fn passphrase_identity(passphrase: &str) -> Box<dyn Identity> {
+    Box::new(age::scrypt::Identity::new(SecretString::from(passphrase.to_string())))
+}
+
+/// Reads an identity file as written by `age-keygen`
+pub fn load_identities(path: &Path) -> Result<Vec<Box<dyn Identity>>, String> {
+    let file = fs::File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    age::IdentityFile::from_buffer(BufReader::new(file))
+        .map_err(|e| format!("Failed to read identities: {}", e))?
+        .into_identities()
+        .map_err(|e| format!("Failed to read identities: {}", e))
+}
+
+/// Writes each note as `<title>.md.age` (Markdown with frontmatter) and its
+/// attachments as `_attachments/<name>.age`, so `age -d` restores a folder
+/// that imports like any Markdown export.
+pub fn export_notes(
+    notes: &[&Note],
+    dir: &Path,
+    blob_store: &BlobStore,
+    recipients: &AgeRecipients,
+    armor: bool,
+) -> Result<usize, String> {
+    fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    let write = |path: &Path, plaintext: &[u8]| {
+        let ciphertext = encrypt(plaintext, recipients, armor)?;
+        fs::write(path, ciphertext).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    };
+
+    let mut used_paths = HashSet::new();
+    let mut exported_blobs = HashSet::new();
+    for note in notes {
+        let mut note = (*note).clone();
+        for attachment in &note.attachments {
+            let file_name = markdown_folder::attachment_file_name(attachment);
+            if exported_blobs.insert(file_name.clone()) {
+                let attachments_dir = dir.join(ATTACHMENTS_DIR);
+                fs::create_dir_all(&attachments_dir)
+                    .map_err(|e| format!("Failed to create {}: {}", attachments_dir.display(), e))?;
+                let data = blob_store.read(&attachment.blob_id)?;
+                write(&attachments_dir.join(format!("{}.{}", file_name, EXTENSION)), &data)?;
+            }
+            note.content = note.content.replace(
+                &format!("(attachment:{})", attachment.blob_id),
+                &format!("({}/{})", ATTACHMENTS_DIR, file_name),
+            );
+        }
+
+        let stem = markdown_folder::sanitize_file_name(&note.title);
+        let path = markdown_folder::unique_path(dir, &stem, &format!("md.{}", EXTENSION), &mut used_paths);
+        write(&path, properties::to_markdown(&note)?.as_bytes())?;
+    }
+    Ok(notes.len())
+}
+
+/// Decrypts `.md.age` files into notes. Linked files next to them are
+/// attached, decrypting `<link>.age` when only the encrypted copy exists.
+pub fn import_files(
+    paths: &[PathBuf],
+    identities: &[Box<dyn Identity>],
+    blob_store: &BlobStore,
+) -> Result<FolderImport, String> {
+    let mut import = FolderImport {
+        notes: Vec::new(),
+        skipped: Vec::new(),
+    };
+    for path in paths {
+        match import_file(path, identities, blob_store) {
+            Ok(note) => import.notes.push(note),
+            Err(e) => import.skipped.push((path.clone(), e)),
+        }
+    }
+    Ok(import)
+}
+
+fn import_file(path: &Path, identities: &[Box<dyn Identity>], blob_store: &BlobStore) -> Result<Note, String> {
+    let ciphertext = fs::read(path).map_err(|e| format!("Failed to read file: {}", e))?;
+    let plaintext = decrypt(&ciphertext, identities)?;
+    let source = String::from_utf8(plaintext).map_err(|_| "The decrypted file is not text".to_string())?;
+
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let title = file_name
+        .strip_suffix(&format!(".{}", EXTENSION))
+        .unwrap_or(&file_name)
+        .trim_end_matches(".markdown")
+        .trim_end_matches(".md");
+    let mut note = properties::from_markdown(&source, title)?;
+
+    let base = path.parent().unwrap_or(Path::new("."));
+    markdown_folder::attach_local_files(&mut note, base, |file| {
+        let encrypted = PathBuf::from(format!("{}.{}", file.display(), EXTENSION));
+        if file.is_file() {
+            blob_store.add_file(file).map(Some)
+        } else if encrypted.is_file() {
+            let ciphertext =
+                fs::read(&encrypted).map_err(|e| format!("Failed to read {}: {}", encrypted.display(), e))?;
+            let name = file
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            blob_store.add_bytes(&name, &decrypt(&ciphertext, identities)?).map(Some)
+        } else {
+            Ok(None)
+        }
+    })?;
+    Ok(note)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attachments::BlobKeys;
+
+    #[test]
+    fn notes_round_trip_through_age_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = BlobStore::new(dir.path().join("source"), &BlobKeys::generate()).unwrap();
+        let target = BlobStore::new(dir.path().join("target"), &BlobKeys::generate()).unwrap();
+
+        let mut note = Note::new("Trip / plan".to_string(), String::new());
+        note.tags.push("travel".to_string());
+        let attachment = source.add_bytes("map.png", b"png").unwrap();
+        note.content = format!("See ![map](attachment:{})\n", attachment.blob_id);
+        note.add_attachment(attachment);
+
+        let identity = x25519::Identity::generate();
+        let colleague = x25519::Identity::generate();
+        let recipients = AgeRecipients::Keys(vec![identity.to_public(), colleague.to_public()]);
+        let export = dir.path().join("export");
+        assert_eq!(export_notes(&[&note], &export, &source, &recipients, true).unwrap(), 1);
+
+        let paths = vec![export.join("Trip _ plan.md.age")];
+        let armored = fs::read_to_string(&paths[0]).unwrap();
+        assert!(armored.starts_with("-----BEGIN AGE ENCRYPTED FILE-----"));
+
+        let identities: Vec<Box<dyn Identity>> = vec![Box::new(colleague)];
+        let import = import_files(&paths, &identities, &target).unwrap();
+        assert!(import.skipped.is_empty());
+        let imported = &import.notes[0];
+        assert_eq!(imported.title, "Trip / plan");
+        assert_eq!(imported.tags, vec!["travel".to_string()]);
+        let blob_id = &imported.attachments[0].blob_id;
+        assert_eq!(imported.content, format!("See ![map](attachment:{})\n", blob_id));
+        assert_eq!(target.read(blob_id).unwrap(), b"png");
+
+        let stranger: Vec<Box<dyn Identity>> = vec![Box::new(x25519::Identity::generate())];
+        assert_eq!(import_files(&paths, &stranger, &target).unwrap().skipped.len(), 1);
+    }
+
+    #[test]
+    fn passphrase_files_decrypt_with_the_same_passphrase() {
+        let recipients = AgeRecipients::Passphrase("hunter2".to_string());
+        let ciphertext = encrypt(b"# Secret\n", &recipients, false).unwrap();
+        assert!(ciphertext.starts_with(b"age-encryption.org/v1"));
+        assert_eq!(decrypt(&ciphertext, &[passphrase_identity("hunter2")]).unwrap(), b"# Secret\n");
+
+        assert!(parse_recipients("age1nope").is_err());
+        let key = x25519::Identity::generate().to_public().to_string();
+        assert_eq!(parse_recipients(&format!("# Alice\n{}, {}", key, key)).unwrap().len(), 2);
+    }
+}
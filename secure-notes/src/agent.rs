@@ -0,0 +1,519 @@
+use crate::crypto;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use zeroize::{Zeroize, Zeroizing};
+
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+
+/// How long the agent keeps a key unless told otherwise
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+/// Overrides where the agent socket lives
+const SOCKET_ENV: &str = "SECURE_NOTES_AGENT_SOCK";
+const SOCKET_NAME: &str = "secure-notes-agent.sock";
+/// A client that stops talking must not block the agent for long
+const IO_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// One JSON line per request; the agent answers with one `Response` line
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "request", rename_all = "snake_case")]
+enum Request {
+    Unlock {
+        key: Zeroizing<String>,
+        salt: String,
+        /// Uses the agent's timeout when missing; 0 keeps the key until locked
+        timeout_secs: Option<u64>,
+    },
+    Key,
+    Lock,
+    SetTimeout { timeout_secs: u64 },
+    Status,
+    Stop,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct Response {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    key: Option<Zeroizing<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    salt: Option<String>,
+    unlocked: bool,
+    expires_in_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AgentStatus {
+    pub unlocked: bool,
+    /// `None` while locked or when the key is kept until `lock`
+    pub expires_in: Option<Duration>,
+}
+
+impl From<&Response> for AgentStatus {
+    fn from(response: &Response) -> Self {
+        Self {
+            unlocked: response.unlocked,
+            expires_in: response.expires_in_secs.map(Duration::from_secs),
+        }
+    }
+}
+
+/// Where clients and the agent meet: `$SECURE_NOTES_AGENT_SOCK`, the user's
+/// runtime directory, or a private folder in the temp directory
+pub fn socket_path() -> Result<PathBuf, String> {
+    if let Some(path) = std::env::var_os(SOCKET_ENV) {
+        return Ok(PathBuf::from(path));
+    }
+    if let Some(dir) = std::env::var_os("XDG_RUNTIME_DIR") {
+        return Ok(PathBuf::from(dir).join(SOCKET_NAME));
+    }
+    let dir = std::env::temp_dir().join(format!("secure-notes-{}", user_id()));
+    private_dir(&dir)?;
+    Ok(dir.join(SOCKET_NAME))
+}
+
+/// Accepts `90`, `90s`, `15m`, `2h` or `1d`; `0` means until locked
+pub fn parse_duration(text: &str) -> Result<Duration, String> {
+    let text = text.trim();
+    let (number, unit) = match text.find(|c: char| !c.is_ascii_digit()) {
+        Some(index) => text.split_at(index),
+        None => (text, "s"),
+    };
+    let number: u64 = number.parse().map_err(|_| format!("Invalid duration: {}", text))?;
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(format!("Invalid duration: {} (use s, m, h or d)", text)),
+    };
+    Ok(Duration::from_secs(number.saturating_mul(seconds)))
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m {}s", secs / 60, secs % 60),
+        _ => format!("{}h {}m", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+/// A connection to a running agent
+pub struct Agent {
+    path: PathBuf,
+}
+
+impl Agent {
+    pub fn new() -> Result<Self, String> {
+        Ok(Self { path: socket_path()? })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.status().is_ok()
+    }
+
+    pub fn status(&self) -> Result<AgentStatus, String> {
+        Ok(AgentStatus::from(&self.request(&Request::Status)?))
+    }
+
+    /// Hands the agent a derived vault key. A `timeout` of zero keeps it until locked.
+    pub fn unlock(&self, key: &[u8; 32], salt: &str, timeout: Option<Duration>) -> Result<AgentStatus, String> {
+        let request = Request::Unlock {
+            key: Zeroizing::new(crypto::encode_base64(key)),
+            salt: salt.to_string(),
+            timeout_secs: timeout.map(|timeout| timeout.as_secs()),
+        };
+        Ok(AgentStatus::from(&self.request(&request)?))
+    }
+
+    /// The held key and its salt; `None` if no agent runs or it is locked
+    pub fn key(&self) -> Option<(Zeroizing<[u8; 32]>, String)> {
+        let response = self.request(&Request::Key).ok()?;
+        let bytes = Zeroizing::new(crypto::decode_base64(response.key.as_ref()?).ok()?);
+        let mut key = Zeroizing::new([0u8; 32]);
+        if bytes.len() != key.len() {
+            return None;
+        }
+        key.copy_from_slice(&bytes);
+        Some((key, response.salt?))
+    }
+
+    pub fn lock(&self) -> Result<(), String> {
+        self.request(&Request::Lock).map(|_| ())
+    }
+
+    /// Sets the timeout for future keys and restarts the countdown of the held one
+    pub fn set_timeout(&self, timeout: Duration) -> Result<AgentStatus, String> {
+        let request = Request::SetTimeout {
+            timeout_secs: timeout.as_secs(),
+        };
+        Ok(AgentStatus::from(&self.request(&request)?))
+    }
+
+    pub fn stop(&self) -> Result<(), String> {
+        self.request(&Request::Stop).map(|_| ())
+    }
+
+    #[cfg(unix)]
+    fn request(&self, request: &Request) -> Result<Response, String> {
+        let stream = UnixStream::connect(&self.path)
+            .map_err(|e| format!("No agent is running at {}: {}", self.path.display(), e))?;
+        stream.set_read_timeout(Some(IO_TIMEOUT)).ok();
+        let mut json = Zeroizing::new(
+            serde_json::to_string(request).map_err(|e| format!("Failed to serialize request: {}", e))?,
+        );
+        json.push('\n');
+        (&stream)
+            .write_all(json.as_bytes())
+            .map_err(|e| format!("Failed to talk to the agent: {}", e))?;
+
+        let mut line = Zeroizing::new(String::new());
+        BufReader::new(&stream)
+            .read_line(&mut line)
+            .map_err(|e| format!("Failed to read the agent's answer: {}", e))?;
+        let mut response: Response =
+            serde_json::from_str(&line).map_err(|e| format!("Invalid answer from the agent: {}", e))?;
+        match response.error.take() {
+            Some(error) => Err(error),
+            None => Ok(response),
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn request(&self, _request: &Request) -> Result<Response, String> {
+        Err("The unlock agent is only supported on Unix".to_string())
+    }
+}
+
+/// A vault key kept out of swap and wiped when dropped
+struct LockedKey(Box<[u8; 32]>);
+
+impl LockedKey {
+    fn new(bytes: &[u8]) -> Result<Self, String> {
+        let mut key = Box::new([0u8; 32]);
+        if bytes.len() != key.len() {
+            return Err("Invalid key length".to_string());
+        }
+        lock_memory(&mut key[..]);
+        key.copy_from_slice(bytes);
+        Ok(Self(key))
+    }
+}
+
+impl Drop for LockedKey {
+    fn drop(&mut self) {
+        self.0.zeroize();
+        unlock_memory(&mut self.0[..]);
+    }
+}
+
+#[cfg(unix)]
+fn lock_memory(memory: &mut [u8]) {
+    // SAFETY: the range is a live allocation owned by the caller
+    if unsafe { libc::mlock(memory.as_ptr().cast(), memory.len()) } != 0 {
+        eprintln!("secure-notes agent: could not lock the key in memory; it may be swapped to disk");
+    }
+}
+
+#[cfg(unix)]
+fn unlock_memory(memory: &mut [u8]) {
+    // SAFETY: same range as in `lock_memory`
+    unsafe {
+        libc::munlock(memory.as_ptr().cast(), memory.len());
+    }
+}
+
+#[cfg(not(unix))]
+fn lock_memory(_memory: &mut [u8]) {}
+
+#[cfg(not(unix))]
+fn unlock_memory(_memory: &mut [u8]) {}
+
+struct HeldKey {
+    key: LockedKey,
+    salt: String,
+    expires_at: Option<Instant>,
+}
+
+struct AgentState {
+    held: Option<HeldKey>,
+    timeout: Duration,
+}
+
+fn expiry(timeout: Duration) -> Option<Instant> {
+    (!timeout.is_zero()).then(|| Instant::now() + timeout)
+}
+
+impl AgentState {
+    fn forget_expired(&mut self) {
+        let expired = self
+            .held
+            .as_ref()
+            .and_then(|held| held.expires_at)
+            .is_some_and(|at| at <= Instant::now());
+        if expired {
+            self.held = None;
+        }
+    }
+
+    fn status(&self) -> Response {
+        Response {
+            unlocked: self.held.is_some(),
+            expires_in_secs: self
+                .held
+                .as_ref()
+                .and_then(|held| held.expires_at)
+                .map(|at| at.saturating_duration_since(Instant::now()).as_secs()),
+            ..Default::default()
+        }
+    }
+
+    fn respond(&mut self, request: Request) -> Result<Response, String> {
+        self.forget_expired();
+        match request {
+            Request::Unlock { key, salt, timeout_secs } => {
+                let bytes = Zeroizing::new(crypto::decode_base64(&key)?);
+                let timeout = timeout_secs.map(Duration::from_secs).unwrap_or(self.timeout);
+                self.held = Some(HeldKey {
+                    key: LockedKey::new(&bytes)?,
+                    salt,
+                    expires_at: expiry(timeout),
+                });
+            }
+            Request::Key => {
+                let held = self.held.as_ref().ok_or("The agent is locked")?;
+                return Ok(Response {
+                    key: Some(Zeroizing::new(crypto::encode_base64(&held.key.0[..]))),
+                    salt: Some(held.salt.clone()),
+                    ..self.status()
+                });
+            }
+            Request::Lock | Request::Stop => self.held = None,
+            Request::SetTimeout { timeout_secs } => {
+                self.timeout = Duration::from_secs(timeout_secs);
+                if let Some(held) = &mut self.held {
+                    held.expires_at = expiry(self.timeout);
+                }
+            }
+            Request::Status => {}
+        }
+        Ok(self.status())
+    }
+}
+
+/// Runs the agent on `path` until a `stop` request arrives
+#[cfg(unix)]
+pub fn serve(path: &Path, timeout: Duration) -> Result<(), String> {
+    use std::os::unix::fs::{FileTypeExt, MetadataExt, PermissionsExt};
+
+    if UnixStream::connect(path).is_ok() {
+        return Err(format!("An agent is already running at {}", path.display()));
+    }
+    match std::fs::symlink_metadata(path) {
+        // Left behind by an agent that did not shut down cleanly
+        Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(path)
+            .map_err(|e| format!("Failed to remove the old socket {}: {}", path.display(), e))?,
+        Ok(_) => return Err(format!("{} exists and is not a socket", path.display())),
+        Err(_) => {}
+    }
+    let listener = UnixListener::bind(path).map_err(|e| format!("Failed to listen on {}: {}", path.display(), e))?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))
+        .map_err(|e| format!("Failed to restrict {}: {}", path.display(), e))?;
+    let socket_id = |metadata: std::fs::Metadata| (metadata.dev(), metadata.ino());
+    let bound = std::fs::symlink_metadata(path).map(socket_id).ok();
+    disable_core_dumps();
+
+    let state = Arc::new(Mutex::new(AgentState { held: None, timeout }));
+    // Wipes an expired key even when nobody asks for it
+    let expiring = Arc::downgrade(&state);
+    thread::spawn(move || {
+        while let Some(state) = expiring.upgrade() {
+            state.lock().forget_expired();
+            drop(state);
+            thread::sleep(Duration::from_secs(1));
+        }
+    });
+
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else {
+            continue;
+        };
+        if peer_user_id(&stream).is_some_and(|uid| uid != user_id()) {
+            continue;
+        }
+        match handle(&stream, &state) {
+            Ok(true) => break,
+            Ok(false) => {}
+            Err(e) => eprintln!("secure-notes agent: {}", e),
+        }
+    }
+    // Only our own socket; another agent may have taken over the path
+    if bound.is_some() && std::fs::symlink_metadata(path).map(socket_id).ok() == bound {
+        std::fs::remove_file(path).ok();
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn serve(_path: &Path, _timeout: Duration) -> Result<(), String> {
+    Err("The unlock agent is only supported on Unix".to_string())
+}
+
+/// Answers one request; `true` once the agent should stop
+#[cfg(unix)]
+fn handle(stream: &UnixStream, state: &Mutex<AgentState>) -> Result<bool, String> {
+    stream.set_read_timeout(Some(IO_TIMEOUT)).ok();
+    stream.set_write_timeout(Some(IO_TIMEOUT)).ok();
+    let mut line = Zeroizing::new(String::new());
+    BufReader::new(stream)
+        .read_line(&mut line)
+        .map_err(|e| format!("Failed to read request: {}", e))?;
+
+    let request = serde_json::from_str::<Request>(&line).map_err(|e| format!("Invalid request: {}", e));
+    let stop = matches!(request, Ok(Request::Stop));
+    let response = request
+        .and_then(|request| state.lock().respond(request))
+        .unwrap_or_else(|error| Response {
+            error: Some(error),
+            ..Default::default()
+        });
+    let mut json = Zeroizing::new(
+        serde_json::to_string(&response).map_err(|e| format!("Failed to serialize response: {}", e))?,
+    );
+    json.push('\n');
+    let mut writer = stream;
+    writer
+        .write_all(json.as_bytes())
+        .map_err(|e| format!("Failed to answer: {}", e))?;
+    Ok(stop)
+}
+
+#[cfg(unix)]
+fn user_id() -> u32 {
+    // SAFETY: geteuid cannot fail
+    unsafe { libc::geteuid() }
+}
+
+#[cfg(not(unix))]
+fn user_id() -> u32 {
+    0
+}
+
+/// The uid of the connecting process, where the platform reports it
+#[cfg(target_os = "linux")]
+fn peer_user_id(stream: &UnixStream) -> Option<u32> {
+    use std::os::fd::AsRawFd;
+    let mut credentials = libc::ucred { pid: 0, uid: 0, gid: 0 };
+    let mut length = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    // SAFETY: the buffer and its length describe a valid ucred
+    let result = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            (&mut credentials as *mut libc::ucred).cast(),
+            &mut length,
+        )
+    };
+    (result == 0).then_some(credentials.uid)
+}
+
+/// Elsewhere the socket's permissions are the only gate
+#[cfg(all(unix, not(target_os = "linux")))]
+fn peer_user_id(_stream: &UnixStream) -> Option<u32> {
+    None
+}
+
+/// Keeps the key out of core dumps and stops other processes of the same
+/// user from attaching a debugger
+#[cfg(target_os = "linux")]
+fn disable_core_dumps() {
+    // SAFETY: PR_SET_DUMPABLE only changes a flag of this process
+    unsafe {
+        libc::prctl(libc::PR_SET_DUMPABLE, 0, 0, 0, 0);
+    }
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn disable_core_dumps() {}
+
+/// Creates a folder only this user can enter, refusing one that someone
+/// else could have prepared
+fn private_dir(dir: &Path) -> Result<(), String> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+        std::fs::DirBuilder::new().mode(0o700).create(dir).ok();
+        let metadata =
+            std::fs::symlink_metadata(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        if !metadata.is_dir() || metadata.uid() != user_id() || metadata.mode() & 0o077 != 0 {
+            return Err(format!("{} is not a private folder of this user", dir.display()));
+        }
+    }
+    #[cfg(not(unix))]
+    std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn agent_holds_the_key_until_locked_or_expired() {
+        assert_eq!(parse_duration("90").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("15m").unwrap(), Duration::from_secs(900));
+        assert_eq!(parse_duration("2h").unwrap(), Duration::from_secs(7200));
+        assert!(parse_duration("5 minutes").is_err());
+        assert!(parse_duration("m").is_err());
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("agent.sock");
+        let server = {
+            let path = path.clone();
+            thread::spawn(move || serve(&path, Duration::from_secs(1)))
+        };
+        let agent = Agent { path: path.clone() };
+        while !agent.is_running() {
+            thread::sleep(Duration::from_millis(10));
+        }
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        assert!(serve(&path, DEFAULT_TIMEOUT).unwrap_err().contains("already running"));
+
+        let key = crypto::generate_key();
+        assert!(agent.key().is_none());
+        let status = agent.unlock(&key, "salt", Some(Duration::ZERO)).unwrap();
+        assert_eq!(status, AgentStatus { unlocked: true, expires_in: None });
+        let (held, salt) = agent.key().unwrap();
+        assert_eq!((*held, salt.as_str()), (key, "salt"));
+        agent.lock().unwrap();
+        assert!(agent.key().is_none());
+
+        // Without an explicit timeout the agent's own one applies
+        agent.unlock(&key, "salt", None).unwrap();
+        thread::sleep(Duration::from_millis(1100));
+        assert!(!agent.status().unwrap().unlocked);
+
+        agent.stop().unwrap();
+        server.join().unwrap().unwrap();
+        assert!(!path.exists());
+        assert!(!agent.is_running());
+
+        // Never replaces something that is not a socket
+        std::fs::write(&path, "notes").unwrap();
+        assert!(serve(&path, DEFAULT_TIMEOUT).unwrap_err().contains("not a socket"));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "notes");
+    }
+}
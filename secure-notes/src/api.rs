@@ -0,0 +1,556 @@
+use crate::crypto;
+use crate::note::{GeoLocation, Note, NoteSummary};
+use crate::storage::{self, SecureStorage};
+use eframe::egui;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+/// Bodies larger than this are refused unread
+const MAX_BODY_BYTES: usize = 1024 * 1024;
+/// The app only answers between frames, which can take a while when minimized
+const ANSWER_TIMEOUT: Duration = Duration::from_secs(10);
+const TOKEN_FILE: &str = "api.json";
+
+// Standard JSON-RPC 2.0 error codes
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+// Errors of this API
+const FAILED: i64 = -32000;
+const VAULT_LOCKED: i64 = -32001;
+const NOTE_NOT_FOUND: i64 = -32002;
+
+/// A request waiting for the app to run it against the vault
+struct Call {
+    request: Value,
+    reply: Sender<Value>,
+}
+
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<String> for RpcError {
+    fn from(message: String) -> Self {
+        Self::new(FAILED, message)
+    }
+}
+
+/// Where the running app tells integrations its address and token
+pub fn token_file_path() -> Result<PathBuf, String> {
+    let dirs = directories::ProjectDirs::from("com", "secnotes", "SecureNotes").ok_or("Failed to get data directory")?;
+    Ok(dirs.runtime_dir().unwrap_or(dirs.data_dir()).join(TOKEN_FILE))
+}
+
+/// JSON-RPC over HTTP on 127.0.0.1. The server thread only checks requests;
+/// the app runs them between frames in `answer`, so the vault never leaves
+/// the UI thread.
+pub struct ApiServer {
+    server: Arc<Server>,
+    stopped: Arc<AtomicBool>,
+    calls: Receiver<Call>,
+    url: String,
+    token_file: PathBuf,
+}
+
+impl ApiServer {
+    /// Listens on `port` (0 picks a free one) and writes a fresh token to
+    /// `token_file`, readable only by this user
+    pub fn start(ctx: &egui::Context, port: u16, token_file: PathBuf) -> Result<Self, String> {
+        let server = Server::http(("127.0.0.1", port)).map_err(|e| format!("Failed to start the API server: {}", e))?;
+        let port = server
+            .server_addr()
+            .to_ip()
+            .map(|address| address.port())
+            .ok_or("The API server has no TCP address")?;
+        let url = format!("http://127.0.0.1:{}/rpc", port);
+        let token = hex::encode(crypto::generate_key());
+
+        if let Some(dir) = token_file.parent() {
+            fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        }
+        fs::remove_file(&token_file).ok();
+        let contents = json!({ "url": url, "token": token });
+        storage::write_private(&token_file, &format!("{:#}\n", contents))?;
+
+        let server = Arc::new(server);
+        let stopped = Arc::new(AtomicBool::new(false));
+        let (sender, calls) = mpsc::channel();
+        let (listener, stopping, ctx) = (Arc::clone(&server), Arc::clone(&stopped), ctx.clone());
+        thread::spawn(move || loop {
+            match listener.recv() {
+                Ok(request) => serve(request, port, &token, &sender, &ctx),
+                Err(_) if stopping.load(Ordering::SeqCst) => break,
+                Err(e) => eprintln!("API server: {}", e),
+            }
+        });
+        Ok(Self {
+            server,
+            stopped,
+            calls,
+            url,
+            token_file,
+        })
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn token_file(&self) -> &Path {
+        &self.token_file
+    }
+
+    /// Runs waiting calls; `true` if any of them changed notes
+    pub fn answer(&self, storage: &mut SecureStorage) -> bool {
+        let mut changed = false;
+        while let Ok(call) = self.calls.try_recv() {
+            let id = call.request.get("id").cloned().unwrap_or(Value::Null);
+            let response = match run(storage, call.request, &mut changed) {
+                Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                Err(e) => json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "error": { "code": e.code, "message": e.message },
+                }),
+            };
+            call.reply.send(response).ok();
+        }
+        changed
+    }
+}
+
+impl Drop for ApiServer {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        self.server.unblock();
+        fs::remove_file(&self.token_file).ok();
+    }
+}
+
+fn respond(request: Request, status: u16, body: &Value) {
+    let content_type = Header::from_bytes("Content-Type", "application/json").expect("valid header");
+    let response = Response::from_string(body.to_string())
+        .with_status_code(status)
+        .with_header(content_type);
+    request.respond(response).ok();
+}
+
+fn header<'a>(request: &'a Request, name: &'static str) -> Option<&'a str> {
+    request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv(name))
+        .map(|header| header.value.as_str())
+}
+
+/// Compares without revealing how many leading characters matched
+fn same_token(given: &str, token: &str) -> bool {
+    given.len() == token.len() && given.bytes().zip(token.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// Checks a request and hands it to the app
+fn serve(mut request: Request, port: u16, token: &str, calls: &Sender<Call>, ctx: &egui::Context) {
+    let error = |message: &str| json!({ "error": message });
+    // Web pages can reach localhost too; they always send an Origin, and a
+    // rebound DNS name shows up in Host
+    let local_host = [format!("127.0.0.1:{}", port), format!("localhost:{}", port)];
+    let from_local_host = header(&request, "Host").is_some_and(|host| local_host.iter().any(|local| local == host));
+    if header(&request, "Origin").is_some() || !from_local_host {
+        return respond(request, 403, &error("Only local programs may use the API"));
+    }
+    if request.url() != "/rpc" {
+        return respond(request, 404, &error("Not found"));
+    }
+    if *request.method() != Method::Post {
+        return respond(request, 405, &error("Use POST"));
+    }
+    let authorized = header(&request, "Authorization")
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|given| same_token(given, token));
+    if !authorized {
+        return respond(request, 401, &error("Missing or wrong token"));
+    }
+    if request.body_length().is_some_and(|length| length > MAX_BODY_BYTES) {
+        return respond(request, 413, &error("Request too large"));
+    }
+
+    let mut body = Vec::new();
+    let read = request
+        .as_reader()
+        .take(MAX_BODY_BYTES as u64 + 1)
+        .read_to_end(&mut body);
+    if read.is_err() || body.len() > MAX_BODY_BYTES {
+        return respond(request, 413, &error("Request too large"));
+    }
+    let parsed = match serde_json::from_slice::<Value>(&body) {
+        Ok(value) if value.is_object() => value,
+        Ok(_) => {
+            let message = "Expected a single JSON-RPC request object";
+            let body = json!({ "jsonrpc": "2.0", "id": null, "error": { "code": INVALID_REQUEST, "message": message } });
+            return respond(request, 200, &body);
+        }
+        Err(e) => {
+            let message = format!("Invalid JSON: {}", e);
+            let body = json!({ "jsonrpc": "2.0", "id": null, "error": { "code": PARSE_ERROR, "message": message } });
+            return respond(request, 200, &body);
+        }
+    };
+
+    // Notifications have no id and get no answer
+    let notification = parsed.get("id").is_none();
+    let (reply, answer) = mpsc::channel();
+    if calls.send(Call { request: parsed, reply }).is_err() {
+        return respond(request, 503, &error("The app is shutting down"));
+    }
+    ctx.request_repaint();
+    match answer.recv_timeout(ANSWER_TIMEOUT) {
+        Ok(_) if notification => {
+            request.respond(Response::empty(204)).ok();
+        }
+        Ok(body) => respond(request, 200, &body),
+        Err(_) => respond(request, 503, &error("The app did not answer in time")),
+    }
+}
+
+#[derive(Deserialize)]
+struct RpcRequest {
+    jsonrpc: String,
+    method: String,
+    params: Option<Value>,
+}
+
+fn params<T: DeserializeOwned>(params: Option<Value>) -> Result<T, RpcError> {
+    serde_json::from_value(params.unwrap_or_else(|| json!({})))
+        .map_err(|e| RpcError::new(INVALID_PARAMS, format!("Invalid params: {}", e)))
+}
+
+#[derive(Deserialize)]
+struct IdParams {
+    id: String,
+}
+
+#[derive(Deserialize)]
+struct ListParams {
+    tag: Option<String>,
+    notebook: Option<String>,
+    #[serde(default)]
+    archived: bool,
+}
+
+#[derive(Deserialize)]
+struct CreateParams {
+    title: String,
+    #[serde(default)]
+    content: String,
+    #[serde(default)]
+    tags: Vec<String>,
+    notebook: Option<String>,
+}
+
+/// Fields that are left out stay as they are
+#[derive(Deserialize)]
+struct UpdateParams {
+    id: String,
+    title: Option<String>,
+    content: Option<String>,
+    /// An empty name moves the note out of its notebook
+    notebook: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct SearchParams {
+    query: String,
+    #[serde(default)]
+    archived: bool,
+}
+
+#[derive(Deserialize)]
+struct TagParams {
+    id: String,
+    #[serde(default)]
+    add: Vec<String>,
+    #[serde(default)]
+    remove: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct LocationParams {
+    id: String,
+    latitude: f64,
+    longitude: f64,
+    #[serde(default)]
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct NearParams {
+    latitude: f64,
+    longitude: f64,
+    radius_km: f64,
+}
+
+fn check_coordinates(latitude: f64, longitude: f64) -> Result<(), RpcError> {
+    if !(-90.0..=90.0).contains(&latitude) || !(-180.0..=180.0).contains(&longitude) {
+        return Err(RpcError::new(INVALID_PARAMS, "Coordinates out of range"));
+    }
+    Ok(())
+}
+
+fn summaries(notes: &[&Note]) -> Value {
+    json!(notes.iter().map(|note| NoteSummary::from(*note)).collect::<Vec<_>>())
+}
+
+fn find_note(storage: &SecureStorage, id: &str) -> Result<Note, RpcError> {
+    storage
+        .get_note(id)
+        .cloned()
+        .ok_or_else(|| RpcError::new(NOTE_NOT_FOUND, format!("No note with id {}", id)))
+}
+
+fn run(storage: &mut SecureStorage, request: Value, changed: &mut bool) -> Result<Value, RpcError> {
+    let request: RpcRequest = serde_json::from_value(request)
+        .map_err(|e| RpcError::new(INVALID_REQUEST, format!("Invalid request: {}", e)))?;
+    if request.jsonrpc != "2.0" {
+        return Err(RpcError::new(INVALID_REQUEST, "Only JSON-RPC 2.0 is supported"));
+    }
+    if !storage.is_unlocked() {
+        return Err(RpcError::new(VAULT_LOCKED, "The vault is locked"));
+    }
+
+    let reads = matches!(
+        request.method.as_str(),
+        "notes.list" | "notes.get" | "notes.search" | "locations.near"
+    );
+    let result = match request.method.as_str() {
+        "notes.list" => {
+            let ListParams { tag, notebook, archived } = params(request.params)?;
+            let notes: Vec<&Note> = storage
+                .get_all_notes()
+                .into_iter()
+                .filter(|note| archived || !note.archived)
+                .filter(|note| tag.as_ref().is_none_or(|tag| note.tags.contains(tag)))
+                .filter(|note| notebook.is_none() || note.notebook == notebook)
+                .collect();
+            summaries(&notes)
+        }
+        "notes.get" => {
+            let IdParams { id } = params(request.params)?;
+            json!(find_note(storage, &id)?)
+        }
+        "notes.create" => {
+            let CreateParams {
+                title,
+                content,
+                tags,
+                notebook,
+            } = params(request.params)?;
+            let mut note = Note::new(title, content);
+            for tag in tags {
+                note.add_tag(tag);
+            }
+            note.set_notebook(notebook);
+            storage.add_note(note.clone())?;
+            json!(note)
+        }
+        "notes.update" => {
+            let UpdateParams {
+                id,
+                title,
+                content,
+                notebook,
+            } = params(request.params)?;
+            let mut note = find_note(storage, &id)?;
+            if let Some(title) = title {
+                note.update_title(title);
+            }
+            if let Some(content) = content {
+                note.update_content(content)?;
+            }
+            if let Some(notebook) = notebook {
+                note.set_notebook(Some(notebook).filter(|name| !name.is_empty()));
+            }
+            storage.update_note(note.clone())?;
+            json!(note)
+        }
+        "notes.delete" => {
+            let IdParams { id } = params(request.params)?;
+            find_note(storage, &id)?;
+            storage.delete_note(&id)?;
+            Value::Null
+        }
+        "notes.search" => {
+            let SearchParams { query, archived } = params(request.params)?;
+            summaries(&storage.search_notes(&query, archived))
+        }
+        "notes.tag" => {
+            let TagParams { id, add, remove } = params(request.params)?;
+            let mut note = find_note(storage, &id)?;
+            for tag in add {
+                note.add_tag(tag);
+            }
+            for tag in remove {
+                note.remove_tag(&tag);
+            }
+            let tags = note.tags.clone();
+            storage.update_note(note)?;
+            json!(tags)
+        }
+        "locations.set" => {
+            let LocationParams {
+                id,
+                latitude,
+                longitude,
+                name,
+            } = params(request.params)?;
+            check_coordinates(latitude, longitude)?;
+            let mut note = find_note(storage, &id)?;
+            note.set_location(GeoLocation::new(latitude, longitude, name));
+            let location = note.location.clone();
+            storage.update_note(note)?;
+            json!(location)
+        }
+        "locations.clear" => {
+            let IdParams { id } = params(request.params)?;
+            let mut note = find_note(storage, &id)?;
+            note.remove_location();
+            storage.update_note(note)?;
+            Value::Null
+        }
+        "locations.near" => {
+            let NearParams {
+                latitude,
+                longitude,
+                radius_km,
+            } = params(request.params)?;
+            check_coordinates(latitude, longitude)?;
+            let mut near: Vec<(f64, &Note)> = storage
+                .get_all_notes()
+                .into_iter()
+                .filter_map(|note| Some((note.location.as_ref()?.distance_km(latitude, longitude), note)))
+                .filter(|(distance, _)| *distance <= radius_km)
+                .collect();
+            near.sort_by(|a, b| a.0.total_cmp(&b.0));
+            json!(near
+                .into_iter()
+                .map(|(distance, note)| json!({ "note": NoteSummary::from(note), "distance_km": distance }))
+                .collect::<Vec<_>>())
+        }
+        method => return Err(RpcError::new(METHOD_NOT_FOUND, format!("Unknown method {}", method))),
+    };
+    *changed |= !reads;
+    Ok(result)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    /// Answers calls on this thread, as the app would, until `client` is done
+    fn pump<T>(api: &ApiServer, storage: &mut SecureStorage, client: thread::JoinHandle<T>) -> T {
+        while !client.is_finished() {
+            api.answer(storage);
+            thread::sleep(Duration::from_millis(5));
+        }
+        client.join().unwrap()
+    }
+
+    fn call(url: &str, token: &str, method: &str, params: Value) -> Value {
+        reqwest::blocking::Client::new()
+            .post(url)
+            .bearer_auth(token)
+            .json(&json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params }))
+            .send()
+            .unwrap()
+            .json()
+            .unwrap()
+    }
+
+    #[test]
+    fn api_needs_the_token_and_an_unlocked_vault() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut storage = SecureStorage::with_data_dir(&dir.path().join("vault"));
+        let token_file = dir.path().join(TOKEN_FILE);
+        let api = ApiServer::start(&egui::Context::default(), 0, token_file.clone()).unwrap();
+
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!(fs::metadata(&token_file).unwrap().permissions().mode() & 0o777, 0o600);
+        let session: Value = serde_json::from_str(&fs::read_to_string(&token_file).unwrap()).unwrap();
+        let url = session["url"].as_str().unwrap().to_string();
+        let token = session["token"].as_str().unwrap().to_string();
+        assert_eq!(url, api.url());
+
+        let (client_url, client_token) = (url.clone(), token.clone());
+        let locked = pump(&api, &mut storage, thread::spawn(move || {
+            let client = reqwest::blocking::Client::new();
+            let no_token = client.post(&client_url).body("{}").send().unwrap().status();
+            let from_page = client
+                .post(&client_url)
+                .bearer_auth(&client_token)
+                .header("Origin", "https://example.com")
+                .body("{}")
+                .send()
+                .unwrap()
+                .status();
+            let locked = call(&client_url, &client_token, "notes.list", json!({}));
+            (no_token.as_u16(), from_page.as_u16(), locked)
+        }));
+        assert_eq!((locked.0, locked.1), (401, 403));
+        assert_eq!(locked.2["error"]["code"], VAULT_LOCKED);
+
+        storage.unlock("password").unwrap();
+        let (client_url, client_token) = (url.clone(), token.clone());
+        let (created, tagged, found, near, unknown) = pump(&api, &mut storage, thread::spawn(move || {
+            let call = |method: &str, params: Value| call(&client_url, &client_token, method, params);
+            let created = call("notes.create", json!({ "title": "Harbour", "content": "ferry times" }));
+            let id = created["result"]["id"].as_str().unwrap().to_string();
+            let tagged = call("notes.tag", json!({ "id": id, "add": ["travel"] }));
+            call("locations.set", json!({ "id": id, "latitude": 55.68, "longitude": 12.59 }));
+            let found = call("notes.search", json!({ "query": "ferry" }));
+            let near = call("locations.near", json!({ "latitude": 55.67, "longitude": 12.57, "radius_km": 5.0 }));
+            let unknown = call("notes.rename", json!({}));
+            (created, tagged, found, near, unknown)
+        }));
+        assert_eq!(created["result"]["title"], "Harbour");
+        assert_eq!(tagged["result"], json!(["travel"]));
+        assert_eq!(found["result"][0]["title"], "Harbour");
+        assert_eq!(near["result"][0]["note"]["tags"], json!(["travel"]));
+        assert_eq!(unknown["error"]["code"], METHOD_NOT_FOUND);
+        assert_eq!(storage.get_all_notes()[0].location.as_ref().unwrap().latitude, 55.68);
+
+        let mut archived = storage.get_all_notes()[0].clone();
+        archived.archived = true;
+        storage.update_note(archived).unwrap();
+        let (client_url, client_token) = (url.clone(), token.clone());
+        let (hidden, included) = pump(&api, &mut storage, thread::spawn(move || {
+            let call = |params: Value| call(&client_url, &client_token, "notes.search", params);
+            (call(json!({ "query": "ferry" })), call(json!({ "query": "ferry", "archived": true })))
+        }));
+        assert_eq!(hidden["result"], json!([]));
+        assert_eq!(included["result"][0]["title"], "Harbour");
+
+        drop(api);
+        assert!(!token_file.exists());
+    }
+}
@@ -0,0 +1,240 @@
+use crate::crypto;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// A file attached to a note; the content lives in an encrypted blob
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Attachment {
+    /// Keyed hash of the plaintext, which is also the blob file name
+    pub blob_id: String,
+    pub file_name: String,
+    pub size: u64,
+    pub added_at: DateTime<Utc>,
+}
+
+impl Attachment {
+    pub fn extension(&self) -> String {
+        Path::new(&self.file_name)
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .unwrap_or_default()
+    }
+
+    pub fn is_image(&self) -> bool {
+        matches!(self.extension().as_str(), "png" | "jpg" | "jpeg" | "gif" | "webp" | "bmp")
+    }
+
+    pub fn icon(&self) -> &'static str {
+        match self.extension().as_str() {
+            _ if self.is_image() => "🖼",
+            "pdf" => "📄",
+            "gpx" | "kml" | "geojson" => "🗺",
+            _ => "📎",
+        }
+    }
+}
+
+/// Keys for the blob store, kept inside the encrypted vault so that a
+/// password change does not require re-encrypting every attachment
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BlobKeys {
+    /// Base64 key for the content hash, so blob names reveal nothing about the files
+    pub hash_key: String,
+    /// Base64 key for blob encryption
+    pub encryption_key: String,
+}
+
+impl BlobKeys {
+    pub fn generate() -> Self {
+        Self {
+            hash_key: crypto::encode_base64(&crypto::generate_key()),
+            encryption_key: crypto::encode_base64(&crypto::generate_key()),
+        }
+    }
+}
+
+/// Directory of encrypted, content-addressed blobs
+#[derive(Clone)]
+pub struct BlobStore {
+    dir: PathBuf,
+    hash_key: [u8; 32],
+    encryption_key: [u8; 32],
+}
+
+fn decode_key(encoded: &str) -> Result<[u8; 32], String> {
+    crypto::decode_base64(encoded)?
+        .try_into()
+        .map_err(|_| "Invalid attachment key".to_string())
+}
+
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+impl BlobStore {
+    pub fn new(dir: PathBuf, keys: &BlobKeys) -> Result<Self, String> {
+        Ok(Self {
+            dir,
+            hash_key: decode_key(&keys.hash_key)?,
+            encryption_key: decode_key(&keys.encryption_key)?,
+        })
+    }
+
+    fn blob_path(&self, blob_id: &str) -> PathBuf {
+        self.dir.join(format!("{}.blob", blob_id))
+    }
+
+    pub fn contains(&self, blob_id: &str) -> bool {
+        self.blob_path(blob_id).exists()
+    }
+
+    /// Encrypts a file into the store; identical content is stored once
+    pub fn add_file(&self, path: &Path) -> Result<Attachment, String> {
+        let open = || File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e));
+        let size = open()?
+            .metadata()
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?
+            .len();
+        let blob_id = crypto::keyed_hash(&mut BufReader::new(open()?), &self.hash_key)?;
+        if !self.contains(&blob_id) {
+            self.write_blob(&blob_id, &mut BufReader::new(open()?))?;
+        }
+
+        let file_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "attachment".to_string());
+        Ok(Attachment {
+            blob_id,
+            file_name,
+            size,
+            added_at: Utc::now(),
+        })
+    }
+
+    /// Stores in-memory data, e.g. an image pasted from the clipboard
+    pub fn add_bytes(&self, file_name: &str, data: &[u8]) -> Result<Attachment, String> {
+        let blob_id = crypto::keyed_hash(&mut &data[..], &self.hash_key)?;
+        if !self.contains(&blob_id) {
+            self.write_blob(&blob_id, &mut &data[..])?;
+        }
+        Ok(Attachment {
+            blob_id,
+            file_name: file_name.to_string(),
+            size: data.len() as u64,
+            added_at: Utc::now(),
+        })
+    }
+
+    fn write_blob(&self, blob_id: &str, reader: &mut impl std::io::Read) -> Result<(), String> {
+        fs::create_dir_all(&self.dir).map_err(|e| format!("Failed to create attachment dir: {}", e))?;
+
+        // Write to a temporary file first so a crash never leaves a partial blob
+        let temp_path = self.dir.join(format!("{}.tmp", blob_id));
+        let file = File::create(&temp_path).map_err(|e| format!("Failed to create blob: {}", e))?;
+        let mut writer = BufWriter::new(file);
+        let result = crypto::encrypt_stream(reader, &mut writer, &self.encryption_key).and_then(|()| {
+            writer
+                .into_inner()
+                .map_err(|e| e.to_string())
+                .and_then(|file| file.sync_all().map_err(|e| e.to_string()))
+                .map_err(|e| format!("Failed to write blob: {}", e))
+        });
+        if let Err(e) = result {
+            fs::remove_file(&temp_path).ok();
+            return Err(e);
+        }
+        fs::rename(&temp_path, self.blob_path(blob_id)).map_err(|e| format!("Failed to store blob: {}", e))
+    }
+
+    /// Decrypts a blob into `writer`
+    pub fn decrypt_to(&self, blob_id: &str, writer: &mut impl Write) -> Result<(), String> {
+        let file = File::open(self.blob_path(blob_id)).map_err(|e| format!("Failed to open attachment: {}", e))?;
+        crypto::decrypt_stream(&mut BufReader::new(file), writer, &self.encryption_key)
+    }
+
+    pub fn read(&self, blob_id: &str) -> Result<Vec<u8>, String> {
+        let mut data = Vec::new();
+        self.decrypt_to(blob_id, &mut data)?;
+        Ok(data)
+    }
+
+    /// Decrypts a blob to a file chosen by the user
+    pub fn export(&self, blob_id: &str, destination: &Path) -> Result<(), String> {
+        let file = File::create(destination).map_err(|e| format!("Failed to create {}: {}", destination.display(), e))?;
+        let mut writer = BufWriter::new(file);
+        let result = self.decrypt_to(blob_id, &mut writer).and_then(|()| {
+            writer.flush().map_err(|e| format!("Failed to write {}: {}", destination.display(), e))
+        });
+        if result.is_err() {
+            fs::remove_file(destination).ok();
+        }
+        result
+    }
+
+    /// Deletes blobs no note refers to anymore, returning how many were removed
+    pub fn collect_garbage(&self, referenced: &HashSet<&str>) -> Result<usize, String> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(format!("Failed to read attachment dir: {}", e)),
+        };
+
+        let mut removed = 0;
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Some(blob_id) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+            let is_blob = path.extension().is_some_and(|ext| ext == "blob" || ext == "tmp");
+            if is_blob && !referenced.contains(blob_id) {
+                fs::remove_file(&path).map_err(|e| format!("Failed to remove blob: {}", e))?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identical_content_is_stored_once_and_collected_when_unused() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = BlobStore::new(dir.path().join("attachments"), &BlobKeys::generate()).unwrap();
+
+        let source = dir.path().join("track.gpx");
+        fs::write(&source, b"<gpx></gpx>").unwrap();
+        let from_file = store.add_file(&source).unwrap();
+        let from_bytes = store.add_bytes("copy.gpx", b"<gpx></gpx>").unwrap();
+        let other = store.add_bytes("photo.png", b"not really a png").unwrap();
+
+        assert_eq!(from_file.blob_id, from_bytes.blob_id);
+        assert_eq!(from_file.size, 11);
+        assert_eq!(store.read(&from_file.blob_id).unwrap(), b"<gpx></gpx>");
+        let stored = fs::read(store.blob_path(&from_file.blob_id)).unwrap();
+        assert!(!stored.windows(5).any(|w| w == b"<gpx>"));
+
+        let referenced = HashSet::from([from_file.blob_id.as_str()]);
+        assert_eq!(store.collect_garbage(&referenced).unwrap(), 1);
+        assert!(store.contains(&from_file.blob_id));
+        assert!(!store.contains(&other.blob_id));
+    }
+}
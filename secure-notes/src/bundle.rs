@@ -0,0 +1,254 @@
+use crate::attachments::BlobStore;
+use crate::crypto;
+use crate::note::Note;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+pub const EXTENSION: &str = "snbundle";
+const MAGIC: &str = "SECURE-NOTES-BUNDLE 1";
+const NOTES_ENTRY: &str = "notes.json";
+const BLOBS_DIR: &str = "blobs/";
+
+/// What to do with a bundled note whose id already exists in the vault
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// Import it as a new note next to the existing one
+    #[default]
+    KeepBoth,
+    Replace,
+    Skip,
+}
+
+impl ConflictPolicy {
+    pub const ALL: [ConflictPolicy; 3] = [ConflictPolicy::KeepBoth, ConflictPolicy::Replace, ConflictPolicy::Skip];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ConflictPolicy::KeepBoth => "Keep both",
+            ConflictPolicy::Replace => "Replace existing",
+            ConflictPolicy::Skip => "Skip",
+        }
+    }
+}
+
+/// Writes notes and their attachments into a single password-protected file.
+/// The bundle is a tar archive encrypted with a key derived from `password`.
+pub fn export(notes: &[&Note], blob_store: &BlobStore, password: &str, path: &Path) -> Result<(), String> {
+    let plaintext = pack(notes, blob_store)?;
+    let salt = crypto::generate_salt();
+    let key = crypto::derive_key(password, &salt)?;
+    let file = File::create(path).map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+    let mut writer = BufWriter::new(file);
+    let result = writeln!(writer, "{}\n{}", MAGIC, salt)
+        .map_err(|e| format!("Failed to write bundle: {}", e))
+        .and_then(|()| crypto::encrypt_stream(&mut &plaintext[..], &mut writer, &key))
+        .and_then(|()| writer.flush().map_err(|e| format!("Failed to write bundle: {}", e)));
+    if result.is_err() {
+        fs::remove_file(path).ok();
+    }
+    result
+}
+
+/// Unencrypted tar archive of the notes as JSON and their attachments
+pub fn pack(notes: &[&Note], blob_store: &BlobStore) -> Result<Vec<u8>, String> {
+    let mut archive = tar::Builder::new(Vec::new());
+    let json = serde_json::to_vec(notes).map_err(|e| format!("Failed to serialize notes: {}", e))?;
+    append(&mut archive, NOTES_ENTRY, &json)?;
+
+    let mut blob_ids: Vec<&str> = notes
+        .iter()
+        .flat_map(|note| &note.attachments)
+        .map(|attachment| attachment.blob_id.as_str())
+        .collect();
+    blob_ids.sort();
+    blob_ids.dedup();
+    for blob_id in blob_ids {
+        let data = blob_store.read(blob_id)?;
+        append(&mut archive, &format!("{}{}", BLOBS_DIR, blob_id), &data)?;
+    }
+    archive
+        .into_inner()
+        .map_err(|e| format!("Failed to build bundle: {}", e))
+}
+
+fn append(archive: &mut tar::Builder<Vec<u8>>, name: &str, data: &[u8]) -> Result<(), String> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o600);
+    header.set_cksum();
+    archive
+        .append_data(&mut header, name, data)
+        .map_err(|e| format!("Failed to build bundle: {}", e))
+}
+
+/// Decrypted content of a bundle, not yet merged into the vault
+pub struct Bundle {
+    pub notes: Vec<Note>,
+    /// Attachment data keyed by the blob id used in the exporting vault
+    blobs: HashMap<String, Vec<u8>>,
+}
+
+pub fn read(path: &Path, password: &str) -> Result<Bundle, String> {
+    let file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let mut reader = BufReader::new(file);
+    let mut header = String::new();
+    let mut salt = String::new();
+    reader
+        .read_line(&mut header)
+        .and_then(|_| reader.read_line(&mut salt))
+        .map_err(|e| format!("Failed to read bundle: {}", e))?;
+    if header.trim_end() != MAGIC {
+        return Err("Not a Secure Notes bundle".to_string());
+    }
+
+    let key = crypto::derive_key(password, salt.trim_end())?;
+    let mut plaintext = Vec::new();
+    crypto::decrypt_stream(&mut reader, &mut plaintext, &key)
+        .map_err(|_| "Wrong password or damaged bundle".to_string())?;
+
+    unpack(&plaintext)
+}
+
+/// Reads an archive written by `pack`
+pub fn unpack(plaintext: &[u8]) -> Result<Bundle, String> {
+    let mut notes = None;
+    let mut blobs = HashMap::new();
+    let mut archive = tar::Archive::new(plaintext);
+    let entries = archive.entries().map_err(|e| format!("Failed to read bundle: {}", e))?;
+    for entry in entries {
+        let mut entry = entry.map_err(|e| format!("Failed to read bundle: {}", e))?;
+        let name = entry
+            .path()
+            .map_err(|e| format!("Failed to read bundle: {}", e))?
+            .to_string_lossy()
+            .into_owned();
+        let mut data = Vec::new();
+        entry
+            .read_to_end(&mut data)
+            .map_err(|e| format!("Failed to read bundle: {}", e))?;
+        if name == NOTES_ENTRY {
+            notes = Some(serde_json::from_slice(&data).map_err(|e| format!("Failed to parse notes: {}", e))?);
+        } else if let Some(blob_id) = name.strip_prefix(BLOBS_DIR) {
+            blobs.insert(blob_id.to_string(), data);
+        }
+    }
+
+    Ok(Bundle {
+        notes: notes.ok_or("The bundle contains no notes")?,
+        blobs,
+    })
+}
+
+/// Notes ready to add to the vault, and how many bundled notes were left out
+pub struct Merge {
+    pub notes: Vec<Note>,
+    pub skipped: usize,
+}
+
+impl Bundle {
+    /// Bundled notes whose id is already used by a different version in the vault
+    pub fn conflicts(&self, existing: &[&Note]) -> usize {
+        let existing: HashMap<&str, &Note> = existing.iter().map(|note| (note.id.as_str(), *note)).collect();
+        self.notes
+            .iter()
+            .filter(|note| existing.get(note.id.as_str()).is_some_and(|other| !same_version(note, other)))
+            .count()
+    }
+
+    /// Re-encrypts the attachments with this vault's keys and resolves id
+    /// conflicts. Notes identical to the vault copy are always skipped.
+    pub fn merge(self, existing: &[&Note], policy: ConflictPolicy, blob_store: &BlobStore) -> Result<Merge, String> {
+        let existing: HashMap<&str, &Note> = existing.iter().map(|note| (note.id.as_str(), *note)).collect();
+        let mut merge = Merge {
+            notes: Vec::new(),
+            skipped: 0,
+        };
+
+        for mut note in self.notes {
+            if let Some(other) = existing.get(note.id.as_str()) {
+                match policy {
+                    _ if same_version(&note, other) => {
+                        merge.skipped += 1;
+                        continue;
+                    }
+                    ConflictPolicy::Skip => {
+                        merge.skipped += 1;
+                        continue;
+                    }
+                    ConflictPolicy::Replace => {}
+                    ConflictPolicy::KeepBoth => {
+                        note.id = uuid::Uuid::new_v4().to_string();
+                        note.title = format!("{} (imported)", note.title);
+                    }
+                }
+            }
+
+            // Blob ids are keyed per vault, so every attachment gets a new id here
+            for attachment in &mut note.attachments {
+                let data = self
+                    .blobs
+                    .get(&attachment.blob_id)
+                    .ok_or_else(|| format!("The bundle is missing attachment {}", attachment.file_name))?;
+                let stored = blob_store.add_bytes(&attachment.file_name, data)?;
+                note.content = note.content.replace(
+                    &format!("attachment:{}", attachment.blob_id),
+                    &format!("attachment:{}", stored.blob_id),
+                );
+                attachment.blob_id = stored.blob_id;
+            }
+            merge.notes.push(note);
+        }
+        Ok(merge)
+    }
+}
+
+fn same_version(a: &Note, b: &Note) -> bool {
+    a.modified_at == b.modified_at && a.title == b.title && a.content == b.content
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attachments::BlobKeys;
+
+    #[test]
+    fn bundles_round_trip_between_vaults_and_resolve_conflicts() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = BlobStore::new(dir.path().join("source"), &BlobKeys::generate()).unwrap();
+        let target = BlobStore::new(dir.path().join("target"), &BlobKeys::generate()).unwrap();
+
+        let mut photo = Note::new("Photo".to_string(), String::new());
+        let attachment = source.add_bytes("cat.png", b"meow").unwrap();
+        photo.content = format!("![cat](attachment:{})", attachment.blob_id);
+        photo.add_attachment(attachment);
+        let plain = Note::new("Plain".to_string(), "unchanged".to_string());
+
+        let path = dir.path().join(format!("notes.{}", EXTENSION));
+        export(&[&photo, &plain], &source, "correct horse", &path).unwrap();
+        let raw = fs::read(&path).unwrap();
+        assert!(!raw.windows(4).any(|w| w == b"meow"));
+        assert!(read(&path, "wrong").is_err());
+
+        // The vault already has `plain` unchanged and an edited copy of `photo`
+        let mut edited = photo.clone();
+        edited.update_content("edited".to_string()).unwrap();
+        let existing = [&edited, &plain];
+        let bundle = read(&path, "correct horse").unwrap();
+        assert_eq!(bundle.conflicts(&existing), 1);
+
+        let merge = bundle.merge(&existing, ConflictPolicy::KeepBoth, &target).unwrap();
+        assert_eq!(merge.skipped, 1);
+        let imported = &merge.notes[0];
+        assert_ne!(imported.id, photo.id);
+        assert_eq!(imported.title, "Photo (imported)");
+        let blob_id = &imported.attachments[0].blob_id;
+        assert_eq!(imported.content, format!("![cat](attachment:{})", blob_id));
+        assert_eq!(target.read(blob_id).unwrap(), b"meow");
+
+        let bundle = read(&path, "correct horse").unwrap();
+        let merge = bundle.merge(&existing, ConflictPolicy::Replace, &target).unwrap();
+        assert_eq!(merge.notes[0].id, photo.id);
+    }
+}
@@ -0,0 +1,313 @@
+use crate::note::Note;
+use crate::tasks;
+use chrono::{Datelike, Duration, Local, Months, NaiveDate, NaiveTime, Weekday};
+use eframe::egui;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DateKind {
+    Created,
+    Modified,
+    Reminder,
+    Due,
+}
+
+impl DateKind {
+    pub const ALL: [DateKind; 4] = [
+        DateKind::Created,
+        DateKind::Modified,
+        DateKind::Reminder,
+        DateKind::Due,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            DateKind::Created => "Created",
+            DateKind::Modified => "Modified",
+            DateKind::Reminder => "Reminders",
+            DateKind::Due => "Due tasks",
+        }
+    }
+
+    pub fn icon(self) -> &'static str {
+        match self {
+            DateKind::Created => "🆕",
+            DateKind::Modified => "✏",
+            DateKind::Reminder => "⏰",
+            DateKind::Due => "☐",
+        }
+    }
+}
+
+/// A note placed on the calendar by one of its dates
+#[derive(Debug, Clone, PartialEq)]
+pub struct CalendarEntry {
+    pub date: NaiveDate,
+    /// Local time of day; due dates have none
+    pub time: Option<NaiveTime>,
+    pub kind: DateKind,
+    pub note_id: String,
+    pub title: String,
+}
+
+/// Collects every dated event of the notes, in chronological order
+pub fn entries(notes: &[&Note]) -> Vec<CalendarEntry> {
+    let mut entries = Vec::new();
+    for note in notes {
+        let mut push = |at: chrono::DateTime<Local>, kind, title: &str| {
+            entries.push(CalendarEntry {
+                date: at.date_naive(),
+                time: Some(at.time()),
+                kind,
+                note_id: note.id.clone(),
+                title: title.to_string(),
+            });
+        };
+
+        push(note.created_at.with_timezone(&Local), DateKind::Created, &note.title);
+        if note.modified_at != note.created_at {
+            push(note.modified_at.with_timezone(&Local), DateKind::Modified, &note.title);
+        }
+        for reminder in &note.reminders {
+            push(reminder.at.with_timezone(&Local), DateKind::Reminder, &note.title);
+        }
+
+        for task in tasks::parse_tasks(&note.content) {
+            if let (Some(due), false) = (task.due, task.checked) {
+                entries.push(CalendarEntry {
+                    date: due,
+                    time: None,
+                    kind: DateKind::Due,
+                    note_id: note.id.clone(),
+                    title: format!("{} — {}", tasks::strip_due(&task.text), note.title),
+                });
+            }
+        }
+    }
+    entries.sort_by_key(|entry| (entry.date, entry.time, entry.kind));
+    entries
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Zoom {
+    Year,
+    #[default]
+    Month,
+    Week,
+    Day,
+}
+
+impl Zoom {
+    pub const ALL: [Zoom; 4] = [Zoom::Year, Zoom::Month, Zoom::Week, Zoom::Day];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Zoom::Year => "Year",
+            Zoom::Month => "Month",
+            Zoom::Week => "Week",
+            Zoom::Day => "Day",
+        }
+    }
+
+    /// Half-open range of days shown around `anchor`
+    pub fn range(self, anchor: NaiveDate) -> (NaiveDate, NaiveDate) {
+        match self {
+            Zoom::Year => {
+                let start = NaiveDate::from_ymd_opt(anchor.year(), 1, 1).unwrap_or(anchor);
+                (start, shift_month(start, 12))
+            }
+            Zoom::Month => {
+                let start = first_of_month(anchor);
+                (start, shift_month(start, 1))
+            }
+            Zoom::Week => {
+                let start = anchor - Duration::days(anchor.weekday().num_days_from_monday() as i64);
+                (start, start + Duration::days(7))
+            }
+            Zoom::Day => (anchor, anchor + Duration::days(1)),
+        }
+    }
+
+    /// Moves the anchor by `delta` periods of this zoom level
+    pub fn step(self, anchor: NaiveDate, delta: i32) -> NaiveDate {
+        match self {
+            Zoom::Year => shift_month(anchor, delta * 12),
+            Zoom::Month => shift_month(anchor, delta),
+            Zoom::Week => anchor + Duration::weeks(delta as i64),
+            Zoom::Day => anchor + Duration::days(delta as i64),
+        }
+    }
+
+    pub fn title(self, anchor: NaiveDate) -> String {
+        match self {
+            Zoom::Year => anchor.format("%Y").to_string(),
+            Zoom::Month => anchor.format("%B %Y").to_string(),
+            Zoom::Week => {
+                let (start, end) = self.range(anchor);
+                format!(
+                    "Week {} · {} – {}",
+                    anchor.iso_week().week(),
+                    start.format("%d %b"),
+                    (end - Duration::days(1)).format("%d %b %Y")
+                )
+            }
+            Zoom::Day => anchor.format("%A, %d %B %Y").to_string(),
+        }
+    }
+
+    pub fn zoom_in(self) -> Zoom {
+        match self {
+            Zoom::Year => Zoom::Month,
+            Zoom::Month => Zoom::Week,
+            Zoom::Week | Zoom::Day => Zoom::Day,
+        }
+    }
+
+    pub fn zoom_out(self) -> Zoom {
+        match self {
+            Zoom::Day => Zoom::Week,
+            Zoom::Week => Zoom::Month,
+            Zoom::Month | Zoom::Year => Zoom::Year,
+        }
+    }
+}
+
+pub fn first_of_month(date: NaiveDate) -> NaiveDate {
+    date.with_day(1).unwrap_or(date)
+}
+
+pub fn shift_month(month: NaiveDate, delta: i32) -> NaiveDate {
+    let month = first_of_month(month);
+    let shifted = if delta >= 0 {
+        month.checked_add_months(Months::new(delta as u32))
+    } else {
+        month.checked_sub_months(Months::new(delta.unsigned_abs()))
+    };
+    shifted.unwrap_or(month)
+}
+
+/// "◀ October 2026 ▶" header that moves the displayed month
+pub fn month_header(ui: &mut egui::Ui, month: &mut NaiveDate) {
+    ui.horizontal(|ui| {
+        if ui.small_button("◀").clicked() {
+            *month = shift_month(*month, -1);
+        }
+        ui.label(egui::RichText::new(month.format("%B %Y").to_string()).strong());
+        if ui.small_button("▶").clicked() {
+            *month = shift_month(*month, 1);
+        }
+    });
+}
+
+/// Monday-first month grid. Days for which `marked` returns true are highlighted.
+/// Returns the day that was clicked, if any.
+pub fn month_grid(
+    ui: &mut egui::Ui,
+    id_source: &str,
+    month: NaiveDate,
+    selected: Option<NaiveDate>,
+    marked: impl Fn(NaiveDate) -> bool,
+) -> Option<NaiveDate> {
+    let month = first_of_month(month);
+    let today = Local::now().date_naive();
+    let offset = month.weekday().num_days_from_monday() as i64;
+    let grid_start = month - Duration::days(offset);
+    let accent = ui.visuals().selection.bg_fill;
+    let mut clicked = None;
+
+    egui::Grid::new(id_source)
+        .spacing([2.0, 2.0])
+        .min_col_width(28.0)
+        .show(ui, |ui| {
+            for weekday in [
+                Weekday::Mon,
+                Weekday::Tue,
+                Weekday::Wed,
+                Weekday::Thu,
+                Weekday::Fri,
+                Weekday::Sat,
+                Weekday::Sun,
+            ] {
+                ui.label(egui::RichText::new(&weekday.to_string()[..2]).small().weak());
+            }
+            ui.end_row();
+
+            for week in 0..6 {
+                for day_index in 0..7 {
+                    let day = grid_start + Duration::days(week * 7 + day_index);
+                    if day.month() != month.month() {
+                        ui.label("");
+                        continue;
+                    }
+
+                    let mut text = egui::RichText::new(day.day().to_string());
+                    if marked(day) {
+                        text = text.strong().color(ui.visuals().strong_text_color());
+                    }
+                    let response = ui.add_sized(
+                        [28.0, 22.0],
+                        egui::SelectableLabel::new(selected == Some(day), text),
+                    );
+                    if marked(day) {
+                        ui.painter().circle_filled(
+                            response.rect.center_bottom() - egui::vec2(0.0, 3.0),
+                            2.0,
+                            accent,
+                        );
+                    }
+                    if day == today {
+                        ui.painter().rect_stroke(
+                            response.rect,
+                            3.0,
+                            egui::Stroke::new(1.0, ui.visuals().weak_text_color()),
+                        );
+                    }
+                    if response.clicked() {
+                        clicked = Some(day);
+                    }
+                }
+                ui.end_row();
+            }
+        });
+
+    clicked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reminders::{Recurrence, Reminder};
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn zoom_ranges_cover_the_anchor_period() {
+        let anchor = NaiveDate::from_ymd_opt(2026, 3, 5).unwrap();
+        let day = |m, d| NaiveDate::from_ymd_opt(2026, m, d).unwrap();
+
+        assert_eq!(Zoom::Year.range(anchor), (day(1, 1), NaiveDate::from_ymd_opt(2027, 1, 1).unwrap()));
+        assert_eq!(Zoom::Month.range(anchor), (day(3, 1), day(4, 1)));
+        assert_eq!(Zoom::Week.range(anchor), (day(3, 2), day(3, 9)));
+        assert_eq!(Zoom::Day.range(anchor), (anchor, day(3, 6)));
+        assert_eq!(Zoom::Month.step(day(1, 31), 1), day(2, 1));
+        assert_eq!(Zoom::Week.step(anchor, -1), day(2, 26));
+    }
+
+    #[test]
+    fn entries_include_reminders_and_open_due_tasks() {
+        let mut note = Note::new(
+            "Trip".to_string(),
+            "- [ ] Book hotel @due(2026-03-10)\n- [x] Pack @due(2026-03-09)\n".to_string(),
+        );
+        note.created_at = Utc.with_ymd_and_hms(2026, 3, 1, 12, 0, 0).unwrap();
+        note.modified_at = note.created_at;
+        note.reminders.push(Reminder::new(
+            Utc.with_ymd_and_hms(2026, 3, 8, 12, 0, 0).unwrap(),
+            Recurrence::Once,
+        ));
+
+        let kinds: Vec<_> = entries(&[&note]).iter().map(|entry| entry.kind).collect();
+        assert_eq!(kinds, vec![DateKind::Created, DateKind::Reminder, DateKind::Due]);
+        let due = entries(&[&note]).pop().unwrap();
+        assert_eq!(due.date, NaiveDate::from_ymd_opt(2026, 3, 10).unwrap());
+        assert_eq!(due.title, "Book hotel — Trip");
+    }
+}
@@ -0,0 +1,551 @@
+use crate::agent::{self, Agent, AgentStatus};
+use crate::markdown_folder;
+use crate::note::{Note, NoteSummary};
+use crate::properties;
+use crate::storage::{self, SecureStorage};
+use clap::{Parser, Subcommand};
+use serde::Serialize;
+use std::fs;
+use std::io::{self, BufRead, BufReader, IsTerminal, Read, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+
+/// Secure Notes. Without a command the app window opens.
+#[derive(Parser)]
+#[command(name = "secure-notes", version)]
+pub struct Cli {
+    /// Print machine-readable JSON instead of text
+    #[arg(long, global = true)]
+    json: bool,
+    /// Read the password from this file descriptor (one per line) instead of prompting
+    #[arg(long, global = true, value_name = "FD")]
+    password_fd: Option<i32>,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// List notes, most recently modified first
+    List {
+        #[arg(long)]
+        tag: Option<String>,
+        #[arg(long)]
+        notebook: Option<String>,
+        /// Include archived notes
+        #[arg(long)]
+        archived: bool,
+    },
+    /// Print a note as Markdown with frontmatter
+    Show { note: String },
+    /// Create a note; the content is read from stdin unless given
+    Add {
+        title: String,
+        #[arg(long)]
+        content: Option<String>,
+        #[arg(long = "tag")]
+        tags: Vec<String>,
+        #[arg(long)]
+        notebook: Option<String>,
+    },
+    /// Edit a note's content in $VISUAL or $EDITOR
+    Edit { note: String },
+    /// Search notes; `key:value` terms filter on properties
+    Search {
+        query: String,
+        /// Include archived notes
+        #[arg(long)]
+        archived: bool,
+    },
+    /// Add tags to a note, or remove them with --remove
+    Tag {
+        note: String,
+        #[arg(required = true)]
+        tags: Vec<String>,
+        #[arg(long)]
+        remove: bool,
+    },
+    /// Write every note as a plain (unencrypted) Markdown folder
+    Export { dir: PathBuf },
+    /// Import a folder of Markdown files
+    Import { dir: PathBuf },
+    /// Change the vault password
+    Passwd,
+    /// Keep the vault key in an agent so commands stop asking for the password
+    Agent {
+        #[command(subcommand)]
+        command: AgentCommand,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum AgentCommand {
+    /// Start the agent in the background
+    Start {
+        /// How long a key is kept, e.g. 90s, 15m or 2h; 0 keeps it until locked
+        #[arg(long, default_value = "15m", value_parser = agent::parse_duration)]
+        timeout: Duration,
+        /// Run in this terminal instead of the background
+        #[arg(long)]
+        foreground: bool,
+    },
+    /// Ask for the password once and hand the key to the agent, starting it if needed
+    Unlock {
+        /// Overrides the agent's timeout for this key
+        #[arg(long, value_parser = agent::parse_duration)]
+        timeout: Option<Duration>,
+    },
+    /// Make the agent forget the key
+    Lock,
+    /// Change how long the agent keeps the key, counting from now
+    Timeout {
+        #[arg(value_parser = agent::parse_duration)]
+        duration: Duration,
+    },
+    /// Show whether the agent runs and holds a key
+    Status,
+    /// Stop the agent, forgetting the key
+    Stop,
+}
+
+/// Runs a command and reports errors on stderr
+pub fn run(cli: Cli) -> ExitCode {
+    let Some(command) = cli.command else {
+        return ExitCode::SUCCESS;
+    };
+    let mut passwords = Passwords { fd: cli.password_fd, lines: None };
+    let result = execute(command, cli.json, &mut passwords);
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("secure-notes: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Passwords come either from the terminal or, for scripts, one per line
+/// from an inherited file descriptor
+struct Passwords {
+    fd: Option<i32>,
+    lines: Option<Box<dyn BufRead>>,
+}
+
+impl Passwords {
+    fn next(&mut self, prompt: &str) -> Result<String, String> {
+        let Some(fd) = self.fd else {
+            // rpassword asks on the controlling terminal, so piping into stdin still works
+            return rpassword::prompt_password(prompt)
+                .map_err(|e| format!("Failed to read password (use --password-fd without a terminal): {}", e));
+        };
+        if self.lines.is_none() {
+            self.lines = Some(Box::new(BufReader::new(open_fd(fd)?)));
+        }
+        read_password_line(self.lines.as_mut().ok_or("Password input closed")?)
+    }
+}
+
+#[cfg(unix)]
+fn open_fd(fd: i32) -> Result<fs::File, String> {
+    use std::os::fd::BorrowedFd;
+    if fd < 0 {
+        return Err(format!("Invalid file descriptor {}", fd));
+    }
+    // SAFETY: the caller handed us the descriptor and keeps it open; we only
+    // borrow it long enough to duplicate it, so closing our copy leaves theirs
+    // (and stdin, stdout or stderr) alone
+    let borrowed = unsafe { BorrowedFd::borrow_raw(fd) };
+    let owned = borrowed
+        .try_clone_to_owned()
+        .map_err(|e| format!("Failed to open file descriptor {}: {}", fd, e))?;
+    Ok(fs::File::from(owned))
+}
+
+#[cfg(not(unix))]
+fn open_fd(_fd: i32) -> Result<fs::File, String> {
+    Err("--password-fd is only supported on Unix".to_string())
+}
+
+fn read_password_line(reader: &mut dyn BufRead) -> Result<String, String> {
+    let mut line = String::new();
+    let read = reader
+        .read_line(&mut line)
+        .map_err(|e| format!("Failed to read password: {}", e))?;
+    if read == 0 {
+        return Err("No password given".to_string());
+    }
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// Unlocks the vault with the agent's key if it holds one, or else with a
+/// password. Commands that add notes may create a new vault.
+fn open_storage(passwords: &mut Passwords, create: bool) -> Result<SecureStorage, String> {
+    if let Some((key, salt)) = Agent::new().ok().and_then(|agent| agent.key()) {
+        let mut storage = SecureStorage::new();
+        // A key for an older password falls through to asking again
+        if storage.unlock_with_key(&key, &salt).is_ok() {
+            return Ok(storage);
+        }
+    }
+    unlock_with_password(passwords, create)
+}
+
+fn unlock_with_password(passwords: &mut Passwords, create: bool) -> Result<SecureStorage, String> {
+    let mut storage = SecureStorage::new();
+    if storage.has_existing_data() {
+        storage.unlock(&passwords.next("Vault password: ")?)?;
+        return Ok(storage);
+    }
+    if !create {
+        return Err("No vault found; add a note or open Secure Notes to create one".to_string());
+    }
+    let password = new_password(passwords, "Password for the new vault: ")?;
+    storage.unlock(&password)?;
+    Ok(storage)
+}
+
+/// Asks twice on a terminal; a descriptor supplies it once
+fn new_password(passwords: &mut Passwords, prompt: &str) -> Result<String, String> {
+    let password = passwords.next(prompt)?;
+    if password.is_empty() {
+        return Err("The password must not be empty".to_string());
+    }
+    if passwords.fd.is_none() && passwords.next("Repeat the password: ")? != password {
+        return Err("The passwords do not match".to_string());
+    }
+    Ok(password)
+}
+
+/// A note by id, unique id prefix or title (case-insensitive)
+fn find_note<'a>(notes: &[&'a Note], query: &str) -> Result<&'a Note, String> {
+    if let Some(note) = notes.iter().find(|note| note.id == query) {
+        return Ok(note);
+    }
+    let mut matches: Vec<&Note> = notes.iter().copied().filter(|note| note.id.starts_with(query)).collect();
+    if matches.is_empty() {
+        matches = notes
+            .iter()
+            .copied()
+            .filter(|note| note.title.eq_ignore_ascii_case(query))
+            .collect();
+    }
+    match matches.as_slice() {
+        [note] => Ok(note),
+        [] => Err(format!("No note matches {}", query)),
+        _ => Err(format!(
+            "{} notes match {}; use the id: {}",
+            matches.len(),
+            query,
+            matches.iter().map(|note| note.id.as_str()).collect::<Vec<_>>().join(", ")
+        )),
+    }
+}
+
+/// Like `print!`, but a closed pipe (e.g. into `head`) is an error, not a panic
+fn output(text: &str) -> Result<(), String> {
+    io::stdout()
+        .lock()
+        .write_all(text.as_bytes())
+        .map_err(|e| format!("Failed to write output: {}", e))
+}
+
+fn print_json<T: Serialize>(value: &T) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(value).map_err(|e| format!("Failed to serialize output: {}", e))?;
+    output(&format!("{}\n", json))
+}
+
+fn print_notes(notes: &[&Note], json: bool) -> Result<(), String> {
+    if json {
+        return print_json(&notes.iter().map(|note| NoteSummary::from(*note)).collect::<Vec<_>>());
+    }
+    for note in notes {
+        let tags: String = note.tags.iter().map(|tag| format!(" #{}", tag)).collect();
+        output(&format!(
+            "{}  {}  {}{}\n",
+            note.id,
+            note.modified_at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M"),
+            note.title,
+            tags
+        ))?;
+    }
+    Ok(())
+}
+
+fn execute(command: Command, json: bool, passwords: &mut Passwords) -> Result<(), String> {
+    if let Command::Agent { command } = command {
+        return control_agent(command, json, passwords);
+    }
+    let creates_notes = matches!(command, Command::Add { .. } | Command::Import { .. });
+    // Changing the password always asks for the current one, even when the
+    // agent could unlock the vault
+    let mut storage = match command {
+        Command::Passwd => unlock_with_password(passwords, false)?,
+        _ => open_storage(passwords, creates_notes)?,
+    };
+    match command {
+        Command::List { tag, notebook, archived } => {
+            let notes: Vec<&Note> = storage
+                .get_all_notes()
+                .into_iter()
+                .filter(|note| archived || !note.archived)
+                .filter(|note| tag.as_ref().is_none_or(|tag| note.tags.contains(tag)))
+                .filter(|note| notebook.is_none() || note.notebook == notebook)
+                .collect();
+            print_notes(&notes, json)
+        }
+        Command::Show { note } => {
+            let note = find_note(&storage.get_all_notes(), &note)?;
+            match json {
+                true => print_json(note),
+                false => output(&properties::to_markdown(note)?),
+            }
+        }
+        Command::Add {
+            title,
+            content,
+            tags,
+            notebook,
+        } => {
+            let content = match content {
+                Some(content) => content,
+                None if io::stdin().is_terminal() => String::new(),
+                None => {
+                    let mut content = String::new();
+                    io::stdin()
+                        .read_to_string(&mut content)
+                        .map_err(|e| format!("Failed to read stdin: {}", e))?;
+                    content
+                }
+            };
+            let mut note = Note::new(title, content);
+            for tag in tags {
+                note.add_tag(tag);
+            }
+            note.set_notebook(notebook);
+            let id = note.id.clone();
+            storage.add_note(note)?;
+            match json {
+                true => print_json(&serde_json::json!({ "id": id })),
+                false => output(&format!("{}\n", id)),
+            }
+        }
+        Command::Edit { note } => {
+            let mut note = find_note(&storage.get_all_notes(), &note)?.clone();
+            let content = edit_in_editor(&note.content)?;
+            if content == note.content {
+                return match json {
+                    true => Ok(()),
+                    false => output("No changes\n"),
+                };
+            }
+            note.update_content(content)?;
+            storage.update_note(note)
+        }
+        Command::Search { query, archived } => print_notes(&storage.search_notes(&query, archived), json),
+        Command::Tag { note, tags, remove } => {
+            let mut note = find_note(&storage.get_all_notes(), &note)?.clone();
+            for tag in tags {
+                match remove {
+                    true => note.remove_tag(&tag),
+                    false => note.add_tag(tag),
+                }
+            }
+            let tags = note.tags.clone();
+            storage.update_note(note)?;
+            match json {
+                true => print_json(&tags),
+                false => output(&format!("{}\n", tags.join(", "))),
+            }
+        }
+        Command::Export { dir } => {
+            let notes = storage.get_all_notes();
+            let count = markdown_folder::export_folder(&notes, &dir, &storage.blob_store()?)?;
+            report_count(json, "exported", count)
+        }
+        Command::Import { dir } => {
+            let import = markdown_folder::import_folder(&dir, &storage.blob_store()?)?;
+            for (path, reason) in &import.skipped {
+                eprintln!("Skipped {}: {}", path.display(), reason);
+            }
+            let count = storage.import_notes(import.notes)?;
+            report_count(json, "imported", count)
+        }
+        Command::Passwd => {
+            let password = new_password(passwords, "New password: ")?;
+            storage.change_password(&password)?;
+            // Keep the agent usable with the new key, for the time it had left
+            if let Ok(agent) = Agent::new() {
+                if let (Ok(status), Some((key, salt))) = (agent.status(), storage.unlocked_key()) {
+                    if status.unlocked {
+                        agent.unlock(&key, &salt, Some(status.expires_in.unwrap_or(Duration::ZERO)))?;
+                    }
+                }
+            }
+            match json {
+                true => Ok(()),
+                false => output("Password changed\n"),
+            }
+        }
+        Command::Agent { .. } => unreachable!("agent commands do not open the vault"),
+    }
+}
+
+fn control_agent(command: AgentCommand, json: bool, passwords: &mut Passwords) -> Result<(), String> {
+    let agent = Agent::new()?;
+    let status = match command {
+        AgentCommand::Start { timeout, foreground } => {
+            if foreground {
+                return agent::serve(agent.path(), timeout);
+            }
+            start_agent(&agent, timeout)?
+        }
+        AgentCommand::Unlock { timeout } => {
+            let storage = unlock_with_password(passwords, false)?;
+            let (key, salt) = storage.unlocked_key().ok_or("Storage is locked")?;
+            if !agent.is_running() {
+                start_agent(&agent, agent::DEFAULT_TIMEOUT)?;
+            }
+            agent.unlock(&key, &salt, timeout)?
+        }
+        AgentCommand::Lock => {
+            agent.lock()?;
+            agent.status()?
+        }
+        AgentCommand::Timeout { duration } => agent.set_timeout(duration)?,
+        AgentCommand::Status => match agent.status() {
+            Ok(status) => status,
+            Err(_) if json => return print_json(&serde_json::json!({ "running": false })),
+            Err(_) => return output("No agent is running\n"),
+        },
+        AgentCommand::Stop => {
+            agent.stop()?;
+            return match json {
+                true => Ok(()),
+                false => output("Agent stopped\n"),
+            };
+        }
+    };
+    print_agent_status(&status, json)
+}
+
+/// Runs `agent start --foreground` detached from this terminal and waits
+/// until it answers
+fn start_agent(agent: &Agent, timeout: Duration) -> Result<AgentStatus, String> {
+    if agent.is_running() {
+        return Err(format!("An agent is already running at {}", agent.path().display()));
+    }
+    let program = std::env::current_exe().map_err(|e| format!("Failed to find this program: {}", e))?;
+    let mut command = std::process::Command::new(program);
+    command
+        .args(["agent", "start", "--foreground", "--timeout"])
+        .arg(timeout.as_secs().to_string())
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null());
+    #[cfg(unix)]
+    {
+        // Its own process group, so Ctrl+C in this terminal leaves it running
+        use std::os::unix::process::CommandExt;
+        command.process_group(0);
+    }
+    let mut child = command.spawn().map_err(|e| format!("Failed to start the agent: {}", e))?;
+    let started = Instant::now();
+    while started.elapsed() < Duration::from_secs(5) {
+        if let Ok(status) = agent.status() {
+            return Ok(status);
+        }
+        if let Ok(Some(exit)) = child.try_wait() {
+            return Err(format!("The agent exited with {}", exit));
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    Err("The agent did not start in time".to_string())
+}
+
+fn print_agent_status(status: &AgentStatus, json: bool) -> Result<(), String> {
+    if json {
+        return print_json(&serde_json::json!({
+            "running": true,
+            "unlocked": status.unlocked,
+            "expires_in_secs": status.expires_in.map(|expires_in| expires_in.as_secs()),
+        }));
+    }
+    match (status.unlocked, status.expires_in) {
+        (false, _) => output("Agent is locked\n"),
+        (true, None) => output("Agent holds the key until locked\n"),
+        (true, Some(expires_in)) => output(&format!(
+            "Agent holds the key for {}\n",
+            agent::format_duration(expires_in)
+        )),
+    }
+}
+
+fn report_count(json: bool, action: &str, count: usize) -> Result<(), String> {
+    match json {
+        true => print_json(&serde_json::json!({ action: count })),
+        false => output(&format!("{} {} notes\n", capitalize(action), count)),
+    }
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    chars
+        .next()
+        .map(|first| first.to_uppercase().chain(chars).collect())
+        .unwrap_or_default()
+}
+
+/// Opens the text in the user's editor through a private temporary file,
+/// which is removed again afterwards
+fn edit_in_editor(text: &str) -> Result<String, String> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    let path = std::env::temp_dir().join(format!("secure-notes-{}.md", uuid::Uuid::new_v4()));
+    storage::write_private(&path, text)?;
+
+    // The editor may be given with arguments, e.g. `code --wait`
+    let mut parts = editor.split_whitespace();
+    let program = parts.next().ok_or("$EDITOR is empty")?;
+    let status = std::process::Command::new(program)
+        .args(parts)
+        .arg(&path)
+        .status();
+    let edited = fs::read_to_string(&path);
+    fs::remove_file(&path).ok();
+
+    let status = status.map_err(|e| format!("Failed to start {}: {}", editor, e))?;
+    if !status.success() {
+        return Err(format!("{} exited with {}", editor, status));
+    }
+    edited.map_err(|e| format!("Failed to read the edited note: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notes_resolve_by_id_prefix_or_title_and_passwords_by_line() {
+        let mut first = Note::new("Groceries".to_string(), String::new());
+        first.id = "abc123".to_string();
+        let mut second = Note::new("groceries".to_string(), String::new());
+        second.id = "abd456".to_string();
+        let notes = vec![&first, &second];
+
+        assert_eq!(find_note(&notes, "abc123").unwrap().id, "abc123");
+        assert_eq!(find_note(&notes, "abd").unwrap().id, "abd456");
+        assert!(find_note(&notes, "ab").unwrap_err().contains("2 notes match"));
+        assert!(find_note(&notes, "GROCERIES").is_err());
+        assert!(find_note(&notes, "nothing").is_err());
+
+        let cli = Cli::try_parse_from(["secure-notes", "--json", "tag", "abc", "home", "--remove"]).unwrap();
+        assert!(cli.json);
+        assert!(matches!(cli.command, Some(Command::Tag { remove: true, .. })));
+
+        let mut input: &[u8] = b"old password\nnew password\r\n";
+        assert_eq!(read_password_line(&mut input).unwrap(), "old password");
+        assert_eq!(read_password_line(&mut input).unwrap(), "new password");
+        assert!(read_password_line(&mut input).is_err());
+    }
+}
@@ -0,0 +1,203 @@
+use crate::attachments::Attachment;
+use crate::crypto;
+use automerge::transaction::{CommitOptions, Transactable};
+use automerge::{ActorId, AutoCommit, ObjType, ReadDoc, ROOT};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sha2::{Digest, Sha256};
+use std::fmt;
+
+const CONTENT_KEY: &str = "content";
+
+/// Note content as an Automerge text document, so edits made on two
+/// devices merge without conflicts. Kept in the note as the compacted
+/// document; the vault and sync logs encrypt it along with the note.
+///
+/// Blob ids differ between vaults, so attachment links are stored under a
+/// key derived from the attachment itself and resolved when read.
+#[derive(Clone)]
+pub struct CrdtText {
+    document: Vec<u8>,
+}
+
+/// Identifies an attachment the same way in every vault
+fn attachment_key(attachment: &Attachment) -> String {
+    let digest = Sha256::digest(format!(
+        "{}\n{}\n{}",
+        attachment.file_name,
+        attachment.size,
+        attachment.added_at.to_rfc3339()
+    ));
+    format!("~{}", &hex::encode(digest)[..16])
+}
+
+fn normalize(content: &str, attachments: &[Attachment]) -> String {
+    attachments.iter().fold(content.to_string(), |content, attachment| {
+        content.replace(
+            &format!("attachment:{}", attachment.blob_id),
+            &format!("attachment:{}", attachment_key(attachment)),
+        )
+    })
+}
+
+fn resolve(text: &str, attachments: &[Attachment]) -> String {
+    attachments.iter().fold(text.to_string(), |text, attachment| {
+        text.replace(
+            &format!("attachment:{}", attachment_key(attachment)),
+            &format!("attachment:{}", attachment.blob_id),
+        )
+    })
+}
+
+/// Actor for changes that every device must produce identically
+fn shared_actor(seed: &str) -> ActorId {
+    ActorId::from(&Sha256::digest(seed)[..16])
+}
+
+impl CrdtText {
+    /// Starts a document with `content`. The first change depends only on
+    /// the note, so devices that enable CRDT content for the same note on
+    /// their own still share its history.
+    pub fn new(note_id: &str, content: &str, attachments: &[Attachment]) -> Result<Self, String> {
+        let text = normalize(content, attachments);
+        let mut doc = AutoCommit::new().with_actor(shared_actor(&format!("{}\n{}", note_id, text)));
+        let content = doc
+            .put_object(ROOT, CONTENT_KEY, ObjType::Text)
+            .map_err(|e| format!("Failed to create CRDT content: {}", e))?;
+        doc.update_text(&content, &text)
+            .map_err(|e| format!("Failed to create CRDT content: {}", e))?;
+        doc.commit_with(CommitOptions::default().with_time(0));
+        Ok(Self { document: doc.save() })
+    }
+
+    fn load(&self) -> Result<(AutoCommit, automerge::ObjId), String> {
+        let doc = AutoCommit::load(&self.document).map_err(|e| format!("Failed to load CRDT content: {}", e))?;
+        let content = match doc.get(ROOT, CONTENT_KEY) {
+            Ok(Some((_, content))) => content,
+            _ => return Err("The CRDT document has no content".to_string()),
+        };
+        Ok((doc, content))
+    }
+
+    /// The note content, with attachment links pointing at `attachments`
+    pub fn content(&self, attachments: &[Attachment]) -> Result<String, String> {
+        let (doc, content) = self.load()?;
+        let text = doc
+            .text(&content)
+            .map_err(|e| format!("Failed to read CRDT content: {}", e))?;
+        Ok(resolve(&text, attachments))
+    }
+
+    /// Records the edit from the current text to `content` as character
+    /// insertions and deletions
+    pub fn update(&mut self, content: &str, attachments: &[Attachment]) -> Result<(), String> {
+        self.record(content, attachments, ActorId::random(), None)
+    }
+
+    /// Like `update`, but devices passing the same `seed` produce the same
+    /// change, so an edit made by sync on both sides is only applied once
+    pub fn update_as(&mut self, content: &str, attachments: &[Attachment], seed: &str) -> Result<(), String> {
+        self.record(content, attachments, shared_actor(seed), Some(0))
+    }
+
+    fn record(
+        &mut self,
+        content: &str,
+        attachments: &[Attachment],
+        actor: ActorId,
+        time: Option<i64>,
+    ) -> Result<(), String> {
+        let (mut doc, object) = self.load()?;
+        doc.set_actor(actor);
+        doc.update_text(&object, normalize(content, attachments))
+            .map_err(|e| format!("Failed to update CRDT content: {}", e))?;
+        let mut options = CommitOptions::default();
+        if let Some(time) = time {
+            options = options.with_time(time);
+        }
+        doc.commit_with(options);
+        self.document = doc.save();
+        Ok(())
+    }
+
+    /// Identifies the set of edits in the document. Documents that saw the
+    /// same edits have the same version even if their bytes differ.
+    pub fn version(&self) -> String {
+        let Ok((mut doc, _)) = self.load() else {
+            return String::new();
+        };
+        let mut heads: Vec<String> = doc.get_heads().iter().map(|head| head.to_string()).collect();
+        heads.sort();
+        heads.join(",")
+    }
+
+    /// Combines the edits of both documents; the result does not depend on
+    /// which side merges into which
+    pub fn merge(&self, other: &CrdtText) -> Result<CrdtText, String> {
+        let (mut doc, _) = self.load()?;
+        let (mut other, _) = other.load()?;
+        doc.merge(&mut other)
+            .map_err(|e| format!("Failed to merge CRDT content: {}", e))?;
+        Ok(Self { document: doc.save() })
+    }
+}
+
+impl fmt::Debug for CrdtText {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CrdtText({} bytes)", self.document.len())
+    }
+}
+
+impl Serialize for CrdtText {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&crypto::encode_base64(&self.document))
+    }
+}
+
+impl<'de> Deserialize<'de> for CrdtText {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        let document = crypto::decode_base64(&encoded).map_err(serde::de::Error::custom)?;
+        Ok(Self { document })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn concurrent_edits_merge_the_same_way_on_both_sides() {
+        // Two devices enable CRDT content for the same note on their own
+        let mut laptop = CrdtText::new("note-1", "shopping: milk", &[]).unwrap();
+        let mut desktop = CrdtText::new("note-1", "shopping: milk", &[]).unwrap();
+        assert_eq!(laptop.version(), desktop.version());
+
+        laptop.update("shopping: oat milk", &[]).unwrap();
+        desktop.update("shopping: milk, eggs", &[]).unwrap();
+
+        let merged = laptop.merge(&desktop).unwrap();
+        assert_eq!(merged.content(&[]).unwrap(), "shopping: oat milk, eggs");
+        assert_eq!(merged.version(), desktop.merge(&laptop).unwrap().version());
+        assert_ne!(merged.version(), laptop.version());
+
+        let json = serde_json::to_string(&merged).unwrap();
+        let restored: CrdtText = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.content(&[]).unwrap(), "shopping: oat milk, eggs");
+    }
+
+    #[test]
+    fn attachment_links_survive_new_blob_ids() {
+        let attachment = Attachment {
+            blob_id: "aaaa".to_string(),
+            file_name: "cat.png".to_string(),
+            size: 3,
+            added_at: chrono::Utc::now(),
+        };
+        let moved = Attachment {
+            blob_id: "bbbb".to_string(),
+            ..attachment.clone()
+        };
+        let crdt = CrdtText::new("note-1", "![cat](attachment:aaaa)", &[attachment]).unwrap();
+        assert_eq!(crdt.content(&[moved]).unwrap(), "![cat](attachment:bbbb)");
+    }
+}
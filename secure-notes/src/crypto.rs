@@ -1,14 +1,26 @@
 use chacha20poly1305::{
-    aead::{Aead, KeyInit, OsRng},
+    aead::{
+        stream::{DecryptorBE32, EncryptorBE32},
+        Aead, KeyInit, OsRng,
+    },
     ChaCha20Poly1305, Nonce,
 };
 use argon2::{Argon2, PasswordHasher};
 use argon2::password_hash::SaltString;
 use rand::RngCore;
 use base64::{Engine as _, engine::general_purpose};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::io::{Read, Write};
 
 const NONCE_SIZE: usize = 12;
 
+/// Plaintext bytes per chunk of a streamed ciphertext
+const STREAM_CHUNK_SIZE: usize = 64 * 1024;
+/// Nonce prefix for the STREAM construction (the last 5 bytes are counter and flag)
+const STREAM_NONCE_SIZE: usize = 7;
+const TAG_SIZE: usize = 16;
+
 /// Derives a 32-byte encryption key from a password using Argon2
 pub fn derive_key(password: &str, salt: &str) -> Result<[u8; 32], String> {
     let argon2 = Argon2::default();
@@ -66,6 +78,114 @@ pub fn decrypt(encrypted_data: &[u8], key: &[u8; 32]) -> Result<Vec<u8>, String>
         .map_err(|e| format!("Decryption failed: {}", e))
 }
 
+/// Generates a random 32-byte key
+pub fn generate_key() -> [u8; 32] {
+    let mut key = [0u8; 32];
+    OsRng.fill_bytes(&mut key);
+    key
+}
+
+/// Keyed SHA-256 digest of everything read from `reader`, hex encoded
+pub fn keyed_hash(reader: &mut impl Read, key: &[u8; 32]) -> Result<String, String> {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key).map_err(|e| e.to_string())?;
+    let mut buffer = vec![0u8; STREAM_CHUNK_SIZE];
+    loop {
+        let read = reader
+            .read(&mut buffer)
+            .map_err(|e| format!("Failed to read data: {}", e))?;
+        if read == 0 {
+            break;
+        }
+        mac.update(&buffer[..read]);
+    }
+    Ok(hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Fills `buffer` as far as possible, returning how many bytes were read
+fn read_full(reader: &mut impl Read, buffer: &mut [u8]) -> Result<usize, String> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(read) => filled += read,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(format!("Failed to read data: {}", e)),
+        }
+    }
+    Ok(filled)
+}
+
+/// Encrypts a stream in fixed-size chunks (STREAM construction), so large
+/// files never have to be held in memory
+pub fn encrypt_stream(reader: &mut impl Read, writer: &mut impl Write, key: &[u8; 32]) -> Result<(), String> {
+    let mut nonce = [0u8; STREAM_NONCE_SIZE];
+    OsRng.fill_bytes(&mut nonce);
+    writer
+        .write_all(&nonce)
+        .map_err(|e| format!("Failed to write data: {}", e))?;
+
+    let mut encryptor = EncryptorBE32::from_aead(ChaCha20Poly1305::new(key.into()), (&nonce).into());
+    let mut chunk = vec![0u8; STREAM_CHUNK_SIZE];
+    let mut next = vec![0u8; STREAM_CHUNK_SIZE];
+    let mut len = read_full(reader, &mut chunk)?;
+
+    loop {
+        // Read ahead to know whether this chunk is the last one
+        let next_len = if len == STREAM_CHUNK_SIZE { read_full(reader, &mut next)? } else { 0 };
+        if next_len == 0 {
+            let ciphertext = encryptor
+                .encrypt_last(&chunk[..len])
+                .map_err(|e| format!("Encryption failed: {}", e))?;
+            return writer
+                .write_all(&ciphertext)
+                .map_err(|e| format!("Failed to write data: {}", e));
+        }
+
+        let ciphertext = encryptor
+            .encrypt_next(&chunk[..len])
+            .map_err(|e| format!("Encryption failed: {}", e))?;
+        writer
+            .write_all(&ciphertext)
+            .map_err(|e| format!("Failed to write data: {}", e))?;
+        std::mem::swap(&mut chunk, &mut next);
+        len = next_len;
+    }
+}
+
+/// Decrypts a stream written by `encrypt_stream`, rejecting truncated input
+pub fn decrypt_stream(reader: &mut impl Read, writer: &mut impl Write, key: &[u8; 32]) -> Result<(), String> {
+    let mut nonce = [0u8; STREAM_NONCE_SIZE];
+    if read_full(reader, &mut nonce)? != STREAM_NONCE_SIZE {
+        return Err("Invalid encrypted data".to_string());
+    }
+
+    let mut decryptor = DecryptorBE32::from_aead(ChaCha20Poly1305::new(key.into()), (&nonce).into());
+    let mut chunk = vec![0u8; STREAM_CHUNK_SIZE + TAG_SIZE];
+    let mut next = vec![0u8; STREAM_CHUNK_SIZE + TAG_SIZE];
+    let mut len = read_full(reader, &mut chunk)?;
+
+    loop {
+        let next_len = if len == chunk.len() { read_full(reader, &mut next)? } else { 0 };
+        if next_len == 0 {
+            let plaintext = decryptor
+                .decrypt_last(&chunk[..len])
+                .map_err(|e| format!("Decryption failed: {}", e))?;
+            return writer
+                .write_all(&plaintext)
+                .map_err(|e| format!("Failed to write data: {}", e));
+        }
+
+        let plaintext = decryptor
+            .decrypt_next(&chunk[..len])
+            .map_err(|e| format!("Decryption failed: {}", e))?;
+        writer
+            .write_all(&plaintext)
+            .map_err(|e| format!("Failed to write data: {}", e))?;
+        std::mem::swap(&mut chunk, &mut next);
+        len = next_len;
+    }
+}
+
 /// Encodes binary data to base64 string
 pub fn encode_base64(data: &[u8]) -> String {
     general_purpose::STANDARD.encode(data)
@@ -77,3 +197,35 @@ pub fn decode_base64(data: &str) -> Result<Vec<u8>, String> {
         .decode(data)
         .map_err(|e| e.to_string())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stream_round_trips_across_chunk_boundaries() {
+        let key = generate_key();
+        for size in [0, 10, STREAM_CHUNK_SIZE, STREAM_CHUNK_SIZE * 2 + 7] {
+            let data: Vec<u8> = (0..size).map(|i| (i % 251) as u8).collect();
+            let mut encrypted = Vec::new();
+            encrypt_stream(&mut data.as_slice(), &mut encrypted, &key).unwrap();
+
+            let mut decrypted = Vec::new();
+            decrypt_stream(&mut encrypted.as_slice(), &mut decrypted, &key).unwrap();
+            assert_eq!(decrypted, data, "size {}", size);
+
+            if size > STREAM_CHUNK_SIZE {
+                let truncated = &encrypted[..STREAM_NONCE_SIZE + STREAM_CHUNK_SIZE + TAG_SIZE];
+                assert!(decrypt_stream(&mut &truncated[..], &mut Vec::new(), &key).is_err());
+            }
+        }
+    }
+
+    #[test]
+    fn keyed_hash_depends_on_key() {
+        let data = b"same content";
+        let first = keyed_hash(&mut &data[..], &[1; 32]).unwrap();
+        assert_eq!(first, keyed_hash(&mut &data[..], &[1; 32]).unwrap());
+        assert_ne!(first, keyed_hash(&mut &data[..], &[2; 32]).unwrap());
+    }
+}
@@ -0,0 +1,175 @@
+use crate::highlight;
+use crate::images::ImageCache;
+use crate::markdown::{self, RenderOutput};
+use crate::tasks;
+use eframe::egui;
+use egui::epaint::text::cursor::PCursor;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EditorLayout {
+    #[default]
+    Editor,
+    Preview,
+    Split,
+}
+
+impl EditorLayout {
+    pub const ALL: [EditorLayout; 3] = [EditorLayout::Editor, EditorLayout::Preview, EditorLayout::Split];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            EditorLayout::Editor => "✏️ Editor",
+            EditorLayout::Preview => "👁 Preview",
+            EditorLayout::Split => "◫ Split",
+        }
+    }
+}
+
+pub fn editor_id() -> egui::Id {
+    egui::Id::new("note_editor")
+}
+
+/// Inserts text at the editor's cursor, or appends it when the editor has no cursor
+pub fn insert_at_cursor(ctx: &egui::Context, content: &mut String, text: &str) {
+    let cursor = egui::TextEdit::load_state(ctx, editor_id())
+        .and_then(|state| state.cursor.char_range())
+        .map(|range| range.primary.index);
+    let index = cursor
+        .and_then(|chars| content.char_indices().nth(chars).map(|(index, _)| index))
+        .unwrap_or(content.len());
+    content.insert_str(index, text);
+}
+
+/// Renders the raw Markdown editor with syntax highlighting
+pub fn show_editor(ui: &mut egui::Ui, content: &mut String) -> egui::text_edit::TextEditOutput {
+    let mut layouter = highlight::markdown_layouter;
+    egui::TextEdit::multiline(content)
+        .id(editor_id())
+        .desired_width(f32::INFINITY)
+        .desired_rows(20)
+        .font(egui::TextStyle::Monospace)
+        .layouter(&mut layouter)
+        .show(ui)
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+enum Pane {
+    #[default]
+    Editor,
+    Preview,
+}
+
+/// Editor and live preview side by side, scroll-synchronised by source line
+#[derive(Default)]
+pub struct SplitView {
+    leader: Pane,
+    editor_offset: f32,
+    preview_offset: f32,
+    editor_target: Option<f32>,
+    preview_target: Option<f32>,
+}
+
+impl SplitView {
+    pub fn show(&mut self, ui: &mut egui::Ui, content: &mut String, images: &mut ImageCache) {
+        ui.columns(2, |columns| {
+            let mut editor_area = egui::ScrollArea::vertical()
+                .id_source("split_editor")
+                .auto_shrink(false);
+            if let Some(offset) = self.editor_target.take() {
+                editor_area = editor_area.vertical_scroll_offset(offset);
+            }
+            let editor = editor_area.show(&mut columns[0], |ui| show_editor(ui, content));
+
+            let mut preview_area = egui::ScrollArea::vertical()
+                .id_source("split_preview")
+                .auto_shrink(false);
+            if let Some(offset) = self.preview_target.take() {
+                preview_area = preview_area.vertical_scroll_offset(offset);
+            }
+            let preview = preview_area.show(&mut columns[1], |ui| markdown::show(ui, content, images));
+            if let Some(line) = preview.inner.toggled_task {
+                if let Some(updated) = tasks::toggle_task(content, line) {
+                    *content = updated;
+                }
+            }
+
+            let ctx = columns[0].ctx().clone();
+            if let Some(pointer) = ctx.pointer_hover_pos() {
+                if editor.inner_rect.contains(pointer) {
+                    self.leader = Pane::Editor;
+                } else if preview.inner_rect.contains(pointer) {
+                    self.leader = Pane::Preview;
+                }
+            }
+            let typing = ctx.input(|i| {
+                i.events
+                    .iter()
+                    .any(|event| matches!(event, egui::Event::Key { .. } | egui::Event::Text(_)))
+            });
+            if editor.inner.response.has_focus() && typing {
+                self.leader = Pane::Editor;
+            }
+
+            // Where the galley starts inside the editor's scroll content
+            let galley_top = editor.inner.galley_pos.y - editor.inner_rect.top() + editor.state.offset.y;
+            let galley = &editor.inner.galley;
+            let editor_offset = editor.state.offset.y;
+            let preview_offset = preview.state.offset.y;
+
+            match self.leader {
+                Pane::Editor if (editor_offset - self.editor_offset).abs() > 0.5 => {
+                    let line = line_at(galley, editor_offset - galley_top);
+                    self.preview_target = preview.inner.positions.offset_for_line(line);
+                }
+                Pane::Preview if (preview_offset - self.preview_offset).abs() > 0.5 => {
+                    if let Some(line) = preview.inner.positions.line_for_offset(preview_offset) {
+                        self.editor_target = Some(galley_top + offset_of_line(galley, line));
+                    }
+                }
+                _ => {}
+            }
+
+            self.editor_offset = editor_offset;
+            self.preview_offset = preview_offset;
+            if self.editor_target.is_some() || self.preview_target.is_some() {
+                ctx.request_repaint();
+            }
+        });
+    }
+}
+
+/// Renders the preview on its own
+pub fn show_preview(ui: &mut egui::Ui, content: &str, images: &mut ImageCache) -> RenderOutput {
+    egui::ScrollArea::vertical()
+        .show(ui, |ui| markdown::show(ui, content, images))
+        .inner
+}
+
+fn paragraph_top(galley: &egui::Galley, paragraph: usize) -> f32 {
+    if paragraph > galley.end().pcursor.paragraph {
+        return galley.rect.bottom();
+    }
+    galley
+        .pos_from_pcursor(PCursor {
+            paragraph,
+            offset: 0,
+            prefer_next_row: true,
+        })
+        .top()
+}
+
+/// Fractional source line shown at a vertical offset within the galley
+fn line_at(galley: &egui::Galley, y: f32) -> f32 {
+    let line = galley.cursor_from_pos(egui::vec2(0.0, y.max(0.0))).pcursor.paragraph;
+    let top = paragraph_top(galley, line);
+    let bottom = paragraph_top(galley, line + 1);
+    line as f32 + ((y - top) / (bottom - top).max(1.0)).clamp(0.0, 1.0)
+}
+
+fn offset_of_line(galley: &egui::Galley, line: f32) -> f32 {
+    let whole = line.floor() as usize;
+    let top = paragraph_top(galley, whole);
+    let bottom = paragraph_top(galley, whole + 1);
+    top + (bottom - top) * line.fract()
+}
@@ -0,0 +1,316 @@
+use crate::crypto;
+use crate::note::Note;
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+/// Folder in the vault directory with one encrypted file per note
+pub const NOTES_DIR: &str = "notes";
+const NOTE_EXTENSION: &str = "enc";
+/// Says nothing about what changed, since commit messages are not encrypted
+const COMMIT_MESSAGE: &str = "Update vault";
+
+/// Notes stored as separate encrypted files. A file is only rewritten when
+/// its note changed, so history shows which notes each save touched.
+pub struct NoteFiles {
+    dir: PathBuf,
+    /// Digest of the plaintext last written for each note
+    written: HashMap<String, [u8; 32]>,
+}
+
+impl NoteFiles {
+    pub fn new(vault_dir: &Path) -> Self {
+        Self {
+            dir: vault_dir.join(NOTES_DIR),
+            written: HashMap::new(),
+        }
+    }
+
+    fn path(&self, note_id: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", file_stem(note_id), NOTE_EXTENSION))
+    }
+
+    pub fn load(&mut self, key: &[u8; 32]) -> Result<Vec<Note>, String> {
+        self.written.clear();
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return Ok(Vec::new());
+        };
+        let mut notes = Vec::new();
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some(NOTE_EXTENSION) {
+                continue;
+            }
+            let data = fs::read(&path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+            let json = crypto::decrypt(&data, key)?;
+            let note = decode_note(&json)?;
+            self.written.insert(note.id.clone(), Sha256::digest(&json).into());
+            notes.push(note);
+        }
+        Ok(notes)
+    }
+
+    /// Writes notes that changed since the last write and deletes the files
+    /// of notes that no longer exist
+    pub fn write<'a>(&mut self, notes: impl Iterator<Item = &'a Note>, key: &[u8; 32]) -> Result<(), String> {
+        fs::create_dir_all(&self.dir).map_err(|e| format!("Failed to create {}: {}", self.dir.display(), e))?;
+        let mut written = HashMap::new();
+        for note in notes {
+            let json = serde_json::to_vec(note).map_err(|e| format!("Failed to serialize note: {}", e))?;
+            let digest: [u8; 32] = Sha256::digest(&json).into();
+            let path = self.path(&note.id);
+            if self.written.get(&note.id) != Some(&digest) || !path.exists() {
+                fs::write(&path, crypto::encrypt(&json, key)?)
+                    .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+            }
+            written.insert(note.id.clone(), digest);
+        }
+        for note_id in self.written.keys() {
+            if !written.contains_key(note_id) {
+                fs::remove_file(self.path(note_id)).ok();
+            }
+        }
+        self.written = written;
+        Ok(())
+    }
+
+    /// Removes the folder after switching back to a single vault file
+    pub fn remove(&mut self) -> Result<(), String> {
+        self.written.clear();
+        if self.dir.exists() {
+            fs::remove_dir_all(&self.dir).map_err(|e| format!("Failed to remove {}: {}", self.dir.display(), e))?;
+        }
+        Ok(())
+    }
+}
+
+/// Note ids arrive from bundles, shares and sync, so anything but
+/// `[A-Za-z0-9-]` is hex-encoded instead of being used as a path
+fn file_stem(note_id: &str) -> String {
+    if !note_id.is_empty() && note_id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        return note_id.to_string();
+    }
+    format!("_{}", hex::encode(note_id))
+}
+
+fn note_id(file_stem: &str) -> Option<String> {
+    match file_stem.strip_prefix('_') {
+        Some(encoded) => String::from_utf8(hex::decode(encoded).ok()?).ok(),
+        None => Some(file_stem.to_string()),
+    }
+}
+
+pub fn decode_note(json: &[u8]) -> Result<Note, String> {
+    serde_json::from_slice(json).map_err(|e| format!("Failed to parse note: {}", e))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoteChange {
+    Added,
+    Modified,
+    Deleted,
+}
+
+/// One commit that touched notes. Only file names are readable without the
+/// vault key, so this is all a remote can learn about the history.
+#[derive(Debug, Clone)]
+pub struct HistoryEntry {
+    pub commit: String,
+    pub at: DateTime<Utc>,
+    pub notes: Vec<(String, NoteChange)>,
+}
+
+/// A git repository around the vault directory. Only the encrypted vault
+/// file, note files and attachments are tracked; `.gitignore` excludes the
+/// rest so plaintext can never be committed by accident.
+pub struct GitHistory {
+    dir: PathBuf,
+}
+
+impl GitHistory {
+    /// Creates the repository if needed. Safe to call on an existing one.
+    pub fn init(dir: &Path, vault_file: &str) -> Result<Self, String> {
+        let history = Self { dir: dir.to_path_buf() };
+        if !dir.join(".git").exists() {
+            history.git(&["init", "--quiet"])?;
+        }
+        let gitignore = format!(
+            "# Only encrypted vault data is committed\n/*\n!/.gitignore\n!/{}\n!/{}/\n!/attachments/\n",
+            vault_file, NOTES_DIR
+        );
+        fs::write(dir.join(".gitignore"), gitignore).map_err(|e| format!("Failed to write .gitignore: {}", e))?;
+        Ok(history)
+    }
+
+    fn run(&self, args: &[&str]) -> Result<Output, String> {
+        Command::new("git")
+            .arg("-C")
+            .arg(&self.dir)
+            .args(args)
+            // Fail instead of waiting for a password nobody can type
+            .env("GIT_TERMINAL_PROMPT", "0")
+            .output()
+            .map_err(|e| format!("Failed to run git: {}", e))
+    }
+
+    fn git(&self, args: &[&str]) -> Result<String, String> {
+        let output = self.run(args)?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(format!("git {} failed: {}", args[0], stderr.trim()));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    /// Commits everything the vault wrote; `false` if nothing changed
+    pub fn commit(&self) -> Result<bool, String> {
+        self.git(&["add", "--all"])?;
+        if self.run(&["diff", "--cached", "--quiet"])?.status.success() {
+            return Ok(false);
+        }
+        self.git(&[
+            "-c",
+            "user.name=Secure Notes",
+            "-c",
+            "user.email=secure-notes@localhost",
+            "commit",
+            "--quiet",
+            "--message",
+            COMMIT_MESSAGE,
+        ])?;
+        Ok(true)
+    }
+
+    /// Recent commits that added, changed or deleted notes, newest first
+    pub fn log(&self, limit: usize) -> Result<Vec<HistoryEntry>, String> {
+        if self.run(&["rev-parse", "--verify", "--quiet", "HEAD"])?.stdout.is_empty() {
+            return Ok(Vec::new());
+        }
+        let limit = format!("--max-count={}", limit);
+        let output = self.git(&["log", &limit, "--format=%x1e%H%x1f%aI", "--name-status", "--", NOTES_DIR])?;
+        let mut entries = Vec::new();
+        for record in output.split('\x1e').filter(|record| !record.trim().is_empty()) {
+            let mut lines = record.lines();
+            let Some((commit, at)) = lines.next().and_then(|header| header.split_once('\x1f')) else {
+                continue;
+            };
+            let notes = lines
+                .filter_map(|line| {
+                    let (status, path) = line.split_once('\t')?;
+                    let change = match status {
+                        "A" => NoteChange::Added,
+                        "D" => NoteChange::Deleted,
+                        _ => NoteChange::Modified,
+                    };
+                    let note_id = note_id(&Path::new(path).file_stem()?.to_string_lossy())?;
+                    Some((note_id, change))
+                })
+                .collect();
+            entries.push(HistoryEntry {
+                commit: commit.to_string(),
+                at: DateTime::parse_from_rfc3339(at)
+                    .map_err(|e| format!("Invalid commit date: {}", e))?
+                    .with_timezone(&Utc),
+                notes,
+            });
+        }
+        Ok(entries)
+    }
+
+    /// The encrypted note file as of `commit`
+    pub fn note_file_at(&self, commit: &str, note_id: &str) -> Result<Vec<u8>, String> {
+        let spec = format!("{}:{}/{}.{}", commit, NOTES_DIR, file_stem(note_id), NOTE_EXTENSION);
+        let output = self.run(&["show", &spec])?;
+        if !output.status.success() {
+            return Err(format!("The note is not part of commit {}", &commit[..commit.len().min(8)]));
+        }
+        Ok(output.stdout)
+    }
+
+    pub fn remote_url(&self) -> Option<String> {
+        self.git(&["remote", "get-url", "origin"])
+            .ok()
+            .map(|url| url.trim().to_string())
+    }
+
+    pub fn set_remote_url(&self, url: &str) -> Result<(), String> {
+        match self.remote_url() {
+            Some(_) => self.git(&["remote", "set-url", "origin", url]),
+            None => self.git(&["remote", "add", "origin", url]),
+        }
+        .map(|_| ())
+    }
+
+    fn branch(&self) -> Result<String, String> {
+        Ok(self.git(&["rev-parse", "--abbrev-ref", "HEAD"])?.trim().to_string())
+    }
+
+    pub fn push(&self) -> Result<(), String> {
+        self.git(&["push", "--quiet", "--set-upstream", "origin", &self.branch()?])
+            .map(|_| ())
+    }
+
+    /// Fast-forwards to the remote. Both sides rewrite the vault file on
+    /// every save, so diverged histories cannot be merged automatically.
+    pub fn pull(&self) -> Result<(), String> {
+        self.git(&["pull", "--quiet", "--ff-only", "origin", &self.branch()?])
+            .map(|_| ())
+            .map_err(|e| match e.contains("fast-forward") {
+                true => "This vault and the remote both have new saves; use folder or WebDAV sync to combine devices"
+                    .to_string(),
+                false => e,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn history_tracks_changed_notes_without_plaintext() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = crypto::generate_key();
+        let history = GitHistory::init(dir.path(), "notes.enc").unwrap();
+        fs::write(dir.path().join("notes.enc"), b"encrypted vault").unwrap();
+        fs::write(dir.path().join("settings.json"), b"plaintext").unwrap();
+
+        let mut files = NoteFiles::new(dir.path());
+        let mut first = Note::new("Diary".to_string(), "secret words".to_string());
+        let mut second = Note::new("Shopping".to_string(), "eggs".to_string());
+        // An id from an untrusted import must not escape the notes folder
+        second.id = "../../escape".to_string();
+        files.write([&first, &second].into_iter(), &key).unwrap();
+        assert!(history.commit().unwrap());
+        assert!(!history.commit().unwrap());
+
+        // Only the edited note is rewritten
+        first.update_content("more secret words".to_string()).unwrap();
+        files.write([&first, &second].into_iter(), &key).unwrap();
+        history.commit().unwrap();
+        files.write([&first].into_iter(), &key).unwrap();
+        history.commit().unwrap();
+
+        let log = history.log(10).unwrap();
+        assert_eq!(log.len(), 3);
+        assert_eq!(log[0].notes, vec![(second.id.clone(), NoteChange::Deleted)]);
+        assert_eq!(log[1].notes, vec![(first.id.clone(), NoteChange::Modified)]);
+        assert_eq!(log[2].notes.len(), 2);
+
+        let old = crypto::decrypt(&history.note_file_at(&log[2].commit, &first.id).unwrap(), &key).unwrap();
+        assert_eq!(decode_note(&old).unwrap().content, "secret words");
+
+        let tracked = history.git(&["ls-files"]).unwrap();
+        assert!(!dir.path().parent().unwrap().join("escape.enc").exists());
+        assert!(!tracked.contains("escape"));
+        assert!(!tracked.contains("settings.json"));
+        let everything = history.git(&["log", "--all", "--patch", "--text"]).unwrap();
+        assert!(!everything.contains("secret words"));
+
+        let mut reloaded = NoteFiles::new(dir.path());
+        assert_eq!(reloaded.load(&key).unwrap().len(), 1);
+    }
+}
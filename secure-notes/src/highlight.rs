@@ -0,0 +1,298 @@
+use eframe::egui;
+use egui::text::{LayoutJob, TextFormat};
+use egui::util::cache::{ComputerMut, FrameCache};
+use egui_extras::syntax_highlighting::{self, CodeTheme};
+use std::ops::Range;
+use std::sync::Arc;
+
+/// Renders a fenced code block with syntax highlighting, horizontal scroll and a copy button
+pub fn code_block(ui: &mut egui::Ui, language: Option<&str>, code: &str) {
+    let code = code.trim_end_matches('\n');
+    let theme = CodeTheme::from_style(ui.style());
+
+    egui::Frame::none()
+        .fill(ui.visuals().code_bg_color)
+        .rounding(4.0)
+        .inner_margin(8.0)
+        .show(ui, |ui| {
+            ui.set_width(ui.available_width());
+
+            ui.horizontal(|ui| {
+                if let Some(language) = language {
+                    ui.label(egui::RichText::new(language).small().weak());
+                }
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    if ui.small_button("📋 Copy").clicked() {
+                        ui.output_mut(|o| o.copied_text = code.to_string());
+                    }
+                });
+            });
+
+            egui::ScrollArea::horizontal()
+                .id_source(ui.id().with(code))
+                .show(ui, |ui| {
+                    let job = match language.map(syntax_name) {
+                        Some(syntax) => syntax_highlighting::highlight(ui.ctx(), &theme, code, syntax),
+                        None => LayoutJob::simple(
+                            code.to_string(),
+                            egui::TextStyle::Monospace.resolve(ui.style()),
+                            ui.visuals().text_color(),
+                            f32::INFINITY,
+                        ),
+                    };
+                    ui.add(egui::Label::new(job).extend().selectable(true));
+                });
+        });
+}
+
+/// Maps common fence info strings onto names syntect knows about
+fn syntax_name(language: &str) -> &str {
+    match language {
+        "shell" | "console" | "zsh" | "bash" => "sh",
+        "python" => "py",
+        "rust" => "rs",
+        "javascript" => "js",
+        "typescript" => "ts",
+        "yml" => "yaml",
+        "markdown" => "md",
+        other => other,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MarkdownToken {
+    Plain,
+    Heading,
+    Quote,
+    ListMarker,
+    Fence,
+    Code,
+    Strong,
+    Emphasis,
+    Strikethrough,
+    Link,
+}
+
+/// Splits Markdown source into contiguous, lightly classified ranges for the editor
+pub fn markdown_tokens(text: &str) -> Vec<(Range<usize>, MarkdownToken)> {
+    let mut tokens = Vec::new();
+    let mut in_fence = false;
+    let mut offset = 0;
+
+    for line in text.split_inclusive('\n') {
+        let start = offset;
+        offset += line.len();
+
+        let trimmed = line.trim_start();
+        let indent = line.len() - trimmed.len();
+
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+            tokens.push((start..offset, MarkdownToken::Fence));
+        } else if in_fence {
+            tokens.push((start..offset, MarkdownToken::Code));
+        } else if is_heading(trimmed) {
+            tokens.push((start..offset, MarkdownToken::Heading));
+        } else if trimmed.starts_with('>') {
+            tokens.push((start..offset, MarkdownToken::Quote));
+        } else {
+            let mut body = start;
+            if let Some(marker) = list_marker_len(trimmed) {
+                body = start + indent + marker;
+                tokens.push((start..body, MarkdownToken::ListMarker));
+            }
+            inline_tokens(text, body..offset, &mut tokens);
+        }
+    }
+
+    tokens
+}
+
+fn is_heading(line: &str) -> bool {
+    let hashes = line.bytes().take_while(|&b| b == b'#').count();
+    (1..=6).contains(&hashes)
+        && line[hashes..].chars().next().is_none_or(char::is_whitespace)
+}
+
+/// Length of a list marker (including a task checkbox) at the start of a line
+fn list_marker_len(line: &str) -> Option<usize> {
+    let bytes = line.as_bytes();
+    let mut len = match bytes.first()? {
+        b'-' | b'*' | b'+' if bytes.get(1) == Some(&b' ') => 2,
+        b'0'..=b'9' => {
+            let digits = bytes.iter().take_while(|b| b.is_ascii_digit()).count();
+            match (bytes.get(digits), bytes.get(digits + 1)) {
+                (Some(b'.') | Some(b')'), Some(b' ')) => digits + 2,
+                _ => return None,
+            }
+        }
+        _ => return None,
+    };
+
+    let rest = &line[len..];
+    if rest.starts_with("[ ] ") || rest.starts_with("[x] ") || rest.starts_with("[X] ") {
+        len += 4;
+    }
+    Some(len)
+}
+
+fn inline_tokens(text: &str, range: Range<usize>, tokens: &mut Vec<(Range<usize>, MarkdownToken)>) {
+    let bytes = text.as_bytes();
+    let mut plain_start = range.start;
+    let mut i = range.start;
+
+    while i < range.end {
+        let rest = &text[i..range.end];
+        let span = match bytes[i] {
+            b'`' => closing(rest, "`", 1).map(|len| (len, MarkdownToken::Code)),
+            b'*' | b'_' if rest.starts_with("**") || rest.starts_with("__") => {
+                closing(rest, &rest[..2], 2).map(|len| (len, MarkdownToken::Strong))
+            }
+            b'*' | b'_' if i == 0 || !bytes[i - 1].is_ascii_alphanumeric() => {
+                closing(rest, &rest[..1], 1).map(|len| (len, MarkdownToken::Emphasis))
+            }
+            b'~' if rest.starts_with("~~") => {
+                closing(rest, "~~", 2).map(|len| (len, MarkdownToken::Strikethrough))
+            }
+            b'[' | b'!' => link_len(rest).map(|len| (len, MarkdownToken::Link)),
+            _ => None,
+        };
+
+        match span {
+            Some((len, token)) => {
+                if plain_start < i {
+                    tokens.push((plain_start..i, MarkdownToken::Plain));
+                }
+                tokens.push((i..i + len, token));
+                i += len;
+                plain_start = i;
+            }
+            None => i += rest.chars().next().map_or(1, char::len_utf8),
+        }
+    }
+
+    if plain_start < range.end {
+        tokens.push((plain_start..range.end, MarkdownToken::Plain));
+    }
+}
+
+/// Length of a delimited span starting at `text`, or `None` if it never closes on this line
+fn closing(text: &str, delimiter: &str, open_len: usize) -> Option<usize> {
+    let body = &text[open_len..];
+    if body.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let end = body.find(delimiter)?;
+    if end == 0 || body[..end].contains('\n') {
+        return None;
+    }
+    Some(open_len + end + delimiter.len())
+}
+
+fn link_len(text: &str) -> Option<usize> {
+    let open = if text.starts_with("![") { 2 } else if text.starts_with('[') { 1 } else { return None };
+    let label_end = open + text[open..].find(']')?;
+    if !text[label_end..].starts_with("](") {
+        return None;
+    }
+    let url_end = label_end + text[label_end..].find(')')?;
+    if text[..url_end].contains('\n') {
+        return None;
+    }
+    Some(url_end + 1)
+}
+
+#[derive(Default)]
+struct MarkdownTokenizer;
+
+impl ComputerMut<&str, Arc<Vec<(Range<usize>, MarkdownToken)>>> for MarkdownTokenizer {
+    fn compute(&mut self, text: &str) -> Arc<Vec<(Range<usize>, MarkdownToken)>> {
+        Arc::new(markdown_tokens(text))
+    }
+}
+
+type MarkdownTokenCache = FrameCache<Arc<Vec<(Range<usize>, MarkdownToken)>>, MarkdownTokenizer>;
+
+/// Layouter for `TextEdit` that applies lightweight Markdown highlighting
+pub fn markdown_layouter(ui: &egui::Ui, text: &str, wrap_width: f32) -> Arc<egui::Galley> {
+    let tokens = ui
+        .ctx()
+        .memory_mut(|mem| mem.caches.cache::<MarkdownTokenCache>().get(text));
+
+    let font_id = egui::TextStyle::Monospace.resolve(ui.style());
+    let visuals = ui.visuals();
+    let (accent, code) = if visuals.dark_mode {
+        (egui::Color32::from_rgb(110, 170, 255), egui::Color32::from_rgb(230, 160, 90))
+    } else {
+        (egui::Color32::from_rgb(20, 90, 190), egui::Color32::from_rgb(170, 80, 0))
+    };
+
+    let mut job = LayoutJob::default();
+    for (range, token) in tokens.iter() {
+        let mut format = TextFormat::simple(font_id.clone(), visuals.text_color());
+        match token {
+            MarkdownToken::Plain => {}
+            MarkdownToken::Heading | MarkdownToken::Strong => {
+                format.color = visuals.strong_text_color();
+            }
+            MarkdownToken::Quote => {
+                format.color = visuals.weak_text_color();
+                format.italics = true;
+            }
+            MarkdownToken::ListMarker => format.color = accent,
+            MarkdownToken::Fence => format.color = visuals.weak_text_color(),
+            MarkdownToken::Code => {
+                format.color = code;
+                format.background = visuals.code_bg_color;
+            }
+            MarkdownToken::Emphasis => format.italics = true,
+            MarkdownToken::Strikethrough => {
+                format.strikethrough = egui::Stroke::new(1.0, visuals.text_color());
+            }
+            MarkdownToken::Link => {
+                format.color = visuals.hyperlink_color;
+                format.underline = egui::Stroke::new(1.0, visuals.hyperlink_color);
+            }
+        }
+        job.append(&text[range.clone()], 0.0, format);
+    }
+    job.wrap.max_width = wrap_width;
+    ui.fonts(|fonts| fonts.layout_job(job))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn classify(text: &str) -> Vec<(&str, MarkdownToken)> {
+        markdown_tokens(text)
+            .into_iter()
+            .map(|(range, token)| (&text[range], token))
+            .collect()
+    }
+
+    #[test]
+    fn tokens_cover_the_whole_source() {
+        let text = "# Title\n- [ ] **do** it with `code` and [link](x)\n```sh\nls -la\n```\nsnake_case_name ~~gone~~\n";
+        let tokens = markdown_tokens(text);
+        let mut expected_start = 0;
+        for (range, _) in &tokens {
+            assert_eq!(range.start, expected_start);
+            expected_start = range.end;
+        }
+        assert_eq!(expected_start, text.len());
+    }
+
+    #[test]
+    fn classifies_block_and_inline_markup() {
+        let tokens = classify("# Title\n- [x] **do** `it` [a](b)\n```\nrm -rf\n```\nsnake_case *em* äö\n");
+        assert!(tokens.contains(&("# Title\n", MarkdownToken::Heading)));
+        assert!(tokens.contains(&("- [x] ", MarkdownToken::ListMarker)));
+        assert!(tokens.contains(&("**do**", MarkdownToken::Strong)));
+        assert!(tokens.contains(&("`it`", MarkdownToken::Code)));
+        assert!(tokens.contains(&("[a](b)", MarkdownToken::Link)));
+        assert!(tokens.contains(&("rm -rf\n", MarkdownToken::Code)));
+        assert!(tokens.contains(&("*em*", MarkdownToken::Emphasis)));
+        assert!(!tokens.iter().any(|(text, token)| text.contains("case") && *token != MarkdownToken::Plain));
+    }
+}
@@ -0,0 +1,230 @@
+use crate::attachments::BlobStore;
+use crate::images::{ImageSource, MAX_IMAGE_BYTES};
+use crate::markdown;
+use crate::note::Note;
+use crate::tile_loader::TileLoader;
+use pulldown_cmark::{html, CowStr, Event, Parser, Tag, TagEnd};
+use std::io::Cursor;
+
+/// Size of the map snapshot shown for notes with a location
+pub const MAP_SIZE: (u32, u32) = (640, 280);
+
+const STYLE: &str = r#"
+body { font-family: -apple-system, "Segoe UI", Ubuntu, sans-serif; line-height: 1.5; color: #222; max-width: 48em; margin: 2em auto; padding: 0 1em; }
+article + article { border-top: 1px solid #ccc; margin-top: 3em; padding-top: 1em; }
+.meta { color: #777; font-size: 0.85em; }
+.map { display: block; max-width: 100%; border-radius: 4px; margin: 1em 0; }
+img { max-width: 100%; }
+pre, code { font-family: "Hack", Menlo, Consolas, monospace; background: #f4f4f4; border-radius: 3px; }
+pre { padding: 0.75em; overflow-x: auto; }
+code { padding: 0.1em 0.3em; }
+pre code { padding: 0; }
+blockquote { border-left: 3px solid #ccc; margin-left: 0; padding-left: 1em; color: #555; }
+table { border-collapse: collapse; }
+th, td { border: 1px solid #ccc; padding: 0.3em 0.6em; }
+li input[type=checkbox] { margin-right: 0.4em; }
+@media print { article + article { page-break-before: always; border: none; } }
+"#;
+
+/// Local data an export may embed: attachments and cached map tiles.
+/// Nothing is ever fetched from the network.
+pub struct ExportAssets<'a> {
+    pub blob_store: &'a BlobStore,
+    pub tiles: Option<&'a TileLoader>,
+}
+
+impl ExportAssets<'_> {
+    /// Image bytes for an `attachment:` or local file URL
+    pub fn image(&self, url: &str) -> Option<Vec<u8>> {
+        match ImageSource::parse(url)? {
+            ImageSource::Attachment(blob_id) => self.blob_store.read(&blob_id).ok(),
+            ImageSource::File(path) => {
+                let size = std::fs::metadata(&path).ok()?.len();
+                (size <= MAX_IMAGE_BYTES).then(|| std::fs::read(&path).ok()).flatten()
+            }
+        }
+    }
+
+    /// Map around the note's location, built from cached tiles
+    pub fn map(&self, note: &Note) -> Option<image::RgbaImage> {
+        let location = note.location.as_ref()?;
+        self.tiles?
+            .snapshot(location.latitude, location.longitude, MAP_SIZE.0, MAP_SIZE.1)
+    }
+}
+
+/// One line with dates, notebook, tags and location
+pub fn meta_line(note: &Note) -> String {
+    let mut parts = vec![
+        format!("Created {}", note.created_at.format("%Y-%m-%d %H:%M")),
+        format!("modified {}", note.modified_at.format("%Y-%m-%d %H:%M")),
+    ];
+    if let Some(notebook) = &note.notebook {
+        parts.push(format!("notebook {}", notebook));
+    }
+    if !note.tags.is_empty() {
+        parts.push(note.tags.iter().map(|tag| format!("#{}", tag)).collect::<Vec<_>>().join(" "));
+    }
+    if let Some(location) = &note.location {
+        let coordinates = format!("{:.5}, {:.5}", location.latitude, location.longitude);
+        parts.push(if location.name.is_empty() {
+            coordinates
+        } else {
+            format!("{} ({})", location.name, coordinates)
+        });
+    }
+    parts.join(" · ")
+}
+
+/// Renders notes as one self-contained HTML page with inlined images
+pub fn render(notes: &[&Note], title: &str, assets: &ExportAssets) -> String {
+    let mut page = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>{}</style>\n</head>\n<body>\n",
+        escape_html(title),
+        STYLE
+    );
+    for note in notes {
+        page.push_str("<article>\n");
+        page.push_str(&format!("<h1>{}</h1>\n", escape_html(&note.title)));
+        page.push_str(&format!("<p class=\"meta\">{}</p>\n", escape_html(&meta_line(note))));
+        if let Some(map) = assets.map(note).and_then(|map| encode_png(&map)) {
+            page.push_str(&format!(
+                "<img class=\"map\" alt=\"Map\" src=\"data:image/png;base64,{}\">\n",
+                crate::crypto::encode_base64(&map)
+            ));
+        }
+        page.push_str(&body_html(&note.content, assets));
+        page.push_str("</article>\n");
+    }
+    page.push_str("</body>\n</html>\n");
+    page
+}
+
+/// Markdown to HTML with local images inlined as data URIs. Links to
+/// attachments are dropped since the page cannot carry the files, and raw
+/// HTML is escaped and URLs outside a few safe schemes are dropped so an
+/// exported page cannot run scripts. Dropped links and images keep their text.
+fn body_html(content: &str, assets: &ExportAssets) -> String {
+    let mut link_stack = Vec::new();
+    let mut image_stack = Vec::new();
+    let events = Parser::new_ext(content, markdown::parser_options()).filter_map(|event| match event {
+        Event::Start(Tag::Image {
+            link_type,
+            dest_url,
+            title,
+            id,
+        }) => {
+            let dest_url = match assets.image(&dest_url) {
+                Some(data) => CowStr::from(format!(
+                    "data:{};base64,{}",
+                    mime_type(&data),
+                    crate::crypto::encode_base64(&data)
+                )),
+                None => dest_url,
+            };
+            let safe = dest_url.starts_with("data:image/") || has_safe_scheme(&dest_url);
+            image_stack.push(safe);
+            safe.then_some(Event::Start(Tag::Image {
+                link_type,
+                dest_url,
+                title,
+                id,
+            }))
+        }
+        Event::End(TagEnd::Image) => image_stack.pop().unwrap_or(false).then_some(event),
+        Event::Start(Tag::Link { ref dest_url, .. }) => {
+            let safe = has_safe_scheme(dest_url);
+            link_stack.push(safe);
+            safe.then_some(event)
+        }
+        Event::End(TagEnd::Link) => link_stack.pop().unwrap_or(false).then_some(event),
+        Event::Html(raw) | Event::InlineHtml(raw) => Some(Event::Text(raw)),
+        event => Some(event),
+    });
+    let mut output = String::new();
+    html::push_html(&mut output, events);
+    output
+}
+
+/// Whether a link may stay in the page: web and mail links only, since
+/// schemes like `javascript:` run when clicked
+fn has_safe_scheme(url: &str) -> bool {
+    let scheme = url.split_once(':').map(|(scheme, _)| scheme.to_ascii_lowercase());
+    matches!(scheme.as_deref(), Some("http" | "https" | "mailto"))
+}
+
+pub fn encode_png(image: &image::RgbaImage) -> Option<Vec<u8>> {
+    let mut png = Vec::new();
+    image.write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png).ok()?;
+    Some(png)
+}
+
+fn mime_type(data: &[u8]) -> &'static str {
+    match image::guess_format(data) {
+        Ok(image::ImageFormat::Png) => "image/png",
+        Ok(image::ImageFormat::Jpeg) => "image/jpeg",
+        Ok(image::ImageFormat::Gif) => "image/gif",
+        Ok(image::ImageFormat::WebP) => "image/webp",
+        Ok(image::ImageFormat::Bmp) => "image/bmp",
+        _ => "application/octet-stream",
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attachments::BlobKeys;
+
+    #[test]
+    fn html_export_inlines_attachment_images() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = BlobStore::new(dir.path().to_path_buf(), &BlobKeys::generate()).unwrap();
+        let png = encode_png(&image::RgbaImage::new(2, 2)).unwrap();
+        let image = store.add_bytes("dot.png", &png).unwrap();
+        let file = store.add_bytes("report.pdf", b"%PDF").unwrap();
+
+        let content = format!(
+            "Look: ![dot](attachment:{}) and [the report](attachment:{}) <script>\n\n- [x] done",
+            image.blob_id, file.blob_id
+        );
+        let note = Note::new("A & B".to_string(), content);
+        let assets = ExportAssets {
+            blob_store: &store,
+            tiles: None,
+        };
+        let page = render(&[&note], "Export", &assets);
+
+        assert!(page.contains("<h1>A &amp; B</h1>"));
+        assert!(page.contains("src=\"data:image/png;base64,"));
+        assert!(page.contains("and the report"));
+        assert!(!page.contains("attachment:"));
+        assert!(page.contains("&lt;script&gt;"));
+        assert!(page.contains("<input disabled=\"\" type=\"checkbox\" checked=\"\"/>"));
+    }
+
+    #[test]
+    fn html_export_drops_script_urls() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = BlobStore::new(dir.path().to_path_buf(), &BlobKeys::generate()).unwrap();
+        let content = "[click](javascript:alert(1)) [mixed](JavaScript:alert(2)) \
+            ![pic](javascript:alert(3)) [site](https://example.com) [mail](mailto:a@example.com)";
+        let note = Note::new("Links".to_string(), content.to_string());
+        let assets = ExportAssets {
+            blob_store: &store,
+            tiles: None,
+        };
+        let page = render(&[&note], "Export", &assets);
+
+        assert!(!page.to_ascii_lowercase().contains("javascript:"));
+        assert!(page.contains("click mixed pic"));
+        assert!(page.contains("<a href=\"https://example.com\">site</a>"));
+        assert!(page.contains("<a href=\"mailto:a@example.com\">mail</a>"));
+    }
+}
@@ -0,0 +1,442 @@
+use quick_xml::events::Event;
+use quick_xml::Reader;
+
+/// Minimal DOM for converting (X)HTML to Markdown
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node {
+    Element {
+        name: String,
+        attributes: Vec<(String, String)>,
+        children: Vec<Node>,
+    },
+    Text(String),
+}
+
+impl Node {
+    pub fn attribute(&self, key: &str) -> Option<&str> {
+        match self {
+            Node::Element { attributes, .. } => attributes
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value.as_str()),
+            Node::Text(_) => None,
+        }
+    }
+}
+
+/// HTML entities that show up in ENML and exported HTML
+fn resolve_entity(entity: &str) -> Option<&'static str> {
+    Some(match entity {
+        "amp" => "&",
+        "lt" => "<",
+        "gt" => ">",
+        "quot" => "\"",
+        "apos" => "'",
+        "nbsp" => " ",
+        "ndash" => "–",
+        "mdash" => "—",
+        "hellip" => "…",
+        "lsquo" => "‘",
+        "rsquo" => "’",
+        "ldquo" => "“",
+        "rdquo" => "”",
+        "copy" => "©",
+        "euro" => "€",
+        _ => return None,
+    })
+}
+
+pub fn unescape(raw: &str) -> String {
+    quick_xml::escape::unescape_with(raw, resolve_entity)
+        .map(|text| text.into_owned())
+        .unwrap_or_else(|_| raw.to_string())
+}
+
+/// Name, attributes and children of an element whose end tag is still to come
+type OpenElement = (String, Vec<(String, String)>, Vec<Node>);
+
+/// Parses well-formed-ish XHTML. Mismatched end tags close the nearest open
+/// element instead of failing, since note exports are rarely strict.
+pub fn parse(source: &str) -> Vec<Node> {
+    let mut reader = Reader::from_str(source);
+    reader.config_mut().check_end_names = false;
+
+    // The bottom entry of the stack is the document itself
+    let mut stack: Vec<OpenElement> = vec![(String::new(), Vec::new(), Vec::new())];
+    let attributes = |element: &quick_xml::events::BytesStart<'_>| {
+        element
+            .attributes()
+            .flatten()
+            .map(|attribute| {
+                let key = String::from_utf8_lossy(attribute.key.local_name().as_ref()).to_lowercase();
+                let value = unescape(&String::from_utf8_lossy(&attribute.value));
+                (key, value)
+            })
+            .collect::<Vec<_>>()
+    };
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(element)) if !is_void(element.local_name().as_ref()) => {
+                let name = String::from_utf8_lossy(element.local_name().as_ref()).to_lowercase();
+                stack.push((name, attributes(&element), Vec::new()));
+            }
+            // HTML void elements such as `<br>` are often written without `/>`
+            Ok(Event::Start(element)) | Ok(Event::Empty(element)) => {
+                let name = String::from_utf8_lossy(element.local_name().as_ref()).to_lowercase();
+                let node = Node::Element {
+                    name,
+                    attributes: attributes(&element),
+                    children: Vec::new(),
+                };
+                if let Some(parent) = stack.last_mut() {
+                    parent.2.push(node);
+                }
+            }
+            Ok(Event::End(element)) => {
+                let name = String::from_utf8_lossy(element.local_name().as_ref()).to_lowercase();
+                // Pop up to and including the matching element, if it is open
+                if let Some(depth) = stack.iter().rposition(|(open, _, _)| *open == name).filter(|&d| d > 0) {
+                    while stack.len() > depth {
+                        close(&mut stack);
+                    }
+                }
+            }
+            Ok(Event::Text(text)) => {
+                let text = unescape(&String::from_utf8_lossy(&text));
+                if let Some(parent) = stack.last_mut() {
+                    parent.2.push(Node::Text(text));
+                }
+            }
+            Ok(Event::CData(data)) => {
+                let text = String::from_utf8_lossy(&data).into_owned();
+                if let Some(parent) = stack.last_mut() {
+                    parent.2.push(Node::Text(text));
+                }
+            }
+            Ok(Event::Eof) | Err(_) => break,
+            Ok(_) => {}
+        }
+    }
+
+    while stack.len() > 1 {
+        close(&mut stack);
+    }
+    stack.pop().map(|(_, _, children)| children).unwrap_or_default()
+}
+
+fn is_void(name: &[u8]) -> bool {
+    const VOID_ELEMENTS: [&str; 10] = ["br", "hr", "img", "meta", "link", "input", "col", "area", "wbr", "source"];
+    VOID_ELEMENTS.iter().any(|void| void.as_bytes().eq_ignore_ascii_case(name))
+}
+
+fn close(stack: &mut Vec<OpenElement>) {
+    if let Some((name, attributes, children)) = stack.pop() {
+        if let Some(parent) = stack.last_mut() {
+            parent.2.push(Node::Element {
+                name,
+                attributes,
+                children,
+            });
+        }
+    }
+}
+
+const BLOCK_ELEMENTS: [&str; 25] = [
+    "p", "div", "h1", "h2", "h3", "h4", "h5", "h6", "ul", "ol", "li", "pre", "blockquote", "table", "hr",
+    "en-note", "body", "html", "section", "article", "header", "footer", "center", "dl", "figure",
+];
+
+/// Converts HTML or ENML to Markdown. `media` renders elements that refer to
+/// external resources (`<en-media>`, `<img>`), returning `None` to drop them.
+pub fn to_markdown(source: &str, media: &mut dyn FnMut(&Node) -> Option<String>) -> String {
+    let nodes = parse(source);
+    let mut converter = Converter { media };
+    let blocks = converter.blocks(&nodes);
+    let mut markdown = blocks.join("\n\n");
+    markdown.push('\n');
+    markdown
+}
+
+struct Converter<'a> {
+    media: &'a mut dyn FnMut(&Node) -> Option<String>,
+}
+
+impl Converter<'_> {
+    fn blocks(&mut self, nodes: &[Node]) -> Vec<String> {
+        let mut blocks = Vec::new();
+        let mut paragraph = String::new();
+
+        for node in nodes {
+            let block_name = match node {
+                Node::Element { name, .. } if BLOCK_ELEMENTS.contains(&name.as_str()) => Some(name.as_str()),
+                _ => None,
+            };
+            match block_name {
+                Some(name) => {
+                    flush_paragraph(&mut paragraph, &mut blocks);
+                    self.block(name, node, &mut blocks);
+                }
+                None => paragraph.push_str(&self.inline(node)),
+            }
+        }
+        flush_paragraph(&mut paragraph, &mut blocks);
+        blocks
+    }
+
+    fn block(&mut self, name: &str, node: &Node, blocks: &mut Vec<String>) {
+        let Node::Element { children, .. } = node else {
+            return;
+        };
+        match name {
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                let level = name[1..].parse().unwrap_or(1);
+                let text = collapse_whitespace(&self.inlines(children));
+                if !text.is_empty() {
+                    blocks.push(format!("{} {}", "#".repeat(level), text));
+                }
+            }
+            "ul" | "ol" => {
+                let list = self.list(children, name == "ol");
+                if !list.is_empty() {
+                    blocks.push(list);
+                }
+            }
+            "pre" => {
+                let code = text_content(node);
+                blocks.push(format!("```\n{}\n```", code.trim_end_matches('\n')));
+            }
+            "blockquote" => {
+                let quoted = self.blocks(children).join("\n\n");
+                if !quoted.is_empty() {
+                    let lines: Vec<String> = quoted
+                        .lines()
+                        .map(|line| if line.is_empty() { ">".to_string() } else { format!("> {}", line) })
+                        .collect();
+                    blocks.push(lines.join("\n"));
+                }
+            }
+            "table" => {
+                if let Some(table) = self.table(node) {
+                    blocks.push(table);
+                }
+            }
+            "hr" => blocks.push("---".to_string()),
+            _ => blocks.extend(self.blocks(children)),
+        }
+    }
+
+    fn list(&mut self, items: &[Node], ordered: bool) -> String {
+        let mut lines = Vec::new();
+        let mut number = 1;
+        for item in items {
+            let Node::Element { name, children, .. } = item else {
+                continue;
+            };
+            if name != "li" {
+                continue;
+            }
+            let mut marker = if ordered { format!("{}. ", number) } else { "- ".to_string() };
+            number += 1;
+
+            let blocks = self.blocks(children);
+            let mut content = blocks.join("\n\n");
+            // A checkbox paragraph inside a list item becomes a task list item
+            if let Some(rest) = content.strip_prefix("- [") {
+                marker.clear();
+                content = format!("- [{}", rest);
+            }
+            let indent = " ".repeat(marker.len());
+            for (index, line) in content.lines().enumerate() {
+                if index == 0 {
+                    lines.push(format!("{}{}", marker, line));
+                } else if line.is_empty() {
+                    lines.push(String::new());
+                } else {
+                    lines.push(format!("{}{}", indent, line));
+                }
+            }
+        }
+        lines.join("\n")
+    }
+
+    fn table(&mut self, table: &Node) -> Option<String> {
+        let mut rows = Vec::new();
+        collect_rows(table, &mut rows);
+        let rows: Vec<Vec<String>> = rows
+            .into_iter()
+            .map(|row| {
+                row.iter()
+                    .map(|cell| match cell {
+                        Node::Element { children, .. } => {
+                            collapse_whitespace(&self.inlines(children)).replace('|', "\\|")
+                        }
+                        Node::Text(_) => String::new(),
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let columns = rows.iter().map(Vec::len).max().filter(|&columns| columns > 0)?;
+        let line = |row: &[String]| {
+            let cells: Vec<&str> = (0..columns).map(|i| row.get(i).map_or("", String::as_str)).collect();
+            format!("| {} |", cells.join(" | "))
+        };
+        let mut lines = vec![line(&rows[0]), format!("|{}", " --- |".repeat(columns))];
+        lines.extend(rows[1..].iter().map(|row| line(row)));
+        Some(lines.join("\n"))
+    }
+
+    fn inlines(&mut self, nodes: &[Node]) -> String {
+        nodes.iter().map(|node| self.inline(node)).collect()
+    }
+
+    fn inline(&mut self, node: &Node) -> String {
+        let (name, children) = match node {
+            Node::Text(text) => return escape_markdown(text),
+            Node::Element { name, children, .. } => (name.as_str(), children),
+        };
+        match name {
+            "br" => "  \n".to_string(),
+            "b" | "strong" => wrap(&self.inlines(children), "**"),
+            "i" | "em" => wrap(&self.inlines(children), "*"),
+            "s" | "strike" | "del" => wrap(&self.inlines(children), "~~"),
+            "code" | "tt" => {
+                let code = text_content(node);
+                if code.trim().is_empty() {
+                    code
+                } else {
+                    format!("`{}`", code.replace('`', "'"))
+                }
+            }
+            "a" => {
+                let text = self.inlines(children);
+                match node.attribute("href") {
+                    Some(href) if !href.is_empty() && !text.trim().is_empty() => {
+                        format!("[{}]({})", text.trim(), href.replace(' ', "%20"))
+                    }
+                    _ => text,
+                }
+            }
+            "en-todo" => {
+                let checked = node.attribute("checked") == Some("true");
+                format!("- [{}] ", if checked { "x" } else { " " })
+            }
+            "en-media" | "img" => (self.media)(node).unwrap_or_default(),
+            "script" | "style" | "head" | "title" => String::new(),
+            _ => self.inlines(children),
+        }
+    }
+}
+
+fn flush_paragraph(paragraph: &mut String, blocks: &mut Vec<String>) {
+    let text = collapse_whitespace(paragraph);
+    if !text.is_empty() {
+        blocks.push(text);
+    }
+    paragraph.clear();
+}
+
+/// Collapses runs of whitespace (but keeps explicit `<br>` line breaks)
+fn collapse_whitespace(text: &str) -> String {
+    text.split("  \n")
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .collect::<Vec<_>>()
+        .join("  \n")
+        .trim_end_matches("  \n")
+        .trim()
+        .to_string()
+}
+
+fn wrap(inner: &str, marker: &str) -> String {
+    let trimmed = inner.trim();
+    if trimmed.is_empty() {
+        return inner.to_string();
+    }
+    let leading = if inner.starts_with(char::is_whitespace) { " " } else { "" };
+    let trailing = if inner.ends_with(char::is_whitespace) { " " } else { "" };
+    format!("{}{}{}{}{}", leading, marker, trimmed, marker, trailing)
+}
+
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '*' | '_' | '`' | '[' | ']') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn text_content(node: &Node) -> String {
+    match node {
+        Node::Text(text) => text.clone(),
+        Node::Element { name, .. } if name == "br" => "\n".to_string(),
+        Node::Element { name, children, .. } => {
+            let text: String = children.iter().map(text_content).collect();
+            if name == "div" || name == "p" {
+                format!("{}\n", text)
+            } else {
+                text
+            }
+        }
+    }
+}
+
+fn collect_rows<'a>(node: &'a Node, rows: &mut Vec<Vec<&'a Node>>) {
+    let Node::Element { children, .. } = node else {
+        return;
+    };
+    for child in children {
+        match child {
+            Node::Element { name, children: cells, .. } if name == "tr" => rows.push(
+                cells
+                    .iter()
+                    .filter(|cell| matches!(cell, Node::Element { name, .. } if name == "td" || name == "th"))
+                    .collect(),
+            ),
+            Node::Element { name, .. } if name != "table" => collect_rows(child, rows),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn convert(source: &str) -> String {
+        to_markdown(source, &mut |node| node.attribute("hash").map(|hash| format!("![](media:{})", hash)))
+    }
+
+    #[test]
+    fn converts_enml_blocks_and_inline_styles() {
+        let enml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE en-note SYSTEM "http://xml.evernote.com/pub/enml2.dtd">
+<en-note><h2>Trip&nbsp;plan</h2><div>Book <b>hotel</b> and <a href="https://example.com/a b">flights</a></div>
+<div><en-todo checked="true"/>Pack bags</div><div><en-todo/>Water plants</div>
+<ul><li>One</li><li>Two<ol><li>Nested</li></ol></li></ul>
+<div><en-media type="image/png" hash="abc"/></div>
+<table><tr><th>Item</th><th>Cost</th></tr><tr><td>Train</td><td>40 | 50</td></tr></table>
+<pre>let x = 1;
+let y = 2;</pre></en-note>"#;
+
+        assert_eq!(
+            convert(enml),
+            "## Trip plan\n\n\
+             Book **hotel** and [flights](https://example.com/a%20b)\n\n\
+             - [x] Pack bags\n\n\
+             - [ ] Water plants\n\n\
+             - One\n- Two\n\n  1. Nested\n\n\
+             ![](media:abc)\n\n\
+             | Item | Cost |\n| --- | --- |\n| Train | 40 \\| 50 |\n\n\
+             ```\nlet x = 1;\nlet y = 2;\n```\n"
+        );
+    }
+
+    #[test]
+    fn tolerates_sloppy_html() {
+        assert_eq!(convert("<p>One<br>two</span></p><p>snake_case</p>"), "One  \ntwo\n\nsnake\\_case\n");
+    }
+}
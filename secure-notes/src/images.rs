@@ -0,0 +1,296 @@
+use crate::attachments::BlobStore;
+use eframe::egui;
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender};
+
+/// Files larger than this are not decoded
+pub const MAX_IMAGE_BYTES: u64 = 25 * 1024 * 1024;
+/// Decoded images are downscaled so neither side exceeds this
+const MAX_TEXTURE_SIDE: u32 = 2048;
+/// Largest image shown inline before the viewer is needed
+const MAX_INLINE_HEIGHT: f32 = 400.0;
+const PLACEHOLDER_SIZE: egui::Vec2 = egui::vec2(160.0, 100.0);
+
+/// Where an image referenced from Markdown comes from
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImageSource {
+    /// `attachment:<blob id>`
+    Attachment(String),
+    /// Absolute path or `file://` URL
+    File(PathBuf),
+}
+
+impl ImageSource {
+    /// Remote URLs are never fetched so that viewing a note cannot leak it
+    pub fn parse(url: &str) -> Option<ImageSource> {
+        if let Some(blob_id) = url.strip_prefix("attachment:") {
+            return Some(ImageSource::Attachment(blob_id.to_string()));
+        }
+        let path = PathBuf::from(url.strip_prefix("file://").unwrap_or(url));
+        path.is_absolute().then_some(ImageSource::File(path))
+    }
+}
+
+/// Markdown reference to an attachment, as inserted by the editor
+pub fn attachment_markdown(alt: &str, blob_id: &str) -> String {
+    format!("![{}](attachment:{})", alt.replace([']', '['], ""), blob_id)
+}
+
+enum Slot {
+    Loading,
+    Ready(egui::TextureHandle),
+    Failed(String),
+}
+
+/// Lazily decoded textures for images shown in the Markdown view
+pub struct ImageCache {
+    blob_store: Option<BlobStore>,
+    slots: HashMap<String, Slot>,
+    sender: Sender<(String, Result<egui::ColorImage, String>)>,
+    receiver: Receiver<(String, Result<egui::ColorImage, String>)>,
+    zoomed: Option<String>,
+    zoom: f32,
+}
+
+impl Default for ImageCache {
+    fn default() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Self {
+            blob_store: None,
+            slots: HashMap::new(),
+            sender,
+            receiver,
+            zoomed: None,
+            zoom: 1.0,
+        }
+    }
+}
+
+impl ImageCache {
+    /// Attachments can only be shown while the vault is unlocked
+    pub fn set_blob_store(&mut self, blob_store: Option<BlobStore>) {
+        self.blob_store = blob_store;
+        self.slots.clear();
+        self.zoomed = None;
+    }
+
+    /// Shows an image inline, decoding it in the background the first time it
+    /// scrolls into view. Clicking opens the zoom viewer.
+    pub fn show_inline(&mut self, ui: &mut egui::Ui, url: &str, alt: &str) {
+        let Some(source) = ImageSource::parse(url) else {
+            let label = if alt.is_empty() { url } else { alt };
+            ui.hyperlink_to(format!("🖼 {}", label), url);
+            return;
+        };
+
+        self.receive(ui.ctx());
+        match self.slots.get(url) {
+            Some(Slot::Ready(texture)) => {
+                let size = texture.size_vec2();
+                let scale = (ui.available_width() / size.x)
+                    .min(MAX_INLINE_HEIGHT / size.y)
+                    .min(1.0);
+                let response = ui
+                    .add(egui::Image::new(texture).fit_to_exact_size(size * scale).sense(egui::Sense::click()))
+                    .on_hover_text(if alt.is_empty() { "Click to zoom" } else { alt });
+                if response.clicked() {
+                    self.zoomed = Some(url.to_string());
+                    self.zoom = 1.0;
+                }
+            }
+            Some(Slot::Failed(error)) => {
+                ui.label(egui::RichText::new(format!("🖼 {} ({})", alt, error)).weak())
+                    .on_hover_text(url);
+            }
+            Some(Slot::Loading) => {
+                placeholder(ui, alt, true);
+            }
+            None => {
+                if placeholder(ui, alt, false) {
+                    self.slots.insert(url.to_string(), Slot::Loading);
+                    self.load(ui.ctx(), url.to_string(), source);
+                }
+            }
+        }
+    }
+
+    fn load(&self, ctx: &egui::Context, url: String, source: ImageSource) {
+        let sender = self.sender.clone();
+        let blob_store = self.blob_store.clone();
+        let ctx = ctx.clone();
+        std::thread::spawn(move || {
+            let result = read_source(&source, blob_store.as_ref()).and_then(|data| decode(&data));
+            sender.send((url, result)).ok();
+            ctx.request_repaint();
+        });
+    }
+
+    fn receive(&mut self, ctx: &egui::Context) {
+        while let Ok((url, result)) = self.receiver.try_recv() {
+            let slot = match result {
+                Ok(image) => Slot::Ready(ctx.load_texture(&url, image, egui::TextureOptions::LINEAR)),
+                Err(e) => Slot::Failed(e),
+            };
+            self.slots.insert(url, slot);
+        }
+    }
+
+    /// Window with the full-resolution image and zoom controls
+    pub fn show_viewer(&mut self, ctx: &egui::Context) {
+        let Some(url) = self.zoomed.clone() else {
+            return;
+        };
+        let Some(Slot::Ready(texture)) = self.slots.get(&url) else {
+            self.zoomed = None;
+            return;
+        };
+
+        let mut open = true;
+        let zoom = &mut self.zoom;
+        egui::Window::new("🖼 Image")
+            .open(&mut open)
+            .default_size([800.0, 600.0])
+            .resizable(true)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    if ui.button("➖").clicked() {
+                        *zoom = (*zoom / 1.25).max(0.1);
+                    }
+                    ui.add(egui::Slider::new(zoom, 0.1..=8.0).logarithmic(true).suffix("×"));
+                    if ui.button("➕").clicked() {
+                        *zoom = (*zoom * 1.25).min(8.0);
+                    }
+                    if ui.button("Fit").clicked() {
+                        let available = ui.available_size();
+                        let size = texture.size_vec2();
+                        *zoom = (available.x / size.x).min(available.y / size.y);
+                    }
+                    if ui.button("1:1").clicked() {
+                        *zoom = 1.0;
+                    }
+                });
+                ui.separator();
+
+                let scroll = ui.input(|i| if i.modifiers.command { i.raw_scroll_delta.y } else { 0.0 });
+                if scroll != 0.0 {
+                    *zoom = (*zoom * (1.0 + scroll * 0.002)).clamp(0.1, 8.0);
+                }
+                egui::ScrollArea::both().show(ui, |ui| {
+                    ui.add(egui::Image::new(texture).fit_to_exact_size(texture.size_vec2() * *zoom));
+                });
+            });
+
+        if !open {
+            self.zoomed = None;
+        }
+    }
+}
+
+/// Reserves space for an image that is not decoded yet. Returns whether the
+/// placeholder is on screen, i.e. whether decoding should start.
+fn placeholder(ui: &mut egui::Ui, alt: &str, loading: bool) -> bool {
+    let (rect, _) = ui.allocate_exact_size(PLACEHOLDER_SIZE, egui::Sense::hover());
+    let visuals = ui.visuals();
+    ui.painter().rect_filled(rect, 4.0, visuals.faint_bg_color);
+    let text = if loading { format!("⏳ {}", alt) } else { format!("🖼 {}", alt) };
+    ui.painter().text(
+        rect.center(),
+        egui::Align2::CENTER_CENTER,
+        text,
+        egui::FontId::proportional(12.0),
+        visuals.weak_text_color(),
+    );
+    ui.is_rect_visible(rect)
+}
+
+fn read_source(source: &ImageSource, blob_store: Option<&BlobStore>) -> Result<Vec<u8>, String> {
+    match source {
+        ImageSource::Attachment(blob_id) => {
+            let blob_store = blob_store.ok_or("vault is locked")?;
+            let data = blob_store.read(blob_id)?;
+            if data.len() as u64 > MAX_IMAGE_BYTES {
+                return Err("image too large".to_string());
+            }
+            Ok(data)
+        }
+        ImageSource::File(path) => {
+            let size = std::fs::metadata(path)
+                .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?
+                .len();
+            if size > MAX_IMAGE_BYTES {
+                return Err("image too large".to_string());
+            }
+            std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))
+        }
+    }
+}
+
+/// Decodes an image with allocation limits, downscaling it for display
+pub fn decode(data: &[u8]) -> Result<egui::ColorImage, String> {
+    let mut limits = image::Limits::default();
+    limits.max_image_width = Some(16_384);
+    limits.max_image_height = Some(16_384);
+    limits.max_alloc = Some(512 * 1024 * 1024);
+
+    let mut reader = image::ImageReader::new(Cursor::new(data))
+        .with_guessed_format()
+        .map_err(|e| format!("Failed to read image: {}", e))?;
+    reader.limits(limits);
+    let mut image = reader.decode().map_err(|e| format!("Failed to decode image: {}", e))?;
+
+    if image.width() > MAX_TEXTURE_SIDE || image.height() > MAX_TEXTURE_SIDE {
+        image = image.thumbnail(MAX_TEXTURE_SIDE, MAX_TEXTURE_SIDE);
+    }
+    let rgba = image.to_rgba8();
+    let size = [rgba.width() as usize, rgba.height() as usize];
+    Ok(egui::ColorImage::from_rgba_unmultiplied(size, rgba.as_raw()))
+}
+
+/// Reads an image from the system clipboard and encodes it as PNG
+pub fn clipboard_png() -> Result<Vec<u8>, String> {
+    let mut clipboard = arboard::Clipboard::new().map_err(|e| format!("Failed to open clipboard: {}", e))?;
+    let image = clipboard
+        .get_image()
+        .map_err(|_| "The clipboard does not contain an image".to_string())?;
+    let buffer = image::RgbaImage::from_raw(image.width as u32, image.height as u32, image.bytes.into_owned())
+        .ok_or("Invalid clipboard image")?;
+
+    let mut png = Vec::new();
+    buffer
+        .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+        .map_err(|e| format!("Failed to encode image: {}", e))?;
+    Ok(png)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_local_and_attachment_images_are_loaded() {
+        assert_eq!(
+            ImageSource::parse("attachment:abc123"),
+            Some(ImageSource::Attachment("abc123".to_string()))
+        );
+        assert_eq!(
+            ImageSource::parse("file:///tmp/photo.png"),
+            Some(ImageSource::File(PathBuf::from("/tmp/photo.png")))
+        );
+        assert_eq!(ImageSource::parse("https://example.com/tracker.png"), None);
+        assert_eq!(ImageSource::parse("relative.png"), None);
+        assert_eq!(attachment_markdown("a [b]", "id"), "![a b](attachment:id)");
+    }
+
+    #[test]
+    fn large_images_are_downscaled_for_display() {
+        let image = image::RgbaImage::new(MAX_TEXTURE_SIDE * 2, 10);
+        let mut png = Vec::new();
+        image.write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png).unwrap();
+
+        let decoded = decode(&png).unwrap();
+        assert_eq!(decoded.size[0], MAX_TEXTURE_SIDE as usize);
+        assert!(decode(b"not an image").is_err());
+    }
+}
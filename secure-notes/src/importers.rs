@@ -0,0 +1,779 @@
+use crate::attachments::BlobStore;
+use crate::crypto;
+use crate::html_import::{self, Node};
+use crate::note::{GeoLocation, Note};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use md5::{Digest, Md5};
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Other note apps whose exports can be imported
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportFormat {
+    Evernote,
+    JoplinJex,
+    JoplinRaw,
+    StandardNotes,
+    GoogleKeep,
+}
+
+impl ImportFormat {
+    pub const ALL: [ImportFormat; 5] = [
+        ImportFormat::Evernote,
+        ImportFormat::JoplinJex,
+        ImportFormat::JoplinRaw,
+        ImportFormat::StandardNotes,
+        ImportFormat::GoogleKeep,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ImportFormat::Evernote => "Evernote (.enex)…",
+            ImportFormat::JoplinJex => "Joplin export (.jex)…",
+            ImportFormat::JoplinRaw => "Joplin raw export folder…",
+            ImportFormat::StandardNotes => "Standard Notes backup (.txt, .json)…",
+            ImportFormat::GoogleKeep => "Google Keep Takeout folder…",
+        }
+    }
+
+    /// File extensions to pick, or `None` when the export is a folder
+    pub fn extensions(self) -> Option<&'static [&'static str]> {
+        match self {
+            ImportFormat::Evernote => Some(&["enex"]),
+            ImportFormat::JoplinJex => Some(&["jex"]),
+            ImportFormat::StandardNotes => Some(&["txt", "json"]),
+            ImportFormat::JoplinRaw | ImportFormat::GoogleKeep => None,
+        }
+    }
+}
+
+/// A file that belongs to an imported note and is stored as an attachment
+pub struct ImportedFile {
+    pub file_name: String,
+    pub data: Vec<u8>,
+}
+
+pub struct ImportedNote {
+    /// Links to `files` use `import-resource:<index>` until the import is committed
+    pub note: Note,
+    pub files: Vec<ImportedFile>,
+    /// Title of the existing note this one duplicates, if any
+    pub duplicate_of: Option<String>,
+}
+
+impl ImportedNote {
+    fn new(note: Note) -> Self {
+        Self {
+            note,
+            files: Vec::new(),
+            duplicate_of: None,
+        }
+    }
+
+    /// Adds a file and returns the placeholder URL to link it with
+    fn add_file(&mut self, file_name: String, data: Vec<u8>) -> String {
+        self.files.push(ImportedFile { file_name, data });
+        format!("import-resource:{}", self.files.len() - 1)
+    }
+
+    fn link(&mut self, file_name: String, data: Vec<u8>) -> String {
+        let image = is_image_name(&file_name);
+        let label = file_name.replace(['[', ']'], "");
+        let url = self.add_file(file_name, data);
+        if image {
+            format!("![{}]({})", label, url)
+        } else {
+            format!("[📎 {}]({})", label, url)
+        }
+    }
+}
+
+/// Everything found in an export, checked but not yet written to the vault
+pub struct ImportPlan {
+    pub format: ImportFormat,
+    pub source: PathBuf,
+    pub notes: Vec<ImportedNote>,
+    /// Items that were skipped, with the reason
+    pub warnings: Vec<String>,
+}
+
+/// Reads an export without touching the vault
+pub fn read(format: ImportFormat, path: &Path) -> Result<ImportPlan, String> {
+    let mut plan = ImportPlan {
+        format,
+        source: path.to_path_buf(),
+        notes: Vec::new(),
+        warnings: Vec::new(),
+    };
+    match format {
+        ImportFormat::Evernote => read_enex(&read_text(path)?, &mut plan)?,
+        ImportFormat::JoplinJex => read_joplin(&read_tar(path)?, &mut plan),
+        ImportFormat::JoplinRaw => read_joplin(&read_dir_files(path)?, &mut plan),
+        ImportFormat::StandardNotes => read_standard_notes(&read_text(path)?, &mut plan)?,
+        ImportFormat::GoogleKeep => read_google_keep(path, &mut plan)?,
+    }
+    Ok(plan)
+}
+
+impl ImportPlan {
+    /// Marks notes that already exist in the vault or earlier in this import:
+    /// same title, and either the same creation time or the same text
+    pub fn detect_duplicates(&mut self, existing: &[&Note]) {
+        let mut seen: Vec<(String, i64, String, String)> = existing
+            .iter()
+            .map(|note| fingerprint(note, note.title.clone()))
+            .collect();
+        for imported in &mut self.notes {
+            let (title, created, content, _) = fingerprint(&imported.note, String::new());
+            imported.duplicate_of = seen
+                .iter()
+                .find(|other| other.0 == title && (other.1 == created || other.2 == content))
+                .map(|other| if other.3.is_empty() { "an earlier note in this import".to_string() } else { other.3.clone() });
+            if imported.duplicate_of.is_none() {
+                seen.push(fingerprint(&imported.note, String::new()));
+            }
+        }
+    }
+
+    pub fn duplicate_count(&self) -> usize {
+        self.notes.iter().filter(|imported| imported.duplicate_of.is_some()).count()
+    }
+
+    pub fn summary(&self) -> String {
+        let files: usize = self.notes.iter().map(|imported| imported.files.len()).sum();
+        let bytes: usize = self
+            .notes
+            .iter()
+            .flat_map(|imported| &imported.files)
+            .map(|file| file.data.len())
+            .sum();
+        format!(
+            "{} notes found ({} new, {} duplicates), {} attachments ({}), {} skipped items.",
+            self.notes.len(),
+            self.notes.len() - self.duplicate_count(),
+            self.duplicate_count(),
+            files,
+            crate::attachments::format_size(bytes as u64),
+            self.warnings.len()
+        )
+    }
+
+    /// Encrypts the attachments and returns the notes ready to add to the vault
+    pub fn into_notes(self, skip_duplicates: bool, blob_store: &BlobStore) -> Result<Vec<Note>, String> {
+        let mut notes = Vec::new();
+        for imported in self.notes {
+            if skip_duplicates && imported.duplicate_of.is_some() {
+                continue;
+            }
+            let mut note = imported.note;
+            for (index, file) in imported.files.iter().enumerate() {
+                let attachment = blob_store.add_bytes(&file.file_name, &file.data)?;
+                note.content = note.content.replace(
+                    &format!("(import-resource:{})", index),
+                    &format!("(attachment:{})", attachment.blob_id),
+                );
+                note.add_attachment(attachment);
+            }
+            notes.push(note);
+        }
+        Ok(notes)
+    }
+}
+
+fn fingerprint(note: &Note, label: String) -> (String, i64, String, String) {
+    let normalize = |text: &str| text.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+    (
+        normalize(&note.title),
+        note.created_at.timestamp(),
+        normalize(&note.content),
+        label,
+    )
+}
+
+fn is_image_name(file_name: &str) -> bool {
+    let lower = file_name.to_lowercase();
+    [".png", ".jpg", ".jpeg", ".gif", ".webp", ".bmp"]
+        .iter()
+        .any(|ext| lower.ends_with(ext))
+}
+
+fn read_text(path: &Path) -> Result<String, String> {
+    fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))
+}
+
+fn location(latitude: Option<f64>, longitude: Option<f64>) -> Option<GeoLocation> {
+    match (latitude, longitude) {
+        (Some(latitude), Some(longitude)) if latitude != 0.0 || longitude != 0.0 => {
+            Some(GeoLocation::new(latitude, longitude, String::new()))
+        }
+        _ => None,
+    }
+}
+
+fn parse_rfc3339(text: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(text.trim()).ok().map(|time| time.with_timezone(&Utc))
+}
+
+// --- Evernote ---
+
+#[derive(Default)]
+struct EnexNote {
+    title: String,
+    content: String,
+    created: Option<DateTime<Utc>>,
+    updated: Option<DateTime<Utc>>,
+    tags: Vec<String>,
+    latitude: Option<f64>,
+    longitude: Option<f64>,
+    resources: Vec<EnexResource>,
+}
+
+#[derive(Default)]
+struct EnexResource {
+    data: String,
+    mime: String,
+    file_name: String,
+}
+
+fn parse_enex_time(text: &str) -> Option<DateTime<Utc>> {
+    NaiveDateTime::parse_from_str(text.trim(), "%Y%m%dT%H%M%SZ")
+        .ok()
+        .map(|time| time.and_utc())
+}
+
+fn read_enex(source: &str, plan: &mut ImportPlan) -> Result<(), String> {
+    let mut reader = Reader::from_str(source);
+    let mut path: Vec<String> = Vec::new();
+    let mut text = String::new();
+    let mut note: Option<EnexNote> = None;
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(element)) => {
+                let name = String::from_utf8_lossy(element.local_name().as_ref()).into_owned();
+                match name.as_str() {
+                    "note" => note = Some(EnexNote::default()),
+                    "resource" => {
+                        if let Some(note) = &mut note {
+                            note.resources.push(EnexResource::default());
+                        }
+                    }
+                    _ => {}
+                }
+                path.push(name);
+                text.clear();
+            }
+            Ok(Event::Text(content)) => text.push_str(&html_import::unescape(&String::from_utf8_lossy(&content))),
+            Ok(Event::CData(content)) => text.push_str(&String::from_utf8_lossy(&content)),
+            Ok(Event::End(_)) => {
+                let name = path.pop().unwrap_or_default();
+                let parent = path.last().map(String::as_str).unwrap_or_default();
+                if name == "note" {
+                    if let Some(finished) = note.take() {
+                        plan.notes.push(enex_note(finished));
+                    }
+                } else if let Some(note) = &mut note {
+                    let value = std::mem::take(&mut text);
+                    let resource = note.resources.last_mut();
+                    match (parent, name.as_str(), resource) {
+                        ("note", "title", _) => note.title = value.trim().to_string(),
+                        ("note", "content", _) => note.content = value,
+                        ("note", "created", _) => note.created = parse_enex_time(&value),
+                        ("note", "updated", _) => note.updated = parse_enex_time(&value),
+                        ("note", "tag", _) => note.tags.push(value.trim().to_string()),
+                        ("note-attributes", "latitude", _) => note.latitude = value.trim().parse().ok(),
+                        ("note-attributes", "longitude", _) => note.longitude = value.trim().parse().ok(),
+                        ("resource", "data", Some(resource)) => resource.data = value,
+                        ("resource", "mime", Some(resource)) => resource.mime = value.trim().to_string(),
+                        ("resource-attributes", "file-name", Some(resource)) => {
+                            resource.file_name = value.trim().to_string()
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            Ok(Event::Eof) => break,
+            Err(e) => return Err(format!("Failed to parse ENEX: {}", e)),
+            Ok(_) => {}
+        }
+    }
+    Ok(())
+}
+
+fn enex_note(enex: EnexNote) -> ImportedNote {
+    let mut imported = ImportedNote::new(Note::new(enex.title, String::new()));
+
+    // en-media elements refer to resources by the MD5 of their data
+    let mut resources = HashMap::new();
+    for (index, resource) in enex.resources.into_iter().enumerate() {
+        let encoded: String = resource.data.split_whitespace().collect();
+        let Ok(data) = crypto::decode_base64(&encoded) else {
+            continue;
+        };
+        let hash = hex::encode(Md5::digest(&data));
+        let extension = resource.mime.rsplit('/').next().unwrap_or("bin");
+        let file_name = if resource.file_name.is_empty() {
+            format!("attachment-{}.{}", index + 1, extension)
+        } else {
+            resource.file_name
+        };
+        resources.insert(hash, (file_name, data));
+    }
+
+    let mut content = html_import::to_markdown(&enex.content, &mut |node: &Node| {
+        let (file_name, data) = resources.remove(node.attribute("hash")?)?;
+        Some(imported.link(file_name, data))
+    });
+    // Resources that the note body does not show are still attached
+    let mut unreferenced: Vec<_> = resources.into_values().collect();
+    unreferenced.sort_by(|a, b| a.0.cmp(&b.0));
+    for (file_name, data) in unreferenced {
+        content.push_str(&format!("\n{}\n", imported.link(file_name, data)));
+    }
+
+    let note = &mut imported.note;
+    note.content = content;
+    note.tags = enex.tags.into_iter().filter(|tag| !tag.is_empty()).collect();
+    note.location = location(enex.latitude, enex.longitude);
+    if let Some(created) = enex.created {
+        note.created_at = created;
+        note.modified_at = created;
+    }
+    if let Some(updated) = enex.updated {
+        note.modified_at = updated;
+    }
+    imported
+}
+
+// --- Joplin ---
+
+/// Export files keyed by their path relative to the export root
+type ExportFiles = HashMap<String, Vec<u8>>;
+
+fn read_tar(path: &Path) -> Result<ExportFiles, String> {
+    let file = fs::File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let mut archive = tar::Archive::new(file);
+    let entries = archive.entries().map_err(|e| format!("Failed to read archive: {}", e))?;
+
+    let mut files = ExportFiles::new();
+    for entry in entries {
+        let mut entry = entry.map_err(|e| format!("Failed to read archive: {}", e))?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let name = entry
+            .path()
+            .map_err(|e| format!("Failed to read archive: {}", e))?
+            .to_string_lossy()
+            .trim_start_matches("./")
+            .to_string();
+        let mut data = Vec::new();
+        entry
+            .read_to_end(&mut data)
+            .map_err(|e| format!("Failed to read {}: {}", name, e))?;
+        files.insert(name, data);
+    }
+    Ok(files)
+}
+
+fn read_dir_files(root: &Path) -> Result<ExportFiles, String> {
+    let mut files = ExportFiles::new();
+    for dir in [root.to_path_buf(), root.join("resources")] {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if !path.is_file() {
+                continue;
+            }
+            let relative = path.strip_prefix(root).unwrap_or(&path).to_string_lossy().replace('\\', "/");
+            let data = fs::read(&path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+            files.insert(relative, data);
+        }
+    }
+    if !files.keys().any(|name| name.ends_with(".md")) {
+        return Err(format!("{} does not contain a Joplin export", root.display()));
+    }
+    Ok(files)
+}
+
+struct JoplinItem {
+    title: String,
+    body: String,
+    fields: HashMap<String, String>,
+}
+
+impl JoplinItem {
+    /// Items are the title, a blank line, the body and a `key: value` footer;
+    /// items without a title (e.g. note-tag links) are only the footer
+    fn parse(text: &str) -> Option<Self> {
+        let text = text.replace("\r\n", "\n");
+        let (head, footer) = text.trim_end().rsplit_once("\n\n").unwrap_or(("", text.trim()));
+        let mut fields = HashMap::new();
+        for line in footer.lines() {
+            let (key, value) = line.split_once(':')?;
+            if key.is_empty() || !key.chars().all(|c| c.is_ascii_lowercase() || c == '_') {
+                return None;
+            }
+            fields.insert(key.to_string(), value.trim().to_string());
+        }
+        let (title, body) = head.split_once("\n\n").unwrap_or((head, ""));
+        Some(Self {
+            title: title.trim().to_string(),
+            body: body.to_string(),
+            fields,
+        })
+    }
+
+    fn field(&self, key: &str) -> &str {
+        self.fields.get(key).map(String::as_str).unwrap_or_default()
+    }
+
+    fn time(&self, keys: &[&str]) -> Option<DateTime<Utc>> {
+        keys.iter().find_map(|key| parse_rfc3339(self.field(key)))
+    }
+}
+
+fn read_joplin(files: &ExportFiles, plan: &mut ImportPlan) {
+    let mut items: Vec<JoplinItem> = Vec::new();
+    let mut names: Vec<&String> = files.keys().filter(|name| name.ends_with(".md") && !name.contains('/')).collect();
+    names.sort();
+    for name in names {
+        match JoplinItem::parse(&String::from_utf8_lossy(&files[name])) {
+            Some(item) => items.push(item),
+            None => plan.warnings.push(format!("{}: not a Joplin item", name)),
+        }
+    }
+
+    let by_type = |kind: &'static str| items.iter().filter(move |item| item.field("type_") == kind);
+    let folders: HashMap<&str, &JoplinItem> = by_type("2").map(|item| (item.field("id"), item)).collect();
+    let tags: HashMap<&str, &str> = by_type("5").map(|item| (item.field("id"), item.title.as_str())).collect();
+    let resources: HashMap<&str, &JoplinItem> = by_type("4").map(|item| (item.field("id"), item)).collect();
+    let mut note_tags: HashMap<&str, Vec<String>> = HashMap::new();
+    for link in by_type("6") {
+        if let Some(tag) = tags.get(link.field("tag_id")) {
+            note_tags.entry(link.field("note_id")).or_default().push(tag.to_string());
+        }
+    }
+
+    for item in by_type("1") {
+        if !item.field("deleted_time").is_empty() && item.field("deleted_time") != "0" {
+            continue;
+        }
+        let mut imported = ImportedNote::new(Note::new(item.title.clone(), item.body.clone()));
+
+        // Resources are linked as `:/<resource id>`
+        for (id, resource) in &resources {
+            let reference = format!("(:/{})", id);
+            if !imported.note.content.contains(&reference) {
+                continue;
+            }
+            let extension = resource.field("file_extension");
+            let path = if extension.is_empty() {
+                format!("resources/{}", id)
+            } else {
+                format!("resources/{}.{}", id, extension)
+            };
+            let Some(data) = files.get(&path) else {
+                plan.warnings.push(format!("{}: missing resource {}", item.title, resource.title));
+                continue;
+            };
+            let url = imported.add_file(resource.title.clone(), data.clone());
+            imported.note.content = imported.note.content.replace(&reference, &format!("({})", url));
+        }
+
+        // The top-level notebook becomes the notebook, nested ones become tags
+        let mut chain = Vec::new();
+        let mut parent = item.field("parent_id");
+        while let Some(folder) = folders.get(parent) {
+            if chain.len() > 32 {
+                break;
+            }
+            chain.push(folder.title.clone());
+            parent = folder.field("parent_id");
+        }
+        chain.reverse();
+
+        let note = &mut imported.note;
+        let mut chain = chain.into_iter();
+        note.notebook = chain.next();
+        note.tags = chain.chain(note_tags.remove(item.field("id")).unwrap_or_default()).collect();
+        note.tags.dedup();
+        note.location = location(item.field("latitude").parse().ok(), item.field("longitude").parse().ok());
+        if let Some(created) = item.time(&["user_created_time", "created_time"]) {
+            note.created_at = created;
+        }
+        if let Some(updated) = item.time(&["user_updated_time", "updated_time"]) {
+            note.modified_at = updated;
+        }
+        plan.notes.push(imported);
+    }
+}
+
+// --- Standard Notes ---
+
+fn read_standard_notes(source: &str, plan: &mut ImportPlan) -> Result<(), String> {
+    let backup: Value = serde_json::from_str(source).map_err(|e| format!("Failed to parse backup: {}", e))?;
+    let items = backup["items"].as_array().ok_or("Not a Standard Notes backup")?;
+    if items.iter().any(|item| item["content"].is_string()) {
+        return Err("This backup is encrypted; export a decrypted backup from Standard Notes".to_string());
+    }
+
+    let mut tags: HashMap<&str, Vec<String>> = HashMap::new();
+    for tag in items.iter().filter(|item| item["content_type"] == "Tag") {
+        let Some(title) = tag["content"]["title"].as_str() else {
+            continue;
+        };
+        for reference in tag["content"]["references"].as_array().into_iter().flatten() {
+            if let Some(uuid) = reference["uuid"].as_str() {
+                tags.entry(uuid).or_default().push(title.to_string());
+            }
+        }
+    }
+
+    for item in items.iter().filter(|item| item["content_type"] == "Note") {
+        let content = &item["content"];
+        if item["deleted"] == true || content["trashed"] == true {
+            continue;
+        }
+        let title = content["title"].as_str().unwrap_or_default();
+        let text = content["text"].as_str().unwrap_or_default();
+        let mut note = Note::new(title.to_string(), text.to_string());
+        if let Some(uuid) = item["uuid"].as_str() {
+            note.tags = tags.remove(uuid).unwrap_or_default();
+        }
+        let app_data = &content["appData"]["org.standardnotes.sn"];
+        note.pinned = app_data["pinned"] == true;
+        note.archived = app_data["archived"] == true;
+        if let Some(created) = item["created_at"].as_str().and_then(parse_rfc3339) {
+            note.created_at = created;
+        }
+        let updated = app_data["client_updated_at"].as_str().or(item["updated_at"].as_str());
+        if let Some(updated) = updated.and_then(parse_rfc3339) {
+            note.modified_at = updated;
+        }
+        plan.notes.push(ImportedNote::new(note));
+    }
+    Ok(())
+}
+
+// --- Google Keep ---
+
+fn read_google_keep(path: &Path, plan: &mut ImportPlan) -> Result<(), String> {
+    let mut files = Vec::new();
+    if path.is_dir() {
+        collect_json_files(path, &mut files)?;
+    } else {
+        files.push(path.to_path_buf());
+    }
+    files.sort();
+
+    for file in files {
+        let name = file.display().to_string();
+        let keep: Value = match read_text(&file).and_then(|text| {
+            serde_json::from_str(&text).map_err(|e| format!("Failed to parse {}: {}", name, e))
+        }) {
+            Ok(keep) => keep,
+            Err(e) => {
+                plan.warnings.push(e);
+                continue;
+            }
+        };
+        if keep.get("textContent").is_none() && keep.get("listContent").is_none() {
+            plan.warnings.push(format!("{}: not a Keep note", name));
+            continue;
+        }
+        if keep["isTrashed"] == true {
+            continue;
+        }
+        let base = file.parent().unwrap_or(path);
+        plan.notes.push(keep_note(&keep, base, &mut plan.warnings));
+    }
+    Ok(())
+}
+
+fn collect_json_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), String> {
+    let entries = fs::read_dir(dir).map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_json_files(&path, files)?;
+        } else if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("json")) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn keep_note(keep: &Value, base: &Path, warnings: &mut Vec<String>) -> ImportedNote {
+    let title = keep["title"].as_str().unwrap_or_default().to_string();
+    let mut content = keep["textContent"].as_str().unwrap_or_default().to_string();
+    for item in keep["listContent"].as_array().into_iter().flatten() {
+        let checked = if item["isChecked"] == true { "x" } else { " " };
+        content.push_str(&format!("- [{}] {}\n", checked, item["text"].as_str().unwrap_or_default()));
+    }
+    for annotation in keep["annotations"].as_array().into_iter().flatten() {
+        if let Some(url) = annotation["url"].as_str() {
+            let label = annotation["title"].as_str().filter(|title| !title.is_empty()).unwrap_or(url);
+            content.push_str(&format!("\n[{}]({})\n", label.replace(['[', ']'], ""), url));
+        }
+    }
+
+    let fallback = || content.lines().next().unwrap_or("Keep note").chars().take(60).collect::<String>();
+    let title = if title.trim().is_empty() { fallback() } else { title };
+    let mut imported = ImportedNote::new(Note::new(title, content.clone()));
+
+    // Takeout sometimes lists `.jpg` for files saved as `.jpeg`
+    for attachment in keep["attachments"].as_array().into_iter().flatten() {
+        let Some(file_path) = attachment["filePath"].as_str() else {
+            continue;
+        };
+        let mut candidates = vec![base.join(file_path)];
+        if let Some(stem) = file_path.strip_suffix(".jpg") {
+            candidates.push(base.join(format!("{}.jpeg", stem)));
+        }
+        match candidates.iter().find_map(|candidate| fs::read(candidate).ok()) {
+            Some(data) => {
+                let link = imported.link(file_path.to_string(), data);
+                imported.note.content.push_str(&format!("\n{}\n", link));
+            }
+            None => warnings.push(format!("{}: missing attachment {}", imported.note.title, file_path)),
+        }
+    }
+
+    let note = &mut imported.note;
+    note.tags = keep["labels"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|label| label["name"].as_str().map(str::to_string))
+        .collect();
+    note.pinned = keep["isPinned"] == true;
+    note.archived = keep["isArchived"] == true;
+    let timestamp = |key: &str| keep[key].as_i64().and_then(DateTime::from_timestamp_micros);
+    if let Some(created) = timestamp("createdTimestampUsec") {
+        note.created_at = created;
+    }
+    if let Some(updated) = timestamp("userEditedTimestampUsec") {
+        note.modified_at = updated;
+    }
+    imported
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attachments::BlobKeys;
+
+    #[test]
+    fn enex_notes_keep_metadata_and_embedded_images() {
+        let png = b"fake png bytes";
+        let hash = hex::encode(Md5::digest(png));
+        let enex = format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<en-export><note><title>Lisbon &amp; Porto</title>
+<content><![CDATA[<?xml version="1.0"?><en-note><div>Day one</div><en-media type="image/png" hash="{hash}"/></en-note>]]></content>
+<created>20230412T081500Z</created><updated>20230413T090000Z</updated>
+<tag>travel</tag><tag>2023</tag>
+<note-attributes><latitude>38.7223</latitude><longitude>-9.1393</longitude></note-attributes>
+<resource><data encoding="base64">{data}</data><mime>image/png</mime>
+<resource-attributes><file-name>tram.png</file-name></resource-attributes></resource>
+</note></en-export>"#,
+            hash = hash,
+            data = crypto::encode_base64(png),
+        );
+        let mut plan = ImportPlan {
+            format: ImportFormat::Evernote,
+            source: PathBuf::new(),
+            notes: Vec::new(),
+            warnings: Vec::new(),
+        };
+        read_enex(&enex, &mut plan).unwrap();
+
+        let imported = &plan.notes[0];
+        assert_eq!(imported.note.title, "Lisbon & Porto");
+        assert_eq!(imported.note.content, "Day one\n\n![tram.png](import-resource:0)\n");
+        assert_eq!(imported.note.tags, vec!["travel", "2023"]);
+        assert_eq!(imported.note.location.as_ref().map(|l| l.latitude), Some(38.7223));
+        assert_eq!(imported.note.created_at.to_rfc3339(), "2023-04-12T08:15:00+00:00");
+        assert_eq!(imported.files[0].data, png);
+
+        let dir = tempfile::tempdir().unwrap();
+        let store = BlobStore::new(dir.path().to_path_buf(), &BlobKeys::generate()).unwrap();
+        let notes = plan.into_notes(false, &store).unwrap();
+        let blob_id = &notes[0].attachments[0].blob_id;
+        assert!(notes[0].content.contains(&format!("(attachment:{})", blob_id)));
+        assert_eq!(store.read(blob_id).unwrap(), png);
+    }
+
+    #[test]
+    fn joplin_raw_export_maps_folders_tags_and_resources() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir(root.join("resources")).unwrap();
+        let write = |name: &str, text: &str| fs::write(root.join(name), text).unwrap();
+        write("f1.md", "Work\n\nid: f1\nparent_id: \ntype_: 2");
+        write("f2.md", "Clients\n\nid: f2\nparent_id: f1\ntype_: 2");
+        write("t1.md", "urgent\n\nid: t1\ntype_: 5");
+        write("nt.md", "id: nt\nnote_id: n1\ntag_id: t1\ntype_: 6");
+        write("r1.md", "logo.png\n\nid: r1\nfile_extension: png\ntype_: 4");
+        write(
+            "n1.md",
+            "Acme\n\nCall back ![logo](:/r1)\n\nid: n1\nparent_id: f2\n\
+             created_time: 2021-02-03T04:05:06.000Z\nupdated_time: 2021-02-04T04:05:06.000Z\n\
+             latitude: 0.00000000\nlongitude: 0.00000000\ntype_: 1",
+        );
+        fs::write(root.join("resources/r1.png"), b"logo").unwrap();
+
+        let plan = read(ImportFormat::JoplinRaw, root).unwrap();
+        assert!(plan.warnings.is_empty());
+        let note = &plan.notes[0].note;
+        assert_eq!(note.title, "Acme");
+        assert_eq!(note.content, "Call back ![logo](import-resource:0)");
+        assert_eq!(note.notebook.as_deref(), Some("Work"));
+        assert_eq!(note.tags, vec!["Clients", "urgent"]);
+        assert!(note.location.is_none());
+        assert_eq!(plan.notes[0].files[0].file_name, "logo.png");
+    }
+
+    #[test]
+    fn duplicates_are_detected_against_the_vault_and_the_batch() {
+        let backup = r#"{"items": [
+            {"uuid": "a", "content_type": "Note", "created_at": "2022-01-01T10:00:00Z", "updated_at": "2022-01-02T10:00:00Z",
+             "content": {"title": "Groceries", "text": "milk\neggs", "references": []}},
+            {"uuid": "b", "content_type": "Note", "created_at": "2022-03-01T10:00:00Z", "updated_at": "2022-03-01T10:00:00Z",
+             "content": {"title": "groceries", "text": "milk  eggs", "references": []}},
+            {"uuid": "c", "content_type": "Note", "created_at": "2022-03-05T10:00:00Z", "updated_at": "2022-03-05T10:00:00Z",
+             "content": {"title": "Ideas", "text": "new", "appData": {"org.standardnotes.sn": {"pinned": true}}}},
+            {"uuid": "t", "content_type": "Tag", "content": {"title": "home", "references": [{"uuid": "c", "content_type": "Note"}]}}
+        ]}"#;
+        let mut plan = ImportPlan {
+            format: ImportFormat::StandardNotes,
+            source: PathBuf::new(),
+            notes: Vec::new(),
+            warnings: Vec::new(),
+        };
+        read_standard_notes(backup, &mut plan).unwrap();
+        assert_eq!(plan.notes.len(), 3);
+        assert!(plan.notes[2].note.pinned);
+        assert_eq!(plan.notes[2].note.tags, vec!["home"]);
+
+        let mut existing = Note::new("Ideas".to_string(), "Different text".to_string());
+        existing.created_at = parse_rfc3339("2022-03-05T10:00:00Z").unwrap();
+        plan.detect_duplicates(&[&existing]);
+
+        assert_eq!(plan.notes[0].duplicate_of, None);
+        assert_eq!(plan.notes[1].duplicate_of.as_deref(), Some("an earlier note in this import"));
+        assert_eq!(plan.notes[2].duplicate_of.as_deref(), Some("Ideas"));
+        assert_eq!(plan.duplicate_count(), 2);
+
+        let encrypted = r#"{"items": [{"uuid": "x", "content_type": "Note", "content": "004:abc"}]}"#;
+        assert!(read_standard_notes(encrypted, &mut plan).is_err());
+    }
+}
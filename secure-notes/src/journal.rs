@@ -0,0 +1,75 @@
+use crate::note::Note;
+use crate::templates::{self, NoteTemplate};
+use chrono::{Local, NaiveDate, TimeZone};
+use serde::{Deserialize, Serialize};
+
+/// Journal preferences, stored in the vault
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct JournalSettings {
+    /// strftime pattern for entry titles
+    pub title_format: String,
+    pub template_id: Option<String>,
+}
+
+impl Default for JournalSettings {
+    fn default() -> Self {
+        Self {
+            title_format: "%A, %Y-%m-%d".to_string(),
+            template_id: None,
+        }
+    }
+}
+
+impl JournalSettings {
+    pub fn title_for(&self, date: NaiveDate) -> String {
+        templates::format_date(local_noon(date), &self.title_format)
+    }
+
+    /// Creates the journal entry for a day, starting from the configured template
+    pub fn create_entry(&self, date: NaiveDate, template: Option<&NoteTemplate>) -> Note {
+        let mut note = match template {
+            Some(template) => template.instantiate(local_noon(date)),
+            None => Note::new(String::new(), format!("# {}\n\n", self.title_for(date))),
+        };
+        note.title = self.title_for(date);
+        note.journal_date = Some(date);
+        if !note.tags.iter().any(|tag| tag == "journal") {
+            note.tags.push("journal".to_string());
+        }
+        note
+    }
+}
+
+fn local_noon(date: NaiveDate) -> chrono::DateTime<Local> {
+    let noon = date.and_hms_opt(12, 0, 0).unwrap_or_default();
+    Local
+        .from_local_datetime(&noon)
+        .earliest()
+        .unwrap_or_else(Local::now)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_use_title_format_and_template() {
+        let date = NaiveDate::from_ymd_opt(2026, 3, 5).unwrap();
+        let settings = JournalSettings {
+            title_format: "Journal %d.%m.%Y".to_string(),
+            template_id: None,
+        };
+
+        let plain = settings.create_entry(date, None);
+        assert_eq!(plain.title, "Journal 05.03.2026");
+        assert_eq!(plain.journal_date, Some(date));
+        assert_eq!(plain.tags, vec!["journal".to_string()]);
+
+        let template = &templates::built_in()[1];
+        let from_template = settings.create_entry(date, Some(template));
+        assert_eq!(from_template.title, "Journal 05.03.2026");
+        assert!(from_template.content.starts_with("# Thursday, 2026-03-05"));
+        assert_eq!(from_template.tags, vec!["travel".to_string(), "journal".to_string()]);
+    }
+}
@@ -1,14 +1,57 @@
+mod agent;
+mod age_files;
+mod api;
+mod attachments;
+mod bundle;
+mod calendar;
+mod cli;
+mod crdt;
 mod crypto;
+mod editor;
+mod git_history;
+mod highlight;
+mod html_export;
+mod html_import;
+mod images;
+mod importers;
+mod journal;
 mod note;
+mod pdf_export;
+mod properties;
+mod reminders;
+mod settings;
+mod sharing;
+mod sync;
 mod storage;
+mod tasks;
+mod templates;
 mod ui;
+mod webdav;
 mod map;
+mod markdown;
+mod markdown_folder;
 mod tile_loader;
 
+use clap::Parser;
 use eframe::egui;
+use std::process::ExitCode;
 use ui::NotesApp;
 
-fn main() -> Result<(), eframe::Error> {
+fn main() -> ExitCode {
+    let cli = cli::Cli::parse();
+    if cli.command.is_some() {
+        return cli::run(cli);
+    }
+    match run_app() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Failed to start Secure Notes: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run_app() -> Result<(), eframe::Error> {
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
             .with_inner_size([1280.0, 800.0])
@@ -0,0 +1,783 @@
+use crate::highlight;
+use crate::images::ImageCache;
+use eframe::egui;
+use egui::util::cache::{ComputerMut, FrameCache};
+use pulldown_cmark::{Alignment, CodeBlockKind, Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+use std::sync::Arc;
+
+/// Parser options used everywhere note Markdown is interpreted (CommonMark + GFM)
+pub fn parser_options() -> Options {
+    Options::ENABLE_TABLES
+        | Options::ENABLE_FOOTNOTES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS
+        | Options::ENABLE_GFM
+}
+
+/// Maps byte offsets in a text to zero-based line numbers
+pub struct LineIndex {
+    starts: Vec<usize>,
+}
+
+impl LineIndex {
+    pub fn new(text: &str) -> Self {
+        let starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(index, _)| index + 1))
+            .collect();
+        Self { starts }
+    }
+
+    /// The line containing the byte at `offset`
+    pub fn line(&self, offset: usize) -> usize {
+        self.starts.partition_point(|&start| start <= offset) - 1
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.starts.len()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SpanStyle {
+    pub strong: bool,
+    pub emphasis: bool,
+    pub strikethrough: bool,
+    pub code: bool,
+    pub link: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Inline {
+    Text { text: String, style: SpanStyle },
+    Image { url: String, title: String, alt: String },
+    FootnoteReference(String),
+    LineBreak,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListItem {
+    pub checked: Option<bool>,
+    pub line: usize,
+    pub blocks: Vec<Block>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Block {
+    Paragraph(Vec<Inline>),
+    Heading { level: u8, content: Vec<Inline> },
+    Code { language: Option<String>, code: String },
+    Quote(Vec<Block>),
+    List { start: Option<u64>, items: Vec<ListItem> },
+    Table {
+        alignments: Vec<Alignment>,
+        header: Vec<Vec<Inline>>,
+        rows: Vec<Vec<Vec<Inline>>>,
+    },
+    Rule,
+    Html(String),
+    FootnoteDefinition { label: String, blocks: Vec<Block> },
+}
+
+/// A parsed note, with the source line each top-level block starts on
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Document {
+    pub blocks: Vec<Block>,
+    pub lines: Vec<usize>,
+    pub line_count: usize,
+}
+
+/// Parses Markdown into a block tree by walking the `pulldown_cmark` event stream
+pub fn parse(source: &str) -> Document {
+    let lines = LineIndex::new(source);
+    let mut builder = TreeBuilder::default();
+    for (event, range) in Parser::new_ext(source, parser_options()).into_offset_iter() {
+        builder.line = lines.line(range.start);
+        if builder.at_root() && starts_block(&event) {
+            builder.root_line = builder.line;
+        }
+        builder.handle(event);
+    }
+
+    let mut document = builder.finish();
+    document.line_count = lines.line_count();
+    document
+}
+
+fn starts_block(event: &Event<'_>) -> bool {
+    match event {
+        Event::Start(tag) => !matches!(
+            tag,
+            Tag::Emphasis
+                | Tag::Strong
+                | Tag::Strikethrough
+                | Tag::Link { .. }
+                | Tag::Image { .. }
+        ),
+        Event::Rule | Event::Html(_) => true,
+        _ => false,
+    }
+}
+
+enum Container {
+    Root(Vec<Block>),
+    Quote(Vec<Block>),
+    List { start: Option<u64>, items: Vec<ListItem> },
+    Item { checked: Option<bool>, line: usize, blocks: Vec<Block> },
+    Footnote { label: String, blocks: Vec<Block> },
+    Table {
+        alignments: Vec<Alignment>,
+        header: Vec<Vec<Inline>>,
+        rows: Vec<Vec<Vec<Inline>>>,
+        row: Vec<Vec<Inline>>,
+    },
+}
+
+#[derive(Clone, Copy)]
+enum InlineTarget {
+    Paragraph,
+    Heading(u8),
+    TableCell,
+}
+
+struct TreeBuilder {
+    stack: Vec<Container>,
+    inline: Option<(InlineTarget, Vec<Inline>)>,
+    code: Option<(Option<String>, String)>,
+    html: Option<String>,
+    image: Option<(String, String, String)>,
+    strong: usize,
+    emphasis: usize,
+    strikethrough: usize,
+    links: Vec<String>,
+    line: usize,
+    root_line: usize,
+    lines: Vec<usize>,
+}
+
+impl Default for TreeBuilder {
+    fn default() -> Self {
+        Self {
+            stack: vec![Container::Root(Vec::new())],
+            inline: None,
+            code: None,
+            html: None,
+            image: None,
+            strong: 0,
+            emphasis: 0,
+            strikethrough: 0,
+            links: Vec::new(),
+            line: 0,
+            root_line: 0,
+            lines: Vec::new(),
+        }
+    }
+}
+
+impl TreeBuilder {
+    fn handle(&mut self, event: Event<'_>) {
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(text) => {
+                if let Some((_, code)) = &mut self.code {
+                    code.push_str(&text);
+                } else if let Some(html) = &mut self.html {
+                    html.push_str(&text);
+                } else if let Some((_, _, alt)) = &mut self.image {
+                    alt.push_str(&text);
+                } else {
+                    self.push_text(&text, false);
+                }
+            }
+            Event::Code(text) => self.push_text(&text, true),
+            Event::InlineMath(text) | Event::DisplayMath(text) => self.push_text(&text, true),
+            Event::Html(html) => {
+                if let Some(buffer) = &mut self.html {
+                    buffer.push_str(&html);
+                } else {
+                    self.push_block(Block::Html(html.to_string()));
+                }
+            }
+            Event::InlineHtml(html) => self.push_text(&html, true),
+            Event::FootnoteReference(label) => {
+                self.push_inline(Inline::FootnoteReference(label.to_string()));
+            }
+            Event::SoftBreak => self.push_text(" ", false),
+            Event::HardBreak => self.push_inline(Inline::LineBreak),
+            Event::Rule => self.push_block(Block::Rule),
+            Event::TaskListMarker(checked) => {
+                if let Some(Container::Item { checked: slot, .. }) = self.stack.last_mut() {
+                    *slot = Some(checked);
+                }
+            }
+        }
+    }
+
+    fn start(&mut self, tag: Tag<'_>) {
+        match tag {
+            Tag::Paragraph => self.begin_inline(InlineTarget::Paragraph),
+            Tag::Heading { level, .. } => self.begin_inline(InlineTarget::Heading(heading_level(level))),
+            Tag::BlockQuote(_) => {
+                self.flush_inline();
+                self.stack.push(Container::Quote(Vec::new()));
+            }
+            Tag::CodeBlock(kind) => {
+                self.flush_inline();
+                let language = match kind {
+                    CodeBlockKind::Fenced(info) => info
+                        .split_whitespace()
+                        .next()
+                        .map(|lang| lang.to_string()),
+                    CodeBlockKind::Indented => None,
+                };
+                self.code = Some((language, String::new()));
+            }
+            Tag::HtmlBlock => {
+                self.flush_inline();
+                self.html = Some(String::new());
+            }
+            Tag::List(start) => {
+                self.flush_inline();
+                self.stack.push(Container::List { start, items: Vec::new() });
+            }
+            Tag::Item => {
+                self.flush_inline();
+                self.stack.push(Container::Item {
+                    checked: None,
+                    line: self.line,
+                    blocks: Vec::new(),
+                });
+            }
+            Tag::FootnoteDefinition(label) => {
+                self.flush_inline();
+                self.stack.push(Container::Footnote {
+                    label: label.to_string(),
+                    blocks: Vec::new(),
+                });
+            }
+            Tag::Table(alignments) => {
+                self.flush_inline();
+                self.stack.push(Container::Table {
+                    alignments,
+                    header: Vec::new(),
+                    rows: Vec::new(),
+                    row: Vec::new(),
+                });
+            }
+            Tag::TableHead | Tag::TableRow => {}
+            Tag::TableCell => self.inline = Some((InlineTarget::TableCell, Vec::new())),
+            Tag::Emphasis => self.emphasis += 1,
+            Tag::Strong => self.strong += 1,
+            Tag::Strikethrough => self.strikethrough += 1,
+            Tag::Link { dest_url, .. } => self.links.push(dest_url.to_string()),
+            Tag::Image { dest_url, title, .. } => {
+                self.image = Some((dest_url.to_string(), title.to_string(), String::new()));
+            }
+            Tag::MetadataBlock(_) => {}
+        }
+    }
+
+    fn end(&mut self, tag: TagEnd) {
+        match tag {
+            TagEnd::Paragraph | TagEnd::Heading(_) => self.flush_inline(),
+            TagEnd::BlockQuote => {
+                self.flush_inline();
+                if let Some(Container::Quote(blocks)) = self.stack.pop() {
+                    self.push_block(Block::Quote(blocks));
+                }
+            }
+            TagEnd::CodeBlock => {
+                if let Some((language, code)) = self.code.take() {
+                    self.push_block(Block::Code { language, code });
+                }
+            }
+            TagEnd::HtmlBlock => {
+                if let Some(html) = self.html.take() {
+                    self.push_block(Block::Html(html));
+                }
+            }
+            TagEnd::List(_) => {
+                self.flush_inline();
+                if let Some(Container::List { start, items }) = self.stack.pop() {
+                    self.push_block(Block::List { start, items });
+                }
+            }
+            TagEnd::Item => {
+                self.flush_inline();
+                if let Some(Container::Item { checked, line, blocks }) = self.stack.pop() {
+                    if let Some(Container::List { items, .. }) = self.stack.last_mut() {
+                        items.push(ListItem { checked, line, blocks });
+                    }
+                }
+            }
+            TagEnd::FootnoteDefinition => {
+                self.flush_inline();
+                if let Some(Container::Footnote { label, blocks }) = self.stack.pop() {
+                    self.push_block(Block::FootnoteDefinition { label, blocks });
+                }
+            }
+            TagEnd::Table => {
+                if let Some(Container::Table { alignments, header, rows, .. }) = self.stack.pop() {
+                    self.push_block(Block::Table { alignments, header, rows });
+                }
+            }
+            TagEnd::TableHead => {
+                if let Some(Container::Table { header, row, .. }) = self.stack.last_mut() {
+                    *header = std::mem::take(row);
+                }
+            }
+            TagEnd::TableRow => {
+                if let Some(Container::Table { rows, row, .. }) = self.stack.last_mut() {
+                    rows.push(std::mem::take(row));
+                }
+            }
+            TagEnd::TableCell => {
+                let cell = self.inline.take().map(|(_, inlines)| inlines).unwrap_or_default();
+                if let Some(Container::Table { row, .. }) = self.stack.last_mut() {
+                    row.push(cell);
+                }
+            }
+            TagEnd::Emphasis => self.emphasis = self.emphasis.saturating_sub(1),
+            TagEnd::Strong => self.strong = self.strong.saturating_sub(1),
+            TagEnd::Strikethrough => self.strikethrough = self.strikethrough.saturating_sub(1),
+            TagEnd::Link => {
+                self.links.pop();
+            }
+            TagEnd::Image => {
+                if let Some((url, title, alt)) = self.image.take() {
+                    self.push_inline(Inline::Image { url, title, alt });
+                }
+            }
+            TagEnd::MetadataBlock(_) => {}
+        }
+    }
+
+    fn begin_inline(&mut self, target: InlineTarget) {
+        self.flush_inline();
+        self.inline = Some((target, Vec::new()));
+    }
+
+    /// Closes the open paragraph or heading, including the implicit
+    /// paragraphs that tight list items produce.
+    fn flush_inline(&mut self) {
+        let Some((target, content)) = self.inline.take() else {
+            return;
+        };
+        match target {
+            InlineTarget::Paragraph => self.push_block(Block::Paragraph(content)),
+            InlineTarget::Heading(level) => self.push_block(Block::Heading { level, content }),
+            InlineTarget::TableCell => self.inline = Some((target, content)),
+        }
+    }
+
+    fn push_text(&mut self, text: &str, code: bool) {
+        let style = SpanStyle {
+            strong: self.strong > 0,
+            emphasis: self.emphasis > 0,
+            strikethrough: self.strikethrough > 0,
+            code,
+            link: self.links.last().cloned(),
+        };
+
+        // Merge with the previous span when the style is unchanged
+        if let Some((_, inlines)) = &mut self.inline {
+            if let Some(Inline::Text { text: previous, style: previous_style }) = inlines.last_mut() {
+                if *previous_style == style {
+                    previous.push_str(text);
+                    return;
+                }
+            }
+        }
+        self.push_inline(Inline::Text { text: text.to_string(), style });
+    }
+
+    fn push_inline(&mut self, inline: Inline) {
+        if self.inline.is_none() {
+            self.inline = Some((InlineTarget::Paragraph, Vec::new()));
+        }
+        if let Some((_, inlines)) = &mut self.inline {
+            inlines.push(inline);
+        }
+    }
+
+    fn at_root(&self) -> bool {
+        self.stack.len() == 1 && self.inline.is_none() && self.code.is_none() && self.html.is_none()
+    }
+
+    fn push_block(&mut self, block: Block) {
+        for container in self.stack.iter_mut().rev() {
+            match container {
+                Container::Root(blocks) => {
+                    blocks.push(block);
+                    self.lines.push(self.root_line);
+                    return;
+                }
+                Container::Quote(blocks)
+                | Container::Item { blocks, .. }
+                | Container::Footnote { blocks, .. } => {
+                    blocks.push(block);
+                    return;
+                }
+                Container::List { .. } | Container::Table { .. } => {}
+            }
+        }
+    }
+
+    fn finish(mut self) -> Document {
+        self.flush_inline();
+        match self.stack.into_iter().next() {
+            Some(Container::Root(blocks)) => Document {
+                blocks,
+                lines: self.lines,
+                line_count: 0,
+            },
+            _ => Document::default(),
+        }
+    }
+}
+
+fn heading_level(level: HeadingLevel) -> u8 {
+    match level {
+        HeadingLevel::H1 => 1,
+        HeadingLevel::H2 => 2,
+        HeadingLevel::H3 => 3,
+        HeadingLevel::H4 => 4,
+        HeadingLevel::H5 => 5,
+        HeadingLevel::H6 => 6,
+    }
+}
+
+#[derive(Default)]
+struct DocumentParser;
+
+impl ComputerMut<&str, Arc<Document>> for DocumentParser {
+    fn compute(&mut self, source: &str) -> Arc<Document> {
+        Arc::new(parse(source))
+    }
+}
+
+type DocumentCache = FrameCache<Arc<Document>, DocumentParser>;
+
+/// Vertical offsets of rendered top-level blocks, keyed by their source line
+#[derive(Debug, Clone, Default)]
+pub struct BlockPositions(Vec<(usize, f32)>);
+
+impl BlockPositions {
+    /// Content offset at which the (fractional) source line is rendered
+    pub fn offset_for_line(&self, line: f32) -> Option<f32> {
+        let index = self.0.iter().rposition(|&(start, _)| start as f32 <= line)?;
+        let (start, top) = self.0[index];
+        Some(match self.0.get(index + 1) {
+            Some(&(next, bottom)) if next > start => {
+                top + (bottom - top) * ((line - start as f32) / (next - start) as f32).min(1.0)
+            }
+            _ => top,
+        })
+    }
+
+    /// Fractional source line rendered at the given content offset
+    pub fn line_for_offset(&self, offset: f32) -> Option<f32> {
+        let index = self.0.iter().rposition(|&(_, top)| top <= offset).unwrap_or(0);
+        let &(start, top) = self.0.get(index)?;
+        Some(match self.0.get(index + 1) {
+            Some(&(next, bottom)) if bottom > top => {
+                start as f32 + (next - start) as f32 * ((offset - top) / (bottom - top)).clamp(0.0, 1.0)
+            }
+            _ => start as f32,
+        })
+    }
+}
+
+pub struct RenderOutput {
+    pub positions: BlockPositions,
+    /// Source line of a task list item whose checkbox was clicked this frame
+    pub toggled_task: Option<usize>,
+}
+
+/// Renders Markdown source as egui widgets
+pub fn show(ui: &mut egui::Ui, source: &str, images: &mut ImageCache) -> RenderOutput {
+    let document = ui
+        .ctx()
+        .memory_mut(|mem| mem.caches.cache::<DocumentCache>().get(source));
+
+    let top = ui.cursor().top();
+    let mut positions = Vec::with_capacity(document.blocks.len() + 1);
+    let mut renderer = Renderer {
+        table_count: 0,
+        toggled_task: None,
+        images,
+    };
+    for (block, &line) in document.blocks.iter().zip(&document.lines) {
+        positions.push((line, ui.cursor().top() - top));
+        renderer.block(ui, block, 0);
+    }
+    positions.push((document.line_count, ui.cursor().top() - top));
+
+    RenderOutput {
+        positions: BlockPositions(positions),
+        toggled_task: renderer.toggled_task,
+    }
+}
+
+struct Renderer<'a> {
+    table_count: usize,
+    toggled_task: Option<usize>,
+    images: &'a mut ImageCache,
+}
+
+impl Renderer<'_> {
+    fn blocks(&mut self, ui: &mut egui::Ui, blocks: &[Block], depth: usize) {
+        for block in blocks {
+            self.block(ui, block, depth);
+        }
+    }
+
+    fn block(&mut self, ui: &mut egui::Ui, block: &Block, depth: usize) {
+        match block {
+            Block::Paragraph(content) => self.inlines(ui, content, None),
+            Block::Heading { level, content } => {
+                ui.add_space(4.0);
+                self.inlines(ui, content, Some(heading_size(*level)));
+            }
+            Block::Code { language, code } => highlight::code_block(ui, language.as_deref(), code),
+            Block::Quote(children) => {
+                let response = egui::Frame::none()
+                    .inner_margin(egui::Margin { left: 12.0, right: 0.0, top: 2.0, bottom: 2.0 })
+                    .show(ui, |ui| self.blocks(ui, children, depth));
+                let rect = response.response.rect;
+                ui.painter().vline(
+                    rect.left() + 3.0,
+                    rect.y_range(),
+                    egui::Stroke::new(3.0, ui.visuals().weak_text_color()),
+                );
+            }
+            Block::List { start, items } => {
+                for (index, item) in items.iter().enumerate() {
+                    ui.horizontal_top(|ui| {
+                        ui.add_space(12.0 * depth as f32);
+                        match (item.checked, start) {
+                            (Some(checked), _) => {
+                                let mut checked = checked;
+                                if ui.add(egui::Checkbox::without_text(&mut checked)).changed() {
+                                    self.toggled_task = Some(item.line);
+                                }
+                            }
+                            (None, Some(first)) => {
+                                ui.label(format!("{}.", first + index as u64));
+                            }
+                            (None, None) => {
+                                ui.label(bullet(depth));
+                            }
+                        }
+                        ui.vertical(|ui| self.blocks(ui, &item.blocks, depth + 1));
+                    });
+                }
+            }
+            Block::Table { alignments, header, rows } => {
+                self.table_count += 1;
+                egui::Grid::new(ui.id().with(("markdown_table", self.table_count)))
+                    .striped(true)
+                    .spacing([16.0, 4.0])
+                    .show(ui, |ui| {
+                        for (column, cell) in header.iter().enumerate() {
+                            aligned(ui, alignments.get(column), |ui| {
+                                self.inlines(ui, &emphasised(cell), None)
+                            });
+                        }
+                        ui.end_row();
+                        for row in rows {
+                            for (column, cell) in row.iter().enumerate() {
+                                aligned(ui, alignments.get(column), |ui| self.inlines(ui, cell, None));
+                            }
+                            ui.end_row();
+                        }
+                    });
+            }
+            Block::Rule => {
+                ui.separator();
+            }
+            Block::Html(html) => {
+                ui.label(egui::RichText::new(html.trim_end()).monospace().weak());
+            }
+            Block::FootnoteDefinition { label, blocks } => {
+                ui.horizontal_top(|ui| {
+                    ui.label(egui::RichText::new(format!("[{}]", label)).small().weak());
+                    ui.vertical(|ui| self.blocks(ui, blocks, depth));
+                });
+            }
+        }
+    }
+
+    fn inlines(&mut self, ui: &mut egui::Ui, content: &[Inline], size: Option<f32>) {
+        ui.horizontal_wrapped(|ui| {
+            ui.spacing_mut().item_spacing.x = 0.0;
+            for inline in content {
+                match inline {
+                    Inline::Text { text, style } => {
+                        let mut rich = styled(text, style, ui.visuals());
+                        if let Some(size) = size {
+                            rich = rich.size(size).strong();
+                        }
+                        match &style.link {
+                            Some(url) => {
+                                ui.hyperlink_to(rich, url);
+                            }
+                            None => {
+                                ui.label(rich);
+                            }
+                        }
+                    }
+                    Inline::Image { url, alt, .. } => self.images.show_inline(ui, url, alt),
+                    Inline::FootnoteReference(label) => {
+                        ui.label(egui::RichText::new(format!("[{}]", label)).small().raised());
+                    }
+                    Inline::LineBreak => ui.end_row(),
+                }
+            }
+        });
+    }
+}
+
+fn styled(text: &str, style: &SpanStyle, visuals: &egui::Visuals) -> egui::RichText {
+    let mut rich = egui::RichText::new(text);
+    if style.code {
+        rich = rich.code();
+    }
+    if style.strong {
+        rich = rich.strong();
+    }
+    if style.emphasis {
+        rich = rich.italics();
+    }
+    if style.strikethrough {
+        rich = rich.strikethrough();
+    }
+    if style.link.is_some() {
+        rich = rich.color(visuals.hyperlink_color).underline();
+    }
+    rich
+}
+
+fn emphasised(cell: &[Inline]) -> Vec<Inline> {
+    cell.iter()
+        .cloned()
+        .map(|inline| match inline {
+            Inline::Text { text, mut style } => {
+                style.strong = true;
+                Inline::Text { text, style }
+            }
+            other => other,
+        })
+        .collect()
+}
+
+fn aligned(ui: &mut egui::Ui, alignment: Option<&Alignment>, add_contents: impl FnOnce(&mut egui::Ui)) {
+    let align = match alignment {
+        Some(Alignment::Center) => egui::Align::Center,
+        Some(Alignment::Right) => egui::Align::Max,
+        _ => egui::Align::Min,
+    };
+    ui.with_layout(egui::Layout::top_down(align), add_contents);
+}
+
+fn heading_size(level: u8) -> f32 {
+    match level {
+        1 => 28.0,
+        2 => 22.0,
+        3 => 18.0,
+        4 => 16.0,
+        _ => 14.0,
+    }
+}
+
+fn bullet(depth: usize) -> &'static str {
+    match depth % 3 {
+        0 => "•",
+        1 => "◦",
+        _ => "▪",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(content: &[Inline]) -> String {
+        content
+            .iter()
+            .filter_map(|inline| match inline {
+                Inline::Text { text, .. } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parses_inline_styles_and_links() {
+        let blocks = parse("Some **bold**, *italic* and [a link](https://example.com).").blocks;
+        let Block::Paragraph(content) = &blocks[0] else {
+            panic!("expected paragraph, got {:?}", blocks);
+        };
+        assert_eq!(text(content), "Some bold, italic and a link.");
+        assert!(content.iter().any(|inline| matches!(
+            inline,
+            Inline::Text { text, style } if text == "bold" && style.strong
+        )));
+        assert!(content.iter().any(|inline| matches!(
+            inline,
+            Inline::Text { style, .. } if style.link.as_deref() == Some("https://example.com")
+        )));
+    }
+
+    #[test]
+    fn parses_tight_task_lists_and_nested_blocks() {
+        let document = parse("1. first\n2. second\n\n- [x] done\n- [ ] todo\n\n> quoted\n");
+        assert_eq!(document.lines, vec![0, 3, 6]);
+        let blocks = document.blocks;
+        assert_eq!(blocks.len(), 3);
+
+        let Block::List { start: Some(1), items } = &blocks[0] else {
+            panic!("expected ordered list, got {:?}", blocks[0]);
+        };
+        assert_eq!(items.len(), 2);
+
+        let Block::List { start: None, items } = &blocks[1] else {
+            panic!("expected bullet list, got {:?}", blocks[1]);
+        };
+        assert_eq!(items[0].checked, Some(true));
+        assert_eq!(items[1].line, 4);
+        assert_eq!(items[1].checked, Some(false));
+        assert!(matches!(&items[1].blocks[0], Block::Paragraph(content) if text(content) == "todo"));
+
+        assert!(matches!(&blocks[2], Block::Quote(children) if children.len() == 1));
+    }
+
+    #[test]
+    fn parses_tables_and_code_blocks() {
+        let blocks = parse("| a | b |\n|---|--:|\n| 1 | 2 |\n\n```sh\necho hi\n```\n").blocks;
+        let Block::Table { alignments, header, rows } = &blocks[0] else {
+            panic!("expected table, got {:?}", blocks[0]);
+        };
+        assert_eq!(alignments, &vec![Alignment::None, Alignment::Right]);
+        assert_eq!(header.len(), 2);
+        assert_eq!(text(&rows[0][1]), "2");
+
+        assert_eq!(
+            blocks[1],
+            Block::Code {
+                language: Some("sh".to_string()),
+                code: "echo hi\n".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn block_positions_map_lines_to_offsets_and_back() {
+        let positions = BlockPositions(vec![(0, 0.0), (4, 100.0), (10, 160.0)]);
+        assert_eq!(positions.offset_for_line(2.0), Some(50.0));
+        assert_eq!(positions.offset_for_line(7.0), Some(130.0));
+        assert_eq!(positions.line_for_offset(50.0), Some(2.0));
+        assert_eq!(positions.line_for_offset(130.0), Some(7.0));
+        assert_eq!(positions.line_for_offset(500.0), Some(10.0));
+    }
+}
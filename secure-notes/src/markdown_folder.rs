@@ -0,0 +1,250 @@
+use crate::attachments::{Attachment, BlobStore};
+use crate::markdown;
+use crate::note::Note;
+use crate::properties;
+use pulldown_cmark::{Event, Parser, Tag};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Folder next to the exported notes that holds decrypted attachments
+pub const ATTACHMENTS_DIR: &str = "_attachments";
+
+pub struct FolderImport {
+    pub notes: Vec<Note>,
+    /// Files that could not be imported, with the reason
+    pub skipped: Vec<(PathBuf, String)>,
+}
+
+/// Reads a tree of Markdown files. The first folder level becomes the
+/// notebook and deeper folders become tags; frontmatter takes precedence.
+/// Local images and files linked from a note are encrypted into `blob_store`.
+pub fn import_folder(root: &Path, blob_store: &BlobStore) -> Result<FolderImport, String> {
+    let mut files = Vec::new();
+    collect_markdown_files(root, &mut files)?;
+    files.sort();
+
+    let mut import = FolderImport {
+        notes: Vec::new(),
+        skipped: Vec::new(),
+    };
+    for path in files {
+        match import_file(root, &path, blob_store) {
+            Ok(note) => import.notes.push(note),
+            Err(e) => import.skipped.push((path, e)),
+        }
+    }
+    Ok(import)
+}
+
+fn collect_markdown_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), String> {
+    let entries = fs::read_dir(dir).map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let hidden = entry.file_name().to_string_lossy().starts_with('.');
+        if hidden || entry.file_name() == ATTACHMENTS_DIR {
+            continue;
+        }
+        if path.is_dir() {
+            collect_markdown_files(&path, files)?;
+        } else if path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("md") || ext.eq_ignore_ascii_case("markdown"))
+        {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn import_file(root: &Path, path: &Path, blob_store: &BlobStore) -> Result<Note, String> {
+    let source = fs::read_to_string(path).map_err(|e| format!("Failed to read file: {}", e))?;
+    let title = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let mut note = properties::from_markdown(&source, &title)?;
+
+    let folders: Vec<String> = path
+        .parent()
+        .and_then(|parent| parent.strip_prefix(root).ok())
+        .map(|relative| {
+            relative
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy().into_owned())
+                .collect()
+        })
+        .unwrap_or_default();
+    if let Some((notebook, tags)) = folders.split_first() {
+        if note.notebook.is_none() {
+            note.notebook = Some(notebook.clone());
+        }
+        for tag in tags {
+            if !note.tags.contains(tag) {
+                note.tags.push(tag.clone());
+            }
+        }
+    }
+
+    let base = path.parent().unwrap_or(root);
+    attach_local_files(&mut note, base, |file| {
+        file.is_file().then(|| blob_store.add_file(file)).transpose()
+    })?;
+    Ok(note)
+}
+
+/// Replaces relative links with `attachment:` references for every file
+/// `load` stores; links it returns `None` for are left alone
+pub fn attach_local_files(
+    note: &mut Note,
+    base: &Path,
+    mut load: impl FnMut(&Path) -> Result<Option<Attachment>, String>,
+) -> Result<(), String> {
+    for destination in local_link_destinations(&note.content) {
+        let Some(attachment) = load(&base.join(destination.replace("%20", " ")))? else {
+            continue;
+        };
+        let reference = format!("attachment:{}", attachment.blob_id);
+        note.content = note
+            .content
+            .replace(&format!("(<{}>", destination), &format!("({}", reference))
+            .replace(&format!("({}", destination), &format!("({}", reference));
+        note.add_attachment(attachment);
+    }
+    Ok(())
+}
+
+/// Relative link and image targets, which may point at files next to the note
+pub fn local_link_destinations(content: &str) -> Vec<String> {
+    let mut destinations: Vec<String> = Parser::new_ext(content, markdown::parser_options())
+        .filter_map(|event| match event {
+            Event::Start(Tag::Image { dest_url, .. }) | Event::Start(Tag::Link { dest_url, .. }) => {
+                Some(dest_url.into_string())
+            }
+            _ => None,
+        })
+        .filter(|url| !url.is_empty() && !url.contains(':') && !url.starts_with(['#', '/']))
+        .collect();
+    destinations.sort();
+    destinations.dedup();
+    destinations
+}
+
+/// Writes every note as a plain Markdown file with frontmatter, one folder
+/// per notebook. Attachments are decrypted next to them. The output is NOT encrypted.
+pub fn export_folder(notes: &[&Note], root: &Path, blob_store: &BlobStore) -> Result<usize, String> {
+    fs::create_dir_all(root).map_err(|e| format!("Failed to create {}: {}", root.display(), e))?;
+
+    let mut used_paths = HashSet::new();
+    let mut exported_blobs = HashSet::new();
+    for note in notes {
+        let folder = match &note.notebook {
+            Some(notebook) => root.join(sanitize_file_name(notebook)),
+            None => root.to_path_buf(),
+        };
+        let nested = note.notebook.is_some();
+        let mut note = (*note).clone();
+
+        for attachment in &note.attachments {
+            let file_name = attachment_file_name(attachment);
+            if exported_blobs.insert(file_name.clone()) {
+                let dir = root.join(ATTACHMENTS_DIR);
+                fs::create_dir_all(&dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+                blob_store.export(&attachment.blob_id, &dir.join(&file_name))?;
+            }
+            let relative = if nested {
+                format!("../{}/{}", ATTACHMENTS_DIR, file_name)
+            } else {
+                format!("{}/{}", ATTACHMENTS_DIR, file_name)
+            };
+            note.content = note
+                .content
+                .replace(&format!("(attachment:{})", attachment.blob_id), &format!("({})", relative));
+        }
+
+        fs::create_dir_all(&folder).map_err(|e| format!("Failed to create {}: {}", folder.display(), e))?;
+        let path = unique_path(&folder, &sanitize_file_name(&note.title), "md", &mut used_paths);
+        let markdown = properties::to_markdown(&note)?;
+        fs::write(&path, markdown).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    }
+    Ok(notes.len())
+}
+
+/// Name of an exported attachment, unique per blob and free of spaces
+pub fn attachment_file_name(attachment: &Attachment) -> String {
+    format!(
+        "{}-{}",
+        &attachment.blob_id[..8],
+        sanitize_file_name(&attachment.file_name).replace(' ', "-")
+    )
+}
+
+/// `<title>.md`, or `<title> (2).md` and so on when the name is taken
+pub fn unique_path(folder: &Path, stem: &str, extension: &str, used: &mut HashSet<PathBuf>) -> PathBuf {
+    let mut counter = 1;
+    loop {
+        let name = if counter == 1 {
+            format!("{}.{}", stem, extension)
+        } else {
+            format!("{} ({}).{}", stem, counter, extension)
+        };
+        let path = folder.join(name);
+        let key = PathBuf::from(path.to_string_lossy().to_lowercase());
+        if !path.exists() && used.insert(key) {
+            return path;
+        }
+        counter += 1;
+    }
+}
+
+pub fn sanitize_file_name(title: &str) -> String {
+    let name: String = title
+        .chars()
+        .map(|c| if c.is_alphanumeric() || matches!(c, ' ' | '-' | '_' | '.') { c } else { '_' })
+        .collect();
+    let name = name.trim().trim_start_matches('.');
+    if name.is_empty() {
+        "note".to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attachments::BlobKeys;
+
+    #[test]
+    fn folder_round_trip_keeps_notebooks_tags_and_attachments() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = BlobStore::new(dir.path().join("blobs"), &BlobKeys::generate()).unwrap();
+        let source = dir.path().join("source");
+        fs::create_dir_all(source.join("Work/Clients")).unwrap();
+        fs::write(source.join("Work/Clients/Acme.md"), "---\ntags: [sales]\n---\n# Acme\n\n![logo](logo.png)\n").unwrap();
+        fs::write(source.join("Work/Clients/logo.png"), b"png bytes").unwrap();
+        fs::write(source.join("Inbox.md"), "Loose note\n").unwrap();
+        fs::write(source.join("notes.txt"), "ignored").unwrap();
+
+        let import = import_folder(&source, &store).unwrap();
+        assert!(import.skipped.is_empty());
+        let titles: Vec<_> = import.notes.iter().map(|note| note.title.as_str()).collect();
+        assert_eq!(titles, vec!["Inbox", "Acme"]);
+
+        let acme = &import.notes[1];
+        assert_eq!(acme.notebook.as_deref(), Some("Work"));
+        assert_eq!(acme.tags, vec!["sales".to_string(), "Clients".to_string()]);
+        assert_eq!(acme.attachments.len(), 1);
+        let blob_id = &acme.attachments[0].blob_id;
+        assert!(acme.content.contains(&format!("![logo](attachment:{})", blob_id)));
+
+        let export = dir.path().join("export");
+        let notes: Vec<&Note> = import.notes.iter().collect();
+        assert_eq!(export_folder(&notes, &export, &store).unwrap(), 2);
+        let exported = fs::read_to_string(export.join("Work/Acme.md")).unwrap();
+        let attachment = format!("{}-logo.png", &blob_id[..8]);
+        assert!(exported.contains(&format!("![logo](../_attachments/{})", attachment)));
+        assert_eq!(fs::read(export.join("_attachments").join(attachment)).unwrap(), b"png bytes");
+        assert!(export.join("Inbox.md").exists());
+    }
+}
@@ -1,5 +1,9 @@
+use crate::attachments::Attachment;
+use crate::crdt::CrdtText;
+use crate::properties::Properties;
+use crate::reminders::Reminder;
 use serde::{Deserialize, Serialize};
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, Utc};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Note {
@@ -10,9 +14,97 @@ pub struct Note {
     pub modified_at: DateTime<Utc>,
     pub tags: Vec<String>,
     pub location: Option<GeoLocation>,
+    #[serde(default)]
+    pub notebook: Option<String>,
+    #[serde(default)]
+    pub reminders: Vec<Reminder>,
+    /// Day this note is the journal entry for
+    #[serde(default)]
+    pub journal_date: Option<NaiveDate>,
+    #[serde(default)]
+    pub properties: Properties,
+    #[serde(default)]
+    pub attachments: Vec<Attachment>,
+    #[serde(default)]
+    pub pinned: bool,
+    #[serde(default)]
+    pub favourite: bool,
+    /// Archived notes are hidden from the list and search unless requested
+    #[serde(default)]
+    pub archived: bool,
+    /// Edit history of `content` for conflict-free sync, when enabled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub crdt: Option<CrdtText>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NoteSort {
+    #[default]
+    Modified,
+    Created,
+    Title,
+    /// Distance from the map centre; notes without a location go last
+    Distance,
+}
+
+impl NoteSort {
+    pub const ALL: [NoteSort; 4] = [
+        NoteSort::Modified,
+        NoteSort::Created,
+        NoteSort::Title,
+        NoteSort::Distance,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            NoteSort::Modified => "Last modified",
+            NoteSort::Created => "Created",
+            NoteSort::Title => "Title",
+            NoteSort::Distance => "Distance from map centre",
+        }
+    }
+}
+
+/// Sorts pinned notes to the top, then by the chosen order
+pub fn sort_notes(notes: &mut [&Note], sort: NoteSort, centre: (f64, f64)) {
+    let distance = |note: &Note| {
+        note.location
+            .as_ref()
+            .map_or(f64::INFINITY, |loc| loc.distance_km(centre.0, centre.1))
+    };
+    notes.sort_by(|a, b| {
+        b.pinned.cmp(&a.pinned).then_with(|| match sort {
+            NoteSort::Modified => b.modified_at.cmp(&a.modified_at),
+            NoteSort::Created => b.created_at.cmp(&a.created_at),
+            NoteSort::Title => a.title.to_lowercase().cmp(&b.title.to_lowercase()),
+            NoteSort::Distance => distance(a).total_cmp(&distance(b)),
+        })
+    });
+}
+
+/// How notes are listed to scripts and integrations
+#[derive(Serialize)]
+pub struct NoteSummary<'a> {
+    id: &'a str,
+    title: &'a str,
+    modified_at: DateTime<Utc>,
+    tags: &'a [String],
+    notebook: Option<&'a str>,
+}
+
+impl<'a> From<&'a Note> for NoteSummary<'a> {
+    fn from(note: &'a Note) -> Self {
+        Self {
+            id: &note.id,
+            title: &note.title,
+            modified_at: note.modified_at,
+            tags: &note.tags,
+            notebook: note.notebook.as_deref(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct GeoLocation {
     pub latitude: f64,
     pub longitude: f64,
@@ -30,12 +122,36 @@ impl Note {
             modified_at: now,
             tags: Vec::new(),
             location: None,
+            notebook: None,
+            reminders: Vec::new(),
+            journal_date: None,
+            properties: Properties::new(),
+            attachments: Vec::new(),
+            pinned: false,
+            favourite: false,
+            archived: false,
+            crdt: None,
         }
     }
 
-    pub fn update_content(&mut self, content: String) {
+    /// Leaves the note unchanged when its CRDT content cannot record the edit
+    pub fn update_content(&mut self, content: String) -> Result<(), String> {
+        if let Some(crdt) = &mut self.crdt {
+            crdt.update(&content, &self.attachments)?;
+        }
         self.content = content;
         self.modified_at = Utc::now();
+        Ok(())
+    }
+
+    /// Switches between CRDT-backed and plain content
+    pub fn set_crdt(&mut self, enabled: bool) -> Result<(), String> {
+        self.crdt = match enabled {
+            true => Some(CrdtText::new(&self.id, &self.content, &self.attachments)?),
+            false => None,
+        };
+        self.modified_at = Utc::now();
+        Ok(())
     }
 
     pub fn update_title(&mut self, title: String) {
@@ -64,6 +180,63 @@ impl Note {
         self.location = None;
         self.modified_at = Utc::now();
     }
+
+    pub fn set_notebook(&mut self, notebook: Option<String>) {
+        if self.notebook != notebook {
+            self.notebook = notebook;
+            self.modified_at = Utc::now();
+        }
+    }
+
+    pub fn set_properties(&mut self, properties: Properties) {
+        if self.properties != properties {
+            self.properties = properties;
+            self.modified_at = Utc::now();
+        }
+    }
+
+    /// Adds an attachment unless the same file is already attached
+    pub fn add_attachment(&mut self, attachment: Attachment) {
+        let duplicate = self
+            .attachments
+            .iter()
+            .any(|a| a.blob_id == attachment.blob_id && a.file_name == attachment.file_name);
+        if !duplicate {
+            self.attachments.push(attachment);
+            self.modified_at = Utc::now();
+        }
+    }
+
+    pub fn remove_attachment(&mut self, blob_id: &str) {
+        self.attachments.retain(|a| a.blob_id != blob_id);
+        self.modified_at = Utc::now();
+    }
+
+    pub fn add_reminder(&mut self, reminder: Reminder) {
+        self.reminders.push(reminder);
+        self.reminders.sort_by_key(|r| r.at);
+        self.modified_at = Utc::now();
+    }
+
+    pub fn remove_reminder(&mut self, id: &str) {
+        self.reminders.retain(|r| r.id != id);
+        self.modified_at = Utc::now();
+    }
+
+    /// Advances a fired reminder to its next occurrence, dropping one-off reminders.
+    /// Returns the reminder as it was when it fired.
+    pub fn fire_reminder(&mut self, id: &str, now: DateTime<Utc>) -> Option<Reminder> {
+        let index = self.reminders.iter().position(|r| r.id == id)?;
+        let fired = self.reminders[index].clone();
+        match fired.recurrence.next_after(fired.anchor(), now) {
+            Some(next) => self.reminders[index].at = next,
+            None => {
+                self.reminders.remove(index);
+            }
+        }
+        self.reminders.sort_by_key(|r| r.at);
+        Some(fired)
+    }
 }
 
 impl GeoLocation {
@@ -75,6 +248,16 @@ impl GeoLocation {
         }
     }
 
+    /// Great-circle distance in kilometres
+    pub fn distance_km(&self, latitude: f64, longitude: f64) -> f64 {
+        const EARTH_RADIUS_KM: f64 = 6371.0;
+        let (lat1, lat2) = (self.latitude.to_radians(), latitude.to_radians());
+        let dlat = lat2 - lat1;
+        let dlon = (longitude - self.longitude).to_radians();
+        let a = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+        2.0 * EARTH_RADIUS_KM * a.sqrt().asin()
+    }
+
     // Copenhagen, Denmark coordinates
     pub fn copenhagen() -> Self {
         Self::new(55.6761, 12.5683, "Copenhagen, Denmark".to_string())
@@ -90,3 +273,50 @@ impl GeoLocation {
         Self::new(53.5511, 9.9937, "Hamburg, Germany".to_string())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pinned_notes_stay_on_top_of_any_sort() {
+        let mut near = Note::new("b near".to_string(), String::new());
+        near.location = Some(GeoLocation::hamburg());
+        let mut far = Note::new("A far".to_string(), String::new());
+        far.location = Some(GeoLocation::copenhagen());
+        let mut pinned = Note::new("c pinned".to_string(), String::new());
+        pinned.pinned = true;
+
+        let centre = (53.55, 10.0);
+        let mut notes = vec![&near, &far, &pinned];
+        sort_notes(&mut notes, NoteSort::Title, centre);
+        let titles: Vec<_> = notes.iter().map(|note| note.title.as_str()).collect();
+        assert_eq!(titles, vec!["c pinned", "A far", "b near"]);
+
+        sort_notes(&mut notes, NoteSort::Distance, centre);
+        let titles: Vec<_> = notes.iter().map(|note| note.title.as_str()).collect();
+        assert_eq!(titles, vec!["c pinned", "b near", "A far"]);
+    }
+
+    #[test]
+    fn failed_crdt_edits_leave_the_note_unchanged() {
+        let mut note = Note::new("Draft".to_string(), "first".to_string());
+        note.set_crdt(true).unwrap();
+        note.update_content("second".to_string()).unwrap();
+        assert_eq!(note.crdt.as_ref().unwrap().content(&[]).unwrap(), "second");
+
+        let mut json = serde_json::to_value(&note).unwrap();
+        json["crdt"] = serde_json::json!(crate::crypto::encode_base64(b"not a document"));
+        let mut broken: Note = serde_json::from_value(json).unwrap();
+        assert!(broken.update_content("third".to_string()).is_err());
+        assert_eq!(broken.content, "second");
+        assert!(broken.crdt.is_some());
+    }
+
+    #[test]
+    fn distance_between_copenhagen_and_berlin() {
+        let berlin = GeoLocation::berlin();
+        let distance = GeoLocation::copenhagen().distance_km(berlin.latitude, berlin.longitude);
+        assert!((350.0..365.0).contains(&distance), "{}", distance);
+    }
+}
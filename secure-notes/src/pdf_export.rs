@@ -0,0 +1,436 @@
+use crate::html_export::{self, ExportAssets};
+use crate::markdown;
+use crate::note::Note;
+use ab_glyph::{Font, FontVec};
+use eframe::egui;
+use printpdf::{
+    Color, ColorBits, ColorSpace, Image, ImageTransform, ImageXObject, IndirectFontRef, Line, Mm, PdfDocument,
+    PdfDocumentReference, PdfLayerReference, PdfPageIndex, Point, Px, Rgb,
+};
+use pulldown_cmark::{Event, Parser, Tag, TagEnd};
+
+/// A4 in millimetres
+const PAGE_SIZE: (f32, f32) = (210.0, 297.0);
+const MARGIN: f32 = 20.0;
+const PT_TO_MM: f32 = 25.4 / 72.0;
+const INDENT: f32 = 6.0;
+/// Images are downscaled to this width before embedding
+const MAX_IMAGE_PIXELS: u32 = 1600;
+
+/// A unit of laid-out Markdown
+#[derive(Debug, PartialEq)]
+enum Block {
+    Heading(u8, String),
+    Paragraph { text: String, indent: usize, quote: bool },
+    Code(String),
+    Rule,
+    Image(String),
+}
+
+/// Flattens Markdown into blocks. Inline styles are dropped since the PDF
+/// uses a single regular and a single monospace font.
+fn blocks(content: &str) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let mut text = String::new();
+    let mut lists: Vec<Option<u64>> = Vec::new();
+    let mut prefix = String::new();
+    let mut quote = 0;
+    let mut in_image = false;
+
+    let flush = |text: &mut String, prefix: &mut String, blocks: &mut Vec<Block>, indent: usize, quote: bool| {
+        let body = text.trim().trim_end_matches('|').trim_end();
+        if !body.is_empty() || !prefix.is_empty() {
+            blocks.push(Block::Paragraph {
+                text: format!("{}{}", prefix, body),
+                indent,
+                quote,
+            });
+        }
+        text.clear();
+        prefix.clear();
+    };
+
+    for event in Parser::new_ext(content, markdown::parser_options()) {
+        let indent = lists.len().saturating_sub(1) + quote;
+        match event {
+            Event::Start(Tag::Heading { .. }) | Event::Start(Tag::CodeBlock(_)) => {
+                flush(&mut text, &mut prefix, &mut blocks, indent, quote > 0)
+            }
+            Event::End(TagEnd::Heading(level)) => {
+                blocks.push(Block::Heading(level as u8, text.trim().to_string()));
+                text.clear();
+            }
+            Event::End(TagEnd::CodeBlock) => {
+                blocks.push(Block::Code(text.trim_end_matches('\n').to_string()));
+                text.clear();
+            }
+            Event::Start(Tag::List(start)) => {
+                flush(&mut text, &mut prefix, &mut blocks, indent, quote > 0);
+                lists.push(start);
+            }
+            Event::End(TagEnd::List(_)) => {
+                lists.pop();
+            }
+            Event::Start(Tag::Item) => {
+                flush(&mut text, &mut prefix, &mut blocks, indent, quote > 0);
+                prefix = match lists.last_mut() {
+                    Some(Some(number)) => {
+                        *number += 1;
+                        format!("{}. ", *number - 1)
+                    }
+                    _ => "• ".to_string(),
+                };
+            }
+            Event::Start(Tag::BlockQuote(_)) => {
+                flush(&mut text, &mut prefix, &mut blocks, indent, quote > 0);
+                quote += 1;
+            }
+            Event::End(TagEnd::BlockQuote) => {
+                flush(&mut text, &mut prefix, &mut blocks, indent, true);
+                quote -= 1;
+            }
+            Event::End(TagEnd::Paragraph) | Event::End(TagEnd::Item) | Event::End(TagEnd::TableHead)
+            | Event::End(TagEnd::TableRow) => flush(&mut text, &mut prefix, &mut blocks, indent, quote > 0),
+            Event::End(TagEnd::TableCell) => text.push_str(" | "),
+            Event::Start(Tag::Image { dest_url, .. }) => {
+                flush(&mut text, &mut prefix, &mut blocks, indent, quote > 0);
+                blocks.push(Block::Image(dest_url.into_string()));
+                in_image = true;
+            }
+            Event::End(TagEnd::Image) => in_image = false,
+            Event::TaskListMarker(checked) => prefix = if checked { "[x] " } else { "[ ] " }.to_string(),
+            Event::Text(value) | Event::Code(value) | Event::Html(value) | Event::InlineHtml(value) if !in_image => {
+                text.push_str(&value)
+            }
+            Event::SoftBreak => text.push(' '),
+            Event::HardBreak => text.push('\n'),
+            Event::Rule => blocks.push(Block::Rule),
+            _ => {}
+        }
+    }
+    flush(&mut text, &mut prefix, &mut blocks, 0, false);
+    blocks
+}
+
+struct Style {
+    size: f32,
+    mono: bool,
+    grey: bool,
+}
+
+impl Style {
+    const fn new(size: f32) -> Self {
+        Self {
+            size,
+            mono: false,
+            grey: false,
+        }
+    }
+
+    fn line_height(&self) -> f32 {
+        self.size * 1.4 * PT_TO_MM
+    }
+}
+
+struct Writer {
+    doc: PdfDocumentReference,
+    regular: IndirectFontRef,
+    mono: IndirectFontRef,
+    regular_metrics: FontVec,
+    mono_metrics: FontVec,
+    pages: Vec<PdfPageIndex>,
+    layers: Vec<PdfLayerReference>,
+    /// Baseline of the last line, in millimetres from the bottom of the page
+    y: f32,
+}
+
+impl Writer {
+    fn new(title: &str) -> Result<Self, String> {
+        let fonts = egui::FontDefinitions::default();
+        let font_bytes = |name: &str| {
+            fonts
+                .font_data
+                .get(name)
+                .map(|data| data.font.to_vec())
+                .ok_or_else(|| format!("Missing font {}", name))
+        };
+        let (regular_bytes, mono_bytes) = (font_bytes("Ubuntu-Light")?, font_bytes("Hack")?);
+
+        let (doc, page, layer) = PdfDocument::new(title, Mm(PAGE_SIZE.0), Mm(PAGE_SIZE.1), "Page 1");
+        let load = |bytes: &[u8]| doc.add_external_font(bytes).map_err(|e| format!("Failed to load font: {}", e));
+        let regular = load(&regular_bytes)?;
+        let mono = load(&mono_bytes)?;
+        let metrics = |bytes: Vec<u8>| FontVec::try_from_vec(bytes).map_err(|e| format!("Failed to load font: {}", e));
+        let first_layer = doc.get_page(page).get_layer(layer);
+
+        Ok(Self {
+            regular,
+            mono,
+            regular_metrics: metrics(regular_bytes)?,
+            mono_metrics: metrics(mono_bytes)?,
+            pages: vec![page],
+            layers: vec![first_layer],
+            y: PAGE_SIZE.1 - MARGIN,
+            doc,
+        })
+    }
+
+    fn layer(&self) -> &PdfLayerReference {
+        self.layers.last().expect("the document always has a page")
+    }
+
+    fn new_page(&mut self) {
+        let (page, layer) = self.doc.add_page(
+            Mm(PAGE_SIZE.0),
+            Mm(PAGE_SIZE.1),
+            format!("Page {}", self.layers.len() + 1),
+        );
+        self.pages.push(page);
+        self.layers.push(self.doc.get_page(page).get_layer(layer));
+        self.y = PAGE_SIZE.1 - MARGIN;
+    }
+
+    /// Starts a new page unless the current one is still empty
+    fn start_note(&mut self, title: &str) {
+        if self.y < PAGE_SIZE.1 - MARGIN {
+            self.new_page();
+        }
+        let page = *self.pages.last().expect("the document always has a page");
+        self.doc.add_bookmark(title, page);
+    }
+
+    /// Makes room for `height` millimetres, breaking the page if needed
+    fn reserve(&mut self, height: f32) {
+        if self.y - height < MARGIN {
+            self.new_page();
+        }
+        self.y -= height;
+    }
+
+    fn space(&mut self, height: f32) {
+        self.y = (self.y - height).max(MARGIN);
+    }
+
+    fn text_width(&self, text: &str, style: &Style) -> f32 {
+        let font = if style.mono { &self.mono_metrics } else { &self.regular_metrics };
+        let units = font.units_per_em().unwrap_or(1000.0);
+        let advance: f32 = text.chars().map(|c| font.h_advance_unscaled(font.glyph_id(c))).sum();
+        advance / units * style.size * PT_TO_MM
+    }
+
+    /// Greedy word wrap; words wider than a line are split by character
+    fn wrap(&self, line: &str, width: f32, style: &Style) -> Vec<String> {
+        let mut lines = Vec::new();
+        let mut current = String::new();
+        for word in line.split(' ') {
+            let candidate = if current.is_empty() { word.to_string() } else { format!("{} {}", current, word) };
+            if self.text_width(&candidate, style) <= width {
+                current = candidate;
+                continue;
+            }
+            if !current.is_empty() {
+                lines.push(std::mem::take(&mut current));
+            }
+            for c in word.chars() {
+                current.push(c);
+                if self.text_width(&current, style) > width && current.chars().count() > 1 {
+                    current.pop();
+                    lines.push(std::mem::replace(&mut current, c.to_string()));
+                }
+            }
+        }
+        lines.push(current);
+        lines
+    }
+
+    fn text(&mut self, text: &str, style: &Style, indent: f32) {
+        let width = PAGE_SIZE.0 - 2.0 * MARGIN - indent;
+        let grey = if style.grey { 0.45 } else { 0.1 };
+        for line in text.lines() {
+            for wrapped in self.wrap(line, width, style) {
+                self.reserve(style.line_height());
+                let layer = self.layer().clone();
+                layer.set_fill_color(Color::Rgb(Rgb::new(grey, grey, grey, None)));
+                let font = if style.mono { &self.mono } else { &self.regular };
+                layer.use_text(wrapped, style.size, Mm(MARGIN + indent), Mm(self.y), font);
+            }
+        }
+    }
+
+    fn rule(&mut self) {
+        self.reserve(4.0);
+        let layer = self.layer().clone();
+        layer.set_outline_color(Color::Rgb(Rgb::new(0.7, 0.7, 0.7, None)));
+        layer.add_line(Line {
+            points: vec![
+                (Point::new(Mm(MARGIN), Mm(self.y + 2.0)), false),
+                (Point::new(Mm(PAGE_SIZE.0 - MARGIN), Mm(self.y + 2.0)), false),
+            ],
+            is_closed: false,
+        });
+    }
+
+    fn image(&mut self, image: &image::DynamicImage) {
+        let image = if image.width() > MAX_IMAGE_PIXELS {
+            image.resize(MAX_IMAGE_PIXELS, u32::MAX, image::imageops::FilterType::Triangle)
+        } else {
+            image.clone()
+        };
+        // Transparent areas are flattened onto white paper
+        let mut rgb = Vec::with_capacity((image.width() * image.height() * 3) as usize);
+        for pixel in image.to_rgba8().pixels() {
+            let alpha = pixel[3] as f32 / 255.0;
+            rgb.extend(pixel.0[..3].iter().map(|&c| (c as f32 * alpha + 255.0 * (1.0 - alpha)) as u8));
+        }
+
+        // Natural size at 150 dpi, shrunk to fit the text column and the page
+        let natural = image.width() as f32 / 150.0 * 25.4;
+        let aspect = image.height() as f32 / image.width() as f32;
+        let max_height = PAGE_SIZE.1 - 2.0 * MARGIN;
+        let width = natural.min(PAGE_SIZE.0 - 2.0 * MARGIN).min(max_height / aspect);
+        self.reserve(width * aspect);
+
+        let xobject = ImageXObject {
+            width: Px(image.width() as usize),
+            height: Px(image.height() as usize),
+            color_space: ColorSpace::Rgb,
+            bits_per_component: ColorBits::Bit8,
+            interpolate: true,
+            image_data: rgb,
+            image_filter: None,
+            smask: None,
+            clipping_bbox: None,
+        };
+        Image::from(xobject).add_to_layer(
+            self.layer().clone(),
+            ImageTransform {
+                translate_x: Some(Mm(MARGIN)),
+                translate_y: Some(Mm(self.y)),
+                dpi: Some(image.width() as f32 * 25.4 / width),
+                ..Default::default()
+            },
+        );
+        self.space(3.0);
+    }
+
+    fn finish(self) -> Result<Vec<u8>, String> {
+        let total = self.layers.len();
+        let footer = Style {
+            grey: true,
+            ..Style::new(8.0)
+        };
+        for (index, layer) in self.layers.iter().enumerate() {
+            let label = format!("{} / {}", index + 1, total);
+            let x = (PAGE_SIZE.0 - self.text_width(&label, &footer)) / 2.0;
+            layer.set_fill_color(Color::Rgb(Rgb::new(0.45, 0.45, 0.45, None)));
+            layer.use_text(label, footer.size, Mm(x), Mm(MARGIN / 2.0), &self.regular);
+        }
+        self.doc
+            .save_to_bytes()
+            .map_err(|e| format!("Failed to write PDF: {}", e))
+    }
+}
+
+/// Renders notes as an A4 PDF, each note starting on a new page
+pub fn render(notes: &[&Note], title: &str, assets: &ExportAssets) -> Result<Vec<u8>, String> {
+    let mut writer = Writer::new(title)?;
+    let body = Style::new(11.0);
+    let code = Style {
+        mono: true,
+        ..Style::new(9.0)
+    };
+
+    for note in notes {
+        writer.start_note(&note.title);
+        writer.text(&note.title, &Style::new(20.0), 0.0);
+        writer.text(
+            &html_export::meta_line(note),
+            &Style {
+                grey: true,
+                ..Style::new(9.0)
+            },
+            0.0,
+        );
+        writer.space(3.0);
+        if let Some(map) = assets.map(note) {
+            writer.image(&image::DynamicImage::ImageRgba8(map));
+        }
+
+        for block in blocks(&note.content) {
+            match block {
+                Block::Heading(level, text) => {
+                    writer.space(2.0);
+                    let size = [16.0, 14.0, 12.5, 11.5, 11.0, 11.0][(level as usize).clamp(1, 6) - 1];
+                    writer.text(&text, &Style::new(size), 0.0);
+                    writer.space(1.0);
+                }
+                Block::Paragraph { text, indent, quote } => {
+                    let style = Style { grey: quote, ..body };
+                    writer.text(&text, &style, indent as f32 * INDENT);
+                    writer.space(1.5);
+                }
+                Block::Code(text) => {
+                    writer.text(&text, &code, INDENT / 2.0);
+                    writer.space(2.0);
+                }
+                Block::Rule => writer.rule(),
+                Block::Image(url) => {
+                    match assets.image(&url).and_then(|data| image::load_from_memory(&data).ok()) {
+                        Some(image) => writer.image(&image),
+                        None => writer.text(&format!("[image: {}]", url), &Style { grey: true, ..body }, 0.0),
+                    }
+                }
+            }
+        }
+    }
+    writer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attachments::{BlobKeys, BlobStore};
+
+    #[test]
+    fn markdown_flattens_into_blocks() {
+        let source = "# Plan\n\nSome *text*\nwrapped\n\n1. First\n2. Second\n   - [x] nested\n\n> quoted\n\n```\ncode\n```\n\n![map](attachment:abc)\n\n| a | b |\n|---|---|\n| 1 | 2 |\n\n---\n";
+        let paragraph = |text: &str, indent, quote| Block::Paragraph {
+            text: text.to_string(),
+            indent,
+            quote,
+        };
+        assert_eq!(
+            blocks(source),
+            vec![
+                Block::Heading(1, "Plan".to_string()),
+                paragraph("Some text wrapped", 0, false),
+                paragraph("1. First", 0, false),
+                paragraph("2. Second", 0, false),
+                paragraph("[x] nested", 1, false),
+                paragraph("quoted", 1, true),
+                Block::Code("code".to_string()),
+                Block::Image("attachment:abc".to_string()),
+                paragraph("a | b", 0, false),
+                paragraph("1 | 2", 0, false),
+                Block::Rule,
+            ]
+        );
+    }
+
+    #[test]
+    fn long_notes_are_paginated() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = BlobStore::new(dir.path().to_path_buf(), &BlobKeys::generate()).unwrap();
+        let assets = ExportAssets {
+            blob_store: &store,
+            tiles: None,
+        };
+        let long = Note::new("Long".to_string(), "Lorem ipsum dolor sit amet. ".repeat(600));
+        let short = Note::new("Short – ünïcode".to_string(), "Hello".to_string());
+
+        let pdf = render(&[&long, &short], "Export", &assets).unwrap();
+        assert!(pdf.starts_with(b"%PDF"));
+        let pages = pdf.windows(11).filter(|w| w.starts_with(b"/Type/Page") && w[10] != b's').count();
+        assert!(pages >= 3, "expected at least 3 pages, found {}", pages);
+    }
+}
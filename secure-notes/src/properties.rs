@@ -0,0 +1,399 @@
+use crate::note::{GeoLocation, Note};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Structured metadata on a note, keyed by property name
+pub type Properties = BTreeMap<String, PropertyValue>;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value")]
+pub enum PropertyValue {
+    Text(String),
+    Number(f64),
+    Bool(bool),
+    Date(NaiveDate),
+    Url(String),
+    List(Vec<String>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PropertyKind {
+    Text,
+    Number,
+    Bool,
+    Date,
+    Url,
+    List,
+}
+
+impl PropertyKind {
+    pub const ALL: [PropertyKind; 6] = [
+        PropertyKind::Text,
+        PropertyKind::Number,
+        PropertyKind::Bool,
+        PropertyKind::Date,
+        PropertyKind::Url,
+        PropertyKind::List,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            PropertyKind::Text => "Text",
+            PropertyKind::Number => "Number",
+            PropertyKind::Bool => "Checkbox",
+            PropertyKind::Date => "Date",
+            PropertyKind::Url => "URL",
+            PropertyKind::List => "List",
+        }
+    }
+}
+
+impl PropertyValue {
+    pub fn kind(&self) -> PropertyKind {
+        match self {
+            PropertyValue::Text(_) => PropertyKind::Text,
+            PropertyValue::Number(_) => PropertyKind::Number,
+            PropertyValue::Bool(_) => PropertyKind::Bool,
+            PropertyValue::Date(_) => PropertyKind::Date,
+            PropertyValue::Url(_) => PropertyKind::Url,
+            PropertyValue::List(_) => PropertyKind::List,
+        }
+    }
+
+    /// Converts to another kind, keeping as much of the value as possible
+    pub fn convert(&self, kind: PropertyKind) -> PropertyValue {
+        let text = self.to_string();
+        match kind {
+            PropertyKind::Text => PropertyValue::Text(text),
+            PropertyKind::Number => PropertyValue::Number(text.trim().parse().unwrap_or(0.0)),
+            PropertyKind::Bool => PropertyValue::Bool(matches!(text.trim(), "true" | "yes" | "1")),
+            PropertyKind::Date => PropertyValue::Date(
+                NaiveDate::parse_from_str(text.trim(), "%Y-%m-%d")
+                    .unwrap_or_else(|_| chrono::Local::now().date_naive()),
+            ),
+            PropertyKind::Url => PropertyValue::Url(text),
+            PropertyKind::List => PropertyValue::List(split_list(&text)),
+        }
+    }
+
+    /// Infers the type of a value written as plain text
+    pub fn infer(text: &str) -> PropertyValue {
+        let trimmed = text.trim();
+        if let Ok(number) = trimmed.parse::<f64>() {
+            PropertyValue::Number(number)
+        } else if let Ok(flag) = trimmed.parse::<bool>() {
+            PropertyValue::Bool(flag)
+        } else if let Ok(date) = NaiveDate::parse_from_str(trimmed, "%Y-%m-%d") {
+            PropertyValue::Date(date)
+        } else if trimmed.starts_with("http://") || trimmed.starts_with("https://") {
+            PropertyValue::Url(trimmed.to_string())
+        } else {
+            PropertyValue::Text(trimmed.to_string())
+        }
+    }
+
+    pub fn to_yaml(&self) -> serde_yaml::Value {
+        use serde_yaml::Value;
+        match self {
+            PropertyValue::Text(text) | PropertyValue::Url(text) => Value::String(text.clone()),
+            PropertyValue::Number(number) => Value::Number((*number).into()),
+            PropertyValue::Bool(flag) => Value::Bool(*flag),
+            PropertyValue::Date(date) => Value::String(date.format("%Y-%m-%d").to_string()),
+            PropertyValue::List(items) => {
+                Value::Sequence(items.iter().cloned().map(Value::String).collect())
+            }
+        }
+    }
+
+    /// Maps a YAML value onto a property, or `None` for nested mappings
+    pub fn from_yaml(value: &serde_yaml::Value) -> Option<PropertyValue> {
+        use serde_yaml::Value;
+        match value {
+            Value::Bool(flag) => Some(PropertyValue::Bool(*flag)),
+            Value::Number(number) => number.as_f64().map(PropertyValue::Number),
+            Value::String(text) => Some(match PropertyValue::infer(text) {
+                PropertyValue::Number(_) | PropertyValue::Bool(_) => PropertyValue::Text(text.clone()),
+                inferred => inferred,
+            }),
+            Value::Sequence(items) => Some(PropertyValue::List(
+                items.iter().filter_map(yaml_scalar_to_string).collect(),
+            )),
+            Value::Tagged(tagged) => PropertyValue::from_yaml(&tagged.value),
+            Value::Null | Value::Mapping(_) => None,
+        }
+    }
+
+    /// Whether a `key:value` search term matches this value
+    pub fn matches(&self, needle: &str) -> bool {
+        let needle = needle.to_lowercase();
+        match self {
+            PropertyValue::List(items) => items.iter().any(|item| item.to_lowercase().contains(&needle)),
+            PropertyValue::Number(number) => needle.parse::<f64>().is_ok_and(|n| n == *number),
+            _ => self.to_string().to_lowercase().contains(&needle),
+        }
+    }
+}
+
+impl std::fmt::Display for PropertyValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PropertyValue::Text(text) | PropertyValue::Url(text) => write!(f, "{}", text),
+            PropertyValue::Number(number) => write!(f, "{}", number),
+            PropertyValue::Bool(flag) => write!(f, "{}", flag),
+            PropertyValue::Date(date) => write!(f, "{}", date.format("%Y-%m-%d")),
+            PropertyValue::List(items) => write!(f, "{}", items.join(", ")),
+        }
+    }
+}
+
+pub fn split_list(text: &str) -> Vec<String> {
+    text.split(',')
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+fn yaml_scalar_to_string(value: &serde_yaml::Value) -> Option<String> {
+    use serde_yaml::Value;
+    match value {
+        Value::String(text) => Some(text.clone()),
+        Value::Number(number) => Some(number.to_string()),
+        Value::Bool(flag) => Some(flag.to_string()),
+        _ => None,
+    }
+}
+
+/// Search input split into free text and `key:value` property filters
+#[derive(Debug, Default, PartialEq)]
+pub struct SearchQuery {
+    pub text: String,
+    pub filters: Vec<(String, String)>,
+}
+
+impl SearchQuery {
+    pub fn parse(query: &str) -> Self {
+        let mut words = Vec::new();
+        let mut filters = Vec::new();
+        for word in query.split_whitespace() {
+            match word.split_once(':') {
+                Some((key, value)) if !key.is_empty() && !value.starts_with("//") => {
+                    filters.push((key.to_lowercase(), value.to_string()))
+                }
+                _ => words.push(word),
+            }
+        }
+        Self {
+            text: words.join(" ").to_lowercase(),
+            filters,
+        }
+    }
+
+    pub fn matches(&self, note: &Note) -> bool {
+        let text_matches = self.text.is_empty()
+            || note.title.to_lowercase().contains(&self.text)
+            || note.content.to_lowercase().contains(&self.text)
+            || note.tags.iter().any(|tag| tag.to_lowercase().contains(&self.text));
+
+        text_matches
+            && self.filters.iter().all(|(key, value)| {
+                note.properties
+                    .iter()
+                    .find(|(name, _)| name.to_lowercase() == *key)
+                    .is_some_and(|(_, property)| value.is_empty() || property.matches(value))
+            })
+    }
+}
+
+/// Note fields that live in the frontmatter rather than in `properties`
+const RESERVED_KEYS: [&str; 6] = ["title", "tags", "notebook", "created", "modified", "location"];
+
+/// Renders a note as Markdown with a YAML frontmatter block
+pub fn to_markdown(note: &Note) -> Result<String, String> {
+    use serde_yaml::{Mapping, Value};
+
+    let mut front = Mapping::new();
+    front.insert("title".into(), note.title.clone().into());
+    if !note.tags.is_empty() {
+        front.insert(
+            "tags".into(),
+            Value::Sequence(note.tags.iter().cloned().map(Value::String).collect()),
+        );
+    }
+    if let Some(notebook) = &note.notebook {
+        front.insert("notebook".into(), notebook.clone().into());
+    }
+    if let Some(location) = &note.location {
+        let mut mapping = Mapping::new();
+        mapping.insert("name".into(), location.name.clone().into());
+        mapping.insert("latitude".into(), location.latitude.into());
+        mapping.insert("longitude".into(), location.longitude.into());
+        front.insert("location".into(), Value::Mapping(mapping));
+    }
+    front.insert("created".into(), note.created_at.to_rfc3339().into());
+    front.insert("modified".into(), note.modified_at.to_rfc3339().into());
+    for (key, value) in &note.properties {
+        if !RESERVED_KEYS.contains(&key.as_str()) {
+            front.insert(key.clone().into(), value.to_yaml());
+        }
+    }
+
+    let yaml = serde_yaml::to_string(&front)
+        .map_err(|e| format!("Failed to serialize frontmatter: {}", e))?;
+    Ok(format!("---\n{}---\n\n{}", yaml, note.content))
+}
+
+/// Splits `---` delimited frontmatter from the body
+pub fn split_frontmatter(source: &str) -> (Option<&str>, &str) {
+    let Some(rest) = source
+        .strip_prefix("---\n")
+        .or_else(|| source.strip_prefix("---\r\n"))
+    else {
+        return (None, source);
+    };
+
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if line.trim_end() == "---" {
+            let body = &rest[offset + line.len()..];
+            return (Some(&rest[..offset]), body.trim_start_matches(['\r', '\n']));
+        }
+        offset += line.len();
+    }
+    (None, source)
+}
+
+/// Builds a note from Markdown, reading title, tags, dates and properties
+/// from the frontmatter when present
+pub fn from_markdown(source: &str, fallback_title: &str) -> Result<Note, String> {
+    let (front, body) = split_frontmatter(source);
+    let mut note = Note::new(fallback_title.to_string(), body.to_string());
+
+    let Some(front) = front else {
+        return Ok(note);
+    };
+    let mapping: serde_yaml::Mapping = match serde_yaml::from_str(front) {
+        Ok(serde_yaml::Value::Mapping(mapping)) => mapping,
+        Ok(serde_yaml::Value::Null) => return Ok(note),
+        Ok(_) => return Err("Failed to parse frontmatter: expected a mapping".to_string()),
+        Err(e) => return Err(format!("Failed to parse frontmatter: {}", e)),
+    };
+
+    for (key, value) in &mapping {
+        let Some(key) = key.as_str() else {
+            continue;
+        };
+        match key {
+            "title" => {
+                if let Some(title) = value.as_str() {
+                    note.title = title.to_string();
+                }
+            }
+            "tags" => {
+                note.tags = match value {
+                    serde_yaml::Value::Sequence(items) => {
+                        items.iter().filter_map(yaml_scalar_to_string).collect()
+                    }
+                    serde_yaml::Value::String(text) => split_list(text),
+                    _ => Vec::new(),
+                }
+            }
+            "notebook" => note.notebook = value.as_str().map(str::to_string),
+            "location" => note.location = parse_location(value),
+            "created" | "modified" => {
+                let parsed = value
+                    .as_str()
+                    .and_then(|text| chrono::DateTime::parse_from_rfc3339(text).ok())
+                    .map(|date| date.with_timezone(&chrono::Utc));
+                if let Some(date) = parsed {
+                    if key == "created" {
+                        note.created_at = date;
+                    } else {
+                        note.modified_at = date;
+                    }
+                }
+            }
+            _ => {
+                if let Some(property) = PropertyValue::from_yaml(value) {
+                    note.properties.insert(key.to_string(), property);
+                }
+            }
+        }
+    }
+    Ok(note)
+}
+
+/// Accepts `{name, latitude, longitude}` (or `lat`/`lon`) as well as `[lat, lon]`
+fn parse_location(value: &serde_yaml::Value) -> Option<GeoLocation> {
+    use serde_yaml::Value;
+    match value {
+        Value::Mapping(mapping) => {
+            let number = |keys: [&str; 2]| keys.iter().find_map(|key| mapping.get(*key)?.as_f64());
+            let name = mapping.get("name").and_then(Value::as_str).unwrap_or_default();
+            Some(GeoLocation::new(
+                number(["latitude", "lat"])?,
+                number(["longitude", "lon"])?,
+                name.to_string(),
+            ))
+        }
+        Value::Sequence(items) => match items.as_slice() {
+            [lat, lon] => Some(GeoLocation::new(lat.as_f64()?, lon.as_f64()?, String::new())),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frontmatter_round_trips_typed_properties() {
+        let mut note = Note::new("Launch".to_string(), "# Plan\n\nBody\n".to_string());
+        note.tags = vec!["work".to_string()];
+        note.notebook = Some("Projects".to_string());
+        note.location = Some(GeoLocation::berlin());
+        note.properties.insert("priority".to_string(), PropertyValue::Number(2.0));
+        note.properties.insert("done".to_string(), PropertyValue::Bool(false));
+        note.properties.insert(
+            "due".to_string(),
+            PropertyValue::Date(NaiveDate::from_ymd_opt(2026, 4, 1).unwrap()),
+        );
+        note.properties.insert("url".to_string(), PropertyValue::Url("https://example.com".to_string()));
+        note.properties.insert("status".to_string(), PropertyValue::Text("in progress".to_string()));
+        note.properties.insert("people".to_string(), PropertyValue::List(vec!["Ana".to_string()]));
+
+        let markdown = to_markdown(&note).unwrap();
+        assert!(markdown.starts_with("---\ntitle: Launch\n"));
+
+        let imported = from_markdown(&markdown, "fallback").unwrap();
+        assert_eq!(imported.title, "Launch");
+        assert_eq!(imported.content, note.content);
+        assert_eq!(imported.tags, note.tags);
+        assert_eq!(imported.notebook, note.notebook);
+        assert_eq!(imported.location, note.location);
+        assert_eq!(imported.created_at, note.created_at);
+        assert_eq!(imported.properties, note.properties);
+    }
+
+    #[test]
+    fn plain_markdown_has_no_frontmatter() {
+        let note = from_markdown("# Just text\n---\n", "file").unwrap();
+        assert_eq!(note.title, "file");
+        assert_eq!(note.content, "# Just text\n---\n");
+    }
+
+    #[test]
+    fn search_filters_on_properties() {
+        let mut note = Note::new("Roadmap".to_string(), "next quarter".to_string());
+        note.properties.insert("Status".to_string(), PropertyValue::Text("Active".to_string()));
+        note.properties.insert("priority".to_string(), PropertyValue::Number(1.0));
+
+        assert!(SearchQuery::parse("status:active").matches(&note));
+        assert!(SearchQuery::parse("quarter priority:1").matches(&note));
+        assert!(!SearchQuery::parse("priority:2").matches(&note));
+        assert!(!SearchQuery::parse("project:").matches(&note));
+        assert!(SearchQuery::parse("https://example.com").filters.is_empty());
+    }
+}
@@ -0,0 +1,215 @@
+use crate::note::Note;
+use chrono::{DateTime, Duration, Months, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Recurrence {
+    #[default]
+    Once,
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+impl Recurrence {
+    pub const ALL: [Recurrence; 5] = [
+        Recurrence::Once,
+        Recurrence::Daily,
+        Recurrence::Weekly,
+        Recurrence::Monthly,
+        Recurrence::Yearly,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Recurrence::Once => "Once",
+            Recurrence::Daily => "Daily",
+            Recurrence::Weekly => "Weekly",
+            Recurrence::Monthly => "Monthly",
+            Recurrence::Yearly => "Yearly",
+        }
+    }
+
+    /// The `n`th occurrence after `anchor`. Each one is counted from the
+    /// anchor, so a reminder on the 31st is back on the 31st after February.
+    fn nth(self, anchor: DateTime<Utc>, n: u32) -> Option<DateTime<Utc>> {
+        match self {
+            Recurrence::Once => (n == 0).then_some(anchor),
+            Recurrence::Daily => Some(anchor + Duration::days(n.into())),
+            Recurrence::Weekly => Some(anchor + Duration::weeks(n.into())),
+            Recurrence::Monthly => anchor.checked_add_months(Months::new(n)),
+            Recurrence::Yearly => anchor.checked_add_months(Months::new(n.checked_mul(12)?)),
+        }
+    }
+
+    /// First occurrence strictly after `now`, or `None` once a one-off reminder has passed
+    pub fn next_after(self, anchor: DateTime<Utc>, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        (0..).map_while(|n| self.nth(anchor, n)).find(|at| *at > now)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Reminder {
+    pub id: String,
+    pub at: DateTime<Utc>,
+    #[serde(default)]
+    pub recurrence: Recurrence,
+    /// When it was first due; recurrences are counted from here
+    #[serde(default)]
+    pub anchor: Option<DateTime<Utc>>,
+}
+
+impl Reminder {
+    pub fn new(at: DateTime<Utc>, recurrence: Recurrence) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            at,
+            recurrence,
+            anchor: Some(at),
+        }
+    }
+
+    /// Reminders saved before anchors existed count from their current time
+    pub fn anchor(&self) -> DateTime<Utc> {
+        self.anchor.unwrap_or(self.at)
+    }
+}
+
+/// A reminder that has fired and is waiting to be acknowledged
+#[derive(Debug, Clone)]
+pub struct Notification {
+    pub note_id: String,
+    pub title: String,
+    pub due_at: DateTime<Utc>,
+}
+
+/// Keeps pending reminders ordered by their next firing time
+#[derive(Default)]
+pub struct ReminderScheduler {
+    queue: Vec<(DateTime<Utc>, String, String)>,
+}
+
+impl ReminderScheduler {
+    /// Rebuilds the queue from the reminders stored on the notes
+    pub fn reschedule(&mut self, notes: &[&Note]) {
+        self.queue = notes
+            .iter()
+            .flat_map(|note| {
+                note.reminders
+                    .iter()
+                    .map(|reminder| (reminder.at, note.id.clone(), reminder.id.clone()))
+            })
+            .collect();
+        self.queue.sort();
+    }
+
+    pub fn next_due(&self) -> Option<DateTime<Utc>> {
+        self.queue.first().map(|(at, _, _)| *at)
+    }
+
+    /// Removes and returns `(note_id, reminder_id)` for every reminder due by `now`
+    pub fn take_due(&mut self, now: DateTime<Utc>) -> Vec<(String, String)> {
+        let due = self.queue.partition_point(|(at, _, _)| *at <= now);
+        self.queue
+            .drain(..due)
+            .map(|(_, note_id, reminder_id)| (note_id, reminder_id))
+            .collect()
+    }
+}
+
+/// Upcoming reminders across all notes, soonest first
+pub fn upcoming<'a>(notes: &[&'a Note]) -> Vec<(&'a Note, &'a Reminder)> {
+    let mut entries: Vec<(&Note, &Reminder)> = notes
+        .iter()
+        .flat_map(|note| note.reminders.iter().map(move |reminder| (*note, reminder)))
+        .collect();
+    entries.sort_by_key(|(_, reminder)| reminder.at);
+    entries
+}
+
+/// Shows a notification through the desktop environment, without any network access
+pub fn send_desktop_notification(summary: &str, body: &str) -> Result<(), String> {
+    #[cfg(target_os = "linux")]
+    let result = std::process::Command::new("notify-send")
+        .arg("--app-name=Secure Notes")
+        .arg(summary)
+        .arg(body)
+        .spawn();
+
+    #[cfg(target_os = "macos")]
+    let result = std::process::Command::new("osascript")
+        .arg("-e")
+        .arg(format!(
+            "display notification {:?} with title {:?}",
+            body, summary
+        ))
+        .spawn();
+
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    let result: std::io::Result<std::process::Child> = {
+        let _ = (summary, body);
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "not supported on this platform",
+        ))
+    };
+
+    result
+        .map(|_| ())
+        .map_err(|e| format!("Failed to show desktop notification: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn recurring_reminders_skip_to_the_next_future_occurrence() {
+        let at = Utc.with_ymd_and_hms(2026, 1, 31, 9, 0, 0).unwrap();
+        let now = Utc.with_ymd_and_hms(2026, 3, 15, 12, 0, 0).unwrap();
+
+        assert_eq!(Recurrence::Once.next_after(at, now), None);
+        assert_eq!(
+            Recurrence::Weekly.next_after(at, now),
+            Some(Utc.with_ymd_and_hms(2026, 3, 21, 9, 0, 0).unwrap())
+        );
+        assert_eq!(
+            Recurrence::Monthly.next_after(at, now),
+            Some(Utc.with_ymd_and_hms(2026, 3, 31, 9, 0, 0).unwrap())
+        );
+        assert_eq!(
+            Recurrence::Yearly.next_after(Utc.with_ymd_and_hms(2024, 2, 29, 9, 0, 0).unwrap(), now),
+            Some(Utc.with_ymd_and_hms(2027, 2, 28, 9, 0, 0).unwrap())
+        );
+        assert_eq!(Recurrence::Daily.next_after(now, at), Some(now));
+
+        // Firing twice must not leave it stuck on the 28th
+        let mut note = Note::new("Rent".to_string(), String::new());
+        note.reminders.push(Reminder::new(at, Recurrence::Monthly));
+        let id = note.reminders[0].id.clone();
+        note.fire_reminder(&id, at);
+        assert_eq!(note.reminders[0].at, Utc.with_ymd_and_hms(2026, 2, 28, 9, 0, 0).unwrap());
+        note.fire_reminder(&id, note.reminders[0].at);
+        assert_eq!(note.reminders[0].at, Utc.with_ymd_and_hms(2026, 3, 31, 9, 0, 0).unwrap());
+    }
+
+    #[test]
+    fn scheduler_releases_reminders_in_order_once() {
+        let now = Utc.with_ymd_and_hms(2026, 5, 1, 8, 0, 0).unwrap();
+        let mut early = Note::new("Early".to_string(), String::new());
+        early.reminders.push(Reminder::new(now - Duration::hours(1), Recurrence::Once));
+        let mut late = Note::new("Late".to_string(), String::new());
+        late.reminders.push(Reminder::new(now + Duration::hours(1), Recurrence::Daily));
+
+        let mut scheduler = ReminderScheduler::default();
+        scheduler.reschedule(&[&late, &early]);
+
+        assert_eq!(scheduler.next_due(), Some(now - Duration::hours(1)));
+        let due = scheduler.take_due(now);
+        assert_eq!(due, vec![(early.id.clone(), early.reminders[0].id.clone())]);
+        assert!(scheduler.take_due(now).is_empty());
+        assert_eq!(scheduler.next_due(), Some(now + Duration::hours(1)));
+    }
+}
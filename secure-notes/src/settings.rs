@@ -0,0 +1,43 @@
+use crate::editor::EditorLayout;
+use crate::note::NoteSort;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+/// Per-user preferences that are not secret and live outside the vault
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub editor_layout: EditorLayout,
+    pub desktop_notifications: bool,
+    pub note_sort: NoteSort,
+    /// Serve the local JSON-RPC API while the app runs
+    pub api_enabled: bool,
+    /// 0 lets the system pick a free port each session
+    pub api_port: u16,
+}
+
+impl Settings {
+    fn file_path() -> Option<PathBuf> {
+        directories::ProjectDirs::from("com", "secnotes", "SecureNotes")
+            .map(|dirs| dirs.config_dir().join("settings.json"))
+    }
+
+    pub fn load() -> Self {
+        Self::file_path()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|data| serde_json::from_str(&data).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = Self::file_path().ok_or("Failed to get config directory")?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| format!("Failed to create config dir: {}", e))?;
+        }
+
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| format!("Failed to serialize settings: {}", e))?;
+        fs::write(&path, json).map_err(|e| format!("Failed to write settings: {}", e))
+    }
+}
@@ -0,0 +1,287 @@
+use crate::attachments::BlobStore;
+use crate::bundle::{self, Bundle};
+use crate::crypto;
+use crate::note::Note;
+use chrono::{DateTime, Utc};
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use hkdf::Hkdf;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+pub const EXTENSION: &str = "snshare";
+const SHARE_FORMAT: &str = "secure-notes-share-1";
+const PUBLIC_KEY_PREFIX: &str = "snpub1:";
+const KEY_INFO: &[u8] = b"secure-notes share key";
+
+/// This vault's key pairs. They live inside the encrypted vault, so the
+/// private keys are only available while it is unlocked.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VaultIdentity {
+    /// Base64 X25519 secret that shares are encrypted to
+    pub encryption_key: String,
+    /// Base64 Ed25519 secret that outgoing shares are signed with
+    pub signing_key: String,
+}
+
+impl VaultIdentity {
+    pub fn generate() -> Self {
+        Self {
+            encryption_key: crypto::encode_base64(&crypto::generate_key()),
+            signing_key: crypto::encode_base64(&crypto::generate_key()),
+        }
+    }
+
+    fn secrets(&self) -> Result<(StaticSecret, SigningKey), String> {
+        let decode = |encoded: &str| -> Result<[u8; 32], String> {
+            crypto::decode_base64(encoded)?
+                .try_into()
+                .map_err(|_| "Invalid identity key".to_string())
+        };
+        Ok((
+            StaticSecret::from(decode(&self.encryption_key)?),
+            SigningKey::from_bytes(&decode(&self.signing_key)?),
+        ))
+    }
+
+    pub fn public_identity(&self) -> Result<PublicIdentity, String> {
+        let (secret, signing_key) = self.secrets()?;
+        Ok(PublicIdentity {
+            encryption: PublicKey::from(&secret),
+            signing: signing_key.verifying_key(),
+        })
+    }
+}
+
+/// What a contact needs to share with a vault, written as `snpub1:<base64>`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PublicIdentity {
+    encryption: PublicKey,
+    signing: VerifyingKey,
+}
+
+impl PublicIdentity {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let encoded = text
+            .trim()
+            .strip_prefix(PUBLIC_KEY_PREFIX)
+            .ok_or("Public keys start with snpub1:")?;
+        let bytes: [u8; 64] = crypto::decode_base64(encoded)?
+            .try_into()
+            .map_err(|_| "Invalid public key length".to_string())?;
+        let (encryption, signing) = bytes.split_at(32);
+        let encryption: [u8; 32] = encryption.try_into().unwrap_or_default();
+        let signing: [u8; 32] = signing.try_into().unwrap_or_default();
+        Ok(Self {
+            encryption: PublicKey::from(encryption),
+            signing: VerifyingKey::from_bytes(&signing).map_err(|_| "Invalid signing key".to_string())?,
+        })
+    }
+
+    fn to_bytes(self) -> [u8; 64] {
+        let mut bytes = [0u8; 64];
+        bytes[..32].copy_from_slice(self.encryption.as_bytes());
+        bytes[32..].copy_from_slice(self.signing.as_bytes());
+        bytes
+    }
+
+    /// Short hash of both keys for comparing over another channel,
+    /// e.g. `3F2A 91C0 …` in eight groups
+    pub fn fingerprint(&self) -> String {
+        let digest = Sha256::digest(self.to_bytes());
+        digest[..16]
+            .chunks(2)
+            .map(hex::encode_upper)
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+impl fmt::Display for PublicIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", PUBLIC_KEY_PREFIX, crypto::encode_base64(&self.to_bytes()))
+    }
+}
+
+/// A teammate's public key saved in the vault
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Contact {
+    pub name: String,
+    pub public_key: String,
+    pub added_at: DateTime<Utc>,
+}
+
+impl Contact {
+    pub fn new(name: String, public_key: &PublicIdentity) -> Self {
+        Self {
+            name,
+            public_key: public_key.to_string(),
+            added_at: Utc::now(),
+        }
+    }
+
+    pub fn public_identity(&self) -> Result<PublicIdentity, String> {
+        PublicIdentity::parse(&self.public_key)
+    }
+}
+
+pub fn find_contact<'a>(contacts: &'a [Contact], key: &PublicIdentity) -> Option<&'a Contact> {
+    contacts
+        .iter()
+        .find(|contact| contact.public_identity().is_ok_and(|other| other == *key))
+}
+
+/// On-disk share: a bundle encrypted to the recipient and signed by the sender
+#[derive(Serialize, Deserialize)]
+struct ShareFile {
+    format: String,
+    sender: String,
+    recipient: String,
+    /// Base64 X25519 public key generated for this share only
+    ephemeral_key: String,
+    ciphertext: String,
+    signature: String,
+}
+
+impl ShareFile {
+    /// Everything the signature covers, so no field can be swapped
+    fn signed_message(&self) -> Vec<u8> {
+        [
+            &self.format,
+            &self.sender,
+            &self.recipient,
+            &self.ephemeral_key,
+            &self.ciphertext,
+        ]
+        .iter()
+        .flat_map(|field| field.bytes().chain(std::iter::once(b'\n')))
+        .collect()
+    }
+}
+
+fn share_key(shared_secret: &[u8; 32], ephemeral: &PublicKey, recipient: &PublicKey) -> Result<[u8; 32], String> {
+    let mut salt = ephemeral.as_bytes().to_vec();
+    salt.extend_from_slice(recipient.as_bytes());
+    let mut key = [0u8; 32];
+    Hkdf::<Sha256>::new(Some(&salt), shared_secret)
+        .expand(KEY_INFO, &mut key)
+        .map_err(|e| format!("Key derivation failed: {}", e))?;
+    Ok(key)
+}
+
+/// Encrypts notes and their attachments so that only `recipient` can open
+/// them, and signs the result with this vault's identity.
+pub fn share(
+    notes: &[&Note],
+    blob_store: &BlobStore,
+    identity: &VaultIdentity,
+    recipient: &PublicIdentity,
+    path: &Path,
+) -> Result<(), String> {
+    let (_, signing_key) = identity.secrets()?;
+    let ephemeral_secret = StaticSecret::from(crypto::generate_key());
+    let ephemeral = PublicKey::from(&ephemeral_secret);
+    let shared_secret = ephemeral_secret.diffie_hellman(&recipient.encryption);
+    let key = share_key(shared_secret.as_bytes(), &ephemeral, &recipient.encryption)?;
+
+    let plaintext = bundle::pack(notes, blob_store)?;
+    let mut file = ShareFile {
+        format: SHARE_FORMAT.to_string(),
+        sender: identity.public_identity()?.to_string(),
+        recipient: recipient.to_string(),
+        ephemeral_key: crypto::encode_base64(ephemeral.as_bytes()),
+        ciphertext: crypto::encode_base64(&crypto::encrypt(&plaintext, &key)?),
+        signature: String::new(),
+    };
+    file.signature = crypto::encode_base64(&signing_key.sign(&file.signed_message()).to_bytes());
+
+    let json = serde_json::to_string_pretty(&file).map_err(|e| format!("Failed to serialize share: {}", e))?;
+    fs::write(path, json).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+/// A share whose signature checked out, decrypted but not yet imported
+pub struct ReceivedShare {
+    pub sender: PublicIdentity,
+    pub bundle: Bundle,
+}
+
+/// Verifies the sender's signature, then decrypts with this vault's key
+pub fn open(path: &Path, identity: &VaultIdentity) -> Result<ReceivedShare, String> {
+    let json = fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let file: ShareFile = serde_json::from_str(&json).map_err(|_| "Not a Secure Notes share".to_string())?;
+    if file.format != SHARE_FORMAT {
+        return Err(format!("Unsupported share format {}", file.format));
+    }
+
+    let sender = PublicIdentity::parse(&file.sender)?;
+    let signature: [u8; 64] = crypto::decode_base64(&file.signature)?
+        .try_into()
+        .map_err(|_| "Invalid signature".to_string())?;
+    sender
+        .signing
+        .verify_strict(&file.signed_message(), &Signature::from_bytes(&signature))
+        .map_err(|_| "The signature does not match the sender; the share may have been tampered with".to_string())?;
+
+    let own = identity.public_identity()?;
+    if PublicIdentity::parse(&file.recipient)? != own {
+        return Err("This share was encrypted for someone else".to_string());
+    }
+    let ephemeral: [u8; 32] = crypto::decode_base64(&file.ephemeral_key)?
+        .try_into()
+        .map_err(|_| "Invalid share key".to_string())?;
+    let ephemeral = PublicKey::from(ephemeral);
+    let (secret, _) = identity.secrets()?;
+    let key = share_key(secret.diffie_hellman(&ephemeral).as_bytes(), &ephemeral, &own.encryption)?;
+    let plaintext = crypto::decrypt(&crypto::decode_base64(&file.ciphertext)?, &key)?;
+
+    Ok(ReceivedShare {
+        sender,
+        bundle: bundle::unpack(&plaintext)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attachments::BlobKeys;
+    use crate::bundle::ConflictPolicy;
+
+    #[test]
+    fn shares_open_only_for_the_recipient_and_verify_the_sender() {
+        let dir = tempfile::tempdir().unwrap();
+        let alice_store = BlobStore::new(dir.path().join("alice"), &BlobKeys::generate()).unwrap();
+        let bob_store = BlobStore::new(dir.path().join("bob"), &BlobKeys::generate()).unwrap();
+        let (alice, bob, eve) = (VaultIdentity::generate(), VaultIdentity::generate(), VaultIdentity::generate());
+
+        let bob_key = PublicIdentity::parse(&bob.public_identity().unwrap().to_string()).unwrap();
+        assert_eq!(bob_key, bob.public_identity().unwrap());
+        assert_eq!(bob_key.fingerprint().split(' ').count(), 8);
+
+        let mut note = Note::new("Runbook".to_string(), String::new());
+        let attachment = alice_store.add_bytes("diagram.png", b"boxes").unwrap();
+        note.content = format!("![diagram](attachment:{})", attachment.blob_id);
+        note.add_attachment(attachment);
+        let path = dir.path().join(format!("runbook.{}", EXTENSION));
+        share(&[&note], &alice_store, &alice, &bob_key, &path).unwrap();
+
+        assert!(open(&path, &eve).is_err());
+        let received = open(&path, &bob).unwrap();
+        assert_eq!(received.sender, alice.public_identity().unwrap());
+        let contacts = vec![Contact::new("Alice".to_string(), &received.sender)];
+        assert_eq!(find_contact(&contacts, &received.sender).unwrap().name, "Alice");
+
+        let merge = received.bundle.merge(&[], ConflictPolicy::KeepBoth, &bob_store).unwrap();
+        let blob_id = &merge.notes[0].attachments[0].blob_id;
+        assert_eq!(bob_store.read(blob_id).unwrap(), b"boxes");
+
+        // Any edit to the file breaks the signature
+        let mut file: ShareFile = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        file.ciphertext.replace_range(..4, "AAAA");
+        fs::write(&path, serde_json::to_string(&file).unwrap()).unwrap();
+        let error = open(&path, &bob).err().unwrap();
+        assert!(error.contains("signature"), "{}", error);
+    }
+}
@@ -1,9 +1,19 @@
+use crate::attachments::{Attachment, BlobKeys, BlobStore};
 use crate::crypto;
+use crate::git_history::{self, GitHistory, NoteFiles};
+use crate::journal::JournalSettings;
+use crate::markdown_folder;
 use crate::note::Note;
+use crate::properties::SearchQuery;
+use crate::sharing::{Contact, VaultIdentity};
+use crate::sync::{SyncReport, SyncState, SyncTarget};
+use crate::templates::NoteTemplate;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::path::PathBuf;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 #[derive(Debug, Serialize, Deserialize)]
 struct EncryptedData {
@@ -11,20 +21,135 @@ struct EncryptedData {
     data: String, // Base64 encoded encrypted data
 }
 
+/// Everything kept inside the encrypted payload
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct VaultData {
+    notes: Vec<Note>,
+    #[serde(default)]
+    templates: Vec<NoteTemplate>,
+    #[serde(default)]
+    journal: JournalSettings,
+    #[serde(default)]
+    blob_keys: Option<BlobKeys>,
+    #[serde(default)]
+    identity: Option<VaultIdentity>,
+    #[serde(default)]
+    contacts: Vec<Contact>,
+    #[serde(default)]
+    sync: Option<SyncState>,
+    /// Notes live in separate files under a git repository instead of `notes`
+    #[serde(default)]
+    git_history: bool,
+    /// Base64 keys of earlier passwords, which encrypted older commits
+    #[serde(default)]
+    history_keys: Vec<String>,
+}
+
+/// Vaults written before templates existed store a bare list of notes
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredPayload {
+    Legacy(Vec<Note>),
+    Current(Box<VaultData>),
+}
+
+fn decode_payload(decrypted: &[u8]) -> Result<VaultData, String> {
+    let payload: StoredPayload = serde_json::from_slice(decrypted)
+        .map_err(|e| format!("Failed to parse notes: {}", e))?;
+    Ok(match payload {
+        StoredPayload::Current(data) => *data,
+        StoredPayload::Legacy(notes) => VaultData {
+            notes,
+            ..Default::default()
+        },
+    })
+}
+
 pub struct SecureStorage {
     notes: HashMap<String, Note>,
+    templates: Vec<NoteTemplate>,
+    journal: JournalSettings,
+    blob_keys: BlobKeys,
+    identity: VaultIdentity,
+    contacts: Vec<Contact>,
+    sync: Option<SyncState>,
+    git: Option<GitHistory>,
+    history_keys: Vec<String>,
+    note_files: NoteFiles,
+    /// Shared with every `SyncJob` still alive, see `collect_garbage`
+    sync_jobs: Arc<()>,
     file_path: PathBuf,
     encryption_key: Option<[u8; 32]>,
     salt: String,
     is_unlocked: bool,
 }
 
+/// A sync running on a copy of the vault; see `SecureStorage::start_sync`
+pub struct SyncJob {
+    state: SyncState,
+    /// Notes as they were when the job started
+    before: HashMap<String, Note>,
+    notes: HashMap<String, Note>,
+    blob_store: BlobStore,
+    result: Result<SyncReport, String>,
+    _running: Arc<()>,
+}
+
+impl SyncJob {
+    pub fn run(mut self) -> Self {
+        self.result = self.state.sync(&mut self.notes, &self.blob_store);
+        self
+    }
+}
+
+fn same_note(a: Option<&Note>, b: Option<&Note>) -> bool {
+    let json = |note: Option<&Note>| note.map(|note| serde_json::to_value(note).ok());
+    json(a) == json(b)
+}
+
+fn opened_attachments_dir() -> Option<PathBuf> {
+    directories::ProjectDirs::from("com", "secnotes", "SecureNotes")
+        .map(|dirs| dirs.cache_dir().join("opened"))
+}
+
+/// A file directly inside `dir`, whatever the (possibly imported) attachment is called
+fn opened_attachment_path(dir: &Path, attachment: &Attachment) -> PathBuf {
+    let prefix = attachment.blob_id.get(..8).unwrap_or(&attachment.blob_id);
+    dir.join(markdown_folder::sanitize_file_name(&format!("{}-{}", prefix, attachment.file_name)))
+}
+
+/// Removes plaintext copies left behind by `open_attachment`
+pub fn clear_opened_attachments() {
+    if let Some(dir) = opened_attachments_dir() {
+        fs::remove_dir_all(dir).ok();
+    }
+}
+
+/// Creates a new file only the current user can read
+pub fn write_private(path: &Path, text: &str) -> Result<(), String> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(path)
+        .map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+    file.write_all(text.as_bytes()).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
 impl SecureStorage {
     pub fn new() -> Self {
         let data_dir = directories::ProjectDirs::from("com", "secnotes", "SecureNotes")
             .expect("Failed to get data directory");
 
-        let data_path = data_dir.data_dir();
+        Self::with_data_dir(data_dir.data_dir())
+    }
+
+    /// A vault in `data_path` instead of the user's data directory
+    pub fn with_data_dir(data_path: &Path) -> Self {
         fs::create_dir_all(data_path).ok();
 
         let file_path = data_path.join("notes.enc");
@@ -46,6 +171,16 @@ impl SecureStorage {
 
         Self {
             notes: HashMap::new(),
+            templates: Vec::new(),
+            journal: JournalSettings::default(),
+            blob_keys: BlobKeys::generate(),
+            identity: VaultIdentity::generate(),
+            contacts: Vec::new(),
+            sync: None,
+            git: None,
+            history_keys: Vec::new(),
+            note_files: NoteFiles::new(data_path),
+            sync_jobs: Arc::new(()),
             file_path,
             encryption_key: None,
             salt,
@@ -65,11 +200,7 @@ impl SecureStorage {
         let mut active_salt = self.salt.clone();
 
         if self.file_path.exists() {
-            let file_content = fs::read_to_string(&self.file_path)
-                .map_err(|e| format!("Failed to read file: {}", e))?;
-
-            let encrypted_data: EncryptedData = serde_json::from_str(&file_content)
-                .map_err(|e| format!("Failed to parse encrypted data: {}", e))?;
+            let encrypted_data = self.read_vault_file()?;
 
             active_salt = encrypted_data.salt;
             let key = crypto::derive_key(password, &active_salt)?;
@@ -77,10 +208,7 @@ impl SecureStorage {
             let encrypted_bytes = crypto::decode_base64(&encrypted_data.data)?;
             let decrypted = crypto::decrypt(&encrypted_bytes, &key)?;
 
-            let notes: Vec<Note> = serde_json::from_slice(&decrypted)
-                .map_err(|e| format!("Failed to parse notes: {}", e))?;
-
-            self.notes = notes.into_iter().map(|n| (n.id.clone(), n)).collect();
+            self.load(decode_payload(&decrypted)?, &key)?;
             self.encryption_key = Some(key);
         } else {
             if active_salt.is_empty() {
@@ -96,21 +224,92 @@ impl SecureStorage {
         Ok(())
     }
 
-    pub fn save(&self) -> Result<(), String> {
+    /// Unlocks with a key derived earlier, e.g. one held by the unlock agent
+    pub fn unlock_with_key(&mut self, key: &[u8; 32], salt: &str) -> Result<(), String> {
+        if self.file_path.exists() {
+            let encrypted_data = self.read_vault_file()?;
+            if encrypted_data.salt != salt {
+                return Err("The key belongs to a different vault or an old password".to_string());
+            }
+            let decrypted = crypto::decrypt(&crypto::decode_base64(&encrypted_data.data)?, key)?;
+            self.load(decode_payload(&decrypted)?, key)?;
+        }
+        self.encryption_key = Some(*key);
+        self.salt = salt.to_string();
+        self.is_unlocked = true;
+        Ok(())
+    }
+
+    /// The derived key and its salt, for handing to the unlock agent
+    pub fn unlocked_key(&self) -> Option<([u8; 32], String)> {
+        self.encryption_key.map(|key| (key, self.salt.clone()))
+    }
+
+    fn read_vault_file(&self) -> Result<EncryptedData, String> {
+        let file_content = fs::read_to_string(&self.file_path)
+            .map_err(|e| format!("Failed to read file: {}", e))?;
+        serde_json::from_str(&file_content).map_err(|e| format!("Failed to parse encrypted data: {}", e))
+    }
+
+    fn load(&mut self, data: VaultData, key: &[u8; 32]) -> Result<(), String> {
+        let mut notes = data.notes;
+        self.git = None;
+        if data.git_history {
+            notes.extend(self.note_files.load(key)?);
+            self.git = Some(GitHistory::init(self.vault_dir()?, &self.vault_file_name())?);
+        }
+        self.notes = notes.into_iter().map(|n| (n.id.clone(), n)).collect();
+        self.templates = data.templates;
+        self.journal = data.journal;
+        // Vaults from before attachments existed get keys on their next save
+        self.blob_keys = data.blob_keys.unwrap_or_else(BlobKeys::generate);
+        self.identity = data.identity.unwrap_or_else(VaultIdentity::generate);
+        self.contacts = data.contacts;
+        self.sync = data.sync;
+        self.history_keys = data.history_keys;
+        Ok(())
+    }
+
+    fn vault_dir(&self) -> Result<&Path, String> {
+        self.file_path.parent().ok_or("Failed to get data directory".to_string())
+    }
+
+    fn vault_file_name(&self) -> String {
+        self.file_path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&mut self) -> Result<(), String> {
         if !self.is_unlocked {
             return Err("Storage is locked".to_string());
         }
 
-        let key = self
-            .encryption_key
-            .as_ref()
-            .ok_or("No encryption key available")?;
+        let key = self.encryption_key.ok_or("No encryption key available")?;
 
-        let notes: Vec<&Note> = self.notes.values().collect();
+        let notes = match self.git {
+            Some(_) => {
+                self.note_files.write(self.notes.values(), &key)?;
+                Vec::new()
+            }
+            None => self.notes.values().cloned().collect(),
+        };
+        let data = VaultData {
+            notes,
+            templates: self.templates.clone(),
+            journal: self.journal.clone(),
+            blob_keys: Some(self.blob_keys.clone()),
+            identity: Some(self.identity.clone()),
+            contacts: self.contacts.clone(),
+            sync: self.sync.clone(),
+            git_history: self.git.is_some(),
+            history_keys: self.history_keys.clone(),
+        };
         let json =
-            serde_json::to_vec(&notes).map_err(|e| format!("Failed to serialize notes: {}", e))?;
+            serde_json::to_vec(&data).map_err(|e| format!("Failed to serialize notes: {}", e))?;
 
-        let encrypted = crypto::encrypt(&json, key)?;
+        let encrypted = crypto::encrypt(&json, &key)?;
         let encrypted_base64 = crypto::encode_base64(&encrypted);
 
         let encrypted_data = EncryptedData {
@@ -124,9 +323,30 @@ impl SecureStorage {
         fs::write(&self.file_path, json_string)
             .map_err(|e| format!("Failed to write file: {}", e))?;
 
+        if let Some(git) = &self.git {
+            git.commit()
+                .map_err(|e| format!("Saved, but failed to record history: {}", e))?;
+        }
         Ok(())
     }
 
+    /// Re-encrypts the vault under a new password and salt
+    pub fn change_password(&mut self, new_password: &str) -> Result<(), String> {
+        if !self.is_unlocked {
+            return Err("Storage is locked".to_string());
+        }
+        if let (Some(_), Some(old_key)) = (&self.git, self.encryption_key) {
+            // Older commits stay encrypted with the old key
+            self.history_keys.push(crypto::encode_base64(&old_key));
+        }
+        let salt = crypto::generate_salt();
+        self.encryption_key = Some(crypto::derive_key(new_password, &salt)?);
+        self.salt = salt;
+        // Every note file has to be rewritten with the new key
+        self.note_files = NoteFiles::new(self.vault_dir()?);
+        self.save()
+    }
+
     pub fn add_note(&mut self, note: Note) -> Result<(), String> {
         if !self.is_unlocked {
             return Err("Storage is locked".to_string());
@@ -135,6 +355,17 @@ impl SecureStorage {
         self.save()
     }
 
+    /// Adds many notes with a single write of the vault
+    pub fn import_notes(&mut self, notes: Vec<Note>) -> Result<usize, String> {
+        if !self.is_unlocked {
+            return Err("Storage is locked".to_string());
+        }
+        let count = notes.len();
+        self.notes.extend(notes.into_iter().map(|note| (note.id.clone(), note)));
+        self.save()?;
+        Ok(count)
+    }
+
     pub fn update_note(&mut self, note: Note) -> Result<(), String> {
         if !self.is_unlocked {
             return Err("Storage is locked".to_string());
@@ -148,7 +379,90 @@ impl SecureStorage {
             return Err("Storage is locked".to_string());
         }
         self.notes.remove(id);
-        self.save()
+        self.save()?;
+        self.collect_garbage().map(|_| ())
+    }
+
+    pub fn blob_store(&self) -> Result<BlobStore, String> {
+        BlobStore::new(self.vault_dir()?.join("attachments"), &self.blob_keys)
+    }
+
+    /// Encrypts a file into the blob store and attaches it to a note
+    pub fn attach_file(&mut self, note_id: &str, path: &Path) -> Result<Attachment, String> {
+        if !self.is_unlocked {
+            return Err("Storage is locked".to_string());
+        }
+        let attachment = self.blob_store()?.add_file(path)?;
+        self.add_attachment(note_id, attachment)
+    }
+
+    pub fn attach_bytes(&mut self, note_id: &str, file_name: &str, data: &[u8]) -> Result<Attachment, String> {
+        if !self.is_unlocked {
+            return Err("Storage is locked".to_string());
+        }
+        let attachment = self.blob_store()?.add_bytes(file_name, data)?;
+        self.add_attachment(note_id, attachment)
+    }
+
+    fn add_attachment(&mut self, note_id: &str, attachment: Attachment) -> Result<Attachment, String> {
+        let note = self.notes.get_mut(note_id).ok_or("Note not found")?;
+        note.add_attachment(attachment.clone());
+        self.save()?;
+        Ok(attachment)
+    }
+
+    pub fn export_attachment(&self, blob_id: &str, destination: &Path) -> Result<(), String> {
+        if !self.is_unlocked {
+            return Err("Storage is locked".to_string());
+        }
+        self.blob_store()?.export(blob_id, destination)
+    }
+
+    /// Decrypts an attachment into the cache directory and hands it to the
+    /// default application. The plaintext copy is removed on exit.
+    pub fn open_attachment(&self, attachment: &Attachment) -> Result<(), String> {
+        if !self.is_unlocked {
+            return Err("Storage is locked".to_string());
+        }
+        let dir = opened_attachments_dir().ok_or("Failed to get cache directory")?;
+        fs::create_dir_all(&dir).map_err(|e| format!("Failed to create cache dir: {}", e))?;
+        let path = opened_attachment_path(&dir, attachment);
+        self.export_attachment(&attachment.blob_id, &path)?;
+
+        #[cfg(target_os = "macos")]
+        let opener = "open";
+        #[cfg(not(target_os = "macos"))]
+        let opener = "xdg-open";
+        std::process::Command::new(opener)
+            .arg(&path)
+            .spawn()
+            .map(|_| ())
+            .map_err(|e| format!("Failed to open attachment: {}", e))
+    }
+
+    pub fn remove_attachment(&mut self, note_id: &str, blob_id: &str) -> Result<(), String> {
+        if !self.is_unlocked {
+            return Err("Storage is locked".to_string());
+        }
+        let note = self.notes.get_mut(note_id).ok_or("Note not found")?;
+        note.remove_attachment(blob_id);
+        self.save()?;
+        self.collect_garbage().map(|_| ())
+    }
+
+    /// Deletes blobs that no note references anymore. Skipped while a sync
+    /// job is alive, since it may be downloading blobs for notes this vault
+    /// does not have yet; `finish_sync` collects them afterwards.
+    pub fn collect_garbage(&self) -> Result<usize, String> {
+        if Arc::strong_count(&self.sync_jobs) > 1 {
+            return Ok(0);
+        }
+        let referenced: HashSet<&str> = self
+            .notes
+            .values()
+            .flat_map(|note| note.attachments.iter().map(|a| a.blob_id.as_str()))
+            .collect();
+        self.blob_store()?.collect_garbage(&referenced)
     }
 
     pub fn get_note(&self, id: &str) -> Option<&Note> {
@@ -167,21 +481,272 @@ impl SecureStorage {
         notes
     }
 
-    pub fn search_notes(&self, query: &str) -> Vec<&Note> {
+    pub fn get_templates(&self) -> &[NoteTemplate] {
+        if !self.is_unlocked {
+            return &[];
+        }
+        &self.templates
+    }
+
+    pub fn save_template(&mut self, template: NoteTemplate) -> Result<(), String> {
+        if !self.is_unlocked {
+            return Err("Storage is locked".to_string());
+        }
+        match self.templates.iter_mut().find(|t| t.id == template.id) {
+            Some(existing) => *existing = template,
+            None => self.templates.push(template),
+        }
+        self.save()
+    }
+
+    pub fn delete_template(&mut self, id: &str) -> Result<(), String> {
+        if !self.is_unlocked {
+            return Err("Storage is locked".to_string());
+        }
+        self.templates.retain(|t| t.id != id);
+        self.save()
+    }
+
+    pub fn journal_settings(&self) -> &JournalSettings {
+        &self.journal
+    }
+
+    pub fn set_journal_settings(&mut self, settings: JournalSettings) -> Result<(), String> {
+        if !self.is_unlocked {
+            return Err("Storage is locked".to_string());
+        }
+        self.journal = settings;
+        self.save()
+    }
+
+    /// The journal entry for a day, if one has been written
+    pub fn get_journal_entry(&self, date: chrono::NaiveDate) -> Option<&Note> {
+        if !self.is_unlocked {
+            return None;
+        }
+        self.notes
+            .values()
+            .filter(|note| note.journal_date == Some(date))
+            .min_by_key(|note| note.created_at)
+    }
+
+    pub fn journal_dates(&self) -> HashSet<chrono::NaiveDate> {
+        if !self.is_unlocked {
+            return Default::default();
+        }
+        self.notes.values().filter_map(|note| note.journal_date).collect()
+    }
+
+    /// The vault's private sharing keys
+    pub fn identity(&self) -> Result<&VaultIdentity, String> {
+        if !self.is_unlocked {
+            return Err("Storage is locked".to_string());
+        }
+        Ok(&self.identity)
+    }
+
+    pub fn contacts(&self) -> &[Contact] {
+        if !self.is_unlocked {
+            return &[];
+        }
+        &self.contacts
+    }
+
+    /// Adds a contact, replacing any existing entry with the same key
+    pub fn save_contact(&mut self, contact: Contact) -> Result<(), String> {
+        if !self.is_unlocked {
+            return Err("Storage is locked".to_string());
+        }
+        match self.contacts.iter_mut().find(|c| c.public_key == contact.public_key) {
+            Some(existing) => *existing = contact,
+            None => self.contacts.push(contact),
+        }
+        self.save()
+    }
+
+    pub fn delete_contact(&mut self, public_key: &str) -> Result<(), String> {
+        if !self.is_unlocked {
+            return Err("Storage is locked".to_string());
+        }
+        self.contacts.retain(|c| c.public_key != public_key);
+        self.save()
+    }
+
+    /// Where this vault syncs to, if sync is set up
+    pub fn sync_target(&self) -> Option<&SyncTarget> {
+        self.sync.as_ref().map(|sync| &sync.target)
+    }
+
+    /// Changes waiting for the sync target to become reachable
+    pub fn sync_queued(&self) -> usize {
+        self.sync.as_ref().map_or(0, |sync| sync.queued())
+    }
+
+    /// Joins (or creates) a sync folder. `password` protects the folder and
+    /// must be the same on every device.
+    pub fn enable_sync(&mut self, target: SyncTarget, password: &str) -> Result<(), String> {
+        if !self.is_unlocked {
+            return Err("Storage is locked".to_string());
+        }
+        self.sync = Some(SyncState::connect(target, password)?);
+        self.save()
+    }
+
+    pub fn disable_sync(&mut self) -> Result<(), String> {
+        if !self.is_unlocked {
+            return Err("Storage is locked".to_string());
+        }
+        self.sync = None;
+        self.save()
+    }
+
+    /// A copy of the sync state and notes that `SyncJob::run` can sync on
+    /// another thread, so a slow or unreachable remote does not block the app
+    pub fn start_sync(&self) -> Result<SyncJob, String> {
+        if !self.is_unlocked {
+            return Err("Storage is locked".to_string());
+        }
+        let state = self.sync.clone().ok_or("Sync is not set up")?;
+        Ok(SyncJob {
+            state,
+            before: self.notes.clone(),
+            notes: self.notes.clone(),
+            blob_store: self.blob_store()?,
+            result: Ok(SyncReport::default()),
+            _running: Arc::clone(&self.sync_jobs),
+        })
+    }
+
+    /// Takes over the result of a finished job. Notes edited while it ran
+    /// keep those edits, merged with what the sync brought in.
+    pub fn finish_sync(&mut self, job: SyncJob) -> Result<SyncReport, String> {
+        if !self.is_unlocked {
+            return Err("Storage is locked".to_string());
+        }
+        // Sync was turned off or pointed elsewhere in the meantime
+        if self.sync.as_ref().map(|state| &state.target) != Some(&job.state.target) {
+            return Err("Sync settings changed during the sync".to_string());
+        }
+        let SyncJob {
+            state,
+            before,
+            mut notes,
+            result,
+            _running,
+            ..
+        } = job;
+        drop(_running);
+        let ids: HashSet<String> = before.keys().chain(self.notes.keys()).cloned().collect();
+        for id in ids {
+            let current = self.notes.get(&id);
+            if same_note(current, before.get(&id)) {
+                continue;
+            }
+            match (current, before.get(&id), notes.get(&id)) {
+                (Some(current), Some(base), Some(synced)) if !same_note(Some(synced), Some(base)) => {
+                    let mut merged = current.clone();
+                    let merge = diffy::merge(&base.content, &current.content, &synced.content)
+                        .map_err(|_| "Conflicting edits".to_string())
+                        .and_then(|content| merged.update_content(content));
+                    // Keeps the synced text as a separate note instead
+                    if merge.is_err() {
+                        let mut copy = synced.clone();
+                        copy.id = format!("{}-conflict-{}", id, uuid::Uuid::new_v4().simple());
+                        copy.title = format!("{} (conflicted copy)", copy.title);
+                        notes.insert(copy.id.clone(), copy);
+                    }
+                    notes.insert(id, merged);
+                }
+                (Some(current), _, _) => {
+                    notes.insert(id, current.clone());
+                }
+                (None, _, _) => {
+                    notes.remove(&id);
+                }
+            }
+        }
+        self.notes = notes;
+        self.sync = Some(state);
+        // Keep queued changes even if the remote was unreachable
+        self.save()?;
+        // Notes deleted on other devices, or here while the job ran, may
+        // leave attachments behind
+        self.collect_garbage()?;
+        result
+    }
+
+    /// The git repository recording every save, if history is turned on
+    pub fn git_history(&self) -> Option<&GitHistory> {
+        self.git.as_ref()
+    }
+
+    /// Splits notes into separate encrypted files and commits every save
+    /// to a git repository in the vault directory
+    pub fn enable_git_history(&mut self) -> Result<(), String> {
+        if !self.is_unlocked {
+            return Err("Storage is locked".to_string());
+        }
+        self.git = Some(GitHistory::init(self.vault_dir()?, &self.vault_file_name())?);
+        self.save()
+    }
+
+    /// Goes back to a single vault file. The repository and its history stay.
+    pub fn disable_git_history(&mut self) -> Result<(), String> {
+        if !self.is_unlocked {
+            return Err("Storage is locked".to_string());
+        }
+        self.git = None;
+        self.save()?;
+        self.note_files.remove()
+    }
+
+    /// Fast-forwards to the git remote and reloads the vault
+    pub fn pull_history(&mut self) -> Result<(), String> {
+        if !self.is_unlocked {
+            return Err("Storage is locked".to_string());
+        }
+        let key = self.encryption_key.ok_or("No encryption key available")?;
+        self.git.as_ref().ok_or("Git history is not turned on")?.pull()?;
+
+        let encrypted_data = self.read_vault_file()?;
+        if encrypted_data.salt != self.salt {
+            return Err("The pulled vault uses a different password; lock and unlock again".to_string());
+        }
+        let decrypted = crypto::decrypt(&crypto::decode_base64(&encrypted_data.data)?, &key)?;
+        self.load(decode_payload(&decrypted)?, &key)
+    }
+
+    /// A note as it was saved in `commit`
+    pub fn note_version(&self, commit: &str, note_id: &str) -> Result<Note, String> {
+        let key = self.encryption_key.ok_or("Storage is locked")?;
+        let git = self.git.as_ref().ok_or("Git history is not turned on")?;
+        let encrypted = git.note_file_at(commit, note_id)?;
+        if let Ok(json) = crypto::decrypt(&encrypted, &key) {
+            return git_history::decode_note(&json);
+        }
+        // Saved before a password change
+        for old_key in self.history_keys.iter().rev() {
+            let old_key: [u8; 32] = crypto::decode_base64(old_key)?
+                .try_into()
+                .map_err(|_| "Invalid history key".to_string())?;
+            if let Ok(json) = crypto::decrypt(&encrypted, &old_key) {
+                return git_history::decode_note(&json);
+            }
+        }
+        Err("This version was encrypted with a key the vault no longer has".to_string())
+    }
+
+    /// Free-text search; `key:value` terms filter on note properties.
+    /// Archived notes only match when `archived` is set.
+    pub fn search_notes(&self, query: &str, archived: bool) -> Vec<&Note> {
         if !self.is_unlocked {
             return Vec::new();
         }
-        let query_lower = query.to_lowercase();
+        let query = SearchQuery::parse(query);
         self.notes
             .values()
-            .filter(|note| {
-                note.title.to_lowercase().contains(&query_lower)
-                    || note.content.to_lowercase().contains(&query_lower)
-                    || note
-                        .tags
-                        .iter()
-                        .any(|tag| tag.to_lowercase().contains(&query_lower))
-            })
+            .filter(|note| archived || !note.archived)
+            .filter(|note| query.matches(note))
             .collect()
     }
 }
@@ -240,4 +805,106 @@ mod tests {
         restore_env_var("XDG_DATA_HOME", previous_data);
         restore_env_var("XDG_CACHE_HOME", previous_cache);
     }
+
+    #[test]
+    fn opened_attachments_stay_in_the_cache_directory() {
+        let dir = Path::new("/cache/opened");
+        let attachment = Attachment {
+            blob_id: "abc".to_string(),
+            file_name: "../../.bashrc".to_string(),
+            size: 1,
+            added_at: chrono::Utc::now(),
+        };
+        let path = opened_attachment_path(dir, &attachment);
+        assert_eq!(path.parent(), Some(dir));
+        assert_eq!(path.file_name().unwrap(), "abc-.._.._.bashrc");
+    }
+
+    #[test]
+    fn edits_made_while_a_sync_runs_are_merged_with_it() {
+        let dir = tempdir().unwrap();
+        let folder = SyncTarget::Folder(dir.path().join("shared"));
+        let sync = |storage: &mut SecureStorage| storage.finish_sync(storage.start_sync().unwrap().run()).unwrap();
+        let mut laptop = SecureStorage::with_data_dir(&dir.path().join("laptop"));
+        let mut phone = SecureStorage::with_data_dir(&dir.path().join("phone"));
+        for storage in [&mut laptop, &mut phone] {
+            storage.unlock("vault password").unwrap();
+            storage.enable_sync(folder.clone(), "sync password").unwrap();
+        }
+
+        let note = Note::new("List".to_string(), "one\ntwo\nthree\n".to_string());
+        let id = note.id.clone();
+        laptop.add_note(note).unwrap();
+        sync(&mut laptop);
+        sync(&mut phone);
+        let mut edited = phone.get_note(&id).unwrap().clone();
+        edited.update_content("ONE\ntwo\nthree\n".to_string()).unwrap();
+        phone.update_note(edited).unwrap();
+        sync(&mut phone);
+
+        let job = laptop.start_sync().unwrap();
+        let mut edited = laptop.get_note(&id).unwrap().clone();
+        edited.update_content("one\ntwo\nTHREE\n".to_string()).unwrap();
+        laptop.update_note(edited).unwrap();
+        let report = laptop.finish_sync(job.run()).unwrap();
+        assert_eq!(report.received, 1);
+        assert_eq!(laptop.get_note(&id).unwrap().content, "ONE\ntwo\nTHREE\n");
+    }
+
+    #[test]
+    fn deleting_a_note_during_a_sync_keeps_the_blobs_it_downloads() {
+        let dir = tempdir().unwrap();
+        let folder = SyncTarget::Folder(dir.path().join("shared"));
+        let mut laptop = SecureStorage::with_data_dir(&dir.path().join("laptop"));
+        let mut phone = SecureStorage::with_data_dir(&dir.path().join("phone"));
+        for storage in [&mut laptop, &mut phone] {
+            storage.unlock("vault password").unwrap();
+            storage.enable_sync(folder.clone(), "sync password").unwrap();
+        }
+        let note = Note::new("Receipt".to_string(), String::new());
+        let id = note.id.clone();
+        laptop.add_note(note).unwrap();
+        laptop.attach_bytes(&id, "receipt.txt", b"paid").unwrap();
+        laptop.finish_sync(laptop.start_sync().unwrap().run()).unwrap();
+        let scratch = Note::new("Scratch".to_string(), String::new());
+        let scratch_id = scratch.id.clone();
+        phone.add_note(scratch).unwrap();
+
+        let job = phone.start_sync().unwrap().run();
+        phone.delete_note(&scratch_id).unwrap();
+        phone.finish_sync(job).unwrap();
+
+        let blob_id = &phone.get_note(&id).unwrap().attachments[0].blob_id;
+        assert_eq!(phone.blob_store().unwrap().read(blob_id).unwrap(), b"paid");
+        assert!(phone.get_note(&scratch_id).is_none());
+    }
+
+    #[test]
+    fn history_survives_a_password_change() {
+        let dir = tempdir().unwrap();
+        let mut storage = SecureStorage::with_data_dir(dir.path());
+        storage.unlock("old password").unwrap();
+        storage.enable_git_history().unwrap();
+        let note = Note::new("Diary".to_string(), "first draft".to_string());
+        let id = note.id.clone();
+        storage.add_note(note).unwrap();
+        storage.change_password("new password").unwrap();
+
+        let mut storage = SecureStorage::with_data_dir(dir.path());
+        storage.unlock("new password").unwrap();
+        let log = storage.git_history().unwrap().log(10).unwrap();
+        let oldest = &log.last().unwrap().commit;
+        assert_eq!(storage.note_version(oldest, &id).unwrap().content, "first draft");
+    }
+
+    #[test]
+    fn decodes_legacy_payload_without_templates() {
+        let note = Note::new("Title".to_string(), "Body".to_string());
+        let legacy = serde_json::to_vec(&vec![&note]).expect("serialize legacy payload");
+
+        let data = decode_payload(&legacy).expect("decode legacy payload");
+        assert_eq!(data.notes.len(), 1);
+        assert_eq!(data.notes[0].id, note.id);
+        assert!(data.templates.is_empty());
+    }
 }
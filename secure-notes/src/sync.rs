@@ -0,0 +1,930 @@
+use crate::attachments::BlobStore;
+use crate::crypto;
+use crate::note::Note;
+use crate::webdav::{WebDav, WebDavAccount};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+const FOLDER_FILE: &str = "sync.json";
+const LOG_EXTENSION: &str = "log";
+const BLOBS_DIR: &str = "blobs";
+const KEY_CHECK: &[u8] = b"secure-notes sync";
+/// Ancestors of each note's synced revision kept for merging later changes
+const KEPT_ANCESTORS: usize = 4;
+
+/// Written once by the first device so others can derive the same key
+#[derive(Serialize, Deserialize)]
+struct FolderHeader {
+    salt: String,
+    /// `KEY_CHECK` encrypted with the folder key, to catch a wrong password early
+    check: String,
+}
+
+/// Where the shared sync files live. Paths are relative and use `/`.
+pub trait Remote {
+    /// Files at the top level, each with a tag that changes with the file
+    fn list(&self) -> Result<Vec<(String, String)>, String>;
+    fn read(&self, path: &str) -> Result<Option<Vec<u8>>, String>;
+    /// The bytes after `offset`, e.g. what was appended since the last read
+    fn read_from(&self, path: &str, offset: u64) -> Result<Option<Vec<u8>>, String> {
+        let data = self.read(path)?;
+        Ok(data.map(|data| data.get(offset as usize..).unwrap_or_default().to_vec()))
+    }
+    fn write(&self, path: &str, data: &[u8]) -> Result<(), String>;
+    fn exists(&self, path: &str) -> Result<bool, String>;
+    fn append(&self, path: &str, data: &[u8]) -> Result<(), String>;
+}
+
+/// A folder kept in sync between devices by another tool
+struct Folder<'a>(&'a Path);
+
+impl Remote for Folder<'_> {
+    fn list(&self) -> Result<Vec<(String, String)>, String> {
+        let entries = fs::read_dir(self.0).map_err(|e| format!("Failed to read {}: {}", self.0.display(), e))?;
+        let mut files = Vec::new();
+        for entry in entries.flatten() {
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if metadata.is_file() {
+                let modified = metadata
+                    .modified()
+                    .ok()
+                    .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                    .unwrap_or_default();
+                let tag = format!("{}-{}", metadata.len(), modified.as_nanos());
+                files.push((entry.file_name().to_string_lossy().into_owned(), tag));
+            }
+        }
+        Ok(files)
+    }
+
+    fn read(&self, path: &str) -> Result<Option<Vec<u8>>, String> {
+        let path = self.0.join(path);
+        if !path.exists() {
+            return Ok(None);
+        }
+        fs::read(&path)
+            .map(Some)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))
+    }
+
+    fn read_from(&self, path: &str, offset: u64) -> Result<Option<Vec<u8>>, String> {
+        let path = self.0.join(path);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let mut data = Vec::new();
+        fs::File::open(&path)
+            .and_then(|mut file| {
+                file.seek(SeekFrom::Start(offset))?;
+                file.read_to_end(&mut data)
+            })
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        Ok(Some(data))
+    }
+
+    fn write(&self, path: &str, data: &[u8]) -> Result<(), String> {
+        let path = self.0.join(path);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        }
+        fs::write(&path, data).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+
+    fn exists(&self, path: &str) -> Result<bool, String> {
+        Ok(self.0.join(path).exists())
+    }
+
+    fn append(&self, path: &str, data: &[u8]) -> Result<(), String> {
+        let path = self.0.join(path);
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .and_then(|mut file| file.write_all(data))
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum SyncTarget {
+    /// Shared folder, e.g. kept in sync by Syncthing or a NAS
+    Folder(PathBuf),
+    WebDav(WebDavAccount),
+}
+
+impl SyncTarget {
+    fn with_remote<T>(&self, run: impl FnOnce(&dyn Remote) -> Result<T, String>) -> Result<T, String> {
+        match self {
+            SyncTarget::Folder(folder) => run(&Folder(folder)),
+            SyncTarget::WebDav(account) => run(&WebDav::new(account)?),
+        }
+    }
+}
+
+impl fmt::Display for SyncTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SyncTarget::Folder(folder) => write!(f, "Folder: {}", folder.display()),
+            SyncTarget::WebDav(account) => write!(f, "WebDAV: {}", account.url),
+        }
+    }
+}
+
+/// Entries appended to a device log since it was last read
+struct NewEntries {
+    device_id: String,
+    tag: String,
+    /// Offset just after the last complete entry
+    end: u64,
+    changes: Vec<Change>,
+}
+
+/// Sync settings and bookkeeping for this device, kept inside the vault
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncState {
+    #[serde(alias = "folder")]
+    pub target: SyncTarget,
+    pub device_id: String,
+    /// Base64 key shared by every device using the folder
+    key: String,
+    /// Log entries already applied, per remote device. Only in vaults
+    /// synced before `log_offsets`; used once to skip those entries.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    seen: HashMap<String, usize>,
+    /// Bytes of each device's log already read and applied
+    #[serde(default)]
+    log_offsets: HashMap<String, u64>,
+    /// Recent revisions, so merges need not read old log entries again
+    #[serde(default)]
+    revisions: HashMap<String, Revision>,
+    /// Last revision of each note this device published or applied
+    #[serde(default)]
+    synced: HashMap<String, SyncedNote>,
+    /// Local blob id for each attachment id used in the logs
+    #[serde(default)]
+    blob_ids: HashMap<String, String>,
+    /// Encrypted log lines not uploaded yet, e.g. while offline
+    #[serde(default)]
+    queued_changes: Vec<String>,
+    /// Attachment ids (as in the logs) not uploaded yet
+    #[serde(default)]
+    queued_blobs: Vec<String>,
+    /// Tag of each log when it was last read, to skip unchanged ones
+    #[serde(skip)]
+    log_tags: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SyncedNote {
+    revision: String,
+    /// Digest of the note at that revision, `None` once it was deleted
+    digest: Option<String>,
+}
+
+/// One entry in a device's change log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Change {
+    revision: String,
+    parent: Option<String>,
+    note_id: String,
+    at: DateTime<Utc>,
+    /// The whole note after the change, or `None` for a deletion.
+    /// Attachment ids are the folder's, not the vault's.
+    note: Option<Note>,
+}
+
+/// What merging later changes needs to know about a change
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Revision {
+    parent: Option<String>,
+    at: DateTime<Utc>,
+    digest: Option<String>,
+    /// Note content after the change, the base for merging concurrent edits
+    content: Option<String>,
+}
+
+impl From<&Change> for Revision {
+    fn from(change: &Change) -> Self {
+        Self {
+            parent: change.parent.clone(),
+            at: change.at,
+            digest: change_digest(change),
+            content: change.note.as_ref().map(|note| note.content.clone()),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SyncReport {
+    pub sent: usize,
+    pub received: usize,
+    pub merged: usize,
+    pub conflicts: usize,
+}
+
+impl SyncReport {
+    pub fn summary(&self) -> String {
+        let mut summary = format!("{} sent, {} received", self.sent, self.received);
+        if self.merged > 0 {
+            summary.push_str(&format!(", {} merged", self.merged));
+        }
+        if self.conflicts > 0 {
+            summary.push_str(&format!(", {} conflicts copied", self.conflicts));
+        }
+        summary
+    }
+}
+
+fn digest(note: &Note) -> String {
+    // CRDT documents with the same edits can still differ byte for byte
+    let mut note = note.clone();
+    let version = note.crdt.take().map(|crdt| crdt.version());
+    let mut hasher = Sha256::new();
+    hasher.update(serde_json::to_vec(&note).unwrap_or_default());
+    if let Some(version) = version {
+        hasher.update(version);
+    }
+    hex::encode(hasher.finalize())
+}
+
+fn change_digest(change: &Change) -> Option<String> {
+    change.note.as_ref().map(digest)
+}
+
+impl SyncState {
+    /// Joins a sync folder, setting it up if this is the first device.
+    /// Every device must use the same password for the folder.
+    pub fn connect(target: SyncTarget, password: &str) -> Result<Self, String> {
+        let key = target.with_remote(|remote| match remote.read(FOLDER_FILE)? {
+            Some(json) => {
+                let header: FolderHeader =
+                    serde_json::from_slice(&json).map_err(|e| format!("Failed to parse sync folder: {}", e))?;
+                let key = crypto::derive_key(password, &header.salt)?;
+                let check = crypto::decrypt(&crypto::decode_base64(&header.check)?, &key);
+                if check.as_deref() != Ok(KEY_CHECK) {
+                    return Err("The sync folder was set up with a different password".to_string());
+                }
+                Ok(key)
+            }
+            None => {
+                let salt = crypto::generate_salt();
+                let key = crypto::derive_key(password, &salt)?;
+                let header = FolderHeader {
+                    salt,
+                    check: crypto::encode_base64(&crypto::encrypt(KEY_CHECK, &key)?),
+                };
+                let json = serde_json::to_string_pretty(&header)
+                    .map_err(|e| format!("Failed to serialize sync folder: {}", e))?;
+                remote.write(FOLDER_FILE, json.as_bytes())?;
+                Ok(key)
+            }
+        })?;
+
+        Ok(Self {
+            target,
+            device_id: uuid::Uuid::new_v4().to_string(),
+            key: crypto::encode_base64(&key),
+            seen: HashMap::new(),
+            log_offsets: HashMap::new(),
+            revisions: HashMap::new(),
+            synced: HashMap::new(),
+            blob_ids: HashMap::new(),
+            queued_changes: Vec::new(),
+            queued_blobs: Vec::new(),
+            log_tags: HashMap::new(),
+        })
+    }
+
+    fn key(&self) -> Result<[u8; 32], String> {
+        crypto::decode_base64(&self.key)?
+            .try_into()
+            .map_err(|_| "Invalid sync key".to_string())
+    }
+
+    /// Changes waiting to be uploaded
+    pub fn queued(&self) -> usize {
+        self.queued_changes.len()
+    }
+
+    /// Publishes local edits to this device's log, then merges every other
+    /// device's new entries into `notes`. Merge results are published too.
+    /// Edits stay queued in the state when the remote cannot be reached.
+    pub fn sync(&mut self, notes: &mut HashMap<String, Note>, blob_store: &BlobStore) -> Result<SyncReport, String> {
+        let target = self.target.clone();
+        target.with_remote(|remote| self.sync_with(remote, notes, blob_store))
+    }
+
+    fn sync_with(
+        &mut self,
+        remote: &dyn Remote,
+        notes: &mut HashMap<String, Note>,
+        blob_store: &BlobStore,
+    ) -> Result<SyncReport, String> {
+        let mut report = SyncReport::default();
+        report.sent += self.publish(notes, blob_store)?;
+        self.upload(remote, blob_store)?;
+
+        let logs = self.read_logs(remote)?;
+        // Taken out while applying, since `apply` also changes the state
+        let mut revisions = std::mem::take(&mut self.revisions);
+        for change in logs.iter().flat_map(|log| &log.changes) {
+            revisions.insert(change.revision.clone(), Revision::from(change));
+        }
+        let applied = self.apply_logs(&logs, &revisions, notes, blob_store, remote, &mut report);
+        self.revisions = revisions;
+        applied?;
+        self.prune_revisions();
+
+        report.sent += self.publish(notes, blob_store)?;
+        self.upload(remote, blob_store)?;
+        Ok(report)
+    }
+
+    /// Queues a change for every note that differs from its synced revision
+    fn publish(&mut self, notes: &HashMap<String, Note>, blob_store: &BlobStore) -> Result<usize, String> {
+        let now = Utc::now();
+        let mut changes = Vec::new();
+        for note in notes.values() {
+            let portable = self.portable_note(note, blob_store)?;
+            let synced = self.synced.get(&note.id);
+            if synced.is_some_and(|synced| synced.digest.as_deref() == Some(digest(&portable).as_str())) {
+                continue;
+            }
+            changes.push(Change {
+                revision: uuid::Uuid::new_v4().to_string(),
+                parent: synced.map(|synced| synced.revision.clone()),
+                note_id: note.id.clone(),
+                at: now,
+                note: Some(portable),
+            });
+        }
+        for (note_id, synced) in &self.synced {
+            if synced.digest.is_some() && !notes.contains_key(note_id) {
+                changes.push(Change {
+                    revision: uuid::Uuid::new_v4().to_string(),
+                    parent: Some(synced.revision.clone()),
+                    note_id: note_id.clone(),
+                    at: now,
+                    note: None,
+                });
+            }
+        }
+        if changes.is_empty() {
+            return Ok(0);
+        }
+
+        let key = self.key()?;
+        for change in &changes {
+            let json = serde_json::to_vec(change).map_err(|e| format!("Failed to serialize change: {}", e))?;
+            self.queued_changes
+                .push(crypto::encode_base64(&crypto::encrypt(&json, &key)?));
+        }
+        for change in &changes {
+            self.synced.insert(
+                change.note_id.clone(),
+                SyncedNote {
+                    revision: change.revision.clone(),
+                    digest: change_digest(change),
+                },
+            );
+            self.revisions.insert(change.revision.clone(), Revision::from(change));
+        }
+        Ok(changes.len())
+    }
+
+    /// Sends queued attachments, then queued changes, so no change can
+    /// arrive before the attachments it refers to
+    fn upload(&mut self, remote: &dyn Remote, blob_store: &BlobStore) -> Result<(), String> {
+        let key = self.key()?;
+        while let Some(portable) = self.queued_blobs.first().cloned() {
+            let path = format!("{}/{}", BLOBS_DIR, portable);
+            // A blob deleted in the meantime is no longer referenced by any change
+            let local = self.blob_ids.get(&portable).filter(|local| blob_store.contains(local));
+            if let Some(local) = local {
+                if !remote.exists(&path)? {
+                    let data = blob_store.read(local)?;
+                    let mut encrypted = Vec::new();
+                    crypto::encrypt_stream(&mut &data[..], &mut encrypted, &key)?;
+                    remote.write(&path, &encrypted)?;
+                }
+            }
+            self.queued_blobs.remove(0);
+        }
+
+        if !self.queued_changes.is_empty() {
+            let lines: String = self.queued_changes.iter().map(|line| format!("{}\n", line)).collect();
+            remote.append(&format!("{}.{}", self.device_id, LOG_EXTENSION), lines.as_bytes())?;
+            self.queued_changes.clear();
+        }
+        Ok(())
+    }
+
+    /// What was appended to each device's log since it was last read, this
+    /// device's own included, sorted by device. A log is read up to its first
+    /// unreadable line, which is usually one the file sync has not finished
+    /// copying.
+    fn read_logs(&self, remote: &dyn Remote) -> Result<Vec<NewEntries>, String> {
+        let key = self.key()?;
+        let mut logs = Vec::new();
+        for (name, tag) in remote.list()? {
+            let Some(device_id) = name.strip_suffix(&format!(".{}", LOG_EXTENSION)) else {
+                continue;
+            };
+            if self.log_tags.get(device_id) == Some(&tag) {
+                continue;
+            }
+            let offset = self.log_offsets.get(device_id).copied().unwrap_or(0);
+            let data = remote.read_from(&name, offset)?.unwrap_or_default();
+            let (changes, read) = parse_log(&data, &key);
+            logs.push(NewEntries {
+                device_id: device_id.to_string(),
+                tag,
+                end: offset + read as u64,
+                changes,
+            });
+        }
+        logs.sort_by(|a, b| a.device_id.cmp(&b.device_id));
+        Ok(logs)
+    }
+
+    /// Applies other devices' new entries, moving each log's offset past
+    /// them once all of its entries are in
+    fn apply_logs(
+        &mut self,
+        logs: &[NewEntries],
+        index: &HashMap<String, Revision>,
+        notes: &mut HashMap<String, Note>,
+        blob_store: &BlobStore,
+        remote: &dyn Remote,
+        report: &mut SyncReport,
+    ) -> Result<(), String> {
+        for log in logs {
+            if log.device_id != self.device_id {
+                // Entries a vault from before read offsets had applied already
+                let skip = match self.log_offsets.contains_key(&log.device_id) {
+                    true => 0,
+                    false => self.seen.get(&log.device_id).copied().unwrap_or(0),
+                };
+                for change in log.changes.iter().skip(skip) {
+                    self.apply(change, index, notes, blob_store, remote, report)?;
+                }
+            }
+            self.seen.remove(&log.device_id);
+            self.log_offsets.insert(log.device_id.clone(), log.end);
+            self.log_tags.insert(log.device_id.clone(), log.tag.clone());
+        }
+        Ok(())
+    }
+
+    /// Forgets revisions merges can no longer need: anything more than
+    /// `KEPT_ANCESTORS` steps behind a note's synced revision. A device that
+    /// falls further behind than that gets conflict copies instead of
+    /// line merges for notes edited on both sides.
+    fn prune_revisions(&mut self) {
+        let mut kept = HashSet::new();
+        for synced in self.synced.values() {
+            let mut current = Some(synced.revision.as_str());
+            for _ in 0..=KEPT_ANCESTORS {
+                let Some(revision) = current else {
+                    break;
+                };
+                kept.insert(revision.to_string());
+                current = self.revisions.get(revision).and_then(|revision| revision.parent.as_deref());
+            }
+        }
+        self.revisions.retain(|revision, _| kept.contains(revision));
+    }
+
+    fn apply(
+        &mut self,
+        change: &Change,
+        index: &HashMap<String, Revision>,
+        notes: &mut HashMap<String, Note>,
+        blob_store: &BlobStore,
+        shared: &dyn Remote,
+        report: &mut SyncReport,
+    ) -> Result<(), String> {
+        let remote_digest = change_digest(change);
+        let remote_synced = SyncedNote {
+            revision: change.revision.clone(),
+            digest: remote_digest.clone(),
+        };
+        let Some(local) = self.synced.get(&change.note_id).cloned() else {
+            if let Some(note) = &change.note {
+                notes.insert(change.note_id.clone(), self.local_note(note, blob_store, shared)?);
+                report.received += 1;
+            }
+            self.synced.insert(change.note_id.clone(), remote_synced);
+            return Ok(());
+        };
+
+        let local_note = match notes.get(&change.note_id) {
+            Some(note) => Some(self.portable_note(note, blob_store)?),
+            None => None,
+        };
+        // Already identical, e.g. both devices merged the same way
+        if local_note.as_ref().map(digest) == remote_digest {
+            self.synced.insert(change.note_id.clone(), remote_synced);
+            return Ok(());
+        }
+        if ancestors(&local.revision, index).contains(change.revision.as_str()) {
+            return Ok(());
+        }
+
+        let parent = change.parent.as_deref().and_then(|parent| index.get(parent));
+        if parent.is_some_and(|parent| parent.digest == local.digest) {
+            match &change.note {
+                Some(note) => {
+                    notes.insert(change.note_id.clone(), self.local_note(note, blob_store, shared)?);
+                }
+                None => {
+                    notes.remove(&change.note_id);
+                }
+            }
+            self.synced.insert(change.note_id.clone(), remote_synced);
+            report.received += 1;
+            return Ok(());
+        }
+
+        // Concurrent edits. Both devices resolve them the same way, so the
+        // later change by (time, revision) wins and the results converge.
+        match (local_note, &change.note) {
+            (None, None) => {}
+            // An edit always survives a concurrent deletion
+            (Some(_), None) => {}
+            (None, Some(remote)) => {
+                notes.insert(change.note_id.clone(), self.local_note(remote, blob_store, shared)?);
+                report.received += 1;
+            }
+            (Some(local_note), Some(remote)) => {
+                let local_at = index.get(&local.revision).map(|local| local.at);
+                let remote_wins = (Some(change.at), &change.revision) > (local_at, &local.revision);
+                let (winner, loser, loser_revision) = if remote_wins {
+                    (remote.clone(), local_note, &local.revision)
+                } else {
+                    (local_note, remote.clone(), &change.revision)
+                };
+
+                if let (Some(ours), Some(theirs)) = (&winner.crdt, &loser.crdt) {
+                    let mut merged = winner.clone();
+                    for attachment in &loser.attachments {
+                        if !merged.attachments.iter().any(|a| a.blob_id == attachment.blob_id) {
+                            merged.attachments.push(attachment.clone());
+                        }
+                    }
+                    let crdt = ours.merge(theirs)?;
+                    merged.content = crdt.content(&merged.attachments)?;
+                    merged.crdt = Some(crdt);
+                    notes.insert(change.note_id.clone(), self.local_note(&merged, blob_store, shared)?);
+                    self.synced.insert(change.note_id.clone(), remote_synced);
+                    report.merged += 1;
+                    return Ok(());
+                }
+
+                let base = common_ancestor(&local.revision, &change.revision, index)
+                    .and_then(|base| base.content.as_deref())
+                    .unwrap_or_default();
+                match diffy::merge(base, &winner.content, &loser.content) {
+                    Ok(content) => {
+                        let mut merged = winner;
+                        if let Some(crdt) = &mut merged.crdt {
+                            // Only one side had CRDT content; record the merge
+                            // identically on both devices
+                            let mut revisions = [local.revision.as_str(), change.revision.as_str()];
+                            revisions.sort();
+                            crdt.update_as(&content, &merged.attachments, &revisions.join("\n"))?;
+                        }
+                        merged.content = content;
+                        notes.insert(change.note_id.clone(), self.local_note(&merged, blob_store, shared)?);
+                        report.merged += 1;
+                    }
+                    Err(_) => {
+                        let mut copy = loser;
+                        copy.id = format!(
+                            "{}-conflict-{}",
+                            change.note_id,
+                            loser_revision.get(..8).unwrap_or(loser_revision)
+                        );
+                        copy.title = format!("{} (conflicted copy)", copy.title);
+                        if !notes.contains_key(&copy.id) {
+                            notes.insert(copy.id.clone(), self.local_note(&copy, blob_store, shared)?);
+                        }
+                        notes.insert(change.note_id.clone(), self.local_note(&winner, blob_store, shared)?);
+                        report.conflicts += 1;
+                    }
+                }
+            }
+        }
+        self.synced.insert(change.note_id.clone(), remote_synced);
+        Ok(())
+    }
+
+    /// The note as written to the logs, with attachments under folder-wide
+    /// ids. New attachments are queued for upload.
+    fn portable_note(&mut self, note: &Note, blob_store: &BlobStore) -> Result<Note, String> {
+        let mut note = note.clone();
+        for attachment in &mut note.attachments {
+            let known = self
+                .blob_ids
+                .iter()
+                .find(|(_, local)| **local == attachment.blob_id)
+                .map(|(portable, _)| portable.clone());
+            let portable = match known {
+                Some(portable) => portable,
+                None => {
+                    let key = self.key()?;
+                    let data = blob_store.read(&attachment.blob_id)?;
+                    let portable = crypto::keyed_hash(&mut &data[..], &key)?;
+                    self.queued_blobs.push(portable.clone());
+                    self.blob_ids.insert(portable.clone(), attachment.blob_id.clone());
+                    portable
+                }
+            };
+            note.content = note.content.replace(
+                &format!("attachment:{}", attachment.blob_id),
+                &format!("attachment:{}", portable),
+            );
+            attachment.blob_id = portable;
+        }
+        Ok(note)
+    }
+
+    /// The inverse of `portable_note`, importing attachments this vault lacks
+    fn local_note(&mut self, note: &Note, blob_store: &BlobStore, shared: &dyn Remote) -> Result<Note, String> {
+        let mut note = note.clone();
+        for attachment in &mut note.attachments {
+            let local = match self.blob_ids.get(&attachment.blob_id) {
+                Some(local) if blob_store.contains(local) => local.clone(),
+                _ => {
+                    let encrypted = shared
+                        .read(&format!("{}/{}", BLOBS_DIR, attachment.blob_id))?
+                        .ok_or(format!("Attachment {} has not arrived yet", attachment.file_name))?;
+                    let mut data = Vec::new();
+                    crypto::decrypt_stream(&mut &encrypted[..], &mut data, &self.key()?)?;
+                    let local = blob_store.add_bytes(&attachment.file_name, &data)?.blob_id;
+                    self.blob_ids.insert(attachment.blob_id.clone(), local.clone());
+                    local
+                }
+            };
+            note.content = note.content.replace(
+                &format!("attachment:{}", attachment.blob_id),
+                &format!("attachment:{}", local),
+            );
+            attachment.blob_id = local;
+        }
+        Ok(note)
+    }
+}
+
+/// Complete lines up to the first unreadable one, and how many bytes they took
+fn parse_log(data: &[u8], key: &[u8; 32]) -> (Vec<Change>, usize) {
+    let mut changes = Vec::new();
+    let mut read = 0;
+    while let Some(end) = data[read..].iter().position(|byte| *byte == b'\n') {
+        let line = String::from_utf8_lossy(&data[read..read + end]);
+        let change = crypto::decode_base64(line.trim_end())
+            .and_then(|encrypted| crypto::decrypt(&encrypted, key))
+            .and_then(|json| serde_json::from_slice::<Change>(&json).map_err(|e| e.to_string()));
+        let Ok(change) = change else {
+            break;
+        };
+        changes.push(change);
+        read += end + 1;
+    }
+    (changes, read)
+}
+
+/// Revisions reachable from `revision` through parents, itself included
+fn ancestors<'a>(revision: &'a str, index: &'a HashMap<String, Revision>) -> HashSet<&'a str> {
+    let mut seen = HashSet::new();
+    let mut current = Some(revision);
+    while let Some(revision) = current {
+        if !seen.insert(revision) {
+            break;
+        }
+        current = index.get(revision).and_then(|change| change.parent.as_deref());
+    }
+    seen
+}
+
+fn common_ancestor<'a>(a: &str, b: &str, index: &'a HashMap<String, Revision>) -> Option<&'a Revision> {
+    let ours = ancestors(a, index);
+    let mut seen = HashSet::new();
+    let mut current = index.get_key_value(b);
+    while let Some((revision, change)) = current {
+        if ours.contains(revision.as_str()) {
+            return Some(change);
+        }
+        if !seen.insert(revision.as_str()) {
+            break;
+        }
+        current = change.parent.as_deref().and_then(|parent| index.get_key_value(parent));
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attachments::BlobKeys;
+
+    struct Device {
+        state: SyncState,
+        notes: HashMap<String, Note>,
+        store: BlobStore,
+    }
+
+    impl Device {
+        fn new(root: &Path, name: &str) -> Self {
+            Self {
+                state: SyncState::connect(SyncTarget::Folder(root.join("shared")), "sync password").unwrap(),
+                notes: HashMap::new(),
+                store: BlobStore::new(root.join(name), &BlobKeys::generate()).unwrap(),
+            }
+        }
+
+        fn sync(&mut self) -> SyncReport {
+            self.state.sync(&mut self.notes, &self.store).unwrap()
+        }
+
+        fn edit(&mut self, id: &str, from: &str, to: &str) {
+            let note = self.notes.get_mut(id).unwrap();
+            let content = note.content.replacen(from, to, 1);
+            note.update_content(content).unwrap();
+        }
+    }
+
+    #[test]
+    fn logs_are_read_incrementally_up_to_a_half_copied_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut laptop, mut desktop) = (Device::new(dir.path(), "laptop"), Device::new(dir.path(), "desktop"));
+        let note = Note::new("Plan".to_string(), "one\n".to_string());
+        let id = note.id.clone();
+        laptop.notes.insert(id.clone(), note);
+        laptop.sync();
+        laptop.edit(&id, "one", "two");
+        laptop.sync();
+
+        // The file sync has copied only part of the second line so far
+        let log = dir.path().join("shared").join(format!("{}.{}", laptop.state.device_id, LOG_EXTENSION));
+        let full = fs::read(&log).unwrap();
+        let first_line = full.iter().position(|byte| *byte == b'\n').unwrap() + 1;
+        fs::write(&log, &full[..first_line + 10]).unwrap();
+        assert_eq!(desktop.sync().received, 1);
+        assert_eq!(desktop.notes[&id].content, "one\n");
+        assert_eq!(desktop.state.log_offsets[&laptop.state.device_id], first_line as u64);
+
+        fs::write(&log, &full).unwrap();
+        assert_eq!(desktop.sync().received, 1);
+        assert_eq!(desktop.notes[&id].content, "two\n");
+    }
+
+    /// Counts the log bytes a sync downloads
+    struct Counting<'a> {
+        folder: Folder<'a>,
+        downloaded: std::cell::Cell<usize>,
+    }
+
+    impl Remote for Counting<'_> {
+        fn list(&self) -> Result<Vec<(String, String)>, String> {
+            self.folder.list()
+        }
+        fn read(&self, path: &str) -> Result<Option<Vec<u8>>, String> {
+            self.read_from(path, 0)
+        }
+        fn read_from(&self, path: &str, offset: u64) -> Result<Option<Vec<u8>>, String> {
+            let data = self.folder.read_from(path, offset)?;
+            self.downloaded.set(self.downloaded.get() + data.as_ref().map_or(0, Vec::len));
+            Ok(data)
+        }
+        fn write(&self, path: &str, data: &[u8]) -> Result<(), String> {
+            self.folder.write(path, data)
+        }
+        fn exists(&self, path: &str) -> Result<bool, String> {
+            self.folder.exists(path)
+        }
+        fn append(&self, path: &str, data: &[u8]) -> Result<(), String> {
+            self.folder.append(path, data)
+        }
+    }
+
+    #[test]
+    fn later_syncs_read_only_new_log_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut laptop, mut desktop) = (Device::new(dir.path(), "laptop"), Device::new(dir.path(), "desktop"));
+        let note = Note::new("Plan".to_string(), "one\ntwo\n".to_string());
+        let id = note.id.clone();
+        laptop.notes.insert(id.clone(), note);
+        laptop.sync();
+        laptop.edit(&id, "one", "ONE");
+        laptop.sync();
+        assert_eq!(desktop.sync().received, 2);
+
+        // Offsets and recent revisions are kept in the vault across restarts
+        desktop.state = serde_json::from_value(serde_json::to_value(&desktop.state).unwrap()).unwrap();
+        laptop.edit(&id, "two", "TWO");
+        laptop.sync();
+        let log = dir.path().join("shared").join(format!("{}.{}", laptop.state.device_id, LOG_EXTENSION));
+        let log = fs::read(&log).unwrap();
+        let last_line = log[..log.len() - 1].iter().rposition(|byte| *byte == b'\n').unwrap() + 1;
+
+        let shared = dir.path().join("shared");
+        let remote = Counting {
+            folder: Folder(&shared),
+            downloaded: std::cell::Cell::new(0),
+        };
+        let report = desktop.state.sync_with(&remote, &mut desktop.notes, &desktop.store).unwrap();
+        assert_eq!(report.received, 1);
+        assert_eq!(desktop.notes[&id].content, "ONE\nTWO\n");
+        assert_eq!(remote.downloaded.get(), log.len() - last_line);
+    }
+
+    #[test]
+    fn concurrent_edits_merge_or_become_conflict_copies() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut laptop, mut desktop) = (Device::new(dir.path(), "laptop"), Device::new(dir.path(), "desktop"));
+        assert!(SyncState::connect(SyncTarget::Folder(dir.path().join("shared")), "wrong").is_err());
+
+        let mut note = Note::new("Plan".to_string(), "one\ntwo\nthree\n".to_string());
+        let attachment = laptop.store.add_bytes("photo.jpg", b"jpeg").unwrap();
+        note.content.push_str(&format!("![photo](attachment:{})\n", attachment.blob_id));
+        note.add_attachment(attachment);
+        let id = note.id.clone();
+        laptop.notes.insert(id.clone(), note);
+        assert_eq!(laptop.sync().sent, 1);
+        assert_eq!(desktop.sync().received, 1);
+        let copied = &desktop.notes[&id];
+        assert_eq!(desktop.store.read(&copied.attachments[0].blob_id).unwrap(), b"jpeg");
+
+        // Edits to different lines merge on both devices
+        laptop.edit(&id, "one", "ONE");
+        desktop.edit(&id, "three", "THREE");
+        laptop.sync();
+        assert_eq!(desktop.sync().merged, 1);
+        assert_eq!(laptop.sync().merged, 1);
+        desktop.sync();
+        for device in [&laptop, &desktop] {
+            let note = &device.notes[&id];
+            let expected = format!("ONE\ntwo\nTHREE\n![photo](attachment:{})\n", note.attachments[0].blob_id);
+            assert_eq!(note.content, expected);
+        }
+
+        // Edits to the same line keep one version and copy the other
+        laptop.edit(&id, "two", "laptop");
+        desktop.edit(&id, "two", "desktop");
+        laptop.sync();
+        assert_eq!(desktop.sync().conflicts, 1);
+        assert_eq!(laptop.sync().conflicts, 1);
+        desktop.sync();
+        laptop.sync();
+        assert_eq!(laptop.notes.len(), 2);
+        assert_eq!(desktop.notes.len(), 2);
+        // Attachment links differ only by each vault's own blob ids
+        let text = |note: &Note| note.content.lines().take(3).collect::<Vec<_>>().join("\n");
+        assert_eq!(text(&laptop.notes[&id]), text(&desktop.notes[&id]));
+        let copy = laptop.notes.values().find(|note| note.id != id).unwrap();
+        assert!(copy.title.ends_with("(conflicted copy)"));
+        assert_ne!(text(copy), text(&laptop.notes[&id]));
+        assert_eq!(text(&desktop.notes[&copy.id]), text(copy));
+
+        // Deletions propagate
+        let copy_id = copy.id.clone();
+        laptop.notes.remove(&copy_id);
+        laptop.sync();
+        desktop.sync();
+        assert_eq!(desktop.notes.len(), 1);
+    }
+
+    #[test]
+    fn crdt_notes_merge_edits_to_the_same_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut laptop, mut desktop) = (Device::new(dir.path(), "laptop"), Device::new(dir.path(), "desktop"));
+        let mut note = Note::new("Groceries".to_string(), "milk\n".to_string());
+        note.set_crdt(true).unwrap();
+        let id = note.id.clone();
+        laptop.notes.insert(id.clone(), note);
+        laptop.sync();
+        desktop.sync();
+
+        laptop.edit(&id, "milk", "oat milk");
+        desktop.edit(&id, "milk", "milk, eggs");
+        laptop.sync();
+        assert_eq!(desktop.sync().merged, 1);
+        assert_eq!(laptop.sync().merged, 1);
+        assert_eq!(desktop.sync().received + laptop.sync().received, 0);
+        for device in [&laptop, &desktop] {
+            assert_eq!(device.notes.len(), 1);
+            assert_eq!(device.notes[&id].content, "oat milk, eggs\n");
+        }
+    }
+}
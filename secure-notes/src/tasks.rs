@@ -0,0 +1,117 @@
+use crate::markdown;
+use chrono::NaiveDate;
+use pulldown_cmark::{Event, Parser};
+
+/// A `- [ ]` / `- [x]` item found in a note
+#[derive(Debug, Clone, PartialEq)]
+pub struct Task {
+    pub line: usize,
+    pub checked: bool,
+    pub text: String,
+    pub due: Option<NaiveDate>,
+}
+
+impl Task {
+    pub fn is_overdue(&self, today: NaiveDate) -> bool {
+        !self.checked && self.due.is_some_and(|due| due < today)
+    }
+}
+
+/// Finds all task list items in Markdown content
+pub fn parse_tasks(content: &str) -> Vec<Task> {
+    let lines = markdown::LineIndex::new(content);
+    Parser::new_ext(content, markdown::parser_options())
+        .into_offset_iter()
+        .filter_map(|(event, range)| match event {
+            Event::TaskListMarker(checked) => {
+                let line = lines.line(range.start);
+                let end = content[range.end..]
+                    .find('\n')
+                    .map_or(content.len(), |index| range.end + index);
+                let text = content[range.end..end].trim().to_string();
+                Some(Task {
+                    line,
+                    checked,
+                    due: parse_due(&text),
+                    text,
+                })
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Extracts the date from an `@due(YYYY-MM-DD)` annotation
+pub fn parse_due(text: &str) -> Option<NaiveDate> {
+    let start = text.find("@due(")? + "@due(".len();
+    let end = start + text[start..].find(')')?;
+    NaiveDate::parse_from_str(text[start..end].trim(), "%Y-%m-%d").ok()
+}
+
+/// Removes the `@due(...)` annotation from task text for display
+pub fn strip_due(text: &str) -> String {
+    let Some(start) = text.find("@due(") else {
+        return text.to_string();
+    };
+    let end = text[start..].find(')').map_or(text.len(), |index| start + index + 1);
+    format!("{}{}", &text[..start], &text[end..]).trim().to_string()
+}
+
+/// Flips the checkbox of the task on the given source line
+pub fn toggle_task(content: &str, line: usize) -> Option<String> {
+    let task = parse_tasks(content).into_iter().find(|task| task.line == line)?;
+
+    let mut lines: Vec<&str> = content.split('\n').collect();
+    let source = *lines.get(line)?;
+    let (from, to) = if task.checked { ("x]", " ]") } else { (" ]", "x]") };
+    let marker = source.find('[')?;
+    let rest = &source[marker + 1..];
+    let rest = rest
+        .strip_prefix(from)
+        .or_else(|| if task.checked { rest.strip_prefix("X]") } else { None })?;
+    let updated = format!("{}[{}{}", &source[..marker], to, rest);
+
+    lines[line] = &updated;
+    Some(lines.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTENT: &str = "# Trip\n\n- [ ] Book ferry @due(2026-11-01)\n- [x] Pack bags\n  - [ ] Nested [link](x)\n\n```\n- [ ] not a task\n```\n";
+
+    #[test]
+    fn parses_tasks_outside_code_blocks() {
+        let tasks = parse_tasks(CONTENT);
+        assert_eq!(tasks.len(), 3);
+        assert_eq!(tasks[0].line, 2);
+        assert!(!tasks[0].checked);
+        assert_eq!(tasks[0].due, NaiveDate::from_ymd_opt(2026, 11, 1));
+        assert_eq!(strip_due(&tasks[0].text), "Book ferry");
+        assert!(tasks[1].checked);
+        assert_eq!(tasks[2].line, 4);
+        assert_eq!(tasks[2].text, "Nested [link](x)");
+    }
+
+    #[test]
+    fn toggles_tasks_in_place() {
+        let toggled = toggle_task(CONTENT, 2).expect("toggle open task");
+        assert!(toggled.contains("- [x] Book ferry @due(2026-11-01)\n"));
+
+        let toggled = toggle_task(&toggled, 3).expect("toggle done task");
+        assert!(toggled.contains("- [ ] Pack bags\n"));
+        assert_eq!(toggled.len(), CONTENT.len());
+
+        assert_eq!(toggle_task(CONTENT, 7), None);
+    }
+
+    #[test]
+    fn overdue_only_applies_to_open_tasks() {
+        let today = NaiveDate::from_ymd_opt(2026, 11, 2).unwrap();
+        let tasks = parse_tasks(CONTENT);
+        assert!(tasks[0].is_overdue(today));
+        assert!(!tasks[1].is_overdue(today));
+        assert!(!tasks[2].is_overdue(today));
+    }
+}
@@ -0,0 +1,142 @@
+use crate::note::{GeoLocation, Note};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+/// Blueprint for new notes, stored in the vault
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NoteTemplate {
+    pub id: String,
+    pub name: String,
+    /// Title with placeholders such as `{date}` or `{date:%d.%m.%Y}`
+    pub title_pattern: String,
+    pub body: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub notebook: Option<String>,
+    #[serde(default)]
+    pub location: Option<GeoLocation>,
+}
+
+impl NoteTemplate {
+    pub fn new(name: String) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            name,
+            title_pattern: "{date}".to_string(),
+            body: String::new(),
+            tags: Vec::new(),
+            notebook: None,
+            location: None,
+        }
+    }
+
+    pub fn is_built_in(&self) -> bool {
+        self.id.starts_with("builtin-")
+    }
+
+    /// Creates a note from the template, expanding date placeholders
+    pub fn instantiate(&self, now: DateTime<Local>) -> Note {
+        let mut note = Note::new(
+            expand_placeholders(&self.title_pattern, now),
+            expand_placeholders(&self.body, now),
+        );
+        note.tags = self.tags.clone();
+        note.notebook = self.notebook.clone();
+        note.location = self.location.clone();
+        note
+    }
+}
+
+/// Replaces `{date}`, `{time}`, `{datetime}`, `{weekday}` and `{date:<strftime>}`
+pub fn expand_placeholders(pattern: &str, now: DateTime<Local>) -> String {
+    let mut result = String::with_capacity(pattern.len());
+    let mut rest = pattern;
+
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find('}').map(|index| start + index) else {
+            rest = &rest[start..];
+            break;
+        };
+
+        let placeholder = &rest[start + 1..end];
+        let format = match placeholder {
+            "date" => Some("%Y-%m-%d"),
+            "time" => Some("%H:%M"),
+            "datetime" => Some("%Y-%m-%d %H:%M"),
+            "weekday" => Some("%A"),
+            _ => placeholder.strip_prefix("date:"),
+        };
+        match format {
+            Some(format) => result.push_str(&format_date(now, format)),
+            None => result.push_str(&rest[start..=end]),
+        }
+        rest = &rest[end + 1..];
+    }
+
+    result.push_str(rest);
+    result
+}
+
+/// Formats a date, falling back to the raw pattern if it is not valid strftime
+pub fn format_date(now: DateTime<Local>, format: &str) -> String {
+    use std::fmt::Write;
+    let mut formatted = String::new();
+    match write!(formatted, "{}", now.format(format)) {
+        Ok(()) => formatted,
+        Err(_) => format.to_string(),
+    }
+}
+
+/// Templates that ship with the app and cannot be deleted
+pub fn built_in() -> Vec<NoteTemplate> {
+    vec![
+        NoteTemplate {
+            id: "builtin-meeting".to_string(),
+            name: "Meeting notes".to_string(),
+            title_pattern: "Meeting {date}".to_string(),
+            body: "# Meeting {date}\n\n**Time:** {time}\n**Attendees:**\n\n## Agenda\n\n1. \n\n## Notes\n\n## Action items\n\n- [ ] \n".to_string(),
+            tags: vec!["meeting".to_string()],
+            notebook: Some("Meetings".to_string()),
+            location: None,
+        },
+        NoteTemplate {
+            id: "builtin-travel".to_string(),
+            name: "Travel log".to_string(),
+            title_pattern: "Travel log {date}".to_string(),
+            body: "# {weekday}, {date}\n\n## Route\n\n## Highlights\n\n## Expenses\n\n| Item | Amount |\n|------|-------:|\n|      |        |\n".to_string(),
+            tags: vec!["travel".to_string()],
+            notebook: Some("Travel".to_string()),
+            location: None,
+        },
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn expands_date_placeholders() {
+        let now = Local.with_ymd_and_hms(2026, 3, 5, 14, 30, 0).unwrap();
+        assert_eq!(
+            expand_placeholders("Meeting {date} {time} ({weekday})", now),
+            "Meeting 2026-03-05 14:30 (Thursday)"
+        );
+        assert_eq!(expand_placeholders("{date:%d.%m.%Y}", now), "05.03.2026");
+        assert_eq!(expand_placeholders("{unknown} {date", now), "{unknown} {date");
+    }
+
+    #[test]
+    fn instantiates_notes_with_defaults() {
+        let now = Local.with_ymd_and_hms(2026, 3, 5, 14, 30, 0).unwrap();
+        let template = &built_in()[0];
+        let note = template.instantiate(now);
+        assert_eq!(note.title, "Meeting 2026-03-05");
+        assert!(note.content.starts_with("# Meeting 2026-03-05"));
+        assert_eq!(note.tags, vec!["meeting".to_string()]);
+        assert_eq!(note.notebook.as_deref(), Some("Meetings"));
+    }
+}
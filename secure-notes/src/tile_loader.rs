@@ -2,7 +2,11 @@ use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use parking_lot::Mutex;
-use image::DynamicImage;
+use image::{DynamicImage, Rgba, RgbaImage};
+
+const TILE_PIXELS: u32 = 256;
+/// Zoom levels tried for snapshots, closest first
+const SNAPSHOT_ZOOMS: std::ops::RangeInclusive<u8> = 3..=16;
 
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
 pub struct TileCoord {
@@ -99,6 +103,49 @@ impl TileLoader {
         None
     }
 
+    /// Renders a map around a point from tiles already in the disk cache,
+    /// never downloading. Uses the closest zoom whose centre tile is cached.
+    pub fn snapshot(&self, lat: f64, lon: f64, width: u32, height: u32) -> Option<RgbaImage> {
+        let zoom = SNAPSHOT_ZOOMS.rev().find(|&zoom| {
+            let (x, y) = world_pixel(lat, lon, zoom);
+            let centre = TileCoord {
+                x: (x / TILE_PIXELS as f64).floor() as i32,
+                y: (y / TILE_PIXELS as f64).floor() as i32,
+                z: zoom,
+            };
+            Self::get_tile_path(&self.cache_dir, &centre).exists()
+        })?;
+
+        let (centre_x, centre_y) = world_pixel(lat, lon, zoom);
+        let left = centre_x - width as f64 / 2.0;
+        let top = centre_y - height as f64 / 2.0;
+        let mut canvas = RgbaImage::from_pixel(width, height, Rgba([230, 230, 230, 255]));
+
+        let tiles = 1_i64 << zoom;
+        let first_x = (left / TILE_PIXELS as f64).floor() as i64;
+        let first_y = (top / TILE_PIXELS as f64).floor() as i64;
+        let last_x = ((left + width as f64) / TILE_PIXELS as f64).floor() as i64;
+        let last_y = ((top + height as f64) / TILE_PIXELS as f64).floor() as i64;
+        for tile_y in first_y.max(0)..=last_y.min(tiles - 1) {
+            for tile_x in first_x..=last_x {
+                let coord = TileCoord {
+                    x: tile_x.rem_euclid(tiles) as i32,
+                    y: tile_y as i32,
+                    z: zoom,
+                };
+                let Ok(tile) = image::open(Self::get_tile_path(&self.cache_dir, &coord)) else {
+                    continue;
+                };
+                let x = tile_x * TILE_PIXELS as i64 - left.round() as i64;
+                let y = tile_y * TILE_PIXELS as i64 - top.round() as i64;
+                image::imageops::overlay(&mut canvas, &tile.to_rgba8(), x, y);
+            }
+        }
+
+        draw_marker(&mut canvas, width as i64 / 2, height as i64 / 2);
+        Some(canvas)
+    }
+
     fn load_or_download_tile(cache_dir: &Path, coord: TileCoord) -> Option<DynamicImage> {
         let tile_path = Self::get_tile_path(cache_dir, &coord);
 
@@ -168,3 +215,60 @@ impl TileLoader {
         img.save(Self::get_tile_path(cache_dir, coord))
     }
 }
+
+/// Web Mercator position in pixels at a zoom level
+fn world_pixel(lat: f64, lon: f64, zoom: u8) -> (f64, f64) {
+    let size = (1_u64 << zoom) as f64 * TILE_PIXELS as f64;
+    let lat_rad = lat.clamp(-85.0, 85.0).to_radians();
+    let x = (lon + 180.0) / 360.0 * size;
+    let y = (1.0 - lat_rad.tan().asinh() / std::f64::consts::PI) / 2.0 * size;
+    (x, y)
+}
+
+/// Red dot with a white ring, like the marker on the interactive map
+fn draw_marker(canvas: &mut RgbaImage, centre_x: i64, centre_y: i64) {
+    const RADIUS: i64 = 8;
+    for dy in -RADIUS..=RADIUS {
+        for dx in -RADIUS..=RADIUS {
+            let distance = dx * dx + dy * dy;
+            let (x, y) = (centre_x + dx, centre_y + dy);
+            if distance > RADIUS * RADIUS || x < 0 || y < 0 || x >= canvas.width() as i64 || y >= canvas.height() as i64 {
+                continue;
+            }
+            let colour = if distance > (RADIUS - 3) * (RADIUS - 3) {
+                Rgba([255, 255, 255, 255])
+            } else {
+                Rgba([220, 40, 40, 255])
+            };
+            canvas.put_pixel(x as u32, y as u32, colour);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshots_only_use_cached_tiles() {
+        let dir = tempfile::tempdir().unwrap();
+        let loader = TileLoader {
+            cache: Arc::new(Mutex::new(HashMap::new())),
+            in_flight: Arc::new(Mutex::new(HashSet::new())),
+            cache_dir: dir.path().to_path_buf(),
+        };
+        assert!(loader.snapshot(55.6761, 12.5683, 200, 100).is_none());
+
+        // Copenhagen at zoom 10 lies in tile 547/320
+        let coord = TileCoord { x: 547, y: 320, z: 10 };
+        TileLoader::ensure_tile_dir(&loader.cache_dir, &coord).unwrap();
+        RgbaImage::from_pixel(256, 256, Rgba([0, 0, 255, 255]))
+            .save(TileLoader::get_tile_path(&loader.cache_dir, &coord))
+            .unwrap();
+
+        let snapshot = loader.snapshot(55.6761, 12.5683, 200, 100).unwrap();
+        assert_eq!(snapshot.dimensions(), (200, 100));
+        assert_eq!(snapshot.get_pixel(100, 50), &Rgba([220, 40, 40, 255]));
+        assert!(snapshot.pixels().any(|pixel| *pixel == Rgba([0, 0, 255, 255])));
+    }
+}
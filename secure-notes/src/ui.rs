@@ -1,10 +1,38 @@
+use crate::age_files::{self, AgeRecipients};
+use crate::agent::Agent;
+use crate::api::{self, ApiServer};
+use crate::attachments;
+use crate::bundle::{self, Bundle, ConflictPolicy};
+use crate::calendar::{self, CalendarEntry, DateKind, Zoom};
+use crate::editor::{self, EditorLayout, SplitView};
+use crate::git_history::{HistoryEntry, NoteChange};
+use crate::html_export::{self, ExportAssets};
+use crate::images::{self, ImageCache};
+use crate::importers::{self, ImportFormat, ImportPlan};
 use crate::map::{MapView, Router};
-use crate::note::{GeoLocation, Note};
-use crate::storage::SecureStorage;
+use crate::markdown_folder;
+use crate::note::{self, GeoLocation, Note, NoteSort};
+use crate::pdf_export;
+use crate::properties::{self, Properties, PropertyKind, PropertyValue};
+use crate::reminders::{self, Notification, Recurrence, Reminder, ReminderScheduler};
+use crate::settings::Settings;
+use crate::sharing::{self, Contact, PublicIdentity, ReceivedShare};
+use crate::storage::{self, SecureStorage, SyncJob};
+use crate::sync::{SyncReport, SyncTarget};
+use crate::webdav::WebDavAccount;
+use crate::tasks::{self, Task};
+use crate::templates::{self, NoteTemplate};
 use crate::tile_loader::TileCoord;
 use eframe::egui;
-use pulldown_cmark::{html, Parser};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver};
+use std::time::{Duration, Instant};
+
+/// How often the sync target is checked while the vault is open
+const SYNC_INTERVAL: Duration = Duration::from_secs(60);
+/// How often a running sync is checked for its result
+const SYNC_POLL_INTERVAL: Duration = Duration::from_millis(250);
 
 pub struct NotesApp {
     storage: SecureStorage,
@@ -14,17 +42,106 @@ pub struct NotesApp {
     // UI state
     selected_note_id: Option<String>,
     search_query: String,
+    show_archived: bool,
+    favourites_only: bool,
     new_note_title: String,
     edit_content: String,
+    /// Why the last edit to the open note could not be saved
+    note_error: Option<String>,
     edit_title: String,
+    edit_notebook: String,
+    edit_properties: Properties,
+    new_property_name: String,
+    new_property_kind: PropertyKind,
     
     // View mode
+    main_view: MainView,
     view_mode: ViewMode,
-    show_markdown_preview: bool,
+    settings: Settings,
+    split_view: SplitView,
+    images: ImageCache,
+    
+    // Import/export state
+    confirm_plain_export: bool,
+    import_message: Option<String>,
+    import_plan: Option<ImportPlan>,
+    import_skip_duplicates: bool,
+    encrypted_export: Option<EncryptedExport>,
+    export_selection: HashSet<String>,
+    export_password: String,
+    export_password_confirm: String,
+    bundle_import_path: Option<PathBuf>,
+    bundle_import: Option<Bundle>,
+    bundle_policy: ConflictPolicy,
+    age_use_passphrase: bool,
+    age_recipients: String,
+    age_armor: bool,
+    age_import_paths: Vec<PathBuf>,
+    age_identity_path: Option<PathBuf>,
+    
+    // Sync state
+    show_sync_setup: bool,
+    sync_use_webdav: bool,
+    sync_folder_input: Option<PathBuf>,
+    sync_webdav: WebDavAccount,
+    sync_password: String,
+    sync_error: Option<String>,
+    last_sync: Option<Instant>,
+    /// Result of the sync running on a worker thread
+    sync_job: Option<Receiver<SyncJob>>,
+    sync_status: Option<Result<(chrono::DateTime<chrono::Local>, SyncReport), String>>,
+    
+    // Git history state
+    show_history: bool,
+    history: Vec<HistoryEntry>,
+    /// Titles of notes in `history`, including deleted ones
+    history_titles: HashMap<String, String>,
+    history_remote: String,
+    history_status: Option<Result<String, String>>,
+    
+    // Local API state
+    api: Option<ApiServer>,
+    api_error: Option<String>,
+    
+    // Sharing state
+    show_contacts: bool,
+    contact_name: String,
+    contact_key: String,
+    contact_error: Option<String>,
+    share_note_id: Option<String>,
+    share_recipient: Option<String>,
+    received_share: Option<ReceivedShare>,
+    
+    // Template state
+    show_templates: bool,
+    template_draft: Option<NoteTemplate>,
+    template_tags: String,
+    template_notebook: String,
+    
+    // Journal state
+    journal_month: chrono::NaiveDate,
+    show_journal_settings: bool,
+    journal_format: String,
+    
+    // Calendar state
+    calendar_zoom: Zoom,
+    calendar_anchor: chrono::NaiveDate,
+    calendar_timeline: bool,
+    calendar_kinds: Vec<DateKind>,
+    
+    // Tasks state
+    show_completed_tasks: bool,
+    
+    // Reminder state
+    reminders: ReminderScheduler,
+    notifications: Vec<Notification>,
+    reminder_date: chrono::NaiveDate,
+    reminder_hour: u32,
+    reminder_minute: u32,
+    reminder_recurrence: Recurrence,
     
     // Map state
     map_view: MapView,
-    show_map: bool,
     show_route: bool,
     selected_locations: Vec<GeoLocation>,
     route_start: Option<GeoLocation>,
@@ -33,6 +150,15 @@ pub struct NotesApp {
     tile_textures: HashMap<TileCoord, egui::TextureHandle>,
 }
 
+#[derive(PartialEq, Clone, Copy)]
+enum MainView {
+    Notes,
+    Map,
+    Tasks,
+    Upcoming,
+    Calendar,
+}
+
 #[derive(PartialEq)]
 enum ViewMode {
     List,
@@ -40,6 +166,29 @@ enum ViewMode {
     View,
 }
 
+#[derive(Clone, Copy)]
+enum NoteFlag {
+    Pinned,
+    Favourite,
+    Archived,
+}
+
+/// Buttons in the history window that need the whole app
+enum HistoryAction {
+    Enable,
+    Disable,
+    Pull,
+    Restore(String, String, NoteChange),
+}
+
+fn note_flag_labels(note: &Note) -> (&'static str, &'static str, &'static str) {
+    (
+        if note.pinned { "📌 Unpin" } else { "📌 Pin" },
+        if note.favourite { "⭐ Unfavourite" } else { "⭐ Favourite" },
+        if note.archived { "🗄 Unarchive" } else { "🗄 Archive" },
+    )
+}
+
 #[derive(PartialEq, Clone, Copy)]
 enum SelectingMode {
     None,
@@ -47,28 +196,137 @@ enum SelectingMode {
     End,
 }
 
+/// Output of the encrypted export window
+#[derive(PartialEq, Clone, Copy)]
+enum EncryptedExport {
+    /// One password-protected `.snbundle` file
+    Bundle,
+    /// A folder of `.md.age` files readable with the `age` tool
+    Age,
+}
+
+#[derive(PartialEq, Clone, Copy)]
+enum DocumentFormat {
+    Html,
+    Pdf,
+}
+
+impl DocumentFormat {
+    const ALL: [DocumentFormat; 2] = [DocumentFormat::Html, DocumentFormat::Pdf];
+
+    fn label(self) -> &'static str {
+        match self {
+            DocumentFormat::Html => "HTML",
+            DocumentFormat::Pdf => "PDF",
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            DocumentFormat::Html => "html",
+            DocumentFormat::Pdf => "pdf",
+        }
+    }
+}
+
 impl NotesApp {
-    pub fn new(_cc: &eframe::CreationContext<'_>) -> Self {
-        Self {
+    pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
+        let mut app = Self {
             storage: SecureStorage::new(),
             password_input: String::new(),
             unlock_error: None,
             selected_note_id: None,
             search_query: String::new(),
+            show_archived: false,
+            favourites_only: false,
             new_note_title: String::new(),
             edit_content: String::new(),
+            note_error: None,
             edit_title: String::new(),
+            edit_notebook: String::new(),
+            edit_properties: Properties::new(),
+            new_property_name: String::new(),
+            new_property_kind: PropertyKind::Text,
+            main_view: MainView::Notes,
             view_mode: ViewMode::List,
-            show_markdown_preview: false,
+            settings: Settings::load(),
+            split_view: SplitView::default(),
+            images: ImageCache::default(),
+            confirm_plain_export: false,
+            import_message: None,
+            import_plan: None,
+            import_skip_duplicates: true,
+            encrypted_export: None,
+            export_selection: HashSet::new(),
+            export_password: String::new(),
+            export_password_confirm: String::new(),
+            bundle_import_path: None,
+            bundle_import: None,
+            bundle_policy: ConflictPolicy::default(),
+            age_use_passphrase: true,
+            age_recipients: String::new(),
+            age_armor: false,
+            age_import_paths: Vec::new(),
+            age_identity_path: None,
+            show_sync_setup: false,
+            sync_use_webdav: false,
+            sync_folder_input: None,
+            sync_webdav: WebDavAccount {
+                url: String::new(),
+                username: String::new(),
+                password: String::new(),
+            },
+            sync_password: String::new(),
+            sync_error: None,
+            last_sync: None,
+            sync_job: None,
+            sync_status: None,
+            show_history: false,
+            history: Vec::new(),
+            history_titles: HashMap::new(),
+            history_remote: String::new(),
+            history_status: None,
+            api: None,
+            api_error: None,
+            show_contacts: false,
+            contact_name: String::new(),
+            contact_key: String::new(),
+            contact_error: None,
+            share_note_id: None,
+            share_recipient: None,
+            received_share: None,
+            show_templates: false,
+            template_draft: None,
+            template_tags: String::new(),
+            template_notebook: String::new(),
+            journal_month: calendar::first_of_month(chrono::Local::now().date_naive()),
+            show_journal_settings: false,
+            journal_format: String::new(),
+            calendar_zoom: Zoom::Month,
+            calendar_anchor: chrono::Local::now().date_naive(),
+            calendar_timeline: false,
+            calendar_kinds: DateKind::ALL.to_vec(),
+            show_completed_tasks: false,
+            reminders: ReminderScheduler::default(),
+            notifications: Vec::new(),
+            reminder_date: chrono::Local::now().date_naive(),
+            reminder_hour: 9,
+            reminder_minute: 0,
+            reminder_recurrence: Recurrence::Once,
             map_view: MapView::new(),
-            show_map: false,
             show_route: false,
             selected_locations: Vec::new(),
             route_start: None,
             route_end: None,
             selecting_mode: SelectingMode::None,
             tile_textures: HashMap::new(),
+        };
+        // Skip the password screen when the unlock agent holds the key
+        app.unlock_from_agent().ok();
+        if app.settings.api_enabled {
+            app.start_api(&cc.egui_ctx);
         }
+        app
     }
 
     fn render_unlock_screen(&mut self, ctx: &egui::Context) {
@@ -105,6 +363,15 @@ impl NotesApp {
                         if ui.add_sized([200.0, 40.0], egui::Button::new("🔓 Unlock Existing Notes")).clicked() {
                             self.attempt_unlock();
                         }
+                        if ui
+                            .add_sized([200.0, 40.0], egui::Button::new("🔑 Unlock from Agent"))
+                            .on_hover_text("Use the key held by `secure-notes agent unlock`")
+                            .clicked()
+                        {
+                            if let Err(e) = self.unlock_from_agent() {
+                                self.unlock_error = Some(e);
+                            }
+                        }
                     } else {
                         // No existing data - show create button
                         if ui.add_sized([200.0, 40.0], egui::Button::new("✨ Create New Storage")).clicked() {
@@ -133,16 +400,33 @@ impl NotesApp {
 
     fn attempt_unlock(&mut self) {
         match self.storage.unlock(&self.password_input) {
-            Ok(_) => {
-                self.unlock_error = None;
-                self.password_input.clear();
-            }
+            Ok(_) => self.unlocked(),
             Err(e) => {
                 self.unlock_error = Some(format!("Failed to unlock: {}", e));
             }
         }
     }
 
+    fn unlock_from_agent(&mut self) -> Result<(), String> {
+        let agent = Agent::new()?;
+        let (key, salt) = agent
+            .key()
+            .ok_or("The unlock agent is not running or holds no key")?;
+        self.storage
+            .unlock_with_key(&key, &salt)
+            .map_err(|e| format!("Failed to unlock from agent: {}", e))?;
+        self.unlocked();
+        Ok(())
+    }
+
+    fn unlocked(&mut self) {
+        self.unlock_error = None;
+        self.password_input.clear();
+        self.images.set_blob_store(self.storage.blob_store().ok());
+        // Reminders that were missed while locked fire right away
+        self.reschedule_reminders();
+    }
+
     fn render_main_ui(&mut self, ctx: &egui::Context) {
         // Top panel with toolbar
         egui::TopBottomPanel::top("toolbar").show(ctx, |ui| {
@@ -155,6 +439,135 @@ impl NotesApp {
                     self.create_new_note();
                 }
                 
+                if ui.button("📔 Today").clicked() {
+                    self.open_journal(chrono::Local::now().date_naive());
+                }
+                
+                ui.menu_button("📥 Import", |ui| {
+                    if ui.button("Markdown files…").clicked() {
+                        ui.close_menu();
+                        self.import_markdown_files();
+                    }
+                    if ui.button("Markdown folder…").clicked() {
+                        ui.close_menu();
+                        self.import_markdown_folder();
+                    }
+                    if ui.button("Encrypted bundle…").clicked() {
+                        ui.close_menu();
+                        self.bundle_import_path = rfd::FileDialog::new()
+                            .add_filter("Secure Notes bundle", &[bundle::EXTENSION])
+                            .pick_file();
+                        self.export_password.clear();
+                    }
+                    if ui.button("age-encrypted Markdown…").clicked() {
+                        ui.close_menu();
+                        self.age_import_paths = rfd::FileDialog::new()
+                            .add_filter("age", &[age_files::EXTENSION])
+                            .pick_files()
+                            .unwrap_or_default();
+                        self.age_identity_path = None;
+                        self.export_password.clear();
+                    }
+                    if ui.button("Shared note…").clicked() {
+                        ui.close_menu();
+                        self.open_share();
+                    }
+                    ui.separator();
+                    for format in ImportFormat::ALL {
+                        if ui.button(format.label()).clicked() {
+                            ui.close_menu();
+                            self.prepare_import(format);
+                        }
+                    }
+                });
+                
+                ui.menu_button("📤 Export", |ui| {
+                    if ui.button("Encrypted bundle…").clicked() {
+                        ui.close_menu();
+                        self.encrypted_export = Some(EncryptedExport::Bundle);
+                        self.export_selection.extend(self.selected_note_id.clone());
+                    }
+                    if ui.button("age-encrypted Markdown…").clicked() {
+                        ui.close_menu();
+                        self.encrypted_export = Some(EncryptedExport::Age);
+                        self.export_selection.extend(self.selected_note_id.clone());
+                    }
+                    if ui.button("Markdown folder (unencrypted)…").clicked() {
+                        ui.close_menu();
+                        self.confirm_plain_export = true;
+                    }
+                    ui.separator();
+                    for format in DocumentFormat::ALL {
+                        ui.menu_button(format!("As {}", format.label()), |ui| {
+                            if ui.button("Notes in the list…").clicked() {
+                                ui.close_menu();
+                                let notes = self.listed_notes().into_iter().cloned().collect();
+                                let title = if self.search_query.is_empty() {
+                                    "Notes".to_string()
+                                } else {
+                                    format!("Search: {}", self.search_query)
+                                };
+                                self.export_document(notes, &title, format);
+                            }
+                            let notebooks: std::collections::BTreeSet<String> = self
+                                .storage
+                                .get_all_notes()
+                                .into_iter()
+                                .filter_map(|note| note.notebook.clone())
+                                .collect();
+                            for notebook in notebooks {
+                                if ui.button(format!("📓 {}…", notebook)).clicked() {
+                                    ui.close_menu();
+                                    let mut notes: Vec<&Note> = self
+                                        .storage
+                                        .get_all_notes()
+                                        .into_iter()
+                                        .filter(|note| note.notebook.as_ref() == Some(&notebook))
+                                        .collect();
+                                    notes.sort_by_key(|note| note.created_at);
+                                    let notes = notes.into_iter().cloned().collect();
+                                    self.export_document(notes, &notebook, format);
+                                }
+                            }
+                        });
+                    }
+                });
+                
+                ui.menu_button("📋 New from template", |ui| {
+                    let mut chosen = None;
+                    for template in self.all_templates() {
+                        if ui.button(&template.name).clicked() {
+                            chosen = Some(template);
+                        }
+                    }
+                    ui.separator();
+                    if ui.button("⚙ Manage templates…").clicked() {
+                        self.show_templates = true;
+                        ui.close_menu();
+                    }
+                    if let Some(template) = chosen {
+                        self.create_note(template.instantiate(chrono::Local::now()));
+                        ui.close_menu();
+                    }
+                });
+                
+                if ui.button("👥 Contacts").clicked() {
+                    self.show_contacts = true;
+                }
+                
+                self.render_sync_menu(ui);
+                self.render_api_menu(ui);
+                
+                if ui.button("🕓 History").clicked() {
+                    self.show_history = true;
+                    self.history_remote = self
+                        .storage
+                        .git_history()
+                        .and_then(|git| git.remote_url())
+                        .unwrap_or_default();
+                    self.refresh_history();
+                }
+                
                 ui.separator();
                 
                 ui.label("🔍");
@@ -166,11 +579,13 @@ impl NotesApp {
                 
                 ui.separator();
                 
-                if ui.button(if self.show_map { "📝 Notes" } else { "🗺️ Map" }).clicked() {
-                    self.show_map = !self.show_map;
-                }
+                ui.selectable_value(&mut self.main_view, MainView::Notes, "📝 Notes");
+                ui.selectable_value(&mut self.main_view, MainView::Map, "🗺️ Map");
+                ui.selectable_value(&mut self.main_view, MainView::Tasks, "☑ Tasks");
+                ui.selectable_value(&mut self.main_view, MainView::Upcoming, "⏰ Upcoming");
+                ui.selectable_value(&mut self.main_view, MainView::Calendar, "📅 Calendar");
                 
-                if self.show_map {
+                if self.main_view == MainView::Map {
                     ui.separator();
                     
                     if self.route_start.is_some() || self.route_end.is_some() || self.show_route {
@@ -182,203 +597,2305 @@ impl NotesApp {
                             self.selecting_mode = SelectingMode::None;
                         }
                     }
-                    
-                    if ui.button("🌍 Sample: DK→DE").clicked() {
-                        self.show_route = true;
-                        self.selected_locations = Router::get_sample_route();
-                        self.route_start = None;
-                        self.route_end = None;
-                    }
+                    
+                    if ui.button("🌍 Sample: DK→DE").clicked() {
+                        self.show_route = true;
+                        self.selected_locations = Router::get_sample_route();
+                        self.route_start = None;
+                        self.route_end = None;
+                    }
+                }
+            });
+        });
+
+        self.render_notification_banner(ctx);
+        self.render_templates_window(ctx);
+        self.render_export_warning(ctx);
+        self.render_import_message(ctx);
+        self.render_import_preview(ctx);
+        self.render_encrypted_export(ctx);
+        self.render_bundle_import(ctx);
+        self.render_age_import(ctx);
+        self.render_sync_setup(ctx);
+        self.render_history(ctx);
+        self.render_contacts(ctx);
+        self.render_share(ctx);
+        self.render_received_share(ctx);
+
+        // Main content
+        match self.main_view {
+            MainView::Map => self.render_map_view(ctx),
+            MainView::Tasks => self.render_tasks_view(ctx),
+            MainView::Upcoming => self.render_upcoming_view(ctx),
+            MainView::Calendar => self.render_calendar_view(ctx),
+            MainView::Notes => {
+                // Sidebar with notes list
+                egui::SidePanel::left("notes_list")
+                    .default_width(250.0)
+                    .show(ctx, |ui| {
+                        self.render_notes_list(ui);
+                    });
+
+                // Main panel with note content
+                egui::CentralPanel::default().show(ctx, |ui| {
+                    self.render_note_content(ui);
+                });
+            }
+        }
+    }
+
+    fn render_notes_list(&mut self, ui: &mut egui::Ui) {
+        self.render_journal_calendar(ui);
+        ui.separator();
+
+        ui.heading("Notes");
+        ui.horizontal(|ui| {
+            let mut sort = self.settings.note_sort;
+            egui::ComboBox::from_id_source("note_sort")
+                .selected_text(sort.label())
+                .show_ui(ui, |ui| {
+                    for option in NoteSort::ALL {
+                        ui.selectable_value(&mut sort, option, option.label());
+                    }
+                });
+            if sort != self.settings.note_sort {
+                self.settings.note_sort = sort;
+                if let Err(e) = self.settings.save() {
+                    eprintln!("Failed to save settings: {}", e);
+                }
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.toggle_value(&mut self.favourites_only, "⭐ Favourites");
+            ui.toggle_value(&mut self.show_archived, "🗄 Archived");
+        });
+        ui.separator();
+
+        let notes = self.listed_notes();
+
+        let mut opened = None;
+        let mut flag_change = None;
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            for note in notes {
+                let is_selected = self.selected_note_id.as_ref() == Some(&note.id);
+                
+                let mut label = String::new();
+                if note.pinned {
+                    label.push_str("📌 ");
+                }
+                if note.favourite {
+                    label.push_str("⭐ ");
+                }
+                label.push_str(&note.title);
+                let response = ui.selectable_label(is_selected, label);
+                
+                if response.clicked() {
+                    opened = Some(note.id.clone());
+                }
+                response.context_menu(|ui| {
+                    let (pin, favourite, archive) = note_flag_labels(note);
+                    if ui.button(pin).clicked() {
+                        flag_change = Some((note.id.clone(), NoteFlag::Pinned));
+                        ui.close_menu();
+                    }
+                    if ui.button(favourite).clicked() {
+                        flag_change = Some((note.id.clone(), NoteFlag::Favourite));
+                        ui.close_menu();
+                    }
+                    if ui.button(archive).clicked() {
+                        flag_change = Some((note.id.clone(), NoteFlag::Archived));
+                        ui.close_menu();
+                    }
+                });
+                
+                ui.label(format!("📅 {}", note.modified_at.format("%Y-%m-%d %H:%M")));
+                
+                if let Some(notebook) = &note.notebook {
+                    ui.label(format!("📓 {}", notebook));
+                }
+                
+                if let Some(loc) = &note.location {
+                    ui.label(format!("📍 {}", loc.name));
+                }
+                
+                if !note.tags.is_empty() {
+                    ui.label(format!("🏷️ {}", note.tags.join(", ")));
+                }
+                
+                ui.separator();
+            }
+        });
+
+        if let Some((note_id, flag)) = flag_change {
+            self.toggle_note_flag(&note_id, flag);
+        }
+        if let Some(note_id) = opened {
+            self.open_note(&note_id);
+        }
+    }
+
+    /// Notes shown in the sidebar: the search result, filtered and sorted
+    fn listed_notes(&self) -> Vec<&Note> {
+        let mut notes: Vec<&Note> = if self.search_query.is_empty() {
+            self.storage.get_all_notes()
+        } else {
+            self.storage.search_notes(&self.search_query, self.show_archived)
+        }
+        .into_iter()
+        .filter(|note| note.archived == self.show_archived)
+        .filter(|note| note.favourite || !self.favourites_only)
+        .collect();
+        let centre = (self.map_view.center_lat, self.map_view.center_lon);
+        note::sort_notes(&mut notes, self.settings.note_sort, centre);
+        notes
+    }
+
+    /// Flips pin/favourite/archive without touching the modification time
+    fn toggle_note_flag(&mut self, note_id: &str, flag: NoteFlag) {
+        let Some(mut note) = self.storage.get_note(note_id).cloned() else {
+            return;
+        };
+        match flag {
+            NoteFlag::Pinned => note.pinned = !note.pinned,
+            NoteFlag::Favourite => note.favourite = !note.favourite,
+            NoteFlag::Archived => note.archived = !note.archived,
+        }
+        if let Err(e) = self.storage.update_note(note) {
+            eprintln!("Failed to save note: {}", e);
+        }
+    }
+
+    fn toggle_crdt(&mut self, note_id: &str) {
+        if self.view_mode == ViewMode::Edit {
+            self.save_current_note();
+        }
+        let Some(mut note) = self.storage.get_note(note_id).cloned() else {
+            return;
+        };
+        if let Err(e) = note.set_crdt(note.crdt.is_none()) {
+            eprintln!("Failed to change merging: {}", e);
+            return;
+        }
+        if let Err(e) = self.storage.update_note(note) {
+            eprintln!("Failed to save note: {}", e);
+        }
+    }
+
+    fn open_note(&mut self, note_id: &str) {
+        if let Some(note) = self.storage.get_note(note_id) {
+            self.selected_note_id = Some(note.id.clone());
+            self.edit_title = note.title.clone();
+            self.edit_content = note.content.clone();
+            self.edit_notebook = note.notebook.clone().unwrap_or_default();
+            self.edit_properties = note.properties.clone();
+            self.note_error = None;
+            self.view_mode = ViewMode::View;
+            self.main_view = MainView::Notes;
+        }
+    }
+
+    fn render_journal_calendar(&mut self, ui: &mut egui::Ui) {
+        let selected = self
+            .selected_note_id
+            .as_ref()
+            .and_then(|id| self.storage.get_note(id))
+            .and_then(|note| note.journal_date);
+        let days = self.storage.journal_dates();
+        let mut clicked = None;
+
+        egui::CollapsingHeader::new("📔 Journal")
+            .default_open(true)
+            .show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    calendar::month_header(ui, &mut self.journal_month);
+                    if ui.small_button("⚙").on_hover_text("Journal settings").clicked() {
+                        self.show_journal_settings = !self.show_journal_settings;
+                        self.journal_format = self.storage.journal_settings().title_format.clone();
+                    }
+                });
+                clicked = calendar::month_grid(ui, "journal_calendar", self.journal_month, selected, |day| {
+                    days.contains(&day)
+                });
+
+                if self.show_journal_settings {
+                    self.render_journal_settings(ui);
+                }
+            });
+
+        if let Some(day) = clicked {
+            self.open_journal(day);
+        }
+    }
+
+    fn render_journal_settings(&mut self, ui: &mut egui::Ui) {
+        let mut settings = self.storage.journal_settings().clone();
+        let templates = self.all_templates();
+        let current_template = settings
+            .template_id
+            .as_ref()
+            .and_then(|id| templates.iter().find(|t| &t.id == id))
+            .map(|t| t.name.clone())
+            .unwrap_or_else(|| "None".to_string());
+
+        ui.separator();
+        egui::Grid::new("journal_settings").num_columns(2).show(ui, |ui| {
+            ui.label("Title:");
+            let response = ui.add(
+                egui::TextEdit::singleline(&mut self.journal_format)
+                    .hint_text("%A, %Y-%m-%d")
+                    .desired_width(120.0),
+            );
+            let format = self.journal_format.trim();
+            if response.lost_focus() && !format.is_empty() {
+                settings.title_format = format.to_string();
+            }
+            ui.end_row();
+
+            ui.label("Template:");
+            egui::ComboBox::from_id_source("journal_template")
+                .selected_text(current_template)
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut settings.template_id, None, "None");
+                    for template in &templates {
+                        ui.selectable_value(
+                            &mut settings.template_id,
+                            Some(template.id.clone()),
+                            &template.name,
+                        );
+                    }
+                });
+            ui.end_row();
+        });
+
+        ui.label(
+            egui::RichText::new(format!("e.g. {}", settings.title_for(chrono::Local::now().date_naive())))
+                .small()
+                .weak(),
+        );
+
+        if &settings != self.storage.journal_settings() {
+            if let Err(e) = self.storage.set_journal_settings(settings) {
+                eprintln!("Failed to save journal settings: {}", e);
+            }
+        }
+    }
+
+    /// Opens the journal entry for a day, creating it if there is none yet
+    fn open_journal(&mut self, date: chrono::NaiveDate) {
+        self.journal_month = calendar::first_of_month(date);
+        if let Some(id) = self.storage.get_journal_entry(date).map(|note| note.id.clone()) {
+            self.open_note(&id);
+            return;
+        }
+
+        let settings = self.storage.journal_settings().clone();
+        let template = settings
+            .template_id
+            .as_ref()
+            .and_then(|id| self.all_templates().into_iter().find(|t| &t.id == id));
+        self.create_note(settings.create_entry(date, template.as_ref()));
+    }
+
+    fn render_note_content(&mut self, ui: &mut egui::Ui) {
+        if let Some(note_id) = &self.selected_note_id.clone() {
+            if let Some(note) = self.storage.get_note(note_id).cloned() {
+                ui.horizontal(|ui| {
+                    ui.heading(&note.title);
+                    
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                        if ui.button("🗑️ Delete").clicked() {
+                            if let Err(e) = self.storage.delete_note(note_id) {
+                                eprintln!("Failed to delete note: {}", e);
+                            }
+                            self.selected_note_id = None;
+                            return;
+                        }
+                        
+                        ui.menu_button("📤 Export", |ui| {
+                            if ui.button("Markdown…").on_hover_text("Markdown with frontmatter").clicked() {
+                                ui.close_menu();
+                                export_markdown(&note);
+                            }
+                            for format in DocumentFormat::ALL {
+                                if ui.button(format!("{}…", format.label())).clicked() {
+                                    ui.close_menu();
+                                    self.export_document(vec![note.clone()], &note.title, format);
+                                }
+                            }
+                            ui.separator();
+                            if ui.button("🔗 Share with contact…").clicked() {
+                                ui.close_menu();
+                                self.share_note_id = Some(note_id.clone());
+                            }
+                        });
+                        
+                        let (pin, favourite, archive) = note_flag_labels(&note);
+                        if ui.button(archive).clicked() {
+                            self.toggle_note_flag(note_id, NoteFlag::Archived);
+                        }
+                        if ui.button(favourite).clicked() {
+                            self.toggle_note_flag(note_id, NoteFlag::Favourite);
+                        }
+                        if ui.button(pin).clicked() {
+                            self.toggle_note_flag(note_id, NoteFlag::Pinned);
+                        }
+                        if ui
+                            .selectable_label(note.crdt.is_some(), "🔀 Merge edits")
+                            .on_hover_text("Merge edits made on several devices character by character when syncing")
+                            .clicked()
+                        {
+                            self.toggle_crdt(note_id);
+                        }
+                        
+                        if self.view_mode == ViewMode::View {
+                            if ui.button("✏️ Edit").clicked() {
+                                self.view_mode = ViewMode::Edit;
+                            }
+                        } else if self.view_mode == ViewMode::Edit {
+                            if ui.button("💾 Save").clicked() {
+                                self.save_current_note();
+                                if self.note_error.is_none() {
+                                    self.view_mode = ViewMode::View;
+                                }
+                            }
+                            if ui.button("❌ Cancel").clicked() {
+                                self.edit_title = note.title.clone();
+                                self.edit_content = note.content.clone();
+                                self.edit_notebook = note.notebook.clone().unwrap_or_default();
+                                self.edit_properties = note.properties.clone();
+                                self.view_mode = ViewMode::View;
+                            }
+                        }
+                    });
+                });
+
+                if let Some(error) = &self.note_error {
+                    ui.colored_label(egui::Color32::RED, error);
+                }
+
+                if let Some(date) = note.journal_date {
+                    ui.horizontal(|ui| {
+                        let mut target = None;
+                        if ui.button("◀ Previous day").clicked() {
+                            target = date.pred_opt();
+                        }
+                        ui.label(format!("📔 {}", date.format("%A, %d %B %Y")));
+                        if ui.button("Next day ▶").clicked() {
+                            target = date.succ_opt();
+                        }
+                        if let Some(target) = target {
+                            if self.view_mode == ViewMode::Edit {
+                                self.save_current_note();
+                            }
+                            self.open_journal(target);
+                        }
+                    });
+                }
+
+                ui.separator();
+
+                self.render_properties(ui, &note);
+
+                if self.view_mode == ViewMode::Edit {
+                    ui.horizontal(|ui| {
+                        ui.label("Title:");
+                        ui.text_edit_singleline(&mut self.edit_title);
+                        ui.label("Notebook:");
+                        ui.add(
+                            egui::TextEdit::singleline(&mut self.edit_notebook)
+                                .hint_text("none")
+                                .desired_width(120.0),
+                        );
+                    });
+                    
+                    ui.separator();
+                    
+                    ui.horizontal(|ui| {
+                        for layout in EditorLayout::ALL {
+                            if ui
+                                .selectable_label(self.settings.editor_layout == layout, layout.label())
+                                .clicked()
+                            {
+                                self.settings.editor_layout = layout;
+                                if let Err(e) = self.settings.save() {
+                                    eprintln!("Failed to save settings: {}", e);
+                                }
+                            }
+                        }
+                        ui.separator();
+                        if ui
+                            .button("📋 Paste image")
+                            .on_hover_text("Attach the image on the clipboard and insert it")
+                            .clicked()
+                        {
+                            self.paste_clipboard_image(ui.ctx(), &note.id);
+                        }
+                    });
+                    
+                    ui.separator();
+                    
+                    match self.settings.editor_layout {
+                        EditorLayout::Editor => {
+                            egui::ScrollArea::vertical().show(ui, |ui| {
+                                editor::show_editor(ui, &mut self.edit_content);
+                            });
+                        }
+                        EditorLayout::Preview => self.render_markdown_preview(ui),
+                        EditorLayout::Split => self.split_view.show(ui, &mut self.edit_content, &mut self.images),
+                    }
+                } else {
+                    // View mode - render markdown
+                    self.render_markdown_preview(ui);
+                }
+
+                ui.separator();
+                
+                // Location section
+                if let Some(loc) = &note.location {
+                    ui.label(format!("📍 Location: {}", loc.name));
+                    ui.label(format!("   Coordinates: {:.4}, {:.4}", loc.latitude, loc.longitude));
+                    
+                    if ui.button("View on Map").clicked() {
+                        self.map_view.set_center(loc.latitude, loc.longitude);
+                        self.map_view.zoom = 12;
+                        self.main_view = MainView::Map;
+                    }
+                }
+
+                // Quick location buttons
+                ui.horizontal(|ui| {
+                    if ui.button("Add Copenhagen").clicked() {
+                        self.add_location_to_current_note(GeoLocation::copenhagen());
+                    }
+                    if ui.button("Add Berlin").clicked() {
+                        self.add_location_to_current_note(GeoLocation::berlin());
+                    }
+                    if ui.button("Add Hamburg").clicked() {
+                        self.add_location_to_current_note(GeoLocation::hamburg());
+                    }
+                });
+
+                ui.separator();
+                self.render_reminders_section(ui, &note);
+
+                ui.separator();
+                self.render_attachments_section(ui, &note);
+            }
+        } else {
+            ui.vertical_centered(|ui| {
+                ui.add_space(100.0);
+                ui.heading("Select a note or create a new one");
+            });
+        }
+    }
+
+    fn render_properties(&mut self, ui: &mut egui::Ui, note: &Note) {
+        if self.view_mode != ViewMode::Edit {
+            if note.properties.is_empty() {
+                return;
+            }
+            egui::Grid::new("note_properties").num_columns(2).striped(true).show(ui, |ui| {
+                for (key, value) in &note.properties {
+                    ui.label(egui::RichText::new(key).strong());
+                    match value {
+                        PropertyValue::Url(url) => {
+                            ui.hyperlink(url);
+                        }
+                        PropertyValue::Bool(flag) => {
+                            ui.label(if *flag { "☑" } else { "☐" });
+                        }
+                        _ => {
+                            ui.label(value.to_string());
+                        }
+                    }
+                    ui.end_row();
+                }
+            });
+            ui.separator();
+            return;
+        }
+
+        let mut removed = None;
+        egui::CollapsingHeader::new("🏷 Properties")
+            .default_open(!self.edit_properties.is_empty())
+            .show(ui, |ui| {
+                egui::Grid::new("edit_properties").num_columns(4).show(ui, |ui| {
+                    for (key, value) in self.edit_properties.iter_mut() {
+                        ui.label(key);
+                        let mut kind = value.kind();
+                        egui::ComboBox::from_id_source(("property_kind", key.as_str()))
+                            .selected_text(kind.label())
+                            .width(90.0)
+                            .show_ui(ui, |ui| {
+                                for option in PropertyKind::ALL {
+                                    ui.selectable_value(&mut kind, option, option.label());
+                                }
+                            });
+                        if kind != value.kind() {
+                            *value = value.convert(kind);
+                        }
+                        property_editor(ui, key, value);
+                        if ui.small_button("🗑").clicked() {
+                            removed = Some(key.clone());
+                        }
+                        ui.end_row();
+                    }
+                });
+
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.new_property_name)
+                            .hint_text("New property")
+                            .desired_width(120.0),
+                    );
+                    egui::ComboBox::from_id_source("new_property_kind")
+                        .selected_text(self.new_property_kind.label())
+                        .show_ui(ui, |ui| {
+                            for option in PropertyKind::ALL {
+                                ui.selectable_value(&mut self.new_property_kind, option, option.label());
+                            }
+                        });
+                    let name = self.new_property_name.trim().to_string();
+                    let valid = !name.is_empty() && !name.contains([':', ' ']) && !self.edit_properties.contains_key(&name);
+                    if ui.add_enabled(valid, egui::Button::new("➕ Add")).clicked() {
+                        let value = PropertyValue::Text(String::new()).convert(self.new_property_kind);
+                        self.edit_properties.insert(name, value);
+                        self.new_property_name.clear();
+                    }
+                });
+            });
+
+        if let Some(key) = removed {
+            self.edit_properties.remove(&key);
+        }
+        ui.separator();
+    }
+
+    fn import_markdown_files(&mut self) {
+        let Some(paths) = rfd::FileDialog::new()
+            .add_filter("Markdown", &["md", "markdown"])
+            .pick_files()
+        else {
+            return;
+        };
+
+        let mut last = None;
+        for path in paths {
+            let title = path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let imported = std::fs::read_to_string(&path)
+                .map_err(|e| format!("Failed to read {}: {}", path.display(), e))
+                .and_then(|source| properties::from_markdown(&source, &title));
+            match imported {
+                Ok(note) => {
+                    last = Some(note.id.clone());
+                    if let Err(e) = self.storage.add_note(note) {
+                        eprintln!("Failed to import note: {}", e);
+                    }
+                }
+                Err(e) => eprintln!("{}", e),
+            }
+        }
+
+        if let Some(note_id) = last {
+            self.open_note(&note_id);
+        }
+    }
+
+    fn import_markdown_folder(&mut self) {
+        let Some(root) = rfd::FileDialog::new().pick_folder() else {
+            return;
+        };
+        let result = self
+            .storage
+            .blob_store()
+            .and_then(|store| markdown_folder::import_folder(&root, &store))
+            .and_then(|import| {
+                let skipped = import.skipped;
+                let count = self.storage.import_notes(import.notes)?;
+                Ok((count, skipped))
+            });
+
+        self.import_message = Some(match result {
+            Ok((count, skipped)) => {
+                let mut message = format!("Imported {} notes from {}.", count, root.display());
+                for (path, reason) in skipped {
+                    message.push_str(&format!("\nSkipped {}: {}", path.display(), reason));
+                }
+                message
+            }
+            Err(e) => format!("Import failed: {}", e),
+        });
+    }
+
+    /// Reads an export from another app and shows what would be imported
+    fn prepare_import(&mut self, format: ImportFormat) {
+        let path = match format.extensions() {
+            Some(extensions) => rfd::FileDialog::new().add_filter(format.label(), extensions).pick_file(),
+            None => rfd::FileDialog::new().pick_folder(),
+        };
+        let Some(path) = path else {
+            return;
+        };
+        match importers::read(format, &path) {
+            Ok(mut plan) => {
+                plan.detect_duplicates(&self.storage.get_all_notes());
+                self.import_plan = Some(plan);
+            }
+            Err(e) => self.import_message = Some(format!("Import failed: {}", e)),
+        }
+    }
+
+    fn render_import_preview(&mut self, ctx: &egui::Context) {
+        let Some(plan) = &self.import_plan else {
+            return;
+        };
+        let (mut import, mut cancel) = (false, false);
+        let skip_duplicates = &mut self.import_skip_duplicates;
+        egui::Window::new("📥 Import preview")
+            .collapsible(false)
+            .resizable(true)
+            .default_width(480.0)
+            .show(ctx, |ui| {
+                ui.label(format!(
+                    "{}: {}",
+                    plan.format.label().trim_end_matches('…'),
+                    plan.source.display()
+                ));
+                ui.strong(plan.summary());
+                ui.separator();
+
+                egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                    for imported in &plan.notes {
+                        let note = &imported.note;
+                        ui.horizontal(|ui| {
+                            match &imported.duplicate_of {
+                                Some(existing) => {
+                                    ui.label("⚠").on_hover_text(format!("Duplicate of {}", existing));
+                                }
+                                None => {
+                                    ui.label("➕");
+                                }
+                            }
+                            ui.label(&note.title);
+                            let mut details = vec![note.created_at.format("%Y-%m-%d").to_string()];
+                            details.extend(note.notebook.iter().map(|notebook| format!("📒 {}", notebook)));
+                            details.extend(note.tags.iter().map(|tag| format!("#{}", tag)));
+                            if !imported.files.is_empty() {
+                                details.push(format!("📎 {}", imported.files.len()));
+                            }
+                            if note.location.is_some() {
+                                details.push("📍".to_string());
+                            }
+                            ui.label(egui::RichText::new(details.join("  ")).weak());
+                        });
+                    }
+                    for warning in &plan.warnings {
+                        ui.label(egui::RichText::new(format!("Skipped {}", warning)).weak());
+                    }
+                });
+
+                ui.separator();
+                ui.checkbox(skip_duplicates, format!("Skip {} duplicates", plan.duplicate_count()));
+                let count = if *skip_duplicates {
+                    plan.notes.len() - plan.duplicate_count()
+                } else {
+                    plan.notes.len()
+                };
+                ui.horizontal(|ui| {
+                    import = ui
+                        .add_enabled(count > 0, egui::Button::new(format!("Import {} notes", count)))
+                        .clicked();
+                    cancel = ui.button("Cancel").clicked();
+                });
+            });
+
+        if import {
+            if let Some(plan) = self.import_plan.take() {
+                let source = plan.source.clone();
+                let result = self
+                    .storage
+                    .blob_store()
+                    .and_then(|store| plan.into_notes(self.import_skip_duplicates, &store))
+                    .and_then(|notes| self.storage.import_notes(notes));
+                self.import_message = Some(match result {
+                    Ok(count) => format!("Imported {} notes from {}.", count, source.display()),
+                    Err(e) => format!("Import failed: {}", e),
+                });
+            }
+        }
+        if cancel {
+            self.import_plan = None;
+        }
+    }
+
+    fn render_encrypted_export(&mut self, ctx: &egui::Context) {
+        let Some(kind) = self.encrypted_export else {
+            return;
+        };
+        let mut notes = self.storage.get_all_notes();
+        notes.sort_by_key(|note| note.title.to_lowercase());
+        let (mut export, mut cancel) = (false, false);
+        let title = match kind {
+            EncryptedExport::Bundle => "🔐 Export encrypted bundle",
+            EncryptedExport::Age => "🔐 Export age-encrypted Markdown",
+        };
+        egui::Window::new(title)
+            .collapsible(false)
+            .resizable(true)
+            .default_width(420.0)
+            .show(ctx, |ui| {
+                ui.label(match kind {
+                    EncryptedExport::Bundle => {
+                        "Selected notes and their attachments are written to one password-protected file."
+                    }
+                    EncryptedExport::Age => {
+                        "Each selected note and attachment is written to its own file that `age -d` can decrypt."
+                    }
+                });
+                ui.horizontal(|ui| {
+                    if ui.button("Select all").clicked() {
+                        self.export_selection = notes.iter().map(|note| note.id.clone()).collect();
+                    }
+                    if ui.button("Select none").clicked() {
+                        self.export_selection.clear();
+                    }
+                });
+                egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                    for note in &notes {
+                        let mut selected = self.export_selection.contains(&note.id);
+                        let mut label = note.title.clone();
+                        if !note.attachments.is_empty() {
+                            label.push_str(&format!("  📎 {}", note.attachments.len()));
+                        }
+                        if ui.checkbox(&mut selected, label).changed() {
+                            if selected {
+                                self.export_selection.insert(note.id.clone());
+                            } else {
+                                self.export_selection.remove(&note.id);
+                            }
+                        }
+                    }
+                });
+                ui.separator();
+                if kind == EncryptedExport::Age {
+                    ui.horizontal(|ui| {
+                        ui.radio_value(&mut self.age_use_passphrase, true, "Passphrase");
+                        ui.radio_value(&mut self.age_use_passphrase, false, "Recipients");
+                    });
+                }
+                let mut ready = !self.export_selection.is_empty();
+                if kind == EncryptedExport::Age && !self.age_use_passphrase {
+                    ui.label("Public keys (age1…), one per line:");
+                    ui.add(
+                        egui::TextEdit::multiline(&mut self.age_recipients)
+                            .desired_rows(3)
+                            .desired_width(f32::INFINITY)
+                            .font(egui::TextStyle::Monospace),
+                    );
+                    if let Err(e) = age_files::parse_recipients(&self.age_recipients) {
+                        if !self.age_recipients.trim().is_empty() {
+                            ui.colored_label(egui::Color32::RED, e);
+                        }
+                        ready = false;
+                    }
+                } else {
+                    egui::Grid::new("export_password").num_columns(2).show(ui, |ui| {
+                        ui.label("Password:");
+                        ui.add(egui::TextEdit::singleline(&mut self.export_password).password(true));
+                        ui.end_row();
+                        ui.label("Confirm:");
+                        ui.add(egui::TextEdit::singleline(&mut self.export_password_confirm).password(true));
+                        ui.end_row();
+                    });
+                    let mismatch = self.export_password != self.export_password_confirm;
+                    if mismatch && !self.export_password_confirm.is_empty() {
+                        ui.colored_label(egui::Color32::RED, "Passwords do not match");
+                    }
+                    ready &= !self.export_password.is_empty() && !mismatch;
+                }
+                if kind == EncryptedExport::Age {
+                    ui.checkbox(&mut self.age_armor, "ASCII armor (PEM text instead of binary)");
+                }
+                ui.horizontal(|ui| {
+                    let label = format!("Export {} notes…", self.export_selection.len());
+                    export = ui.add_enabled(ready, egui::Button::new(label)).clicked();
+                    cancel = ui.button("Cancel").clicked();
+                });
+            });
+
+        if export {
+            let destination = match kind {
+                EncryptedExport::Bundle => rfd::FileDialog::new()
+                    .add_filter("Secure Notes bundle", &[bundle::EXTENSION])
+                    .set_file_name(format!("notes.{}", bundle::EXTENSION))
+                    .save_file(),
+                EncryptedExport::Age => rfd::FileDialog::new().pick_folder(),
+            };
+            if let Some(path) = destination {
+                let selected: Vec<&Note> = notes
+                    .into_iter()
+                    .filter(|note| self.export_selection.contains(&note.id))
+                    .collect();
+                let result = self.storage.blob_store().and_then(|store| match kind {
+                    EncryptedExport::Bundle => bundle::export(&selected, &store, &self.export_password, &path),
+                    EncryptedExport::Age => {
+                        let recipients = if self.age_use_passphrase {
+                            AgeRecipients::Passphrase(self.export_password.clone())
+                        } else {
+                            AgeRecipients::Keys(age_files::parse_recipients(&self.age_recipients)?)
+                        };
+                        age_files::export_notes(&selected, &path, &store, &recipients, self.age_armor).map(|_| ())
+                    }
+                });
+                self.import_message = Some(match result {
+                    Ok(()) => format!("Exported {} notes to {}.", selected.len(), path.display()),
+                    Err(e) => format!("Export failed: {}", e),
+                });
+                cancel = true;
+            }
+        }
+        if cancel {
+            self.encrypted_export = None;
+            self.export_selection.clear();
+            self.export_password.clear();
+            self.export_password_confirm.clear();
+        }
+    }
+
+    fn render_bundle_import(&mut self, ctx: &egui::Context) {
+        let Some(path) = self.bundle_import_path.clone() else {
+            return;
+        };
+        let (mut open, mut import, mut cancel) = (false, false, false);
+        egui::Window::new("🔐 Import encrypted bundle")
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.label(path.display().to_string());
+                match &self.bundle_import {
+                    None => {
+                        ui.horizontal(|ui| {
+                            ui.label("Password:");
+                            let response =
+                                ui.add(egui::TextEdit::singleline(&mut self.export_password).password(true));
+                            open = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                        });
+                        ui.horizontal(|ui| {
+                            open |= ui.button("Open").clicked();
+                            cancel = ui.button("Cancel").clicked();
+                        });
+                    }
+                    Some(bundle) => {
+                        let conflicts = bundle.conflicts(&self.storage.get_all_notes());
+                        ui.label(format!("{} notes, {} with conflicting ids.", bundle.notes.len(), conflicts));
+                        if conflicts > 0 {
+                            egui::ComboBox::from_label("On conflict")
+                                .selected_text(self.bundle_policy.label())
+                                .show_ui(ui, |ui| {
+                                    for policy in ConflictPolicy::ALL {
+                                        ui.selectable_value(&mut self.bundle_policy, policy, policy.label());
+                                    }
+                                });
+                        }
+                        ui.horizontal(|ui| {
+                            import = ui.button("Import").clicked();
+                            cancel = ui.button("Cancel").clicked();
+                        });
+                    }
+                }
+            });
+
+        if open {
+            match bundle::read(&path, &self.export_password) {
+                Ok(bundle) => self.bundle_import = Some(bundle),
+                Err(e) => {
+                    self.import_message = Some(format!("Import failed: {}", e));
+                    cancel = true;
+                }
+            }
+            self.export_password.clear();
+        }
+        if import {
+            if let Some(bundle) = self.bundle_import.take() {
+                let existing: Vec<Note> = self.storage.get_all_notes().into_iter().cloned().collect();
+                let existing: Vec<&Note> = existing.iter().collect();
+                let result = self
+                    .storage
+                    .blob_store()
+                    .and_then(|store| bundle.merge(&existing, self.bundle_policy, &store))
+                    .and_then(|merge| {
+                        let count = self.storage.import_notes(merge.notes)?;
+                        // Replaced notes may leave attachments behind
+                        self.storage.collect_garbage()?;
+                        Ok((count, merge.skipped))
+                    });
+                self.import_message = Some(match result {
+                    Ok((count, skipped)) => {
+                        format!("Imported {} notes from {} ({} skipped).", count, path.display(), skipped)
+                    }
+                    Err(e) => format!("Import failed: {}", e),
+                });
+            }
+            cancel = true;
+        }
+        if cancel {
+            self.bundle_import_path = None;
+            self.bundle_import = None;
+            self.export_password.clear();
+        }
+    }
+
+    fn render_age_import(&mut self, ctx: &egui::Context) {
+        if self.age_import_paths.is_empty() {
+            return;
+        }
+        let (mut import, mut cancel) = (false, false);
+        egui::Window::new("🔐 Import age-encrypted Markdown")
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.label(format!("{} files selected.", self.age_import_paths.len()));
+                match &self.age_identity_path {
+                    None => {
+                        ui.horizontal(|ui| {
+                            ui.label("Passphrase:");
+                            let response =
+                                ui.add(egui::TextEdit::singleline(&mut self.export_password).password(true));
+                            import = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                        });
+                        if ui.button("Use an identity file instead…").clicked() {
+                            self.age_identity_path = rfd::FileDialog::new().pick_file();
+                        }
+                    }
+                    Some(path) => {
+                        ui.label(format!("Identity file: {}", path.display()));
+                        if ui.button("Use a passphrase instead").clicked() {
+                            self.age_identity_path = None;
+                        }
+                    }
+                }
+                ui.horizontal(|ui| {
+                    import |= ui.button("Import").clicked();
+                    cancel = ui.button("Cancel").clicked();
+                });
+            });
+
+        if import {
+            let identities = match &self.age_identity_path {
+                Some(path) => age_files::load_identities(path),
+                None => Ok(vec![age_files::passphrase_identity(&self.export_password)]),
+            };
+            let result = identities
+                .and_then(|identities| {
+                    let store = self.storage.blob_store()?;
+                    age_files::import_files(&self.age_import_paths, &identities, &store)
+                })
+                .and_then(|import| {
+                    let skipped = import.skipped;
+                    let count = self.storage.import_notes(import.notes)?;
+                    Ok((count, skipped))
+                });
+            self.import_message = Some(match result {
+                Ok((count, skipped)) => {
+                    let mut message = format!("Imported {} notes.", count);
+                    for (path, reason) in skipped {
+                        message.push_str(&format!("\nSkipped {}: {}", path.display(), reason));
+                    }
+                    message
+                }
+                Err(e) => format!("Import failed: {}", e),
+            });
+            cancel = true;
+        }
+        if cancel {
+            self.age_import_paths.clear();
+            self.age_identity_path = None;
+            self.export_password.clear();
+        }
+    }
+
+    /// Syncs every `SYNC_INTERVAL`, waiting while a note is being edited so
+    /// remote changes cannot clobber the editor. Offline edits stay queued
+    /// until a later attempt reaches the target.
+    fn check_sync(&mut self, ctx: &egui::Context) {
+        if self.storage.sync_target().is_none() || self.view_mode == ViewMode::Edit {
+            return;
+        }
+        if let Some(running) = &self.sync_job {
+            match running.try_recv() {
+                Ok(job) => {
+                    self.sync_job = None;
+                    self.finish_sync(job);
+                }
+                Err(mpsc::TryRecvError::Empty) => {}
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    self.sync_job = None;
+                    self.sync_status = Some(Err("The sync stopped unexpectedly".to_string()));
+                }
+            }
+        }
+        if self.last_sync.is_none_or(|last| last.elapsed() >= SYNC_INTERVAL) {
+            self.sync_now();
+        }
+        match self.sync_job {
+            Some(_) => ctx.request_repaint_after(SYNC_POLL_INTERVAL),
+            None => ctx.request_repaint_after(SYNC_INTERVAL),
+        }
+    }
+
+    /// Starts a sync on a worker thread; `check_sync` picks up the result
+    fn sync_now(&mut self) {
+        if self.sync_job.is_some() {
+            return;
+        }
+        self.last_sync = Some(Instant::now());
+        match self.storage.start_sync() {
+            Ok(job) => {
+                let (sender, receiver) = mpsc::channel();
+                std::thread::spawn(move || {
+                    sender.send(job.run()).ok();
+                });
+                self.sync_job = Some(receiver);
+            }
+            Err(e) => {
+                eprintln!("Sync failed: {}", e);
+                self.sync_status = Some(Err(e));
+            }
+        }
+    }
+
+    fn finish_sync(&mut self, job: SyncJob) {
+        self.sync_status = Some(
+            self.storage
+                .finish_sync(job)
+                .map(|report| (chrono::Local::now(), report)),
+        );
+        if let Some(Err(e)) = &self.sync_status {
+            eprintln!("Sync failed: {}", e);
+        }
+
+        // Show what other devices changed in the open note
+        self.reload_selected_note();
+    }
+
+    fn reload_selected_note(&mut self) {
+        match self.selected_note_id.as_ref().map(|id| self.storage.get_note(id)) {
+            Some(Some(note)) => {
+                self.edit_title = note.title.clone();
+                self.edit_content = note.content.clone();
+                self.edit_notebook = note.notebook.clone().unwrap_or_default();
+                self.edit_properties = note.properties.clone();
+            }
+            Some(None) => self.selected_note_id = None,
+            None => {}
+        }
+    }
+
+    fn start_api(&mut self, ctx: &egui::Context) {
+        match api::token_file_path().and_then(|path| ApiServer::start(ctx, self.settings.api_port, path)) {
+            Ok(server) => {
+                self.api = Some(server);
+                self.api_error = None;
+            }
+            Err(e) => {
+                eprintln!("Failed to start the API: {}", e);
+                self.api_error = Some(e);
+            }
+        }
+    }
+
+    /// Runs calls from integrations; while locked they are refused
+    fn answer_api_calls(&mut self) {
+        let Some(server) = &self.api else {
+            return;
+        };
+        // A note open in the editor keeps the user's unsaved text
+        if server.answer(&mut self.storage) && self.view_mode != ViewMode::Edit {
+            self.reload_selected_note();
+        }
+    }
+
+    fn render_api_menu(&mut self, ui: &mut egui::Ui) {
+        let label = match self.api {
+            Some(_) => "🔌 API on",
+            None => "🔌 API",
+        };
+        ui.menu_button(label, |ui| {
+            let mut enabled = self.api.is_some();
+            let mut changed = ui
+                .checkbox(&mut enabled, "Local API for editor plugins and scripts")
+                .changed();
+            if changed {
+                self.settings.api_enabled = enabled;
+                match enabled {
+                    true => self.start_api(ui.ctx()),
+                    false => self.api = None,
+                }
+            }
+            ui.horizontal(|ui| {
+                ui.label("Port:");
+                changed |= ui
+                    .add(egui::DragValue::new(&mut self.settings.api_port))
+                    .on_hover_text("0 picks a free port. Takes effect when the API is turned on.")
+                    .changed();
+            });
+            if changed {
+                if let Err(e) = self.settings.save() {
+                    eprintln!("Failed to save settings: {}", e);
+                }
+            }
+            if let Some(server) = &self.api {
+                ui.label(server.url());
+                ui.label(format!("Address and token: {}", server.token_file().display()));
+                if ui.button("📋 Copy token file path").clicked() {
+                    let path = server.token_file().display().to_string();
+                    ui.output_mut(|o| o.copied_text = path);
+                    ui.close_menu();
+                }
+            }
+            if let Some(e) = &self.api_error {
+                ui.colored_label(egui::Color32::RED, e);
+            }
+        });
+    }
+
+    fn render_sync_menu(&mut self, ui: &mut egui::Ui) {
+        let queued = self.storage.sync_queued();
+        let label = match &self.sync_status {
+            _ if self.storage.sync_target().is_none() => "🔄 Sync".to_string(),
+            _ if self.sync_job.is_some() => "🔄 Syncing…".to_string(),
+            None => "🔄 Sync".to_string(),
+            Some(Ok((at, _))) => format!("🔄 Synced {}", at.format("%H:%M")),
+            Some(Err(_)) if queued > 0 => format!("⏸ Offline, {} queued", queued),
+            Some(Err(_)) => "⚠ Sync failed".to_string(),
+        };
+        let response = ui.menu_button(label, |ui| match self.storage.sync_target().cloned() {
+            Some(target) => {
+                ui.label(target.to_string());
+                let editing = self.view_mode == ViewMode::Edit;
+                if ui
+                    .add_enabled(!editing && self.sync_job.is_none(), egui::Button::new("Sync now"))
+                    .on_disabled_hover_text("Save or cancel the note being edited first")
+                    .clicked()
+                {
+                    ui.close_menu();
+                    self.sync_now();
+                }
+                if ui.button("Stop syncing").clicked() {
+                    ui.close_menu();
+                    if let Err(e) = self.storage.disable_sync() {
+                        eprintln!("Failed to stop syncing: {}", e);
+                    }
+                    self.sync_job = None;
+                    self.sync_status = None;
+                }
+            }
+            None => {
+                if ui.button("Set up sync…").clicked() {
+                    ui.close_menu();
+                    self.show_sync_setup = true;
+                }
+            }
+        });
+        match &self.sync_status {
+            Some(Ok((_, report))) => {
+                response.response.on_hover_text(report.summary());
+            }
+            Some(Err(e)) => {
+                response.response.on_hover_text(e);
+            }
+            None => {}
+        }
+    }
+
+    fn render_sync_setup(&mut self, ctx: &egui::Context) {
+        if !self.show_sync_setup {
+            return;
+        }
+        let (mut connect, mut cancel) = (false, false);
+        egui::Window::new("🔄 Sync")
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.radio_value(&mut self.sync_use_webdav, false, "Shared folder");
+                    ui.radio_value(&mut self.sync_use_webdav, true, "WebDAV server");
+                });
+                if self.sync_use_webdav {
+                    ui.label("Sync through a WebDAV folder, e.g. on Nextcloud. Only encrypted data is uploaded.");
+                    egui::Grid::new("webdav_account").num_columns(2).show(ui, |ui| {
+                        ui.label("Address:");
+                        ui.add(
+                            egui::TextEdit::singleline(&mut self.sync_webdav.url)
+                                .hint_text("https://cloud.example.com/remote.php/dav/files/me/notes"),
+                        );
+                        ui.end_row();
+                        ui.label("Username:");
+                        ui.text_edit_singleline(&mut self.sync_webdav.username);
+                        ui.end_row();
+                        ui.label("Server password:");
+                        ui.add(egui::TextEdit::singleline(&mut self.sync_webdav.password).password(true));
+                        ui.end_row();
+                    });
+                } else {
+                    ui.label("Pick a folder that Syncthing, a NAS or similar keeps in sync between your devices.");
+                    ui.horizontal(|ui| {
+                        if ui.button("Choose folder…").clicked() {
+                            self.sync_folder_input = rfd::FileDialog::new().pick_folder();
+                        }
+                        match &self.sync_folder_input {
+                            Some(folder) => ui.label(folder.display().to_string()),
+                            None => ui.weak("No folder chosen"),
+                        };
+                    });
+                }
+                ui.label("Each device writes its own encrypted change log there; the vault file itself stays local.");
+                ui.horizontal(|ui| {
+                    ui.label("Sync password:");
+                    ui.add(egui::TextEdit::singleline(&mut self.sync_password).password(true));
+                });
+                ui.small("Use the same sync password on every device.");
+                if let Some(error) = &self.sync_error {
+                    ui.colored_label(egui::Color32::RED, error);
+                }
+                ui.horizontal(|ui| {
+                    let target_chosen = match self.sync_use_webdav {
+                        true => !self.sync_webdav.url.trim().is_empty(),
+                        false => self.sync_folder_input.is_some(),
+                    };
+                    let ready = target_chosen && !self.sync_password.is_empty();
+                    connect = ui.add_enabled(ready, egui::Button::new("Start syncing")).clicked();
+                    cancel = ui.button("Cancel").clicked();
+                });
+            });
+
+        if connect {
+            let target = match self.sync_use_webdav {
+                true => {
+                    let mut account = self.sync_webdav.clone();
+                    account.url = account.url.trim().to_string();
+                    Some(SyncTarget::WebDav(account))
+                }
+                false => self.sync_folder_input.clone().map(SyncTarget::Folder),
+            };
+            if let Some(target) = target {
+                match self.storage.enable_sync(target, &self.sync_password) {
+                    Ok(()) => {
+                        self.sync_now();
+                        cancel = true;
+                    }
+                    Err(e) => self.sync_error = Some(e),
+                }
+            }
+        }
+        if cancel {
+            self.show_sync_setup = false;
+            self.sync_folder_input = None;
+            self.sync_webdav.password.clear();
+            self.sync_password.clear();
+            self.sync_error = None;
+        }
+    }
+
+    fn render_contacts(&mut self, ctx: &egui::Context) {
+        if !self.show_contacts {
+            return;
+        }
+        let Ok(identity) = self.storage.identity() else {
+            return;
+        };
+        let own_key = identity.public_identity();
+        let mut open = true;
+        let mut add = false;
+        let mut delete = None;
+        egui::Window::new("👥 Contacts")
+            .open(&mut open)
+            .resizable(true)
+            .default_width(460.0)
+            .show(ctx, |ui| {
+                ui.strong("Your public key");
+                match &own_key {
+                    Ok(key) => {
+                        ui.horizontal(|ui| {
+                            ui.monospace(format!("Fingerprint: {}", key.fingerprint()));
+                            if ui.button("📋 Copy key").clicked() {
+                                ui.output_mut(|o| o.copied_text = key.to_string());
+                            }
+                        });
+                        ui.label("Send the key to teammates; they can compare the fingerprint with you.");
+                    }
+                    Err(e) => {
+                        ui.colored_label(egui::Color32::RED, e);
+                    }
+                }
+                ui.separator();
+
+                if self.storage.contacts().is_empty() {
+                    ui.label("No contacts yet.");
+                }
+                egui::Grid::new("contacts").num_columns(3).striped(true).show(ui, |ui| {
+                    for contact in self.storage.contacts() {
+                        ui.label(&contact.name);
+                        match contact.public_identity() {
+                            Ok(key) => ui.monospace(key.fingerprint()),
+                            Err(e) => ui.colored_label(egui::Color32::RED, e),
+                        };
+                        if ui.small_button("🗑").on_hover_text("Remove contact").clicked() {
+                            delete = Some(contact.public_key.clone());
+                        }
+                        ui.end_row();
+                    }
+                });
+                ui.separator();
+
+                ui.strong("Add a contact");
+                egui::Grid::new("new_contact").num_columns(2).show(ui, |ui| {
+                    ui.label("Name:");
+                    ui.text_edit_singleline(&mut self.contact_name);
+                    ui.end_row();
+                    ui.label("Public key:");
+                    ui.add(egui::TextEdit::singleline(&mut self.contact_key).hint_text("snpub1:…"));
+                    ui.end_row();
+                });
+                if let Ok(key) = PublicIdentity::parse(&self.contact_key) {
+                    ui.monospace(format!("Fingerprint: {}", key.fingerprint()));
+                }
+                if let Some(error) = &self.contact_error {
+                    ui.colored_label(egui::Color32::RED, error);
+                }
+                let ready = !self.contact_name.trim().is_empty() && !self.contact_key.trim().is_empty();
+                add = ui.add_enabled(ready, egui::Button::new("➕ Add")).clicked();
+            });
+
+        if add {
+            let result = PublicIdentity::parse(&self.contact_key).and_then(|key| {
+                self.storage
+                    .save_contact(Contact::new(self.contact_name.trim().to_string(), &key))
+            });
+            match result {
+                Ok(()) => {
+                    self.contact_name.clear();
+                    self.contact_key.clear();
+                    self.contact_error = None;
+                }
+                Err(e) => self.contact_error = Some(e),
+            }
+        }
+        if let Some(public_key) = delete {
+            if let Err(e) = self.storage.delete_contact(&public_key) {
+                eprintln!("Failed to delete contact: {}", e);
+            }
+        }
+        if !open {
+            self.show_contacts = false;
+            self.contact_error = None;
+        }
+    }
+
+    fn refresh_history(&mut self) {
+        self.history.clear();
+        self.history_titles.clear();
+        let Some(git) = self.storage.git_history() else {
+            return;
+        };
+        match git.log(100) {
+            Ok(history) => self.history = history,
+            Err(e) => self.history_status = Some(Err(e)),
+        }
+        for entry in &self.history {
+            for (note_id, change) in &entry.notes {
+                if self.history_titles.contains_key(note_id) {
+                    continue;
+                }
+                let title = match self.storage.get_note(note_id) {
+                    Some(note) => note.title.clone(),
+                    None => {
+                        // Deleted notes are read from the commit before the deletion
+                        let commit = match change {
+                            NoteChange::Deleted => format!("{}^", entry.commit),
+                            _ => entry.commit.clone(),
+                        };
+                        match self.storage.note_version(&commit, note_id) {
+                            Ok(note) => format!("{} (deleted)", note.title),
+                            Err(_) => "(deleted note)".to_string(),
+                        }
+                    }
+                };
+                self.history_titles.insert(note_id.clone(), title);
+            }
+        }
+    }
+
+    fn restore_note_version(&mut self, commit: &str, note_id: &str, change: NoteChange) {
+        let commit = match change {
+            NoteChange::Deleted => format!("{}^", commit),
+            _ => commit.to_string(),
+        };
+        let result = self
+            .storage
+            .note_version(&commit, note_id)
+            .and_then(|note| self.storage.update_note(note));
+        self.history_status = Some(result.map(|_| "Restored the note".to_string()));
+        if self.selected_note_id.as_deref() == Some(note_id) {
+            self.open_note(note_id);
+        }
+    }
+
+    fn render_history(&mut self, ctx: &egui::Context) {
+        if !self.show_history {
+            return;
+        }
+        let mut open = true;
+        let mut action = None;
+        egui::Window::new("🕓 History")
+            .open(&mut open)
+            .resizable(true)
+            .default_width(480.0)
+            .show(ctx, |ui| {
+                match &self.history_status {
+                    Some(Ok(message)) => {
+                        ui.label(message);
+                    }
+                    Some(Err(e)) => {
+                        ui.colored_label(egui::Color32::RED, e);
+                    }
+                    None => {}
+                }
+                let Some(git) = self.storage.git_history() else {
+                    ui.label("Keep the vault directory in a git repository and commit every save.");
+                    ui.label("Notes are stored as separate encrypted files, so the history shows which notes changed without revealing their contents.");
+                    if ui.button("Turn on git history").clicked() {
+                        action = Some(HistoryAction::Enable);
+                    }
+                    return;
+                };
+
+                ui.horizontal(|ui| {
+                    ui.label("Remote:");
+                    ui.add(egui::TextEdit::singleline(&mut self.history_remote).hint_text("git@example.com:me/notes.git"));
+                    let remote = self.history_remote.trim();
+                    let changed = git.remote_url().unwrap_or_default() != remote;
+                    if ui.add_enabled(changed && !remote.is_empty(), egui::Button::new("Set")).clicked() {
+                        self.history_status = Some(git.set_remote_url(remote).map(|_| "Remote saved".to_string()));
+                    }
+                });
+                ui.horizontal(|ui| {
+                    let has_remote = git.remote_url().is_some();
+                    if ui.add_enabled(has_remote, egui::Button::new("⬆ Push")).clicked() {
+                        self.history_status = Some(git.push().map(|_| "Pushed".to_string()));
+                    }
+                    if ui.add_enabled(has_remote, egui::Button::new("⬇ Pull")).clicked() {
+                        action = Some(HistoryAction::Pull);
+                    }
+                    if ui
+                        .button("Turn off")
+                        .on_hover_text("Stop committing saves; the repository is kept")
+                        .clicked()
+                    {
+                        action = Some(HistoryAction::Disable);
+                    }
+                });
+                ui.separator();
+
+                egui::ScrollArea::vertical().max_height(360.0).show(ui, |ui| {
+                    if self.history.is_empty() {
+                        ui.weak("No saves recorded yet.");
+                    }
+                    for entry in &self.history {
+                        ui.strong(entry.at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string());
+                        for (note_id, change) in &entry.notes {
+                            ui.horizontal(|ui| {
+                                let icon = match change {
+                                    NoteChange::Added => "➕",
+                                    NoteChange::Modified => "✏",
+                                    NoteChange::Deleted => "🗑",
+                                };
+                                let title = self.history_titles.get(note_id).map(String::as_str).unwrap_or_default();
+                                ui.label(format!("{} {}", icon, title));
+                                if ui
+                                    .small_button("Restore")
+                                    .on_hover_text("Bring back this version of the note")
+                                    .clicked()
+                                {
+                                    action = Some(HistoryAction::Restore(entry.commit.clone(), note_id.clone(), *change));
+                                }
+                            });
+                        }
+                    }
+                });
+            });
+
+        let action_taken = action.is_some();
+        match action {
+            Some(HistoryAction::Enable) => {
+                self.history_status = Some(self.storage.enable_git_history().map(|_| "Git history is on".to_string()));
+            }
+            Some(HistoryAction::Disable) => {
+                self.history_status = Some(self.storage.disable_git_history().map(|_| "Git history is off".to_string()));
+            }
+            Some(HistoryAction::Pull) => {
+                self.history_status = Some(self.storage.pull_history().map(|_| "Pulled".to_string()));
+                if let Some(note_id) = self.selected_note_id.clone() {
+                    self.open_note(&note_id);
+                }
+            }
+            Some(HistoryAction::Restore(commit, note_id, change)) => {
+                self.restore_note_version(&commit, &note_id, change);
+            }
+            None => {}
+        }
+        if action_taken {
+            self.refresh_history();
+        }
+        if !open {
+            self.show_history = false;
+            self.history_status = None;
+        }
+    }
+
+    fn render_share(&mut self, ctx: &egui::Context) {
+        let Some(note) = self.share_note_id.as_ref().and_then(|id| self.storage.get_note(id)).cloned() else {
+            self.share_note_id = None;
+            return;
+        };
+        let contacts = self.storage.contacts().to_vec();
+        let (mut share, mut cancel) = (false, false);
+        egui::Window::new("🔗 Share note")
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.label(format!("\"{}\" is encrypted for one contact and signed with your key.", note.title));
+                if contacts.is_empty() {
+                    ui.label("Add the recipient under 👥 Contacts first.");
+                    if ui.button("Open contacts").clicked() {
+                        self.show_contacts = true;
+                        cancel = true;
+                    }
+                }
+                let selected = contacts
+                    .iter()
+                    .find(|contact| Some(&contact.public_key) == self.share_recipient.as_ref());
+                egui::ComboBox::from_label("Recipient")
+                    .selected_text(selected.map(|contact| contact.name.as_str()).unwrap_or("Choose…"))
+                    .show_ui(ui, |ui| {
+                        for contact in &contacts {
+                            ui.selectable_value(
+                                &mut self.share_recipient,
+                                Some(contact.public_key.clone()),
+                                &contact.name,
+                            );
+                        }
+                    });
+                if let Some(key) = selected.and_then(|contact| contact.public_identity().ok()) {
+                    ui.monospace(format!("Fingerprint: {}", key.fingerprint()));
+                }
+                ui.horizontal(|ui| {
+                    share = ui.add_enabled(selected.is_some(), egui::Button::new("Share…")).clicked();
+                    cancel |= ui.button("Cancel").clicked();
+                });
+            });
+
+        if share {
+            let recipient = contacts
+                .iter()
+                .find(|contact| Some(&contact.public_key) == self.share_recipient.as_ref());
+            if let Some(recipient) = recipient {
+                if let Some(path) = rfd::FileDialog::new()
+                    .add_filter("Secure Notes share", &[sharing::EXTENSION])
+                    .set_file_name(format!(
+                        "{}.{}",
+                        markdown_folder::sanitize_file_name(&note.title),
+                        sharing::EXTENSION
+                    ))
+                    .save_file()
+                {
+                    let result = recipient.public_identity().and_then(|key| {
+                        let store = self.storage.blob_store()?;
+                        sharing::share(&[&note], &store, self.storage.identity()?, &key, &path)
+                    });
+                    self.import_message = Some(match result {
+                        Ok(()) => format!("Shared \"{}\" with {} as {}.", note.title, recipient.name, path.display()),
+                        Err(e) => format!("Sharing failed: {}", e),
+                    });
+                    cancel = true;
+                }
+            }
+        }
+        if cancel {
+            self.share_note_id = None;
+        }
+    }
+
+    /// Verifies and decrypts a share file, then asks how to import it
+    fn open_share(&mut self) {
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("Secure Notes share", &[sharing::EXTENSION])
+            .pick_file()
+        else {
+            return;
+        };
+        match self.storage.identity().and_then(|identity| sharing::open(&path, identity)) {
+            Ok(share) => {
+                self.contact_name.clear();
+                self.received_share = Some(share);
+            }
+            Err(e) => self.import_message = Some(format!("Import failed: {}", e)),
+        }
+    }
+
+    fn render_received_share(&mut self, ctx: &egui::Context) {
+        let Some(share) = &self.received_share else {
+            return;
+        };
+        let sender = share.sender;
+        let contact = sharing::find_contact(self.storage.contacts(), &sender).cloned();
+        let (mut import, mut add_contact, mut cancel) = (false, false, false);
+        egui::Window::new("🔗 Import shared notes")
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                match &contact {
+                    Some(contact) => {
+                        ui.label(format!("✔ Signed by {}", contact.name));
+                    }
+                    None => {
+                        ui.colored_label(
+                            egui::Color32::from_rgb(200, 140, 0),
+                            "⚠ Signed by a key that is not in your contacts.",
+                        );
+                        ui.label("Compare the fingerprint with the sender before trusting the notes.");
+                    }
+                }
+                ui.monospace(format!("Fingerprint: {}", sender.fingerprint()));
+                if contact.is_none() {
+                    ui.horizontal(|ui| {
+                        ui.label("Name:");
+                        ui.text_edit_singleline(&mut self.contact_name);
+                        add_contact = ui
+                            .add_enabled(!self.contact_name.trim().is_empty(), egui::Button::new("Add to contacts"))
+                            .clicked();
+                    });
+                }
+                ui.separator();
+
+                for note in &share.bundle.notes {
+                    ui.label(format!("• {}", note.title));
+                }
+                let conflicts = share.bundle.conflicts(&self.storage.get_all_notes());
+                if conflicts > 0 && contact.is_none() {
+                    ui.label(format!(
+                        "{} notes already exist in this vault. Notes from unknown senders are imported next to them.",
+                        conflicts
+                    ));
+                } else if conflicts > 0 {
+                    ui.label(format!("{} notes already exist in this vault.", conflicts));
+                    egui::ComboBox::from_label("On conflict")
+                        .selected_text(self.bundle_policy.label())
+                        .show_ui(ui, |ui| {
+                            for policy in ConflictPolicy::ALL {
+                                ui.selectable_value(&mut self.bundle_policy, policy, policy.label());
+                            }
+                        });
+                }
+                ui.horizontal(|ui| {
+                    import = ui.button("Import").clicked();
+                    cancel = ui.button("Cancel").clicked();
+                });
+            });
+
+        if add_contact {
+            let contact = Contact::new(self.contact_name.trim().to_string(), &sender);
+            if let Err(e) = self.storage.save_contact(contact) {
+                eprintln!("Failed to save contact: {}", e);
+            }
+        }
+        if import {
+            if let Some(share) = self.received_share.take() {
+                // Only a known sender may overwrite notes, whose ids it chooses
+                let policy = match contact {
+                    Some(_) => self.bundle_policy,
+                    None => ConflictPolicy::KeepBoth,
+                };
+                let existing: Vec<Note> = self.storage.get_all_notes().into_iter().cloned().collect();
+                let existing: Vec<&Note> = existing.iter().collect();
+                let result = self
+                    .storage
+                    .blob_store()
+                    .and_then(|store| share.bundle.merge(&existing, policy, &store))
+                    .and_then(|merge| {
+                        let count = self.storage.import_notes(merge.notes)?;
+                        self.storage.collect_garbage()?;
+                        Ok((count, merge.skipped))
+                    });
+                self.import_message = Some(match result {
+                    Ok((count, skipped)) => format!("Imported {} shared notes ({} skipped).", count, skipped),
+                    Err(e) => format!("Import failed: {}", e),
+                });
+            }
+            cancel = true;
+        }
+        if cancel {
+            self.received_share = None;
+            self.contact_name.clear();
+        }
+    }
+
+    /// Saves notes as a standalone HTML page or PDF, built offline
+    fn export_document(&mut self, notes: Vec<Note>, title: &str, format: DocumentFormat) {
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter(format.label(), &[format.extension()])
+            .set_file_name(format!("{}.{}", markdown_folder::sanitize_file_name(title), format.extension()))
+            .save_file()
+        else {
+            return;
+        };
+
+        let notes: Vec<&Note> = notes.iter().collect();
+        let result = self.storage.blob_store().and_then(|blob_store| {
+            let assets = ExportAssets {
+                blob_store: &blob_store,
+                tiles: self.map_view.tile_loader.as_deref(),
+            };
+            let data = match format {
+                DocumentFormat::Html => html_export::render(&notes, title, &assets).into_bytes(),
+                DocumentFormat::Pdf => pdf_export::render(&notes, title, &assets)?,
+            };
+            std::fs::write(&path, data).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+        });
+        self.import_message = Some(match result {
+            Ok(()) => format!("Exported {} notes to {}.", notes.len(), path.display()),
+            Err(e) => format!("Export failed: {}", e),
+        });
+    }
+
+    fn render_import_message(&mut self, ctx: &egui::Context) {
+        let Some(message) = &self.import_message else {
+            return;
+        };
+        let mut close = false;
+        egui::Window::new("📦 Import / export")
+            .collapsible(false)
+            .resizable(true)
+            .show(ctx, |ui| {
+                egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                    ui.label(message);
+                });
+                close = ui.button("OK").clicked();
+            });
+        if close {
+            self.import_message = None;
+        }
+    }
+
+    fn render_export_warning(&mut self, ctx: &egui::Context) {
+        if !self.confirm_plain_export {
+            return;
+        }
+        let (mut export, mut cancel) = (false, false);
+        egui::Window::new("⚠ Unencrypted export")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                ui.label("All notes and their attachments will be written as plain, unencrypted files.");
+                ui.label("Anyone with access to the folder, its backups or the disk can read them.");
+                ui.add_space(8.0);
+                ui.horizontal(|ui| {
+                    export = ui.button("Choose folder and export").clicked();
+                    cancel = ui.button("Cancel").clicked();
+                });
+            });
+
+        if export {
+            self.confirm_plain_export = false;
+            if let Some(root) = rfd::FileDialog::new().pick_folder() {
+                let notes = self.storage.get_all_notes();
+                let result = self
+                    .storage
+                    .blob_store()
+                    .and_then(|store| markdown_folder::export_folder(&notes, &root, &store));
+                self.import_message = Some(match result {
+                    Ok(count) => format!("Exported {} notes to {} (unencrypted).", count, root.display()),
+                    Err(e) => format!("Export failed: {}", e),
+                });
+            }
+        }
+        if cancel {
+            self.confirm_plain_export = false;
+        }
+    }
+
+    fn render_markdown_preview(&mut self, ui: &mut egui::Ui) {
+        let output = editor::show_preview(ui, &self.edit_content, &mut self.images);
+        if let Some(line) = output.toggled_task {
+            if let Some(content) = tasks::toggle_task(&self.edit_content, line) {
+                self.edit_content = content;
+                if self.view_mode == ViewMode::View {
+                    self.save_current_note();
+                }
+            }
+        }
+    }
+
+    fn render_reminders_section(&mut self, ui: &mut egui::Ui, note: &Note) {
+        ui.label("⏰ Reminders");
+
+        let mut removed = None;
+        for reminder in &note.reminders {
+            ui.horizontal(|ui| {
+                ui.label(format!(
+                    "{} ({})",
+                    reminder.at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M"),
+                    reminder.recurrence.label()
+                ));
+                if ui.small_button("🗑").clicked() {
+                    removed = Some(reminder.id.clone());
+                }
+            });
+        }
+
+        let mut added = None;
+        ui.horizontal(|ui| {
+            ui.add(egui_extras::DatePickerButton::new(&mut self.reminder_date).id_source("reminder_date"));
+            ui.add(egui::DragValue::new(&mut self.reminder_hour).range(0..=23).suffix(" h"));
+            ui.add(egui::DragValue::new(&mut self.reminder_minute).range(0..=59).suffix(" min"));
+            egui::ComboBox::from_id_source("reminder_recurrence")
+                .selected_text(self.reminder_recurrence.label())
+                .show_ui(ui, |ui| {
+                    for recurrence in Recurrence::ALL {
+                        ui.selectable_value(&mut self.reminder_recurrence, recurrence, recurrence.label());
+                    }
+                });
+            if ui.button("➕ Add reminder").clicked() {
+                let local = self
+                    .reminder_date
+                    .and_hms_opt(self.reminder_hour, self.reminder_minute, 0)
+                    .and_then(|time| time.and_local_timezone(chrono::Local).earliest());
+                if let Some(local) = local {
+                    added = Some(Reminder::new(local.with_timezone(&chrono::Utc), self.reminder_recurrence));
+                }
+            }
+        });
+
+        if removed.is_none() && added.is_none() {
+            return;
+        }
+
+        let mut updated_note = note.clone();
+        if let Some(id) = removed {
+            updated_note.remove_reminder(&id);
+        }
+        if let Some(reminder) = added {
+            updated_note.add_reminder(reminder);
+        }
+        if let Err(e) = self.storage.update_note(updated_note) {
+            eprintln!("Failed to update reminders: {}", e);
+        }
+        self.reschedule_reminders();
+    }
+
+    fn render_attachments_section(&mut self, ui: &mut egui::Ui, note: &Note) {
+        let mut removed = None;
+
+        ui.horizontal(|ui| {
+            ui.label(egui::RichText::new("📎 Attachments").strong());
+            if ui.button("➕ Attach files…").clicked() {
+                if let Some(paths) = rfd::FileDialog::new().pick_files() {
+                    self.attach_files(&note.id, &paths);
+                }
+            }
+        });
+
+        let dropped: Vec<std::path::PathBuf> = ui.ctx().input(|i| {
+            i.raw.dropped_files.iter().filter_map(|file| file.path.clone()).collect()
+        });
+        if !dropped.is_empty() {
+            self.attach_files(&note.id, &dropped);
+        }
+
+        if note.attachments.is_empty() {
+            ui.label(egui::RichText::new("Drop files here to attach them").weak());
+            return;
+        }
+
+        egui::Grid::new("attachments_grid").num_columns(3).striped(true).show(ui, |ui| {
+            for attachment in &note.attachments {
+                ui.label(format!("{} {}", attachment.icon(), attachment.file_name));
+                ui.label(attachments::format_size(attachment.size));
+                ui.horizontal(|ui| {
+                    if self.view_mode == ViewMode::Edit
+                        && attachment.is_image()
+                        && ui.small_button("Insert").on_hover_text("Show inline in the note").clicked()
+                    {
+                        let markdown = images::attachment_markdown(&attachment.file_name, &attachment.blob_id);
+                        editor::insert_at_cursor(ui.ctx(), &mut self.edit_content, &markdown);
+                    }
+                    if ui
+                        .small_button("Open")
+                        .on_hover_text("Decrypts a temporary copy for the default application")
+                        .clicked()
+                    {
+                        if let Err(e) = self.storage.open_attachment(attachment) {
+                            eprintln!("Failed to open attachment: {}", e);
+                        }
+                    }
+                    if ui.small_button("Export…").clicked() {
+                        let destination = rfd::FileDialog::new()
+                            .set_file_name(&attachment.file_name)
+                            .save_file();
+                        if let Some(destination) = destination {
+                            if let Err(e) = self.storage.export_attachment(&attachment.blob_id, &destination) {
+                                eprintln!("Failed to export attachment: {}", e);
+                            }
+                        }
+                    }
+                    if ui.small_button("🗑").clicked() {
+                        removed = Some(attachment.blob_id.clone());
+                    }
+                });
+                ui.end_row();
+            }
+        });
+
+        if let Some(blob_id) = removed {
+            if let Err(e) = self.storage.remove_attachment(&note.id, &blob_id) {
+                eprintln!("Failed to remove attachment: {}", e);
+            }
+        }
+    }
+
+    fn paste_clipboard_image(&mut self, ctx: &egui::Context, note_id: &str) {
+        let name = format!("pasted-{}.png", chrono::Local::now().format("%Y%m%d-%H%M%S"));
+        let attached = images::clipboard_png().and_then(|png| self.storage.attach_bytes(note_id, &name, &png));
+        match attached {
+            Ok(attachment) => {
+                let markdown = images::attachment_markdown("pasted image", &attachment.blob_id);
+                editor::insert_at_cursor(ctx, &mut self.edit_content, &markdown);
+            }
+            Err(e) => eprintln!("Failed to paste image: {}", e),
+        }
+    }
+
+    fn attach_files(&mut self, note_id: &str, paths: &[std::path::PathBuf]) {
+        for path in paths {
+            if let Err(e) = self.storage.attach_file(note_id, path) {
+                eprintln!("Failed to attach {}: {}", path.display(), e);
+            }
+        }
+    }
+
+    fn reschedule_reminders(&mut self) {
+        let notes = self.storage.get_all_notes();
+        self.reminders.reschedule(&notes);
+    }
+
+    fn check_reminders(&mut self, ctx: &egui::Context) {
+        let now = chrono::Utc::now();
+        let due = self.reminders.take_due(now);
+
+        for (note_id, reminder_id) in &due {
+            let Some(mut note) = self.storage.get_note(note_id).cloned() else {
+                continue;
+            };
+            let Some(fired) = note.fire_reminder(reminder_id, now) else {
+                continue;
+            };
+
+            if self.settings.desktop_notifications {
+                if let Err(e) = reminders::send_desktop_notification("Secure Notes reminder", &note.title) {
+                    eprintln!("{}", e);
+                }
+            }
+            self.notifications.push(Notification {
+                note_id: note.id.clone(),
+                title: note.title.clone(),
+                due_at: fired.at,
+            });
+
+            if let Err(e) = self.storage.update_note(note) {
+                eprintln!("Failed to update reminder: {}", e);
+            }
+        }
+
+        if !due.is_empty() {
+            self.reschedule_reminders();
+        }
+
+        // Wake up for the next reminder even if there is no user input
+        if let Some(next) = self.reminders.next_due() {
+            ctx.request_repaint_after((next - now).to_std().unwrap_or_default());
+        }
+    }
+
+    fn render_notification_banner(&mut self, ctx: &egui::Context) {
+        if self.notifications.is_empty() {
+            return;
+        }
+
+        let mut dismissed = None;
+        let mut opened = None;
+
+        egui::TopBottomPanel::top("notifications")
+            .frame(egui::Frame::side_top_panel(&ctx.style()).fill(egui::Color32::from_rgb(255, 236, 179)))
+            .show(ctx, |ui| {
+                for (index, notification) in self.notifications.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        ui.colored_label(
+                            egui::Color32::BLACK,
+                            format!(
+                                "⏰ {} — due {}",
+                                notification.title,
+                                notification.due_at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M")
+                            ),
+                        );
+                        if ui.button("Open").clicked() {
+                            opened = Some(notification.note_id.clone());
+                            dismissed = Some(index);
+                        }
+                        if ui.button("Dismiss").clicked() {
+                            dismissed = Some(index);
+                        }
+                    });
+                }
+            });
+
+        if let Some(index) = dismissed {
+            self.notifications.remove(index);
+        }
+        if let Some(note_id) = opened {
+            self.open_note(&note_id);
+        }
+    }
+
+    fn render_upcoming_view(&mut self, ctx: &egui::Context) {
+        let mut opened = None;
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.heading("⏰ Upcoming");
+                ui.separator();
+                if ui
+                    .checkbox(&mut self.settings.desktop_notifications, "Desktop notifications")
+                    .changed()
+                {
+                    if let Err(e) = self.settings.save() {
+                        eprintln!("Failed to save settings: {}", e);
+                    }
+                }
+            });
+            ui.separator();
+
+            let notes = self.storage.get_all_notes();
+            let upcoming = reminders::upcoming(&notes);
+            if upcoming.is_empty() {
+                ui.label("No reminders scheduled. Add one from a note.");
+                return;
+            }
+
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                egui::Grid::new("upcoming_grid")
+                    .striped(true)
+                    .num_columns(3)
+                    .show(ui, |ui| {
+                        for (note, reminder) in upcoming {
+                            ui.label(
+                                reminder
+                                    .at
+                                    .with_timezone(&chrono::Local)
+                                    .format("%a %Y-%m-%d %H:%M")
+                                    .to_string(),
+                            );
+                            ui.label(reminder.recurrence.label());
+                            if ui.link(format!("📝 {}", note.title)).clicked() {
+                                opened = Some(note.id.clone());
+                            }
+                            ui.end_row();
+                        }
+                    });
+            });
+        });
+
+        if let Some(note_id) = opened {
+            self.open_note(&note_id);
+        }
+    }
+
+    fn render_calendar_view(&mut self, ctx: &egui::Context) {
+        let mut opened = None;
+        let mut zoom_to = None;
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.heading("📅 Calendar");
+                ui.separator();
+                if ui.button("➖").on_hover_text("Zoom out").clicked() {
+                    self.calendar_zoom = self.calendar_zoom.zoom_out();
+                }
+                for zoom in Zoom::ALL {
+                    ui.selectable_value(&mut self.calendar_zoom, zoom, zoom.label());
+                }
+                if ui.button("➕").on_hover_text("Zoom in").clicked() {
+                    self.calendar_zoom = self.calendar_zoom.zoom_in();
+                }
+                ui.separator();
+                if ui.button("◀").clicked() {
+                    self.calendar_anchor = self.calendar_zoom.step(self.calendar_anchor, -1);
+                }
+                if ui.button("Today").clicked() {
+                    self.calendar_anchor = chrono::Local::now().date_naive();
+                }
+                if ui.button("▶").clicked() {
+                    self.calendar_anchor = self.calendar_zoom.step(self.calendar_anchor, 1);
+                }
+                ui.label(egui::RichText::new(self.calendar_zoom.title(self.calendar_anchor)).strong());
+                ui.separator();
+                ui.selectable_value(&mut self.calendar_timeline, false, "▦ Calendar");
+                ui.selectable_value(&mut self.calendar_timeline, true, "☰ Timeline");
+            });
+            ui.horizontal(|ui| {
+                for kind in DateKind::ALL {
+                    let mut shown = self.calendar_kinds.contains(&kind);
+                    if ui.checkbox(&mut shown, format!("{} {}", kind.icon(), kind.label())).changed() {
+                        self.calendar_kinds.retain(|k| *k != kind);
+                        if shown {
+                            self.calendar_kinds.push(kind);
+                        }
+                    }
                 }
             });
-        });
+            ui.separator();
 
-        // Main content
-        if self.show_map {
-            self.render_map_view(ctx);
-        } else {
-            // Sidebar with notes list
-            egui::SidePanel::left("notes_list")
-                .default_width(250.0)
-                .show(ctx, |ui| {
-                    self.render_notes_list(ui);
-                });
+            let (start, end) = self.calendar_zoom.range(self.calendar_anchor);
+            let notes = self.storage.get_all_notes();
+            let entries: Vec<CalendarEntry> = calendar::entries(&notes)
+                .into_iter()
+                .filter(|entry| self.calendar_kinds.contains(&entry.kind))
+                .filter(|entry| entry.date >= start && entry.date < end)
+                .collect();
 
-            // Main panel with note content
-            egui::CentralPanel::default().show(ctx, |ui| {
-                self.render_note_content(ui);
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                if self.calendar_timeline {
+                    render_timeline(ui, &entries, &mut opened, &mut zoom_to);
+                } else {
+                    match self.calendar_zoom {
+                        Zoom::Year => render_year_grid(ui, start, &entries, &mut zoom_to),
+                        Zoom::Month | Zoom::Week => {
+                            render_day_columns(ui, self.calendar_zoom, start, end, &entries, &mut opened, &mut zoom_to)
+                        }
+                        Zoom::Day => render_timeline(ui, &entries, &mut opened, &mut zoom_to),
+                    }
+                }
             });
+        });
+
+        if let Some((zoom, day)) = zoom_to {
+            self.calendar_zoom = zoom;
+            self.calendar_anchor = day;
+        }
+        if let Some(note_id) = opened {
+            self.open_note(&note_id);
         }
     }
 
-    fn render_notes_list(&mut self, ui: &mut egui::Ui) {
-        ui.heading("Notes");
-        ui.separator();
+    fn render_tasks_view(&mut self, ctx: &egui::Context) {
+        let today = chrono::Local::now().date_naive();
 
-        let notes = if self.search_query.is_empty() {
-            self.storage.get_all_notes()
-        } else {
-            self.storage.search_notes(&self.search_query)
-        };
+        let mut entries: Vec<(Task, String, String)> = self
+            .storage
+            .get_all_notes()
+            .into_iter()
+            .flat_map(|note| {
+                tasks::parse_tasks(&note.content)
+                    .into_iter()
+                    .map(|task| (task, note.id.clone(), note.title.clone()))
+            })
+            .filter(|(task, _, _)| self.show_completed_tasks || !task.checked)
+            .collect();
+        entries.sort_by_key(|(task, _, title)| (task.due.is_none(), task.due, title.clone(), task.line));
 
-        egui::ScrollArea::vertical().show(ui, |ui| {
-            for note in notes {
-                let is_selected = self.selected_note_id.as_ref() == Some(&note.id);
-                
-                let response = ui.selectable_label(is_selected, &note.title);
-                
-                if response.clicked() {
-                    self.selected_note_id = Some(note.id.clone());
-                    self.edit_title = note.title.clone();
-                    self.edit_content = note.content.clone();
-                    self.view_mode = ViewMode::View;
-                }
-                
-                ui.label(format!("📅 {}", note.modified_at.format("%Y-%m-%d %H:%M")));
-                
-                if let Some(loc) = &note.location {
-                    ui.label(format!("📍 {}", loc.name));
-                }
-                
-                if !note.tags.is_empty() {
-                    ui.label(format!("🏷️ {}", note.tags.join(", ")));
-                }
-                
+        let mut toggled = None;
+        let mut opened = None;
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.heading("☑ Tasks");
                 ui.separator();
+                ui.checkbox(&mut self.show_completed_tasks, "Show completed");
+            });
+            ui.separator();
+
+            if entries.is_empty() {
+                ui.label("No open tasks. Add `- [ ] something @due(2026-11-01)` to a note.");
+                return;
             }
-        });
-    }
 
-    fn render_note_content(&mut self, ui: &mut egui::Ui) {
-        if let Some(note_id) = &self.selected_note_id.clone() {
-            if let Some(note) = self.storage.get_note(note_id).cloned() {
-                ui.horizontal(|ui| {
-                    ui.heading(&note.title);
-                    
-                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
-                        if ui.button("🗑️ Delete").clicked() {
-                            if let Err(e) = self.storage.delete_note(note_id) {
-                                eprintln!("Failed to delete note: {}", e);
-                            }
-                            self.selected_note_id = None;
-                            return;
-                        }
-                        
-                        if self.view_mode == ViewMode::View {
-                            if ui.button("✏️ Edit").clicked() {
-                                self.view_mode = ViewMode::Edit;
-                            }
-                        } else if self.view_mode == ViewMode::Edit {
-                            if ui.button("💾 Save").clicked() {
-                                self.save_current_note();
-                                self.view_mode = ViewMode::View;
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                egui::Grid::new("tasks_grid")
+                    .striped(true)
+                    .num_columns(4)
+                    .show(ui, |ui| {
+                        for (task, note_id, title) in &entries {
+                            let mut checked = task.checked;
+                            if ui.checkbox(&mut checked, "").changed() {
+                                toggled = Some((note_id.clone(), task.line));
                             }
-                            if ui.button("❌ Cancel").clicked() {
-                                self.edit_title = note.title.clone();
-                                self.edit_content = note.content.clone();
-                                self.view_mode = ViewMode::View;
+
+                            let overdue = task.is_overdue(today);
+                            let mut text = egui::RichText::new(tasks::strip_due(&task.text));
+                            if task.checked {
+                                text = text.strikethrough().weak();
+                            } else if overdue {
+                                text = text.color(egui::Color32::RED);
                             }
-                        }
-                    });
-                });
+                            ui.label(text);
 
-                ui.separator();
+                            match task.due {
+                                Some(due) if overdue => {
+                                    ui.colored_label(egui::Color32::RED, format!("⚠ {} (overdue)", due));
+                                }
+                                Some(due) => {
+                                    ui.label(format!("📅 {}", due));
+                                }
+                                None => {
+                                    ui.label("");
+                                }
+                            }
 
-                if self.view_mode == ViewMode::Edit {
-                    ui.horizontal(|ui| {
-                        ui.label("Title:");
-                        ui.text_edit_singleline(&mut self.edit_title);
-                    });
-                    
-                    ui.separator();
-                    
-                    ui.horizontal(|ui| {
-                        if ui.button(if self.show_markdown_preview { "Edit" } else { "Preview" }).clicked() {
-                            self.show_markdown_preview = !self.show_markdown_preview;
+                            if ui.link(format!("📝 {}", title)).clicked() {
+                                opened = Some(note_id.clone());
+                            }
+                            ui.end_row();
                         }
                     });
-                    
-                    ui.separator();
-                    
-                    if self.show_markdown_preview {
-                        self.render_markdown_preview(ui);
-                    } else {
-                        egui::ScrollArea::vertical().show(ui, |ui| {
-                            ui.add(
-                                egui::TextEdit::multiline(&mut self.edit_content)
-                                    .desired_width(f32::INFINITY)
-                                    .desired_rows(20)
-                                    .font(egui::TextStyle::Monospace)
-                            );
-                        });
-                    }
-                } else {
-                    // View mode - render markdown
-                    self.render_markdown_preview(ui);
-                }
-
-                ui.separator();
-                
-                // Location section
-                if let Some(loc) = &note.location {
-                    ui.label(format!("📍 Location: {}", loc.name));
-                    ui.label(format!("   Coordinates: {:.4}, {:.4}", loc.latitude, loc.longitude));
-                    
-                    if ui.button("View on Map").clicked() {
-                        self.map_view.set_center(loc.latitude, loc.longitude);
-                        self.map_view.zoom = 12;
-                        self.show_map = true;
-                    }
-                }
-
-                // Quick location buttons
-                ui.horizontal(|ui| {
-                    if ui.button("Add Copenhagen").clicked() {
-                        self.add_location_to_current_note(GeoLocation::copenhagen());
-                    }
-                    if ui.button("Add Berlin").clicked() {
-                        self.add_location_to_current_note(GeoLocation::berlin());
-                    }
-                    if ui.button("Add Hamburg").clicked() {
-                        self.add_location_to_current_note(GeoLocation::hamburg());
-                    }
-                });
-            }
-        } else {
-            ui.vertical_centered(|ui| {
-                ui.add_space(100.0);
-                ui.heading("Select a note or create a new one");
             });
+        });
+
+        if let Some((note_id, line)) = toggled {
+            self.toggle_task_in_note(&note_id, line);
+        }
+        if let Some(note_id) = opened {
+            self.open_note(&note_id);
         }
     }
 
-    fn render_markdown_preview(&self, ui: &mut egui::Ui) {
-        let parser = Parser::new(&self.edit_content);
-        let mut html_output = String::new();
-        html::push_html(&mut html_output, parser);
-        
-        egui::ScrollArea::vertical().show(ui, |ui| {
-            // Simple markdown rendering
-            for line in self.edit_content.lines() {
-                if line.starts_with("# ") {
-                    ui.heading(&line[2..]);
-                } else if line.starts_with("## ") {
-                    ui.label(egui::RichText::new(&line[3..]).heading().size(20.0));
-                } else if line.starts_with("### ") {
-                    ui.label(egui::RichText::new(&line[4..]).heading().size(16.0));
-                } else if line.starts_with("- ") || line.starts_with("* ") {
-                    ui.label(format!("  • {}", &line[2..]));
-                } else if line.starts_with("**") && line.ends_with("**") && line.len() > 4 {
-                    ui.label(egui::RichText::new(&line[2..line.len()-2]).strong());
-                } else if line.starts_with("*") && line.ends_with("*") && line.len() > 2 {
-                    ui.label(egui::RichText::new(&line[1..line.len()-1]).italics());
-                } else if !line.is_empty() {
-                    ui.label(line);
-                } else {
-                    ui.add_space(8.0);
+    fn toggle_task_in_note(&mut self, note_id: &str, line: usize) {
+        if let Some(mut note) = self.storage.get_note(note_id).cloned() {
+            if let Some(content) = tasks::toggle_task(&note.content, line) {
+                if let Err(e) = note.update_content(content.clone()) {
+                    self.note_error = Some(format!("Failed to update task: {}", e));
+                    return;
+                }
+                if self.selected_note_id.as_deref() == Some(note_id) && self.view_mode != ViewMode::Edit {
+                    self.edit_content = content;
+                }
+                if let Err(e) = self.storage.update_note(note) {
+                    eprintln!("Failed to update task: {}", e);
                 }
             }
-        });
+        }
     }
 
     fn render_map_view(&mut self, ctx: &egui::Context) {
@@ -651,8 +3168,15 @@ impl NotesApp {
     }
 
     fn create_new_note(&mut self) {
-        let note = Note::new("New Note".to_string(), String::new());
+        self.create_note(Note::new("New Note".to_string(), String::new()));
+    }
+
+    fn create_note(&mut self, note: Note) {
         let note_id = note.id.clone();
+        let title = note.title.clone();
+        let content = note.content.clone();
+        let notebook = note.notebook.clone().unwrap_or_default();
+        let properties = note.properties.clone();
         
         if let Err(e) = self.storage.add_note(note) {
             eprintln!("Failed to create note: {}", e);
@@ -660,9 +3184,157 @@ impl NotesApp {
         }
         
         self.selected_note_id = Some(note_id);
-        self.edit_title = "New Note".to_string();
-        self.edit_content = String::new();
+        self.edit_title = title;
+        self.edit_content = content;
+        self.edit_notebook = notebook;
+        self.edit_properties = properties;
         self.view_mode = ViewMode::Edit;
+        self.main_view = MainView::Notes;
+    }
+
+    fn all_templates(&self) -> Vec<NoteTemplate> {
+        templates::built_in()
+            .into_iter()
+            .chain(self.storage.get_templates().iter().cloned())
+            .collect()
+    }
+
+    fn edit_template(&mut self, template: NoteTemplate) {
+        self.template_tags = template.tags.join(", ");
+        self.template_notebook = template.notebook.clone().unwrap_or_default();
+        self.template_draft = Some(template);
+    }
+
+    fn render_templates_window(&mut self, ctx: &egui::Context) {
+        if !self.show_templates {
+            return;
+        }
+
+        let mut open = true;
+        let mut edited = None;
+        let mut deleted = None;
+
+        egui::Window::new("📋 Templates")
+            .open(&mut open)
+            .default_width(480.0)
+            .show(ctx, |ui| {
+                for template in self.all_templates() {
+                    ui.horizontal(|ui| {
+                        ui.label(&template.name);
+                        if template.is_built_in() {
+                            ui.label(egui::RichText::new("built-in").small().weak());
+                        }
+                        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                            if !template.is_built_in() {
+                                if ui.small_button("🗑").clicked() {
+                                    deleted = Some(template.id.clone());
+                                }
+                                if ui.small_button("✏️ Edit").clicked() {
+                                    edited = Some(template.clone());
+                                }
+                            }
+                            if ui.small_button("Duplicate").clicked() {
+                                let mut copy = template.clone();
+                                copy.id = NoteTemplate::new(String::new()).id;
+                                copy.name = format!("{} (copy)", template.name);
+                                edited = Some(copy);
+                            }
+                        });
+                    });
+                }
+
+                if ui.button("➕ New template").clicked() {
+                    edited = Some(NoteTemplate::new("New template".to_string()));
+                }
+
+                let Some(draft) = &mut self.template_draft else {
+                    return;
+                };
+
+                ui.separator();
+                egui::Grid::new("template_form").num_columns(2).show(ui, |ui| {
+                    ui.label("Name:");
+                    ui.text_edit_singleline(&mut draft.name);
+                    ui.end_row();
+
+                    ui.label("Title:");
+                    ui.add(
+                        egui::TextEdit::singleline(&mut draft.title_pattern)
+                            .hint_text("{date}, {time}, {weekday}, {date:%d.%m.%Y}"),
+                    );
+                    ui.end_row();
+
+                    ui.label("Tags:");
+                    ui.add(egui::TextEdit::singleline(&mut self.template_tags).hint_text("comma separated"));
+                    ui.end_row();
+
+                    ui.label("Notebook:");
+                    ui.text_edit_singleline(&mut self.template_notebook);
+                    ui.end_row();
+
+                    ui.label("Location:");
+                    ui.horizontal(|ui| {
+                        let mut has_location = draft.location.is_some();
+                        if ui.checkbox(&mut has_location, "").changed() {
+                            draft.location = has_location.then(GeoLocation::copenhagen);
+                        }
+                        if let Some(location) = &mut draft.location {
+                            ui.add(egui::TextEdit::singleline(&mut location.name).desired_width(120.0));
+                            ui.add(egui::DragValue::new(&mut location.latitude).speed(0.01).range(-90.0..=90.0));
+                            ui.add(egui::DragValue::new(&mut location.longitude).speed(0.01).range(-180.0..=180.0));
+                        }
+                    });
+                    ui.end_row();
+                });
+
+                ui.label("Body:");
+                let mut layouter = crate::highlight::markdown_layouter;
+                ui.add(
+                    egui::TextEdit::multiline(&mut draft.body)
+                        .desired_width(f32::INFINITY)
+                        .desired_rows(8)
+                        .font(egui::TextStyle::Monospace)
+                        .layouter(&mut layouter),
+                );
+
+                let (mut save, mut cancel) = (false, false);
+                ui.horizontal(|ui| {
+                    save = ui.button("💾 Save template").clicked();
+                    cancel = ui.button("❌ Cancel").clicked();
+                });
+
+                if save {
+                    let mut template = draft.clone();
+                    template.tags = self
+                        .template_tags
+                        .split(',')
+                        .map(|tag| tag.trim().to_string())
+                        .filter(|tag| !tag.is_empty())
+                        .collect();
+                    let notebook = self.template_notebook.trim();
+                    template.notebook = (!notebook.is_empty()).then(|| notebook.to_string());
+
+                    if let Err(e) = self.storage.save_template(template) {
+                        eprintln!("Failed to save template: {}", e);
+                    }
+                }
+                if save || cancel {
+                    self.template_draft = None;
+                }
+            });
+
+        if let Some(template) = edited {
+            self.edit_template(template);
+        }
+        if let Some(id) = deleted {
+            if let Err(e) = self.storage.delete_template(&id) {
+                eprintln!("Failed to delete template: {}", e);
+            }
+        }
+        if !open {
+            self.template_draft = None;
+        }
+        self.show_templates = open;
     }
 
     fn save_current_note(&mut self) {
@@ -670,7 +3342,14 @@ impl NotesApp {
             if let Some(note) = self.storage.get_note(note_id).cloned() {
                 let mut updated_note = note;
                 updated_note.update_title(self.edit_title.clone());
-                updated_note.update_content(self.edit_content.clone());
+                if let Err(e) = updated_note.update_content(self.edit_content.clone()) {
+                    self.note_error = Some(format!("Failed to save note: {}", e));
+                    return;
+                }
+                self.note_error = None;
+                let notebook = self.edit_notebook.trim();
+                updated_note.set_notebook((!notebook.is_empty()).then(|| notebook.to_string()));
+                updated_note.set_properties(self.edit_properties.clone());
                 
                 if let Err(e) = self.storage.update_note(updated_note) {
                     eprintln!("Failed to save note: {}", e);
@@ -795,10 +3474,190 @@ impl NotesApp {
 
 impl eframe::App for NotesApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.answer_api_calls();
         if !self.storage.is_unlocked() {
             self.render_unlock_screen(ctx);
         } else {
+            self.check_reminders(ctx);
+            self.check_sync(ctx);
             self.render_main_ui(ctx);
+            self.images.show_viewer(ctx);
+        }
+    }
+
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        // Removes the token file
+        self.api = None;
+        storage::clear_opened_attachments();
+    }
+}
+
+fn calendar_entry_link(ui: &mut egui::Ui, entry: &CalendarEntry, opened: &mut Option<String>) {
+    let time = entry
+        .time
+        .map(|time| time.format("%H:%M ").to_string())
+        .unwrap_or_default();
+    if ui
+        .link(format!("{}{} {}", time, entry.kind.icon(), entry.title))
+        .on_hover_text(entry.kind.label())
+        .clicked()
+    {
+        *opened = Some(entry.note_id.clone());
+    }
+}
+
+/// Vertical list of entries grouped by day
+fn render_timeline(
+    ui: &mut egui::Ui,
+    entries: &[CalendarEntry],
+    opened: &mut Option<String>,
+    zoom_to: &mut Option<(Zoom, chrono::NaiveDate)>,
+) {
+    if entries.is_empty() {
+        ui.label("Nothing happened in this period.");
+        return;
+    }
+
+    for group in entries.chunk_by(|a, b| a.date == b.date) {
+        let day = group[0].date;
+        if ui
+            .link(egui::RichText::new(day.format("%A, %d %B %Y").to_string()).strong())
+            .clicked()
+        {
+            *zoom_to = Some((Zoom::Day, day));
+        }
+        ui.indent(day, |ui| {
+            for entry in group {
+                calendar_entry_link(ui, entry, opened);
+            }
+        });
+        ui.add_space(6.0);
+    }
+}
+
+/// Twelve small month grids; days with entries are highlighted
+fn render_year_grid(
+    ui: &mut egui::Ui,
+    year_start: chrono::NaiveDate,
+    entries: &[CalendarEntry],
+    zoom_to: &mut Option<(Zoom, chrono::NaiveDate)>,
+) {
+    let days: std::collections::HashSet<_> = entries.iter().map(|entry| entry.date).collect();
+    ui.horizontal_wrapped(|ui| {
+        for offset in 0..12 {
+            let month = calendar::shift_month(year_start, offset);
+            ui.group(|ui| {
+                ui.vertical(|ui| {
+                    if ui.link(egui::RichText::new(month.format("%B").to_string()).strong()).clicked() {
+                        *zoom_to = Some((Zoom::Month, month));
+                    }
+                    let id = format!("year_month_{}", offset);
+                    if let Some(day) = calendar::month_grid(ui, &id, month, None, |day| days.contains(&day)) {
+                        *zoom_to = Some((Zoom::Day, day));
+                    }
+                });
+            });
+        }
+    });
+}
+
+/// Month or week grid with the entries of each day listed in its cell
+fn render_day_columns(
+    ui: &mut egui::Ui,
+    zoom: Zoom,
+    start: chrono::NaiveDate,
+    end: chrono::NaiveDate,
+    entries: &[CalendarEntry],
+    opened: &mut Option<String>,
+    zoom_to: &mut Option<(Zoom, chrono::NaiveDate)>,
+) {
+    use chrono::Datelike;
+
+    let per_cell = if zoom == Zoom::Week { usize::MAX } else { 3 };
+    let grid_start = start - chrono::Duration::days(start.weekday().num_days_from_monday() as i64);
+    let weeks = ((end - grid_start).num_days() + 6) / 7;
+    let cell_width = (ui.available_width() / 7.0 - 8.0).max(80.0);
+    let today = chrono::Local::now().date_naive();
+
+    egui::Grid::new("calendar_days")
+        .striped(true)
+        .min_col_width(cell_width)
+        .max_col_width(cell_width)
+        .show(ui, |ui| {
+            for name in ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"] {
+                ui.label(egui::RichText::new(name).weak());
+            }
+            ui.end_row();
+
+            for week in 0..weeks {
+                for weekday in 0..7 {
+                    let day = grid_start + chrono::Duration::days(week * 7 + weekday);
+                    ui.vertical(|ui| {
+                        ui.set_min_height(if zoom == Zoom::Week { 200.0 } else { 70.0 });
+                        if day < start || day >= end {
+                            return;
+                        }
+
+                        let mut number = egui::RichText::new(day.day().to_string());
+                        if day == today {
+                            number = number.strong().color(ui.visuals().selection.bg_fill);
+                        }
+                        if ui.link(number).clicked() {
+                            *zoom_to = Some((Zoom::Day, day));
+                        }
+
+                        let day_entries: Vec<_> = entries.iter().filter(|entry| entry.date == day).collect();
+                        for entry in day_entries.iter().take(per_cell) {
+                            calendar_entry_link(ui, entry, opened);
+                        }
+                        if day_entries.len() > per_cell
+                            && ui.small_button(format!("+{} more", day_entries.len() - per_cell)).clicked()
+                        {
+                            *zoom_to = Some((Zoom::Day, day));
+                        }
+                    });
+                }
+                ui.end_row();
+            }
+        });
+}
+
+fn property_editor(ui: &mut egui::Ui, key: &str, value: &mut PropertyValue) {
+    match value {
+        PropertyValue::Text(text) | PropertyValue::Url(text) => {
+            ui.text_edit_singleline(text);
+        }
+        PropertyValue::Number(number) => {
+            ui.add(egui::DragValue::new(number));
         }
+        PropertyValue::Bool(flag) => {
+            ui.checkbox(flag, "");
+        }
+        PropertyValue::Date(date) => {
+            ui.add(egui_extras::DatePickerButton::new(date).id_source(key));
+        }
+        PropertyValue::List(items) => {
+            let mut text = items.join(", ");
+            if ui.text_edit_singleline(&mut text).changed() {
+                *items = properties::split_list(&text);
+            }
+        }
+    }
+}
+
+fn export_markdown(note: &Note) {
+    let Some(path) = rfd::FileDialog::new()
+        .add_filter("Markdown", &["md"])
+        .set_file_name(format!("{}.md", markdown_folder::sanitize_file_name(&note.title)))
+        .save_file()
+    else {
+        return;
+    };
+
+    let result = properties::to_markdown(note).and_then(|markdown| {
+        std::fs::write(&path, markdown).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    });
+    if let Err(e) = result {
+        eprintln!("Failed to export note: {}", e);
     }
 }
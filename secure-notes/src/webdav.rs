@@ -0,0 +1,445 @@
+use crate::sync::Remote;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use reqwest::blocking::{Client, RequestBuilder, Response};
+use reqwest::{Method, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::thread;
+use std::time::Duration;
+
+const ATTEMPTS: u32 = 3;
+const RETRY_DELAY: Duration = Duration::from_millis(250);
+const PROPFIND_BODY: &str =
+    r#"<?xml version="1.0" encoding="utf-8"?><d:propfind xmlns:d="DAV:"><d:prop><d:getetag/><d:resourcetype/></d:prop></d:propfind>"#;
+
+/// File contents with the ETag they were served with
+type Versioned = (Vec<u8>, Option<String>);
+
+/// A WebDAV collection to sync through, e.g. on Nextcloud
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WebDavAccount {
+    pub url: String,
+    #[serde(default)]
+    pub username: String,
+    #[serde(default)]
+    pub password: String,
+}
+
+/// Blocking WebDAV client. Requests that fail to connect or get a server
+/// error are retried a few times before giving up.
+pub struct WebDav {
+    account: WebDavAccount,
+    client: Client,
+}
+
+impl WebDav {
+    pub fn new(account: &WebDavAccount) -> Result<Self, String> {
+        if !account.url.starts_with("http://") && !account.url.starts_with("https://") {
+            return Err("WebDAV addresses start with http:// or https://".to_string());
+        }
+        let client = Client::builder()
+            .user_agent("SecureNotesApp/0.1.0")
+            .connect_timeout(Duration::from_secs(5))
+            .timeout(Duration::from_secs(30))
+            .build()
+            .map_err(|e| format!("Failed to create WebDAV client: {}", e))?;
+        Ok(Self {
+            account: account.clone(),
+            client,
+        })
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/{}", self.account.url.trim_end_matches('/'), path)
+    }
+
+    fn send(&self, method: &str, path: &str, build: impl Fn(RequestBuilder) -> RequestBuilder) -> Result<Response, String> {
+        let method = Method::from_bytes(method.as_bytes()).map_err(|e| format!("Invalid method: {}", e))?;
+        let mut error = String::new();
+        for attempt in 0..ATTEMPTS {
+            if attempt > 0 {
+                thread::sleep(RETRY_DELAY * 2u32.pow(attempt - 1));
+            }
+            let mut request = self.client.request(method.clone(), self.url(path));
+            if !self.account.username.is_empty() {
+                request = request.basic_auth(&self.account.username, Some(&self.account.password));
+            }
+            match build(request).send() {
+                Ok(response) if response.status().is_server_error() => {
+                    error = format!("{} {} returned {}", method, path, response.status());
+                }
+                Ok(response) if response.status() == StatusCode::UNAUTHORIZED => {
+                    return Err("The WebDAV server rejected the username or password".to_string());
+                }
+                Ok(response) => return Ok(response),
+                Err(e) => error = format!("Failed to reach the WebDAV server: {}", e),
+            }
+        }
+        Err(error)
+    }
+
+    fn get(&self, path: &str) -> Result<Option<Versioned>, String> {
+        let response = self.send("GET", path, |request| request)?;
+        match response.status() {
+            StatusCode::NOT_FOUND => Ok(None),
+            status if status.is_success() => {
+                let etag = etag(&response);
+                let data = response
+                    .bytes()
+                    .map_err(|e| format!("Failed to download {}: {}", path, e))?;
+                Ok(Some((data.to_vec(), etag)))
+            }
+            status => Err(format!("Failed to download {}: {}", path, status)),
+        }
+    }
+
+    /// Uploads `data`; `Ok(false)` when the `If-Match`/`If-None-Match`
+    /// precondition failed because someone else changed the file
+    fn put(&self, path: &str, data: &[u8], expected: Option<Option<&str>>) -> Result<bool, String> {
+        let request = |request: RequestBuilder| {
+            let request = match expected {
+                Some(Some(etag)) => request.header("If-Match", etag),
+                Some(None) => request.header("If-None-Match", "*"),
+                None => request,
+            };
+            request.body(data.to_vec())
+        };
+        let mut response = self.send("PUT", path, request)?;
+        if response.status() == StatusCode::CONFLICT {
+            // The parent collections do not exist yet
+            self.create_collections(path)?;
+            response = self.send("PUT", path, request)?;
+        }
+        match response.status() {
+            StatusCode::PRECONDITION_FAILED => Ok(false),
+            status if status.is_success() => Ok(true),
+            status => Err(format!("Failed to upload {}: {}", path, status)),
+        }
+    }
+
+    /// Creates the base collection and every folder above `path`
+    fn create_collections(&self, path: &str) -> Result<(), String> {
+        let mut folders: Vec<&str> = path.split('/').collect();
+        folders.pop();
+        let mut collection = String::new();
+        self.create_collection(&collection)?;
+        for folder in folders {
+            collection = format!("{}{}/", collection, folder);
+            self.create_collection(&collection)?;
+        }
+        Ok(())
+    }
+
+    fn create_collection(&self, path: &str) -> Result<(), String> {
+        let status = self.send("MKCOL", path, |request| request)?.status();
+        // 405 means the collection already exists
+        if !status.is_success() && status != StatusCode::METHOD_NOT_ALLOWED {
+            return Err(format!("Failed to create {}: {}", self.url(path), status));
+        }
+        Ok(())
+    }
+}
+
+fn etag(response: &Response) -> Option<String> {
+    response
+        .headers()
+        .get("ETag")
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+}
+
+/// Files directly in the collection with their ETags, from a PROPFIND reply
+fn parse_multistatus(xml: &str) -> Result<Vec<(String, String)>, String> {
+    let mut reader = Reader::from_str(xml);
+    let mut files = Vec::new();
+    let (mut href, mut etag, mut collection) = (String::new(), String::new(), false);
+    let mut element = String::new();
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(start)) => {
+                element = String::from_utf8_lossy(start.local_name().as_ref()).into_owned();
+                if element == "response" {
+                    (href, etag, collection) = (String::new(), String::new(), false);
+                }
+            }
+            Ok(Event::Empty(empty)) if empty.local_name().as_ref() == b"collection" => collection = true,
+            Ok(Event::Text(text)) => {
+                let text = text.unescape().map_err(|e| format!("Invalid WebDAV reply: {}", e))?;
+                match element.as_str() {
+                    "href" => href.push_str(text.trim()),
+                    "getetag" => etag.push_str(text.trim()),
+                    _ => {}
+                }
+            }
+            Ok(Event::End(end)) => {
+                element.clear();
+                if end.local_name().as_ref() == b"response" && !collection {
+                    let name = href.trim_end_matches('/').rsplit('/').next().unwrap_or_default();
+                    files.push((name.to_string(), etag.clone()));
+                }
+            }
+            Ok(Event::Eof) => break,
+            Err(e) => return Err(format!("Invalid WebDAV reply: {}", e)),
+            _ => {}
+        }
+    }
+    Ok(files)
+}
+
+impl Remote for WebDav {
+    fn list(&self) -> Result<Vec<(String, String)>, String> {
+        let response = self.send("PROPFIND", "", |request| {
+            request
+                .header("Depth", "1")
+                .header("Content-Type", "application/xml")
+                .body(PROPFIND_BODY)
+        })?;
+        match response.status() {
+            StatusCode::NOT_FOUND => Ok(Vec::new()),
+            status if status.is_success() => {
+                let xml = response
+                    .text()
+                    .map_err(|e| format!("Failed to list the WebDAV folder: {}", e))?;
+                parse_multistatus(&xml)
+            }
+            status => Err(format!("Failed to list the WebDAV folder: {}", status)),
+        }
+    }
+
+    fn read(&self, path: &str) -> Result<Option<Vec<u8>>, String> {
+        Ok(self.get(path)?.map(|(data, _)| data))
+    }
+
+    fn read_from(&self, path: &str, offset: u64) -> Result<Option<Vec<u8>>, String> {
+        if offset == 0 {
+            return self.read(path);
+        }
+        let range = format!("bytes={}-", offset);
+        let response = self.send("GET", path, |request| request.header("Range", range.as_str()))?;
+        match response.status() {
+            StatusCode::NOT_FOUND => Ok(None),
+            // Nothing was added
+            StatusCode::RANGE_NOT_SATISFIABLE => Ok(Some(Vec::new())),
+            status if status.is_success() => {
+                let data = response
+                    .bytes()
+                    .map_err(|e| format!("Failed to download {}: {}", path, e))?;
+                // Servers without range support send the whole file
+                Ok(Some(match status {
+                    StatusCode::PARTIAL_CONTENT => data.to_vec(),
+                    _ => data.get(offset as usize..).unwrap_or_default().to_vec(),
+                }))
+            }
+            status => Err(format!("Failed to download {}: {}", path, status)),
+        }
+    }
+
+    fn write(&self, path: &str, data: &[u8]) -> Result<(), String> {
+        self.put(path, data, None).map(|_| ())
+    }
+
+    fn exists(&self, path: &str) -> Result<bool, String> {
+        let status = self.send("HEAD", path, |request| request)?.status();
+        match status {
+            StatusCode::NOT_FOUND => Ok(false),
+            status if status.is_success() => Ok(true),
+            status => Err(format!("Failed to check {}: {}", path, status)),
+        }
+    }
+
+    /// WebDAV cannot append, so the file is replaced as long as its ETag
+    /// still matches what was read
+    fn append(&self, path: &str, data: &[u8]) -> Result<(), String> {
+        for _ in 0..ATTEMPTS {
+            let (mut contents, etag) = self.get(path)?.unwrap_or_default();
+            contents.extend_from_slice(data);
+            if self.put(path, &contents, Some(etag.as_deref()))? {
+                return Ok(());
+            }
+        }
+        Err(format!("{} kept changing while uploading", path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attachments::{BlobKeys, BlobStore};
+    use crate::note::Note;
+    use crate::sync::{SyncState, SyncTarget};
+    use std::collections::{HashMap, HashSet};
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use tiny_http::{Header, Response, Server};
+
+    /// Just enough of a WebDAV server for sync: files with ETags, folders,
+    /// and switches to make it fail or go offline
+    #[derive(Default)]
+    struct StandIn {
+        files: Mutex<HashMap<String, (Vec<u8>, usize)>>,
+        collections: Mutex<HashSet<String>>,
+        failures: AtomicUsize,
+        offline: AtomicBool,
+        uploads: AtomicUsize,
+        downloads: AtomicUsize,
+    }
+
+    impl StandIn {
+        fn start() -> (Arc<Self>, String) {
+            let server = Server::http("127.0.0.1:0").unwrap();
+            let url = format!("http://{}/dav/vault", server.server_addr().to_ip().unwrap());
+            let stand_in = Arc::new(Self::default());
+            stand_in.collections.lock().unwrap().insert("/dav".to_string());
+            let handler = stand_in.clone();
+            thread::spawn(move || {
+                for mut request in server.incoming_requests() {
+                    let mut body = Vec::new();
+                    request.as_reader().read_to_end(&mut body).unwrap();
+                    let header = |name: &'static str| {
+                        request
+                            .headers()
+                            .iter()
+                            .find(|header| header.field.equiv(name))
+                            .map(|header| header.value.to_string())
+                    };
+                    let (status, data, etag) = handler.handle(
+                        request.method().as_str(),
+                        request.url().trim_end_matches('/'),
+                        body,
+                        header("If-Match"),
+                        header("If-None-Match"),
+                    );
+                    let mut response = Response::from_data(data).with_status_code(status);
+                    if let Some(etag) = etag {
+                        response = response.with_header(Header::from_bytes("ETag", etag).unwrap());
+                    }
+                    let _ = request.respond(response);
+                }
+            });
+            (stand_in, url)
+        }
+
+        fn handle(
+            &self,
+            method: &str,
+            path: &str,
+            body: Vec<u8>,
+            if_match: Option<String>,
+            if_none_match: Option<String>,
+        ) -> (u16, Vec<u8>, Option<String>) {
+            if self.offline.load(Ordering::SeqCst) {
+                return (503, Vec::new(), None);
+            }
+            if self.failures.load(Ordering::SeqCst) > 0 {
+                self.failures.fetch_sub(1, Ordering::SeqCst);
+                return (500, Vec::new(), None);
+            }
+            let mut files = self.files.lock().unwrap();
+            let mut collections = self.collections.lock().unwrap();
+            let tag = |version: usize| format!("\"{}\"", version);
+            let parent = path.rsplit_once('/').map(|(parent, _)| parent).unwrap_or_default();
+            match method {
+                "GET" | "HEAD" => match files.get(path) {
+                    Some((data, version)) if method == "GET" => {
+                        self.downloads.fetch_add(1, Ordering::SeqCst);
+                        (200, data.clone(), Some(tag(*version)))
+                    }
+                    Some((data, version)) => (200, data.clone(), Some(tag(*version))),
+                    None => (404, Vec::new(), None),
+                },
+                "PUT" => {
+                    let current = files.get(path).map(|(_, version)| tag(*version));
+                    if !collections.contains(parent) {
+                        return (409, Vec::new(), None);
+                    }
+                    if if_match.is_some_and(|etag| Some(etag) != current)
+                        || if_none_match.is_some() && current.is_some()
+                    {
+                        return (412, Vec::new(), None);
+                    }
+                    self.uploads.fetch_add(1, Ordering::SeqCst);
+                    let version = self.uploads.load(Ordering::SeqCst);
+                    files.insert(path.to_string(), (body, version));
+                    (201, Vec::new(), Some(tag(version)))
+                }
+                "MKCOL" if collections.contains(path) => (405, Vec::new(), None),
+                "MKCOL" if collections.contains(parent) => {
+                    collections.insert(path.to_string());
+                    (201, Vec::new(), None)
+                }
+                "MKCOL" => (409, Vec::new(), None),
+                "PROPFIND" if collections.contains(path) => {
+                    let mut xml = format!(
+                        "<d:multistatus xmlns:d=\"DAV:\"><d:response><d:href>{}/</d:href><d:propstat><d:prop>\
+                         <d:resourcetype><d:collection/></d:resourcetype></d:prop></d:propstat></d:response>",
+                        path
+                    );
+                    for (file, (_, version)) in files.iter() {
+                        if file.rsplit_once('/').is_some_and(|(dir, _)| dir == path) {
+                            xml.push_str(&format!(
+                                "<d:response><d:href>{}</d:href><d:propstat><d:prop><d:getetag>&quot;{}&quot;</d:getetag>\
+                                 <d:resourcetype/></d:prop></d:propstat></d:response>",
+                                file, version
+                            ));
+                        }
+                    }
+                    xml.push_str("</d:multistatus>");
+                    (207, xml.into_bytes(), None)
+                }
+                "PROPFIND" => (404, Vec::new(), None),
+                _ => (405, Vec::new(), None),
+            }
+        }
+    }
+
+    #[test]
+    fn webdav_sync_retries_and_queues_changes_while_offline() {
+        let (server, url) = StandIn::start();
+        let dir = tempfile::tempdir().unwrap();
+        let account = WebDavAccount {
+            url,
+            username: "me".to_string(),
+            password: "secret".to_string(),
+        };
+        let device = |name: &str| {
+            let state = SyncState::connect(SyncTarget::WebDav(account.clone()), "sync password").unwrap();
+            let store = BlobStore::new(dir.path().join(name), &BlobKeys::generate()).unwrap();
+            (state, HashMap::<String, Note>::new(), store)
+        };
+        let (mut laptop, mut laptop_notes, laptop_store) = device("laptop");
+        let (mut phone, mut phone_notes, phone_store) = device("phone");
+
+        let mut note = Note::new("Packing".to_string(), "passport\n".to_string());
+        let attachment = laptop_store.add_bytes("ticket.pdf", b"pdf").unwrap();
+        note.add_attachment(attachment);
+        laptop_notes.insert(note.id.clone(), note.clone());
+
+        // Transient server errors are retried
+        server.failures.store(2, Ordering::SeqCst);
+        assert_eq!(laptop.sync(&mut laptop_notes, &laptop_store).unwrap().sent, 1);
+        // Only ciphertext reaches the server
+        for (data, _) in server.files.lock().unwrap().values() {
+            let text = String::from_utf8_lossy(data);
+            assert!(!text.contains("passport") && !text.contains("pdf"));
+        }
+
+        // Offline edits are queued and uploaded once the server is back
+        server.offline.store(true, Ordering::SeqCst);
+        laptop_notes.get_mut(&note.id).unwrap().update_content("passport\ncharger\n".to_string()).unwrap();
+        assert!(laptop.sync(&mut laptop_notes, &laptop_store).is_err());
+        assert_eq!(laptop.queued(), 1);
+        server.offline.store(false, Ordering::SeqCst);
+        laptop.sync(&mut laptop_notes, &laptop_store).unwrap();
+        assert_eq!(laptop.queued(), 0);
+
+        assert_eq!(phone.sync(&mut phone_notes, &phone_store).unwrap().received, 2);
+        let received = &phone_notes[&note.id];
+        assert_eq!(received.content, "passport\ncharger\n");
+        assert_eq!(phone_store.read(&received.attachments[0].blob_id).unwrap(), b"pdf");
+
+        // Unchanged logs are not downloaded again
+        let downloads = server.downloads.load(Ordering::SeqCst);
+        assert_eq!(phone.sync(&mut phone_notes, &phone_store).unwrap(), Default::default());
+        assert_eq!(server.downloads.load(Ordering::SeqCst), downloads);
+    }
+}